@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::env;
+use std::process;
+
+/// A screenshot, as 8 bit red, green and blue for each pixel.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+// A few thousand pixels are plenty to find a strip's colour, and much quicker
+const SAMPLES_PER_STRIP: usize = 4000;
+
+/// Reads a binary PPM (P6) image, as written by `grim` and ImageMagick.
+pub fn parse_ppm(ppm: &[u8]) -> Result<Image, String> {
+    let bad = || "Not a binary PPM image".to_string();
+
+    // The header is 4 fields separated by whitespace, with '#' comments
+    let mut fields = Vec::new();
+    let mut at = 0;
+
+    while fields.len() < 4 {
+        while at < ppm.len() && ppm[at].is_ascii_whitespace() {
+            at += 1;
+        }
+
+        if at < ppm.len() && ppm[at] == b'#' {
+            while at < ppm.len() && ppm[at] != b'\n' {
+                at += 1;
+            }
+
+            continue;
+        }
+
+        let start = at;
+
+        while at < ppm.len() && !ppm[at].is_ascii_whitespace() {
+            at += 1;
+        }
+
+        if start == at {
+            return Err(bad());
+        }
+
+        fields.push(String::from_utf8_lossy(&ppm[start..at]).to_string());
+    }
+
+    // Just the one whitespace character before the pixels
+    at += 1;
+
+    let number = |field: &str| field.parse::<usize>().map_err(|_| bad());
+
+    if fields[0] != "P6" || number(&fields[3])? != 255 {
+        return Err(bad());
+    }
+
+    let (width, height) = (number(&fields[1])?, number(&fields[2])?);
+
+    match ppm.get(at..at + width * height * 3) {
+        Some(pixels) if width > 0 && height > 0 => Ok(Image {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        }),
+        _ => Err(bad()),
+    }
+}
+
+/// The most common colour in each of `strips` strips across the screen,
+/// left to right, like the keyboard's regions.
+pub fn strip_colours(image: &Image, strips: usize) -> Vec<u32> {
+    let strip_width = (image.width / strips).max(1);
+    let step = ((strip_width * image.height) / SAMPLES_PER_STRIP).max(1);
+
+    (0..strips)
+        .map(|strip| {
+            // Similar colours are counted together, then averaged
+            let mut buckets: HashMap<u32, (u32, [u32; 3])> = HashMap::new();
+            let left = (strip * strip_width).min(image.width - 1);
+            let width = strip_width.min(image.width - left);
+
+            for sample in (0..width * image.height).step_by(step) {
+                let (x, y) = (left + sample % width, sample / width);
+                let pixel = &image.pixels[(y * image.width + x) * 3..][..3];
+
+                let bucket = (pixel[0] as u32 >> 4) << 8
+                    | (pixel[1] as u32 >> 4) << 4
+                    | pixel[2] as u32 >> 4;
+                let (count, total) = buckets.entry(bucket).or_default();
+
+                *count += 1;
+
+                for (total, channel) in total.iter_mut().zip(pixel) {
+                    *total += *channel as u32;
+                }
+            }
+
+            buckets
+                .values()
+                .max_by_key(|(count, _)| *count)
+                .map_or(0, |(count, total)| {
+                    total
+                        .iter()
+                        .fold(0, |colour, total| (colour << 8) | (total / count))
+                })
+        })
+        .collect()
+}
+
+// A small screenshot is all that's needed, and much quicker to grab
+fn screenshot_command() -> process::Command {
+    match env::var_os("WAYLAND_DISPLAY") {
+        Some(_) => {
+            let mut grim = process::Command::new("grim");
+
+            grim.args(["-t", "ppm", "-s", "0.1", "-"]);
+
+            grim
+        }
+        None => {
+            let mut import = process::Command::new("import");
+
+            import.args(["-silent", "-window", "root", "-resize", "10%", "ppm:-"]);
+
+            import
+        }
+    }
+}
+
+/// Takes a screenshot with `grim` on Wayland, or ImageMagick's `import` on X11.
+pub fn grab_screen() -> Result<Image, String> {
+    let mut command = screenshot_command();
+    let program = command.get_program().to_string_lossy().to_string();
+
+    match command.output() {
+        Ok(output) if output.status.success() => parse_ppm(&output.stdout),
+        Ok(output) => Err(format!(
+            "Unable to take a screenshot with {}: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(err) => Err(format!(
+            "Unable to run {} for screenshots: {}",
+            program, err
+        )),
+    }
+}
+
+#[cfg(test)]
+mod ambient_tests {
+    use super::*;
+
+    fn ppm(width: usize, height: usize, pixel: impl Fn(usize, usize) -> [u8; 3]) -> Vec<u8> {
+        let mut ppm = format!("P6\n# test\n{} {}\n255\n", width, height).into_bytes();
+
+        for y in 0..height {
+            for x in 0..width {
+                ppm.extend(pixel(x, y));
+            }
+        }
+
+        ppm
+    }
+
+    #[test]
+    fn reads_ppm() {
+        let image = parse_ppm(&ppm(2, 1, |x, _| [x as u8, 2, 3])).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, [0, 2, 3, 1, 2, 3]);
+
+        assert!(parse_ppm(b"P3\n1 1\n255\n0 0 0\n").is_err());
+        assert!(parse_ppm(b"P6\n2 2\n255\n\x00").is_err());
+    }
+
+    #[test]
+    fn strips_left_to_right() {
+        // Mostly red on the left, blue on the right, with a little noise
+        let image = ppm(100, 20, |x, y| match (x, y) {
+            (_, 0) => [255, 255, 255],
+            (x, _) if x < 50 => [250, 0, 0],
+            _ => [0, 0, 250],
+        });
+
+        let colours = strip_colours(&parse_ppm(&image).unwrap(), 2);
+
+        assert_eq!(colours, [0xfa0000, 0x0000fa]);
+    }
+}
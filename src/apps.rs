@@ -0,0 +1,487 @@
+use std::env;
+use std::fs::{read_dir, read_to_string};
+use std::process;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::commands::Command;
+use crate::config::{AppConfig, GameConfig};
+use crate::profiles::get_profile;
+
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+// Linux cuts process names short
+const COMM_LENGTH: usize = 15;
+
+/// The focused window, as far as the desktop will tell us.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Window {
+    pub class: String,
+    pub title: String,
+    pub fullscreen: bool,
+}
+
+// The output of a desktop's tool, if it's there and worked
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = process::Command::new(program).args(args).output().ok()?;
+
+    match output.status.success() {
+        true => Some(String::from_utf8_lossy(&output.stdout).to_string()),
+        false => None,
+    }
+}
+
+/// The window id from `xprop -root _NET_ACTIVE_WINDOW`.
+pub fn parse_active_window(xprop: &str) -> Option<String> {
+    let id = xprop.split('#').nth(1)?.trim();
+
+    // Nothing's focused
+    match id.trim_start_matches("0x").trim_start_matches('0') {
+        "" => None,
+        _ => Some(id.to_string()),
+    }
+}
+
+// The quoted values of an xprop property, eg 'WM_CLASS(STRING) = "kitty", "kitty"'
+fn xprop_values<'a>(xprop: &'a str, property: &str) -> Vec<&'a str> {
+    xprop
+        .lines()
+        .find(|line| line.starts_with(&format!("{}(", property)))
+        .and_then(|line| line.split_once(" = "))
+        .map(|(_, values)| {
+            values
+                .split("\", \"")
+                .map(|value| value.trim_matches('"'))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The window from `xprop -id <window> WM_CLASS _NET_WM_NAME _NET_WM_STATE`.
+pub fn parse_xprop_window(xprop: &str) -> Window {
+    let class = xprop_values(xprop, "WM_CLASS");
+    let title = xprop_values(xprop, "_NET_WM_NAME");
+
+    // Atoms aren't quoted, so all the states are one value
+    let state = xprop_values(xprop, "_NET_WM_STATE");
+
+    Window {
+        class: class.last().unwrap_or(&"").to_string(),
+        title: title.first().unwrap_or(&"").to_string(),
+        fullscreen: state.first().is_some_and(|state| {
+            state
+                .split(", ")
+                .any(|atom| atom == "_NET_WM_STATE_FULLSCREEN")
+        }),
+    }
+}
+
+/// The window from `hyprctl activewindow -j`.
+pub fn parse_hyprland_window(json: &str) -> Option<Window> {
+    let window: Value = serde_json::from_str(json).ok()?;
+
+    Some(Window {
+        class: window["class"].as_str()?.to_string(),
+        title: window["title"].as_str().unwrap_or_default().to_string(),
+        // A number for the kind of fullscreen in newer versions
+        fullscreen: window["fullscreen"]
+            .as_bool()
+            .or(window["fullscreen"].as_u64().map(|mode| mode > 0))
+            .unwrap_or_default(),
+    })
+}
+
+/// The focused window in `swaymsg -t get_tree`, a Wayland app or an X11 one.
+pub fn parse_sway_tree(json: &str) -> Option<Window> {
+    fn focused(node: &Value) -> Option<&Value> {
+        if node["focused"].as_bool() == Some(true) && node["type"] == "con" {
+            return Some(node);
+        }
+
+        ["nodes", "floating_nodes"]
+            .iter()
+            .filter_map(|children| node[children].as_array())
+            .flatten()
+            .find_map(focused)
+    }
+
+    let tree: Value = serde_json::from_str(json).ok()?;
+    let node = focused(&tree)?;
+
+    Some(Window {
+        class: node["app_id"]
+            .as_str()
+            .or(node["window_properties"]["class"].as_str())?
+            .to_string(),
+        title: node["name"].as_str().unwrap_or_default().to_string(),
+        fullscreen: node["fullscreen_mode"].as_u64().unwrap_or_default() > 0,
+    })
+}
+
+/// The focused window on Hyprland, sway or X11, using their own tools.
+pub fn active_window() -> Option<Window> {
+    if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        return parse_hyprland_window(&run("hyprctl", &["activewindow", "-j"])?);
+    }
+
+    if env::var_os("SWAYSOCK").is_some() {
+        return parse_sway_tree(&run("swaymsg", &["-t", "get_tree"])?);
+    }
+
+    let id = parse_active_window(&run("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?)?;
+
+    Some(parse_xprop_window(&run(
+        "xprop",
+        &["-id", &id, "WM_CLASS", "_NET_WM_NAME", "_NET_WM_STATE"],
+    )?))
+}
+
+/// Whether any of `names` is among the `running` process names, which are
+/// cut short like those in `/proc`.
+pub fn any_running(names: &[String], running: &[String]) -> bool {
+    names.iter().any(|name| {
+        let name: String = name.chars().take(COMM_LENGTH).collect();
+
+        running
+            .iter()
+            .any(|process| process.eq_ignore_ascii_case(&name))
+    })
+}
+
+// The name of every process, as the kernel knows it
+fn running_processes() -> Vec<String> {
+    let Ok(entries) = read_dir("/proc") else {
+        return vec![];
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .filter_map(|entry| read_to_string(entry.path().join("comm")).ok())
+        .map(|comm| comm.trim_end().to_string())
+        .collect()
+}
+
+struct Rule {
+    class: Option<String>,
+    title: Option<String>,
+    profile: String,
+}
+
+impl Rule {
+    // The whole class, and part of the title, ignoring case
+    fn matches(&self, window: &Window) -> bool {
+        self.class
+            .as_ref()
+            .is_none_or(|class| class.eq_ignore_ascii_case(&window.class))
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|title| window.title.to_lowercase().contains(&title.to_lowercase()))
+    }
+}
+
+#[derive(Debug)]
+pub enum AppChange {
+    Run(Command),
+    Restore,
+}
+
+/// Profiles for applications, from the config's `[[apps]]`, which the daemon
+/// shows while their windows are focused, and the `[game]` profile while a
+/// game is running.
+pub struct Apps {
+    rules: Vec<Rule>,
+    game: Option<String>,
+    fullscreen: bool,
+    processes: Vec<String>,
+    profile: Option<String>,
+    checked: Option<Instant>,
+}
+
+impl Apps {
+    /// Nothing if there are no `[[apps]]` or `[game]` profile.
+    pub fn new(config: &[AppConfig], game: &GameConfig) -> Result<Option<Apps>, String> {
+        if config.is_empty() && game.profile.is_none() {
+            return Ok(None);
+        }
+
+        if game.profile.is_some() && !game.fullscreen && game.processes.is_empty() {
+            return Err("[game] needs fullscreen or some processes to spot games".to_string());
+        }
+
+        let rules = config
+            .iter()
+            .map(|app| match (&app.class, &app.title) {
+                (None, None) => Err(format!(
+                    "[[apps]] for profile '{}' needs a class or title",
+                    app.profile
+                )),
+                _ => Ok(Rule {
+                    class: app.class.clone(),
+                    title: app.title.clone(),
+                    profile: app.profile.clone(),
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Some(Apps {
+            rules,
+            game: game.profile.clone(),
+            fullscreen: game.fullscreen,
+            processes: game.processes.clone(),
+            profile: None,
+            checked: None,
+        }))
+    }
+
+    /// The first profile for `window`, or the game profile if there isn't
+    /// one and a game's running.
+    fn profile_for(&self, window: Option<&Window>, running: &[String]) -> Option<String> {
+        let app = window.and_then(|window| self.rules.iter().find(|rule| rule.matches(window)));
+
+        if let Some(rule) = app {
+            return Some(rule.profile.clone());
+        }
+
+        let gaming = window.is_some_and(|window| self.fullscreen && window.fullscreen)
+            || any_running(&self.processes, running);
+
+        self.game.clone().filter(|_| gaming)
+    }
+
+    /// After the saved command has been shown again, so the application's
+    /// profile is shown again if it's still focused.
+    pub fn forget(&mut self) {
+        self.profile = None;
+        self.checked = None;
+    }
+
+    fn update(&mut self, window: Option<&Window>, running: &[String]) -> Option<AppChange> {
+        let profile = self.profile_for(window, running);
+
+        if profile == self.profile {
+            return None;
+        }
+
+        match std::mem::replace(&mut self.profile, profile.clone()) {
+            None => (),
+            Some(_) if profile.is_some() => (),
+            Some(_) => return Some(AppChange::Restore),
+        }
+
+        let name = profile?;
+
+        match get_profile(&name) {
+            Some(command) => Some(AppChange::Run(command)),
+            None => {
+                eprintln!("No profile called '{}', for [[apps]] or [game]", name);
+
+                None
+            }
+        }
+    }
+
+    /// A profile to show now that its application's focused or a game's
+    /// started, or that the saved command should be put back now neither is.
+    pub fn due(&mut self) -> Option<AppChange> {
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < CHECK_EVERY)
+        {
+            return None;
+        }
+
+        self.checked = Some(Instant::now());
+
+        // Only looked for when there's a list, as it's slower
+        let running = match self.processes.is_empty() || self.game.is_none() {
+            true => vec![],
+            false => running_processes(),
+        };
+
+        self.update(active_window().as_ref(), &running)
+    }
+}
+
+#[cfg(test)]
+mod apps_tests {
+    use super::*;
+
+    fn window(class: &str, title: &str) -> Window {
+        Window {
+            class: class.to_string(),
+            title: title.to_string(),
+            fullscreen: false,
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn x11_windows() {
+        assert_eq!(
+            parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007\n"),
+            Some("0x3a00007".to_string())
+        );
+        assert_eq!(
+            parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0\n"),
+            None
+        );
+
+        let xprop = "WM_CLASS(STRING) = \"kitty\", \"Kitty\"\n\
+                     _NET_WM_NAME(UTF8_STRING) = \"ssh prod-db\"\n\
+                     _NET_WM_STATE(ATOM) = _NET_WM_STATE_FOCUSED\n";
+
+        assert_eq!(parse_xprop_window(xprop), window("Kitty", "ssh prod-db"));
+        assert_eq!(parse_xprop_window(""), window("", ""));
+
+        let xprop = "WM_CLASS(STRING) = \"steam_app_570\", \"steam_app_570\"\n\
+                     _NET_WM_STATE(ATOM) = _NET_WM_STATE_FOCUSED, _NET_WM_STATE_FULLSCREEN\n";
+
+        assert!(parse_xprop_window(xprop).fullscreen);
+    }
+
+    #[test]
+    fn wayland_windows() {
+        assert_eq!(
+            parse_hyprland_window(r#"{"class": "firefox", "title": "News"}"#),
+            Some(window("firefox", "News"))
+        );
+        assert_eq!(parse_hyprland_window("{}"), None);
+
+        let fullscreen = |json| parse_hyprland_window(json).map(|window| window.fullscreen);
+
+        assert_eq!(
+            fullscreen(r#"{"class": "a", "fullscreen": true}"#),
+            Some(true)
+        );
+        assert_eq!(fullscreen(r#"{"class": "a", "fullscreen": 2}"#), Some(true));
+        assert_eq!(
+            fullscreen(r#"{"class": "a", "fullscreen": 0}"#),
+            Some(false)
+        );
+
+        let tree = r#"{"type": "root", "nodes": [{"type": "output", "nodes": [
+            {"type": "con", "focused": false, "app_id": "foot", "name": "a"},
+            {"type": "con", "focused": true, "app_id": null, "name": "Steam",
+             "fullscreen_mode": 1, "window_properties": {"class": "steam"}}]}]}"#;
+
+        assert_eq!(
+            parse_sway_tree(tree),
+            Some(Window {
+                fullscreen: true,
+                ..window("steam", "Steam")
+            })
+        );
+    }
+
+    #[test]
+    fn first_matching_rule() {
+        let app = |class: Option<&str>, title: Option<&str>, profile: &str| AppConfig {
+            class: class.map(String::from),
+            title: title.map(String::from),
+            profile: profile.to_string(),
+        };
+        let apps = Apps::new(
+            &[
+                app(Some("kitty"), Some("PROD"), "alert"),
+                app(Some("steam"), None, "games"),
+                app(None, Some("youtube"), "films"),
+            ],
+            &GameConfig::default(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let profile = |class, title| apps.profile_for(Some(&window(class, title)), &[]);
+
+        assert_eq!(profile("Kitty", "ssh prod-db"), Some("alert".to_string()));
+        assert_eq!(profile("kitty", "vim"), None);
+        assert_eq!(profile("Steam", ""), Some("games".to_string()));
+        assert_eq!(profile("firefox", "YouTube"), Some("films".to_string()));
+        assert_eq!(apps.profile_for(None, &[]), None);
+
+        assert!(Apps::new(&[], &GameConfig::default()).unwrap().is_none());
+        assert!(Apps::new(&[app(None, None, "x")], &GameConfig::default()).is_err());
+    }
+
+    #[test]
+    fn games() {
+        assert!(any_running(&names(&["steam"]), &names(&["bash", "Steam"])));
+        assert!(any_running(
+            &names(&["FactoryGame-Linux-Shipping"]),
+            &names(&["FactoryGame-Lin"])
+        ));
+        assert!(!any_running(
+            &names(&["steam"]),
+            &names(&["steamwebhelper"])
+        ));
+
+        let game = GameConfig {
+            profile: Some("gaming".to_string()),
+            fullscreen: true,
+            processes: names(&["dota2"]),
+        };
+        let apps = Apps::new(
+            &[AppConfig {
+                class: Some("mpv".to_string()),
+                title: None,
+                profile: "films".to_string(),
+            }],
+            &game,
+        )
+        .unwrap()
+        .unwrap();
+
+        let gaming = Some("gaming".to_string());
+        let full = |class| Window {
+            fullscreen: true,
+            ..window(class, "")
+        };
+
+        assert_eq!(apps.profile_for(Some(&full("steam_app_570")), &[]), gaming);
+        assert_eq!(apps.profile_for(None, &names(&["dota2"])), gaming);
+        assert_eq!(apps.profile_for(Some(&window("kitty", "")), &[]), None);
+
+        // The application's own profile first
+        assert_eq!(
+            apps.profile_for(Some(&full("mpv")), &names(&["dota2"])),
+            Some("films".to_string())
+        );
+
+        let game = GameConfig {
+            fullscreen: false,
+            processes: vec![],
+            ..game
+        };
+
+        assert!(Apps::new(&[], &game).is_err());
+    }
+
+    #[test]
+    fn restores_when_unfocused() {
+        let mut apps = Apps::new(
+            &[AppConfig {
+                class: Some("steam".to_string()),
+                title: None,
+                profile: "no such profile".to_string(),
+            }],
+            &GameConfig::default(),
+        )
+        .unwrap()
+        .unwrap();
+
+        // A missing profile is reported, but still counts as being shown
+        assert!(apps.update(Some(&window("steam", "")), &[]).is_none());
+        assert!(apps.update(Some(&window("steam", "")), &[]).is_none());
+        assert!(matches!(
+            apps.update(Some(&window("kitty", "")), &[]),
+            Some(AppChange::Restore)
+        ));
+        assert!(apps.update(None, &[]).is_none());
+    }
+}
@@ -0,0 +1,159 @@
+use std::f64::consts::PI;
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::x11_colours::hsv_to_rgb;
+
+pub const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+// A slow breath, and never quite off so the charge can still be seen
+const PULSE_PERIOD: f64 = 2.0;
+const PULSE_LOWEST: f64 = 0.2;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Charge {
+    pub percent: u8,
+    pub charging: bool,
+}
+
+/// The laptop's charge, averaged over its batteries, from `power_supply`.
+/// Batteries in mice and the like are left out.
+pub fn read_charge(power_supply: &Path) -> Result<Charge, String> {
+    let supplies = read_dir(power_supply)
+        .map_err(|err| format!("Unable to read {}: {}", power_supply.display(), err))?;
+
+    let mut percents = Vec::new();
+    let mut charging = false;
+
+    for supply in supplies.flatten() {
+        let read = |file: &str| {
+            read_to_string(supply.path().join(file)).map(|value| value.trim().to_string())
+        };
+
+        if read("type").ok().as_deref() != Some("Battery")
+            || read("scope").ok().as_deref() == Some("Device")
+        {
+            continue;
+        }
+
+        if let Some(percent) = read("capacity").ok().and_then(|c| c.parse::<u32>().ok()) {
+            percents.push(percent.min(100));
+        }
+
+        charging |= read("status").ok().as_deref() == Some("Charging");
+    }
+
+    match percents.len() {
+        0 => Err("No battery found".to_string()),
+        batteries => Ok(Charge {
+            percent: (percents.iter().sum::<u32>() / batteries as u32) as u8,
+            charging,
+        }),
+    }
+}
+
+/// The whole keyboard from green when full to red when empty, or a `bar`
+/// with a region for each fifth of the charge.
+pub fn battery_colours(charge: &Charge, regions: u8, bar: bool) -> Vec<u32> {
+    let full = charge.percent as f64 / 100.0;
+    let colour = hsv_to_rgb(120.0 * full, 100.0, 100.0);
+
+    // Always at least one, so it's clear the bar is there
+    let lit = match bar {
+        true => ((full * regions as f64).ceil() as u8).max(1),
+        false => regions,
+    };
+
+    (0..regions)
+        .map(|region| match region < lit {
+            true => colour,
+            false => 0,
+        })
+        .collect()
+}
+
+/// How bright a pulse is, from 0.2 to 1, `since` it started.
+pub fn pulse(since: Duration) -> f64 {
+    let breath = (1.0 + (2.0 * PI * since.as_secs_f64() / PULSE_PERIOD).cos()) / 2.0;
+
+    PULSE_LOWEST + (1.0 - PULSE_LOWEST) * breath
+}
+
+#[cfg(test)]
+mod battery_tests {
+    use super::*;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    #[test]
+    fn laptop_batteries() {
+        let dir = std::env::temp_dir().join(format!("g213-cols-battery-{}", std::process::id()));
+        let supply = |name: &str, files: &[(&str, &str)]| {
+            create_dir_all(dir.join(name)).unwrap();
+
+            for (file, value) in files {
+                write(dir.join(name).join(file), format!("{}\n", value)).unwrap();
+            }
+        };
+
+        assert!(read_charge(&dir).is_err());
+
+        supply("AC", &[("type", "Mains"), ("online", "1")]);
+        supply(
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "5")],
+        );
+
+        assert!(read_charge(&dir).is_err());
+
+        supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("capacity", "80"),
+                ("status", "Discharging"),
+            ],
+        );
+        supply(
+            "BAT1",
+            &[
+                ("type", "Battery"),
+                ("capacity", "60"),
+                ("status", "Charging"),
+            ],
+        );
+
+        assert_eq!(
+            read_charge(&dir),
+            Ok(Charge {
+                percent: 70,
+                charging: true
+            })
+        );
+
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn whole_keyboard_or_bar() {
+        let charge = |percent| Charge {
+            percent,
+            charging: false,
+        };
+
+        assert_eq!(battery_colours(&charge(100), 5, false), [0x00ff00; 5]);
+        assert_eq!(battery_colours(&charge(0), 5, false), [0xff0000; 5]);
+        assert_eq!(
+            battery_colours(&charge(50), 5, true),
+            [0xffff00, 0xffff00, 0xffff00, 0, 0]
+        );
+        assert_eq!(battery_colours(&charge(0), 5, true), [0xff0000, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn pulses() {
+        assert_eq!(pulse(Duration::ZERO), 1.0);
+        assert!((pulse(Duration::from_secs(1)) - 0.2).abs() < 1e-9);
+        assert_eq!(pulse(Duration::from_secs(2)), 1.0);
+    }
+}
@@ -1,33 +1,182 @@
-use std::{env::args, process::ExitCode};
+use std::{env::args, process::ExitCode, time::Duration};
 
+use g213_colours::calibration::{parse_corrections, set_gamma, set_region_corrections};
+use g213_colours::cli::parse_args;
 use g213_colours::commands::{
-    get_command, get_saved_command, save_command, Command, Run, Status, Successful,
+    check_command, fade_into, get_saved_command, report_error, save_command, set_config_path,
+    set_json, track_lighting, Command, Run, Status, Successful,
 };
-use g213_colours::g213_keyboard::find_g213_keyboard;
+use g213_colours::config::config;
+use g213_colours::daemon::forward_command;
+use g213_colours::g213_keyboard::{set_retry_policy, set_verbose, DeviceSelector};
+use g213_colours::history::forget_current;
+use g213_colours::profiles::save_profile;
+use g213_colours::trace::start_trace;
+use g213_colours::G213;
+
+// To the slot, or over the saved command, when 'undo' forgets the one it replaces
+fn save(command: &Command, slot: Option<u8>, undoing: bool) {
+    match slot {
+        Some(slot) => save_profile(&slot.to_string(), command),
+        None => {
+            if undoing {
+                forget_current();
+            }
+
+            save_command(command);
+        }
+    }
+}
 
 fn main() -> ExitCode {
-    let device = find_g213_keyboard().expect("No G213 keyboard found, sorry!");
+    let (options, command) = match parse_args(args()) {
+        Ok((options, command)) => (options.with_config(config()), command),
+        Err(err) => {
+            let _ = err.print();
 
-    let args = args().skip(1).collect::<Vec<_>>();
+            // --help and --version end up here too
+            return if err.use_stderr() {
+                ExitCode::from(Status::BadArguments as u8)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
 
-    let mut command = get_command(&args);
+    set_verbose(options.verbose);
+    set_retry_policy(config().device.retry_policy());
 
-    // Use saved command if we have one and no command was specified
-    if let Command::Unknown(_) = &command {
-        if !command.has_args() {
-            if let Some(cmd) = get_saved_command() {
-                command = cmd;
+    if let Some(gamma) = options.gamma {
+        set_gamma(gamma);
+    }
+
+    match parse_corrections(&config().device.regions) {
+        Ok(corrections) => set_region_corrections(corrections),
+        Err(err) => eprintln!("Ignoring [device.regions] in config.toml: {}", err),
+    }
 
+    set_json(options.json);
+    set_config_path(options.config.clone());
+
+    // Use saved command if we have one and no command was specified
+    let command = match command {
+        Some(command) => command,
+        None => match get_saved_command() {
+            Some(command) => {
                 eprintln!("Using saved command: {}", command);
+
+                command
+            }
+            None => {
+                eprintln!("No saved command yet, see 'g213-cols help'");
+
+                return ExitCode::SUCCESS;
             }
+        },
+    };
+
+    // The current command is only forgotten once the one before it has been saved
+    let undoing = matches!(command, Command::Undo);
+
+    // 'profile load' and 'apply' are run, and saved, as the profile's own command
+    let command = match command.resolve() {
+        Ok(command) => command,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return ExitCode::from(Status::BadArguments as u8);
         }
+    };
+
+    if let Some(path) = &options.trace {
+        if let Err(err) = start_trace(path) {
+            report_error(
+                &format!("Unable to trace to {}: {}", path, err),
+                Status::Failure,
+            );
+
+            return ExitCode::from(Status::Failure as u8);
+        }
+    }
+
+    // Checked as far as can be without a keyboard, then saved as if it had been run
+    if options.save_only {
+        let status = check_command(&command);
+
+        match status {
+            Status::Success => save(&command, options.slot, undoing),
+            Status::SuccessNoSave => eprintln!("'{}' isn't saved", command.to_string().trim()),
+            _ => (),
+        }
+
+        return match status.successful() {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::from(status as u8),
+        };
     }
 
-    let cmd_status = command.run(&device);
+    // The daemon only knows about one keyboard, so only use it for the default one,
+    // and only when we're talking to the keyboard ourselves
+    let forwarded = match options.selector {
+        DeviceSelector::First if !options.dry_run && options.trace.is_none() => {
+            forward_command(&command)
+        }
+        _ => None,
+    };
+
+    // Let a running daemon do the work, otherwise find the keyboard(s) and talk
+    // to them ourselves
+    let keyboards = || match options.dry_run {
+        true => Ok(G213::dry_run(&options.selector)),
+        false => G213::select(&options.selector),
+    };
+
+    let cmd_status = match forwarded {
+        Some(status) => status,
+        None => match keyboards() {
+            Ok(keyboards) => {
+                let mut status = Status::SuccessNoSave;
+
+                for keyboard in keyboards.iter() {
+                    let kb_status = match options.fade.filter(|_| !options.dry_run) {
+                        Some(fade) => {
+                            let fade = Duration::from_millis(fade as u64);
+
+                            fade_into(keyboard.keyboard(), &command, fade)
+                        }
+                        None => command.run(keyboard.keyboard()),
+                    };
+
+                    if status.successful() {
+                        status = kb_status;
+                    }
+
+                    // Commands that don't use the keyboard only need running once
+                    if !command.uses_device() {
+                        break;
+                    }
+                }
+
+                status
+            }
+            Err(err) => {
+                let status = Status::from(&err);
+
+                report_error(&err, status);
+
+                status
+            }
+        },
+    };
+
+    // Whatever the command left the regions in, for the next '--fade'
+    if cmd_status.successful() && !options.dry_run {
+        track_lighting(&command);
+    }
 
     // Save the command for future use above, if it was successful
-    if Status::Success == cmd_status {
-        save_command(&command);
+    if Status::Success == cmd_status && !options.dry_run && !options.no_save {
+        save(&command, options.slot, undoing);
     }
 
     if cmd_status.successful() {
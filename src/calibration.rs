@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::sync::Mutex;
+
+use serde::Deserialize;
+
+use crate::commands::{
+    config_file_for_saving, config_file_path, set_file_ownership_to_me, CALIBRATION_FILE,
+};
+use crate::g213_keyboard::{KeyboardRegions, NUM_REGIONS};
+
+/// LEDs are linear, so midtones look brighter than on screen without it.
+///
+/// `2.2` for every channel, or `[2.2, 2.0, 2.4]` for red, green and blue.
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(untagged)]
+pub enum Gamma {
+    All(f64),
+    Channels([f64; 3]),
+}
+
+impl Gamma {
+    fn channels(&self) -> [f64; 3] {
+        match self {
+            Gamma::All(gamma) => [*gamma; 3],
+            Gamma::Channels(channels) => *channels,
+        }
+    }
+}
+
+const GAMMAS: std::ops::RangeInclusive<f64> = 0.1..=5.0;
+
+/// `2.2` or `2.2,2.0,2.4`, as for '--gamma'.
+pub fn parse_gamma(gamma: &str) -> Result<Gamma, String> {
+    let channels = gamma
+        .split(',')
+        .map(|channel| {
+            channel
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|g| GAMMAS.contains(g))
+        })
+        .collect::<Option<Vec<f64>>>();
+
+    match channels.as_deref() {
+        Some([all]) => Ok(Gamma::All(*all)),
+        Some([red, green, blue]) => Ok(Gamma::Channels([*red, *green, *blue])),
+        _ => Err(format!(
+            "A gamma from {} to {}, or one for each of red, green and blue, like 2.2,2.0,2.4",
+            GAMMAS.start(),
+            GAMMAS.end()
+        )),
+    }
+}
+
+// Set once at startup, from '--gamma' or config.toml
+static GAMMA: Mutex<Gamma> = Mutex::new(Gamma::All(1.0));
+
+pub fn set_gamma(gamma: Gamma) {
+    *GAMMA.lock().unwrap() = gamma;
+}
+
+fn gamma_colour(colour: u32, gamma: &Gamma) -> u32 {
+    let [red, green, blue] = gamma.channels();
+
+    let channel = |shift: u32, gamma: f64| {
+        let level = ((colour >> shift) & 0xff) as f64 / 255.0;
+
+        ((level.powf(gamma) * 255.0).round() as u32) << shift
+    };
+
+    channel(16, red) | channel(8, green) | channel(0, blue)
+}
+
+/// How much of red, green and blue a keyboard gets, as percentages, so its
+/// white looks white, eg `[100, 100, 90]` for one that's a little blue.
+pub type WhitePoint = [u8; 3];
+
+pub const WHITE: WhitePoint = [100, 100, 100];
+
+// For keyboards without a serial number, and '--dry-run'
+pub const NO_SERIAL: &str = "default";
+
+/// White points by keyboard serial number, as saved by `calibrate`.
+pub type Calibrations = BTreeMap<String, WhitePoint>;
+
+fn parse_calibrations(calibrations: &str) -> Result<Calibrations, serde_json::Error> {
+    serde_json::from_str(calibrations)
+}
+
+pub fn load_calibrations() -> Calibrations {
+    let path = match config_file_path(CALIBRATION_FILE) {
+        Ok(path) => path,
+        Err(_) => return Calibrations::new(),
+    };
+
+    match read_to_string(&path) {
+        Ok(calibrations) => parse_calibrations(&calibrations).unwrap_or_else(|err| {
+            eprintln!("Ignoring {}: {}", path, err);
+
+            Calibrations::new()
+        }),
+        Err(_) => Calibrations::new(),
+    }
+}
+
+fn save_calibrations(calibrations: &Calibrations) {
+    let path = match config_file_for_saving(CALIBRATION_FILE) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Unable to save calibration: {}", err);
+
+            return;
+        }
+    };
+
+    write(&path, serde_json::to_string_pretty(calibrations).unwrap())
+        .expect("Unable to save calibration");
+
+    set_file_ownership_to_me(path);
+}
+
+/// Keeps the white point for the keyboard with `serial`, or forgets it.
+pub fn save_white_point(serial: &str, white: Option<WhitePoint>) {
+    let mut calibrations = load_calibrations();
+
+    match white {
+        Some(white) => calibrations.insert(serial.to_string(), white),
+        None => calibrations.remove(serial),
+    };
+
+    save_calibrations(&calibrations);
+}
+
+pub fn white_point(calibrations: &Calibrations, serial: Option<&str>) -> WhitePoint {
+    calibrations
+        .get(serial.unwrap_or(NO_SERIAL))
+        .copied()
+        .unwrap_or(WHITE)
+}
+
+/// Red, green and blue percentages, eg `100 100 90`, as for `calibrate`.
+pub fn parse_white_point(args: &[String]) -> Result<WhitePoint, String> {
+    let levels = args
+        .iter()
+        .map(|arg| arg.parse::<u8>().ok().filter(|level| *level <= 100))
+        .collect::<Option<Vec<u8>>>();
+
+    match levels.as_deref() {
+        Some([red, green, blue]) => Ok([*red, *green, *blue]),
+        _ => Err("Red, green and blue from 0 to 100%, like 100 100 90 for less blue".to_string()),
+    }
+}
+
+fn white_colour(colour: u32, white: &WhitePoint) -> u32 {
+    let [red, green, blue] = white.map(|level| u32::from(level.min(100)));
+
+    let channel = |shift: u32, level: u32| (((colour >> shift) & 0xff) * level / 100) << shift;
+
+    channel(16, red) | channel(8, green) | channel(0, blue)
+}
+
+/// Red, green and blue factors for a region that doesn't match the others,
+/// eg `[1.2, 1.2, 1.2]` for a dimmer one.
+pub type Correction = [f64; 3];
+
+const CORRECTIONS: std::ops::RangeInclusive<f64> = 0.0..=2.0;
+
+/// Region numbers and their corrections, from `[device.regions]` in
+/// config.toml, where regions can be named.
+pub fn parse_corrections(
+    corrections: &BTreeMap<String, Correction>,
+) -> Result<BTreeMap<u8, Correction>, String> {
+    corrections
+        .iter()
+        .map(|(region, correction)| {
+            let number = match region.parse::<KeyboardRegions>()? {
+                KeyboardRegions::WholeKeyboard => {
+                    return Err(format!("Region '{}' isn't 1 to {}", region, NUM_REGIONS))
+                }
+                region => region as u8,
+            };
+
+            match correction.iter().all(|factor| CORRECTIONS.contains(factor)) {
+                true => Ok((number, *correction)),
+                false => Err(format!(
+                    "Region '{}' needs factors from {} to {}",
+                    region,
+                    CORRECTIONS.start(),
+                    CORRECTIONS.end()
+                )),
+            }
+        })
+        .collect()
+}
+
+// Set once at startup, from config.toml
+static REGION_CORRECTIONS: Mutex<BTreeMap<u8, Correction>> = Mutex::new(BTreeMap::new());
+
+pub fn set_region_corrections(corrections: BTreeMap<u8, Correction>) {
+    *REGION_CORRECTIONS.lock().unwrap() = corrections;
+}
+
+pub fn regions_corrected() -> bool {
+    !REGION_CORRECTIONS.lock().unwrap().is_empty()
+}
+
+fn corrected_colour(colour: u32, correction: &Correction) -> u32 {
+    let [red, green, blue] = correction;
+
+    let channel = |shift: u32, factor: f64| {
+        let level = ((colour >> shift) & 0xff) as f64 * factor;
+
+        (level.round().min(255.0) as u32) << shift
+    };
+
+    channel(16, *red) | channel(8, *green) | channel(0, *blue)
+}
+
+/// The colour to send for `colour` to look right in `region` of a keyboard
+/// with `white`.
+pub fn calibrate_colour(colour: u32, white: &WhitePoint, region: u8) -> u32 {
+    let colour = white_colour(gamma_colour(colour, &GAMMA.lock().unwrap()), white);
+
+    match REGION_CORRECTIONS.lock().unwrap().get(&region) {
+        Some(correction) => corrected_colour(colour, correction),
+        None => colour,
+    }
+}
+
+#[cfg(test)]
+mod calibration_tests {
+    use super::*;
+
+    #[test]
+    fn gammas() {
+        assert_eq!(parse_gamma("2.2"), Ok(Gamma::All(2.2)));
+        assert_eq!(
+            parse_gamma("2.2, 2,2.4"),
+            Ok(Gamma::Channels([2.2, 2.0, 2.4]))
+        );
+        assert!(parse_gamma("0").is_err());
+        assert!(parse_gamma("2.2,2.0").is_err());
+        assert!(parse_gamma("bright").is_err());
+    }
+
+    #[test]
+    fn gamma_darkens_midtones() {
+        assert_eq!(gamma_colour(0x808080, &Gamma::All(1.0)), 0x808080);
+        assert_eq!(gamma_colour(0xff8000, &Gamma::All(2.2)), 0xff3800);
+        assert_eq!(
+            gamma_colour(0x808080, &Gamma::Channels([1.0, 2.0, 1.0])),
+            0x804080
+        );
+    }
+
+    #[test]
+    fn white_points() {
+        let args = |args: &str| args.split(' ').map(String::from).collect::<Vec<_>>();
+
+        assert_eq!(parse_white_point(&args("100 100 90")), Ok([100, 100, 90]));
+        assert!(parse_white_point(&args("100 90")).is_err());
+        assert!(parse_white_point(&args("100 100 120")).is_err());
+
+        assert_eq!(white_colour(0xffffff, &WHITE), 0xffffff);
+        assert_eq!(white_colour(0xffffff, &[100, 50, 90]), 0xff7fe5);
+    }
+
+    #[test]
+    fn region_corrections() {
+        let corrections = |regions: &[(&str, Correction)]| {
+            parse_corrections(
+                &regions
+                    .iter()
+                    .map(|(region, correction)| (region.to_string(), *correction))
+                    .collect(),
+            )
+        };
+
+        let numpad = corrections(&[("numpad", [1.2, 1.2, 1.2]), ("1", [1.0, 0.9, 1.0])]);
+
+        assert_eq!(
+            numpad.unwrap().into_iter().collect::<Vec<_>>(),
+            [(1, [1.0, 0.9, 1.0]), (5, [1.2, 1.2, 1.2])]
+        );
+        assert!(corrections(&[("6", [1.0, 1.0, 1.0])]).is_err());
+        assert!(corrections(&[("keys", [1.0, 1.0, 1.0])]).is_err());
+        assert!(corrections(&[("5", [1.0, 3.0, 1.0])]).is_err());
+
+        assert_eq!(corrected_colour(0x806040, &[1.0, 0.5, 1.0]), 0x803040);
+        assert_eq!(corrected_colour(0xc0c0c0, &[1.5, 1.5, 1.5]), 0xffffff);
+    }
+
+    #[test]
+    fn white_points_by_serial() {
+        let calibrations = parse_calibrations(r#"{"1234ABCD": [100, 95, 90]}"#).unwrap();
+
+        assert_eq!(white_point(&calibrations, Some("1234ABCD")), [100, 95, 90]);
+        assert_eq!(white_point(&calibrations, Some("5678")), WHITE);
+        assert_eq!(white_point(&calibrations, None), WHITE);
+    }
+}
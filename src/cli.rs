@@ -0,0 +1,1498 @@
+use clap::{Args, Parser, Subcommand};
+
+use crate::calibration::{parse_gamma, Gamma};
+use crate::commands::{
+    parse_speed, Command, CALIBRATE_RESET, FIRE_INTENSITY, FIRE_SPEED, FRAMES_CPU, FRAMES_FPS,
+    LIST_FORMAT, LIST_NO_COLOUR, LIST_RANGE, LIST_REGEX, LIST_SORT, LOAD_USAGE, MONITOR_BAR,
+    MONITOR_BATTERY, MONITOR_EVERY, MONITOR_LOW, MONITOR_NETWORK, NETWORK_INTERFACE, NETWORK_MAX,
+    NETWORK_WHOLE, PROFILE_LOAD, RAW_CONFIRM, REGIONS_MIRROR, TYPING_DECAY, TYPING_INPUT,
+    WAL_WATCH, XRESOURCES_FILE,
+};
+use crate::config::Config;
+use crate::g213_keyboard::{DeviceSelector, MAX_BRIGHTNESS};
+
+/// Set the lighting of Logitech G213 (and friends) keyboards.
+///
+/// Run with no command to re-apply the last successful one, `g213-cols help`
+/// shows a table of all the commands.
+#[derive(Parser, Debug)]
+#[command(name = "g213-cols", version, disable_help_subcommand = true)]
+struct Cli {
+    #[command(flatten)]
+    options: GlobalArgs,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Args, Debug)]
+struct GlobalArgs {
+    /// Use the keyboard at bus:address, or with this serial number
+    #[arg(long, global = true, env = "G213_COLS_DEVICE", conflicts_with = "all")]
+    device: Option<String>,
+
+    /// Send the command to every keyboard
+    #[arg(long, global = true)]
+    all: bool,
+
+    /// Print the USB commands instead of sending them
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Record every USB transfer to this file, for 'replay'
+    #[arg(long, global = true, value_name = "FILE")]
+    trace: Option<String>,
+
+    /// Don't save the command for re-applying later
+    #[arg(long, global = true, conflicts_with = "slot")]
+    no_save: bool,
+
+    /// Only save the command, without sending it to the keyboard
+    #[arg(long, global = true, conflicts_with_all = ["no_save", "dry_run"])]
+    save_only: bool,
+
+    /// Keep the saved command in this file, rather than the config directory
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<String>,
+
+    /// Save the command to this slot, for 'apply', rather than over the saved one
+    #[arg(long, global = true)]
+    slot: Option<u8>,
+
+    /// Show the USB commands as they are sent
+    #[arg(long, short, global = true)]
+    verbose: bool,
+
+    /// Print 'list', 'saved', 'get', 'info' and errors as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Fade from the last colours to the new ones over this many milliseconds
+    #[arg(long, global = true, value_name = "MS")]
+    fade: Option<u16>,
+
+    /// Correct the colours for the LEDs, eg 2.2, or 2.2,2.0,2.4 for red, green and blue
+    #[arg(long, global = true, value_parser = parse_gamma)]
+    gamma: Option<Gamma>,
+
+    /// Make 'random' any colour, or one of the X11 named colours
+    #[arg(long, global = true, value_name = "KIND", value_parser = [RANDOM_RGB, RANDOM_X11])]
+    random: Option<String>,
+}
+
+const RANDOM_RGB: &str = "rgb";
+const RANDOM_X11: &str = "x11";
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Set the whole keyboard to a hex or X11 colour, 'white' by default
+    #[command(visible_alias = "c")]
+    Colour { colour: Vec<String> },
+
+    /// Set one region, 1 to 5, to a colour
+    #[command(visible_alias = "r")]
+    Region {
+        /// 1 to 5, or a name such as 'wasd' or 'numpad', see 'help region',
+        /// or several like 1-3 or 1,5
+        region: String,
+        colour: Vec<String>,
+    },
+
+    /// Set every region, the last colour is repeated for the rest
+    #[command(visible_alias = "rs")]
+    Regions {
+        colours: Vec<String>,
+
+        /// The colours from both ends inwards, so 'red blue white' is red blue white blue red
+        #[arg(long)]
+        mirror: bool,
+    },
+
+    /// Blend two colours across the regions
+    #[command(visible_alias = "g")]
+    Gradient { colours: Vec<String> },
+
+    /// Set the regions to hues evenly spaced around the colour wheel
+    Rainbow {
+        /// The first region's hue, 0 to 359, red by default
+        #[arg(value_parser = clap::value_parser!(u16).range(0..360))]
+        hue: Option<u16>,
+
+        /// 0 to 100, 100 by default
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        saturation: Option<u8>,
+
+        /// 0 to 100, 100 by default
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        value: Option<u8>,
+    },
+
+    /// Breathe a colour, taking 'speed' ms per breath, or slow, medium or fast
+    #[command(visible_alias = "b")]
+    Breathe {
+        #[arg(value_parser = speed_arg)]
+        speed: String,
+        colour: Vec<String>,
+    },
+
+    /// Cycle through all colours, taking 'speed' ms per cycle, see config.toml for a default
+    #[command(visible_alias = "cy")]
+    Cycle {
+        #[arg(value_parser = speed_arg)]
+        speed: Option<String>,
+    },
+
+    /// Sweep a colour across the regions every 'speed' ms, until Ctrl-C
+    #[command(visible_alias = "w")]
+    Wave {
+        #[arg(value_parser = speed_arg)]
+        speed: String,
+        colour: Vec<String>,
+    },
+
+    /// Set the regions to the colours on the screen above them, until Ctrl-C
+    Ambient {
+        /// Screenshots a second, 5 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend taking and reading screenshots
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+    },
+
+    /// Light the regions with the music playing, bass to treble, until Ctrl-C
+    Music {
+        /// Updates a second, 30 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend listening to the music
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+
+        /// A colour for each region, a rainbow by default
+        colours: Vec<String>,
+    },
+
+    /// Light up the regions as their keys are pressed, until Ctrl-C
+    Typing {
+        /// Milliseconds for a region to fade back, 500 by default
+        #[arg(long, value_name = "MS")]
+        decay: Option<u16>,
+
+        /// The evdev device to read key presses from, found by default
+        #[arg(long, value_name = "DEVICE")]
+        input: Option<String>,
+
+        /// Updates a second, 30 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend updating the keyboard
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+
+        /// The colour keys light up in, white by default
+        colour: Vec<String>,
+    },
+
+    /// Show how busy the system is, green to red, until Ctrl-C
+    Load {
+        /// Seconds between checks, 2 by default
+        #[arg(long, value_name = "SECONDS")]
+        every: Option<u16>,
+
+        /// CPU usage rather than the 1 minute load average
+        #[arg(long)]
+        usage: bool,
+
+        /// Light more regions the busier it is, rather than the whole keyboard
+        #[arg(long)]
+        bar: bool,
+
+        /// Colours for idle and flat out, rather than green and red
+        colours: Vec<String>,
+    },
+
+    /// Keep an eye on the system with the keyboard, until Ctrl-C
+    Monitor {
+        #[command(subcommand)]
+        kind: MonitorKind,
+    },
+
+    /// Blink a colour in software, until Ctrl-C or [count] blinks
+    Blink {
+        /// Milliseconds on, and then off, at least 200
+        interval: u16,
+
+        /// The colour, and optionally how many blinks
+        #[arg(value_name = "COLOUR [COUNT]", required = true)]
+        colour: Vec<String>,
+
+        /// Updates a second, 20 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend updating the keyboard
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+    },
+
+    /// Swap two colours between the halves of the keyboard, until Ctrl-C
+    Alternate {
+        /// Milliseconds between swaps, at least 200
+        interval: u16,
+
+        /// The two colours, red and blue by default
+        colours: Vec<String>,
+
+        /// Updates a second, 20 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend updating the keyboard
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+    },
+
+    /// Flickering flames, red through orange to yellow, until Ctrl-C
+    Fire {
+        /// How fierce the fire is, 1 to 100, 70 by default
+        #[arg(long, value_name = "PERCENT")]
+        intensity: Option<u8>,
+
+        /// How quickly it flickers, 1 to 100, 70 by default
+        #[arg(long, value_name = "PERCENT")]
+        speed: Option<u8>,
+
+        /// Updates a second, 15 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend updating the keyboard
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+    },
+
+    /// Digital rain, drops of green landing and fading, until Ctrl-C
+    Matrix {
+        /// Ticks a second, 10 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend updating the keyboard
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+    },
+
+    /// Regions glint at random over a dim colour, until Ctrl-C
+    Sparkle {
+        /// The colour, white by default, and optionally the percent chance
+        /// of each region glinting each tick, 20 by default
+        #[arg(value_name = "COLOUR [DENSITY]")]
+        colour: Vec<String>,
+
+        /// Ticks a second, 10 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend updating the keyboard
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+    },
+
+    /// Fade the keyboard through a list of colours, until Ctrl-C
+    CycleList {
+        /// Milliseconds to fade to each colour, at least 200, or slow, medium or fast
+        #[arg(value_parser = speed_arg)]
+        speed: String,
+
+        /// The colours, at least two
+        #[arg(required = true)]
+        colours: Vec<String>,
+
+        /// Updates a second, 30 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend updating the keyboard
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+    },
+
+    /// Breathe in software between two brightnesses, until Ctrl-C
+    Pulse {
+        /// Milliseconds for each breath, at least 200, or slow, medium or fast
+        #[arg(value_parser = speed_arg)]
+        speed: String,
+
+        /// The colour, then the lowest and highest brightness, 20 and 100 by default
+        #[arg(value_name = "COLOUR [MIN%] [MAX%]")]
+        colour: Vec<String>,
+
+        /// Updates a second, 30 by default
+        #[arg(long, value_name = "FPS")]
+        fps: Option<u32>,
+
+        /// Most of the time to spend updating the keyboard
+        #[arg(long, value_name = "PERCENT")]
+        cpu: Option<u8>,
+    },
+
+    /// A Pomodoro focus timer, work then a break, until Ctrl-C
+    Pomodoro {
+        /// Minutes of work, 25 by default
+        #[arg(value_name = "WORK")]
+        work: Option<u16>,
+
+        /// Minutes of each break, 5 by default
+        #[arg(value_name = "BREAK")]
+        rest: Option<u16>,
+    },
+
+    /// Count down on the regions, then flash, eg 'timer 4m' for tea
+    Timer {
+        /// How long, eg 90s, 25m or 1h30m, or just minutes
+        duration: String,
+
+        /// The colour to light the regions, white by default
+        colour: Vec<String>,
+    },
+
+    /// Set the regions to a pywal theme's colours, ~/.cache/wal/colors.json by default
+    Wal {
+        file: Option<String>,
+
+        /// Keep the keyboard matching the theme as it changes, until Ctrl-C
+        #[arg(long)]
+        watch: bool,
+    },
+
+    /// Set the regions to the terminal colours from xrdb or ~/.Xresources, color1 to 5 by default
+    Xresources {
+        /// The colours to use, eg color4 background
+        names: Vec<String>,
+
+        /// Read this Xresources file instead
+        #[arg(long, value_name = "FILE")]
+        file: Option<String>,
+    },
+
+    /// Turn the lighting off, the saved command is kept
+    #[command(visible_alias = "o")]
+    Off,
+
+    /// Restore the saved command, or 'white'
+    On,
+
+    /// Turn the lighting off, or back on if it's off, eg from a hotkey
+    #[command(visible_alias = "t")]
+    Toggle,
+
+    /// Show, or set from 0 to 100, the brightness of all colours
+    #[command(visible_alias = "br")]
+    Brightness {
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=MAX_BRIGHTNESS as i64))]
+        brightness: Option<u8>,
+    },
+
+    /// Show, or set as percentages, how much red, green and blue this keyboard gets, so white looks white
+    Calibrate {
+        /// Like 100 100 90, for a little less blue
+        #[arg(num_args = 3, value_names = ["RED", "GREEN", "BLUE"], value_parser = clap::value_parser!(u8).range(0..=100))]
+        white: Vec<u8>,
+
+        /// Forget the keyboard's calibration
+        #[arg(long, conflicts_with = "white")]
+        reset: bool,
+    },
+
+    /// Keep the keyboard open and run commands sent to it
+    Daemon,
+
+    /// List the connected keyboards
+    #[command(visible_alias = "d")]
+    Devices,
+
+    /// Re-apply the saved command whenever the keyboard is plugged in
+    Watch,
+
+    /// Write and enable a systemd unit that restores the lighting
+    InstallService {
+        #[arg(default_value = "user", value_parser = ["user", "system"])]
+        kind: String,
+    },
+
+    /// Write a udev rule allowing access without root
+    InstallUdev { group: Option<String> },
+
+    /// Write a systemd sleep hook that restores the lighting after suspend
+    InstallSleepHook,
+
+    /// Send 40 hex digits to the keyboard as is, and show the reply
+    Raw {
+        #[arg(required = true)]
+        hex: Vec<String>,
+
+        /// Send it anyway, raw commands can confuse the keyboard
+        #[arg(long)]
+        yes_i_know: bool,
+    },
+
+    /// Show what a command, or the saved one, would look like, without a keyboard
+    Preview {
+        /// eg 'regions red green blue'
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+
+    /// Re-send the USB transfers recorded with '--trace'
+    Replay { file: String },
+
+    /// Run the commands in a file, one per line, with 'sleep <ms>' between them
+    Run { file: String },
+
+    /// Run commands read from stdin, one per line as for 'run', until it's closed
+    #[command(visible_alias = "-")]
+    Pipe,
+
+    /// Run the saved command, or the one saved to 'slot' with '--slot', and save it as usual
+    Apply { slot: Option<u8> },
+
+    /// Keep the saved command under a name, to load again later
+    #[command(visible_alias = "p")]
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+
+    /// Import colours from GIMP palettes, to use by name
+    Palette {
+        #[command(subcommand)]
+        action: PaletteAction,
+    },
+
+    /// Keep the G213's colours in an OpenRGB .orp profile as a profile, named after the file
+    ImportOpenrgb {
+        file: String,
+
+        /// Call the profile this instead
+        name: Option<String>,
+    },
+
+    /// Write the saved command, or a profile, as an OpenRGB .orp profile
+    ExportOpenrgb {
+        file: String,
+
+        /// The profile to write, rather than the saved command
+        profile: Option<String>,
+    },
+
+    /// List the X11 colours, or those containing every one of 'names'
+    #[command(visible_alias = "l")]
+    List {
+        names: Vec<String>,
+
+        /// The names are case insensitive regular expressions, eg '^dark.*blue$'
+        #[arg(long)]
+        regex: bool,
+
+        /// Only colours whose red, green and blue are between two colours, eg 808080..ffffff
+        #[arg(long, value_name = "FROM..TO")]
+        range: Option<String>,
+
+        /// Print them as text, or json or csv for other tools, '--json' means json
+        #[arg(long, value_parser = ["text", "json", "csv"])]
+        format: Option<String>,
+
+        /// Order them by hue family, lightness or name, rather than as in rgb.txt
+        #[arg(long, value_parser = ["hue", "lightness", "name"])]
+        sort: Option<String>,
+
+        /// Don't show a block of each colour, as when not printing to a terminal
+        #[arg(long, alias = "no-colour")]
+        no_color: bool,
+    },
+
+    /// Show the X11 colour closest to a colour, eg from a screenshot, and how close
+    Nearest { colour: Vec<String> },
+
+    /// Pick colours, regions and effects with the keyboard, changing the lighting as you go
+    Tui,
+
+    /// Show the keyboard's USB ids, interfaces and endpoints, and HID++ info
+    #[command(visible_alias = "i")]
+    Info,
+
+    /// Show the saved command
+    #[command(visible_alias = "s")]
+    Saved,
+
+    /// Show the commands saved before, the current one first
+    History,
+
+    /// Go back to the command saved before the current one
+    Undo,
+
+    /// Show the colour each region was last set to
+    Get {
+        /// Only these, like 'numpad' or 1-3
+        regions: Option<String>,
+    },
+
+    /// Show a table of all the commands, or the region names with 'help region'
+    #[command(visible_aliases = ["h", "?"])]
+    Help { topic: Option<String> },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileAction {
+    /// Keep the saved command as profile 'name'
+    Save { name: String },
+
+    /// Run profile 'name', saving it like any other command
+    Load { name: String },
+
+    /// List the profiles and their commands
+    #[command(visible_alias = "ls")]
+    List,
+
+    /// Forget profile 'name'
+    #[command(visible_alias = "rm")]
+    Delete { name: String },
+}
+
+impl From<ProfileAction> for Vec<String> {
+    fn from(action: ProfileAction) -> Self {
+        match action {
+            ProfileAction::Save { name } => vec!["save".to_string(), name],
+            ProfileAction::Load { name } => vec![PROFILE_LOAD.to_string(), name],
+            ProfileAction::List => vec!["list".to_string()],
+            ProfileAction::Delete { name } => vec!["delete".to_string(), name],
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum MonitorKind {
+    /// The laptop's charge, green to red, pulsing when low or charging
+    Battery {
+        /// Seconds between checks, 10 by default
+        #[arg(long, value_name = "SECONDS")]
+        every: Option<u16>,
+
+        /// Pulse below this charge, 20% by default
+        #[arg(long, value_name = "PERCENT")]
+        low: Option<u8>,
+
+        /// Light a region for each fifth of the charge, rather than the whole keyboard
+        #[arg(long)]
+        bar: bool,
+    },
+
+    /// Downloads on the left, uploads on the right, brighter the faster they go
+    Network {
+        /// The interface to watch, the busiest by default
+        #[arg(long, value_name = "NAME")]
+        interface: Option<String>,
+
+        /// Bytes a second shown at full brightness, 10M by default
+        #[arg(long, value_name = "RATE")]
+        max: Option<String>,
+
+        /// Both together on the whole keyboard, in the first colour
+        #[arg(long)]
+        whole: bool,
+
+        /// Seconds between checks, 1 by default
+        #[arg(long, value_name = "SECONDS")]
+        every: Option<u16>,
+
+        /// Colours for downloads and uploads, rather than green and blue
+        colours: Vec<String>,
+    },
+}
+
+impl From<MonitorKind> for Vec<String> {
+    fn from(kind: MonitorKind) -> Self {
+        match kind {
+            MonitorKind::Battery { every, low, bar } => {
+                let mut args = vec![MONITOR_BATTERY.to_string()];
+
+                if let Some(every) = every {
+                    args.extend([MONITOR_EVERY.to_string(), every.to_string()]);
+                }
+
+                if let Some(low) = low {
+                    args.extend([MONITOR_LOW.to_string(), low.to_string()]);
+                }
+
+                if bar {
+                    args.push(MONITOR_BAR.to_string());
+                }
+
+                args
+            }
+            MonitorKind::Network {
+                interface,
+                max,
+                whole,
+                every,
+                colours,
+            } => {
+                let mut args = vec![MONITOR_NETWORK.to_string()];
+
+                if let Some(interface) = interface {
+                    args.extend([NETWORK_INTERFACE.to_string(), interface]);
+                }
+
+                if let Some(max) = max {
+                    args.extend([NETWORK_MAX.to_string(), max]);
+                }
+
+                if whole {
+                    args.push(NETWORK_WHOLE.to_string());
+                }
+
+                if let Some(every) = every {
+                    args.extend([MONITOR_EVERY.to_string(), every.to_string()]);
+                }
+
+                args.extend(colours);
+
+                args
+            }
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum PaletteAction {
+    /// Read a GIMP .gpl palette, its colours can then be used by name
+    Import { file: String },
+
+    /// List the imported colours
+    #[command(visible_alias = "ls")]
+    List,
+}
+
+/// Options that apply to every command, rather than being part of it.
+#[derive(Debug)]
+pub struct Options {
+    pub selector: DeviceSelector,
+    pub dry_run: bool,
+    pub trace: Option<String>,
+    pub no_save: bool,
+    pub save_only: bool,
+    pub config: Option<String>,
+    pub slot: Option<u8>,
+    pub verbose: bool,
+    pub json: bool,
+    pub fade: Option<u16>,
+    pub gamma: Option<Gamma>,
+}
+
+impl From<GlobalArgs> for Options {
+    fn from(args: GlobalArgs) -> Self {
+        let selector = match (args.all, args.device) {
+            (true, _) => DeviceSelector::All,
+            (false, Some(device)) if !device.is_empty() => DeviceSelector::from(device.as_str()),
+            _ => DeviceSelector::First,
+        };
+
+        Options {
+            selector,
+            dry_run: args.dry_run,
+            trace: args.trace,
+            no_save: args.no_save,
+            save_only: args.save_only,
+            config: args.config,
+            slot: args.slot,
+            verbose: args.verbose,
+            json: args.json,
+            fade: args.fade,
+            gamma: args.gamma,
+        }
+    }
+}
+
+impl Options {
+    /// Fills in what wasn't given on the command line from config.toml.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        if self.selector == DeviceSelector::First {
+            self.selector = match config.device.select.as_deref() {
+                Some("all") => DeviceSelector::All,
+                Some(device) if !device.is_empty() => DeviceSelector::from(device),
+                _ => DeviceSelector::First,
+            };
+        }
+
+        self.gamma = self.gamma.or(config.device.gamma);
+
+        self
+    }
+}
+
+// Milliseconds, eg 5000 or 5s, or a name from the [speeds] in config.toml,
+// kept as given
+fn speed_arg(speed: &str) -> Result<String, String> {
+    parse_speed(speed).map(|_| speed.to_string())
+}
+
+fn with_first(first: String, rest: Vec<String>) -> Vec<String> {
+    let mut args = vec![first];
+    args.extend(rest);
+    args
+}
+
+// '--fps' and '--cpu' for effects that stream colours to the keyboard
+fn frames(fps: Option<u32>, cpu: Option<u8>) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if let Some(fps) = fps {
+        args.extend([FRAMES_FPS.to_string(), fps.to_string()]);
+    }
+
+    if let Some(cpu) = cpu {
+        args.extend([FRAMES_CPU.to_string(), cpu.to_string()]);
+    }
+
+    args
+}
+
+// Commands keep their arguments as strings, so saved commands stay readable
+impl From<CliCommand> for Command {
+    fn from(command: CliCommand) -> Self {
+        match command {
+            CliCommand::Colour { colour } => Command::Colour(colour),
+            CliCommand::Region { region, colour } => Command::Region(with_first(region, colour)),
+            CliCommand::Regions { colours, mirror } => match mirror {
+                true => Command::Regions(with_first(REGIONS_MIRROR.to_string(), colours)),
+                false => Command::Regions(colours),
+            },
+            CliCommand::Gradient { colours } => Command::Gradient(colours),
+            CliCommand::Rainbow {
+                hue,
+                saturation,
+                value,
+            } => {
+                let hsv = [hue, saturation.map(u16::from), value.map(u16::from)];
+
+                Command::Rainbow(hsv.iter().flatten().map(|n| n.to_string()).collect())
+            }
+            CliCommand::Breathe { speed, colour } => Command::Breathe(with_first(speed, colour)),
+            CliCommand::Cycle { speed } => Command::Cycle(speed.into_iter().collect()),
+            CliCommand::Wave { speed, colour } => Command::Wave(with_first(speed, colour)),
+            CliCommand::Ambient { fps, cpu } => Command::Ambient(frames(fps, cpu)),
+            CliCommand::Music { fps, cpu, colours } => {
+                let mut args = frames(fps, cpu);
+
+                args.extend(colours);
+
+                Command::Music(args)
+            }
+            CliCommand::Typing {
+                decay,
+                input,
+                fps,
+                cpu,
+                colour,
+            } => {
+                let mut args = frames(fps, cpu);
+
+                if let Some(decay) = decay {
+                    args.extend([TYPING_DECAY.to_string(), decay.to_string()]);
+                }
+
+                if let Some(input) = input {
+                    args.extend([TYPING_INPUT.to_string(), input]);
+                }
+
+                args.extend(colour);
+
+                Command::Typing(args)
+            }
+            CliCommand::Load {
+                every,
+                usage,
+                bar,
+                colours,
+            } => {
+                let mut args = Vec::new();
+
+                if let Some(every) = every {
+                    args.extend([MONITOR_EVERY.to_string(), every.to_string()]);
+                }
+
+                if usage {
+                    args.push(LOAD_USAGE.to_string());
+                }
+
+                if bar {
+                    args.push(MONITOR_BAR.to_string());
+                }
+
+                args.extend(colours);
+
+                Command::Load(args)
+            }
+            CliCommand::Wal { file, watch } => {
+                let mut args: Vec<String> = file.into_iter().collect();
+
+                if watch {
+                    args.push(WAL_WATCH.to_string());
+                }
+
+                Command::Wal(args)
+            }
+            CliCommand::Xresources { mut names, file } => {
+                if let Some(file) = file {
+                    names.extend([XRESOURCES_FILE.to_string(), file]);
+                }
+
+                Command::Xresources(names)
+            }
+            CliCommand::Off => Command::Off,
+            CliCommand::On => Command::On,
+            CliCommand::Toggle => Command::Toggle,
+            CliCommand::Brightness { brightness } => {
+                Command::Brightness(brightness.iter().map(|b| b.to_string()).collect())
+            }
+            CliCommand::Calibrate { white, reset } => match reset {
+                true => Command::Calibrate(vec![CALIBRATE_RESET.to_string()]),
+                false => Command::Calibrate(white.iter().map(|w| w.to_string()).collect()),
+            },
+            CliCommand::Daemon => Command::Daemon,
+            CliCommand::Devices => Command::Devices,
+            CliCommand::Watch => Command::Watch,
+            CliCommand::InstallService { kind } => Command::InstallService(vec![kind]),
+            CliCommand::InstallUdev { group } => Command::InstallUdev(group.into_iter().collect()),
+            CliCommand::InstallSleepHook => Command::InstallSleepHook,
+            CliCommand::Raw {
+                mut hex,
+                yes_i_know,
+            } => {
+                if yes_i_know {
+                    hex.push(RAW_CONFIRM.to_string());
+                }
+
+                Command::Raw(hex)
+            }
+            CliCommand::Preview { command } => Command::Preview(command),
+            CliCommand::Replay { file } => Command::Replay(vec![file]),
+            CliCommand::Run { file } => Command::Script(vec![file]),
+            CliCommand::Pipe => Command::Pipe,
+            CliCommand::Apply { slot } => {
+                Command::Apply(slot.iter().map(|slot| slot.to_string()).collect())
+            }
+            CliCommand::Monitor { kind } => Command::Monitor(kind.into()),
+            CliCommand::Blink {
+                interval,
+                colour,
+                fps,
+                cpu,
+            } => {
+                let mut args = frames(fps, cpu);
+
+                args.extend(with_first(interval.to_string(), colour));
+
+                Command::Blink(args)
+            }
+            CliCommand::Alternate {
+                interval,
+                colours,
+                fps,
+                cpu,
+            } => {
+                let mut args = frames(fps, cpu);
+
+                args.extend(with_first(interval.to_string(), colours));
+
+                Command::Alternate(args)
+            }
+            CliCommand::Fire {
+                intensity,
+                speed,
+                fps,
+                cpu,
+            } => {
+                let mut args = frames(fps, cpu);
+
+                if let Some(intensity) = intensity {
+                    args.extend([FIRE_INTENSITY.to_string(), intensity.to_string()]);
+                }
+
+                if let Some(speed) = speed {
+                    args.extend([FIRE_SPEED.to_string(), speed.to_string()]);
+                }
+
+                Command::Fire(args)
+            }
+            CliCommand::Matrix { fps, cpu } => Command::Matrix(frames(fps, cpu)),
+            CliCommand::CycleList {
+                speed,
+                colours,
+                fps,
+                cpu,
+            } => {
+                let mut args = frames(fps, cpu);
+
+                args.extend(with_first(speed, colours));
+
+                Command::CycleList(args)
+            }
+            CliCommand::Pulse {
+                speed,
+                colour,
+                fps,
+                cpu,
+            } => {
+                let mut args = frames(fps, cpu);
+
+                args.extend(with_first(speed, colour));
+
+                Command::Pulse(args)
+            }
+            CliCommand::Sparkle { colour, fps, cpu } => {
+                let mut args = frames(fps, cpu);
+
+                args.extend(colour);
+
+                Command::Sparkle(args)
+            }
+            CliCommand::Timer { duration, colour } => Command::Timer(with_first(duration, colour)),
+            CliCommand::Pomodoro { work, rest } => Command::Pomodoro(
+                work.into_iter()
+                    .chain(rest)
+                    .map(|minutes| minutes.to_string())
+                    .collect(),
+            ),
+            CliCommand::Profile { action } => Command::Profile(action.into()),
+            CliCommand::Palette { action } => Command::Palette(match action {
+                PaletteAction::Import { file } => vec!["import".to_string(), file],
+                PaletteAction::List => vec!["list".to_string()],
+            }),
+            CliCommand::ImportOpenrgb { file, name } => {
+                Command::ImportOpenrgb(std::iter::once(file).chain(name).collect())
+            }
+            CliCommand::ExportOpenrgb { file, profile } => {
+                Command::ExportOpenrgb(std::iter::once(file).chain(profile).collect())
+            }
+            CliCommand::List {
+                names,
+                regex,
+                range,
+                format,
+                sort,
+                no_color,
+            } => {
+                let mut args = names;
+
+                if regex {
+                    args.push(LIST_REGEX.to_string());
+                }
+
+                if let Some(range) = range {
+                    args.extend([LIST_RANGE.to_string(), range]);
+                }
+
+                if let Some(format) = format {
+                    args.extend([LIST_FORMAT.to_string(), format]);
+                }
+
+                if let Some(sort) = sort {
+                    args.extend([LIST_SORT.to_string(), sort]);
+                }
+
+                if no_color {
+                    args.push(LIST_NO_COLOUR.to_string());
+                }
+
+                Command::List(args)
+            }
+            CliCommand::Nearest { colour } => Command::Nearest(colour),
+            CliCommand::Tui => Command::Tui,
+            CliCommand::Info => Command::Info,
+            CliCommand::Saved => Command::Saved,
+            CliCommand::History => Command::History,
+            CliCommand::Undo => Command::Undo,
+            CliCommand::Get { regions } => Command::Get(regions.into_iter().collect()),
+            CliCommand::Help { topic } => Command::Help(topic.into_iter().collect()),
+        }
+    }
+}
+
+/// Parses the command line, including the program name, into its [`Options`]
+/// and [`Command`], if one was given.
+///
+/// `--help`, `--version` and mistakes come back as a [`clap::Error`], ready to `exit()`.
+pub fn parse_args<I, T>(args: I) -> Result<(Options, Option<Command>), clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let cli = Cli::try_parse_from(args)?;
+    let x11_randoms = cli.options.random.as_deref() == Some(RANDOM_X11);
+
+    let command = cli
+        .command
+        .map(Command::from)
+        .map(|command| match x11_randoms {
+            true => command.with_x11_randoms(),
+            false => command,
+        });
+
+    Ok((cli.options.into(), command))
+}
+
+/// Parses a command line without the program name, eg from a script, into
+/// its [`Command`], with just the first line of any error.
+pub fn parse_command<'a>(
+    words: impl IntoIterator<Item = &'a str>,
+) -> Result<Option<Command>, String> {
+    parse_args(std::iter::once("g213-cols").chain(words))
+        .map(|(_, command)| command)
+        .map_err(|err| {
+            err.to_string()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches("error: ")
+                .to_string()
+        })
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    fn parse(args: &str) -> (Options, Option<Command>) {
+        parse_args(format!("g213-cols {}", args).split_whitespace()).unwrap()
+    }
+
+    #[test]
+    fn colour_command() {
+        let (_, cmd) = parse("colour");
+
+        assert!(matches!(cmd, Some(Command::Colour(args)) if args.is_empty()));
+    }
+
+    #[test]
+    fn colour_command_with_args() {
+        let (_, cmd) = parse("c lawn green");
+
+        assert!(matches!(cmd, Some(Command::Colour(args)) if args == ["lawn", "green"]));
+    }
+
+    #[test]
+    fn wave_command_with_args() {
+        let (_, cmd) = parse("w 1000 red");
+
+        assert!(matches!(cmd, Some(Command::Wave(args)) if args == ["1000", "red"]));
+    }
+
+    #[test]
+    fn music_command_with_args() {
+        let (_, cmd) = parse("music --fps 20 red blue");
+
+        assert!(
+            matches!(cmd, Some(Command::Music(args)) if args == ["--fps", "20", "red", "blue"])
+        );
+    }
+
+    #[test]
+    fn typing_command_with_args() {
+        let (_, cmd) = parse("typing --decay 300 --input /dev/input/event5 red");
+
+        assert!(matches!(cmd, Some(Command::Typing(args))
+            if args == ["--decay", "300", "--input", "/dev/input/event5", "red"]));
+    }
+
+    #[test]
+    fn load_command_with_args() {
+        let (_, cmd) = parse("load --usage --bar");
+
+        assert!(matches!(cmd, Some(Command::Load(args)) if args == ["--usage", "--bar"]));
+    }
+
+    #[test]
+    fn monitor_battery_with_args() {
+        let (_, cmd) = parse("monitor battery --low 30 --bar");
+
+        assert!(matches!(cmd, Some(Command::Monitor(args))
+            if args == ["battery", "--low", "30", "--bar"]));
+    }
+
+    #[test]
+    fn monitor_network_with_args() {
+        let (_, cmd) = parse("monitor network --max 1M --whole red");
+
+        assert!(matches!(cmd, Some(Command::Monitor(args))
+            if args == ["network", "--max", "1M", "--whole", "red"]));
+    }
+
+    #[test]
+    fn pomodoro_minutes() {
+        assert!(
+            matches!(parse("pomodoro 50 10").1, Some(Command::Pomodoro(args))
+            if args == ["50", "10"])
+        );
+        assert!(matches!(parse("pomodoro").1, Some(Command::Pomodoro(args)) if args.is_empty()));
+    }
+
+    #[test]
+    fn blink_with_count() {
+        assert!(
+            matches!(parse("blink 300 red 5").1, Some(Command::Blink(args))
+            if args == ["300", "red", "5"])
+        );
+        assert!(parse_command(["blink", "300"]).is_err());
+    }
+
+    #[test]
+    fn alternate_with_colours() {
+        assert!(
+            matches!(parse("alternate 400 red blue").1, Some(Command::Alternate(args))
+            if args == ["400", "red", "blue"])
+        );
+    }
+
+    #[test]
+    fn fire_with_options() {
+        assert!(
+            matches!(parse("fire --speed 30 --intensity 90").1, Some(Command::Fire(args))
+            if args == ["--intensity", "90", "--speed", "30"])
+        );
+    }
+
+    #[test]
+    fn effects_tick_rate() {
+        assert!(
+            matches!(parse("matrix --fps 20").1, Some(Command::Matrix(args))
+            if args == ["--fps", "20"])
+        );
+        assert!(
+            matches!(parse("blink --fps 5 300 red").1, Some(Command::Blink(args))
+            if args == ["--fps", "5", "300", "red"])
+        );
+        assert!(
+            matches!(parse("sparkle gold 30 --fps 5").1, Some(Command::Sparkle(args))
+            if args == ["--fps", "5", "gold", "30"])
+        );
+        assert!(
+            matches!(parse("cycle-list 2000 red dark blue").1, Some(Command::CycleList(args))
+            if args == ["2000", "red", "dark", "blue"])
+        );
+        assert!(
+            matches!(parse("pulse 3000 red 10 60").1, Some(Command::Pulse(args))
+            if args == ["3000", "red", "10", "60"])
+        );
+    }
+
+    #[test]
+    fn timer_with_colour() {
+        assert!(
+            matches!(parse("timer 1h30m dark red").1, Some(Command::Timer(args))
+            if args == ["1h30m", "dark", "red"])
+        );
+        assert!(parse_command(["timer"]).is_err());
+    }
+
+    #[test]
+    fn pipe_command() {
+        assert!(matches!(parse("pipe").1, Some(Command::Pipe)));
+        assert!(matches!(parse("-").1, Some(Command::Pipe)));
+    }
+
+    #[test]
+    fn no_command() {
+        let (_, cmd) = parse("");
+
+        assert!(cmd.is_none());
+    }
+
+    #[test]
+    fn typos_are_errors() {
+        assert!(parse_args(["g213-cols", "colur", "red"]).is_err());
+    }
+
+    #[test]
+    fn bad_numbers_are_errors() {
+        assert!(parse_args(["g213-cols", "breathe", "quick", "red"]).is_err());
+        assert!(parse_args(["g213-cols", "cycle", "70000"]).is_err());
+        assert!(parse_args(["g213-cols", "brightness", "101"]).is_err());
+    }
+
+    #[test]
+    fn region_names() {
+        assert!(
+            matches!(parse("r numpad red").1, Some(Command::Region(args)) if args == ["numpad", "red"])
+        );
+    }
+
+    #[test]
+    fn history_and_undo() {
+        assert!(matches!(parse("history").1, Some(Command::History)));
+        assert!(matches!(parse("undo").1, Some(Command::Undo)));
+    }
+
+    #[test]
+    fn get_regions() {
+        assert!(matches!(parse("get").1, Some(Command::Get(args)) if args.is_empty()));
+        assert!(matches!(parse("get 1-3").1, Some(Command::Get(args)) if args == ["1-3"]));
+    }
+
+    #[test]
+    fn regions_mirrored() {
+        assert!(
+            matches!(parse("rs --mirror red blue white").1, Some(Command::Regions(args)) if args == ["--mirror", "red", "blue", "white"])
+        );
+    }
+
+    #[test]
+    fn rainbow_hue_saturation_value() {
+        assert!(matches!(parse("rainbow").1, Some(Command::Rainbow(args)) if args.is_empty()));
+        assert!(
+            matches!(parse("rainbow 200 60").1, Some(Command::Rainbow(args)) if args == ["200", "60"])
+        );
+        assert!(parse_args(["g213-cols", "rainbow", "360"]).is_err());
+    }
+
+    #[test]
+    fn off_and_on_commands() {
+        assert!(matches!(parse("o").1, Some(Command::Off)));
+        assert!(matches!(parse("on").1, Some(Command::On)));
+        assert!(matches!(parse("t").1, Some(Command::Toggle)));
+    }
+
+    #[test]
+    fn brightness_command_with_args() {
+        let (_, cmd) = parse("br 50");
+
+        assert!(matches!(cmd, Some(Command::Brightness(args)) if args == ["50"]));
+    }
+
+    #[test]
+    fn calibrate_command_with_args() {
+        let calibrate = |args: &str| match parse(args).1 {
+            Some(Command::Calibrate(args)) => args,
+            cmd => panic!("{:?}", cmd),
+        };
+
+        assert_eq!(calibrate("calibrate 100 100 90"), ["100", "100", "90"]);
+        assert_eq!(calibrate("calibrate --reset"), [CALIBRATE_RESET]);
+        assert!(calibrate("calibrate").is_empty());
+        assert!(parse_command(["calibrate", "100", "90"]).is_err());
+        assert!(parse_command(["calibrate", "100", "100", "101"]).is_err());
+    }
+
+    #[test]
+    fn devices_and_help_aliases() {
+        assert!(matches!(parse("d").1, Some(Command::Devices)));
+        assert!(matches!(parse("?").1, Some(Command::Help(_))));
+        assert!(matches!(parse("help region").1, Some(Command::Help(args)) if args == ["region"]));
+    }
+
+    #[test]
+    fn install_service_defaults_to_user() {
+        let (_, cmd) = parse("install-service");
+
+        assert!(matches!(cmd, Some(Command::InstallService(args)) if args == ["user"]));
+        assert!(parse_args(["g213-cols", "install-service", "both"]).is_err());
+    }
+
+    #[test]
+    fn raw_command_confirmation() {
+        let (_, cmd) = parse("raw 11ff 0c3a --yes-i-know");
+
+        assert!(
+            matches!(cmd, Some(Command::Raw(args)) if args == ["11ff", "0c3a", "--yes-i-know"])
+        );
+    }
+
+    #[test]
+    fn list_command_format() {
+        let (_, cmd) = parse("l --format csv navy");
+
+        assert!(matches!(cmd, Some(Command::List(args)) if args == ["navy", "--format", "csv"]));
+        assert!(parse_args(["g213-cols", "list", "--format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn profile_commands() {
+        assert!(
+            matches!(parse("profile save work").1, Some(Command::Profile(args)) if args == ["save", "work"])
+        );
+        assert!(matches!(parse("p ls").1, Some(Command::Profile(args)) if args == ["list"]));
+        assert!(parse_args(["g213-cols", "profile", "load"]).is_err());
+    }
+
+    #[test]
+    fn options_config() {
+        let (options, _) = parse("--config /tmp/desk.json saved");
+
+        assert_eq!(options.config, Some("/tmp/desk.json".to_string()));
+    }
+
+    #[test]
+    fn device_from_config() {
+        let mut config = Config::default();
+        config.device.select = Some("all".to_string());
+
+        assert_eq!(
+            parse("off").0.with_config(&config).selector,
+            DeviceSelector::All
+        );
+
+        // The command line wins
+        let (options, _) = parse("--device 1:5 off");
+
+        assert_eq!(
+            options.with_config(&config).selector,
+            DeviceSelector::BusAddress(1, 5)
+        );
+    }
+
+    #[test]
+    fn cycle_speed_is_optional() {
+        assert!(matches!(parse("cycle").1, Some(Command::Cycle(args)) if args.is_empty()));
+    }
+
+    #[test]
+    fn speed_names() {
+        assert!(
+            matches!(parse("b fast red").1, Some(Command::Breathe(args)) if args == ["fast", "red"])
+        );
+        assert!(matches!(parse("cycle Slow").1, Some(Command::Cycle(args)) if args == ["Slow"]));
+        assert!(
+            matches!(parse("pulse medium teal").1, Some(Command::Pulse(args)) if args == ["medium", "teal"])
+        );
+    }
+
+    #[test]
+    fn speed_durations() {
+        assert!(
+            matches!(parse("b 5s red").1, Some(Command::Breathe(args)) if args == ["5s", "red"])
+        );
+        assert!(matches!(parse("cycle 1.5s").1, Some(Command::Cycle(args)) if args == ["1.5s"]));
+        assert!(parse_args(["g213-cols", "cycle", "90s"]).is_err());
+    }
+
+    #[test]
+    fn random_kind() {
+        assert!(matches!(parse("c random").1, Some(Command::Colour(args)) if args == ["random"]));
+        assert!(
+            matches!(parse("rs red random --random x11").1, Some(Command::Regions(args)) if args == ["red", "randomx11"])
+        );
+        assert!(parse_args(["g213-cols", "c", "random", "--random", "hsv"]).is_err());
+    }
+
+    #[test]
+    fn preview_takes_a_command() {
+        assert!(
+            matches!(parse("preview regions red --no-save").1, Some(Command::Preview(args)) if args == ["regions", "red", "--no-save"])
+        );
+        assert!(matches!(parse("preview").1, Some(Command::Preview(args)) if args.is_empty()));
+    }
+
+    #[test]
+    fn xresources_file() {
+        assert!(
+            matches!(parse("xresources color4 --file x.res").1, Some(Command::Xresources(args)) if args == ["color4", "--file", "x.res"])
+        );
+    }
+
+    #[test]
+    fn slots() {
+        let (options, _) = parse("c red --slot 2");
+
+        assert_eq!(options.slot, Some(2));
+        assert!(matches!(parse("apply 2").1, Some(Command::Apply(args)) if args == ["2"]));
+        assert!(matches!(parse("apply").1, Some(Command::Apply(args)) if args.is_empty()));
+        assert!(parse_args(["g213-cols", "c", "red", "--slot", "2", "--no-save"]).is_err());
+    }
+
+    #[test]
+    fn palette_commands() {
+        let (_, cmd) = parse("palette import sunset.gpl");
+
+        assert!(matches!(cmd, Some(Command::Palette(args)) if args == ["import", "sunset.gpl"]));
+        assert!(matches!(parse("palette ls").1, Some(Command::Palette(args)) if args == ["list"]));
+        assert!(
+            matches!(parse("import-openrgb desk.orp").1, Some(Command::ImportOpenrgb(args)) if args == ["desk.orp"])
+        );
+        assert!(
+            matches!(parse("export-openrgb desk.orp work").1, Some(Command::ExportOpenrgb(args)) if args == ["desk.orp", "work"])
+        );
+    }
+
+    #[test]
+    fn wal_command() {
+        assert!(matches!(parse("wal").1, Some(Command::Wal(args)) if args.is_empty()));
+        assert!(
+            matches!(parse("wal --watch theme.json").1, Some(Command::Wal(args)) if args == ["theme.json", "--watch"])
+        );
+    }
+
+    #[test]
+    fn options_device_flag() {
+        let (options, cmd) = parse("--device 1:5 colour red");
+
+        assert_eq!(options.selector, DeviceSelector::BusAddress(1, 5));
+        assert!(matches!(cmd, Some(Command::Colour(args)) if args == ["red"]));
+    }
+
+    #[test]
+    fn options_after_the_command() {
+        let (options, _) = parse("c red --device=ABC --no-save -v");
+
+        assert_eq!(options.selector, DeviceSelector::Serial("ABC".to_string()));
+        assert!(options.no_save);
+        assert!(options.verbose);
+        assert!(!options.json);
+    }
+
+    #[test]
+    fn options_json() {
+        let (options, cmd) = parse("list red --json");
+
+        assert!(options.json);
+        assert!(matches!(cmd, Some(Command::List(args)) if args == ["red"]));
+    }
+
+    #[test]
+    fn options_save_only() {
+        let (options, cmd) = parse("breathe 5000 teal --save-only");
+
+        assert!(options.save_only);
+        assert!(matches!(cmd, Some(Command::Breathe(args)) if args == ["5000", "teal"]));
+        assert!(parse_args(["g213-cols", "off", "--save-only", "--no-save"]).is_err());
+    }
+
+    #[test]
+    fn options_gamma() {
+        assert_eq!(parse("c red --gamma 2.2").0.gamma, Some(Gamma::All(2.2)));
+        assert!(parse_args(["g213-cols", "c", "--gamma", "9"]).is_err());
+    }
+
+    #[test]
+    fn options_fade() {
+        let (options, cmd) = parse("regions red blue --fade 500");
+
+        assert_eq!(options.fade, Some(500));
+        assert!(matches!(cmd, Some(Command::Regions(args)) if args == ["red", "blue"]));
+        assert_eq!(parse("off").0.fade, None);
+    }
+
+    #[test]
+    fn options_all_dry_run_and_trace() {
+        let (options, _) = parse("--all --dry-run --trace t.txt off");
+
+        assert_eq!(options.selector, DeviceSelector::All);
+        assert!(options.dry_run);
+        assert_eq!(options.trace, Some("t.txt".to_string()));
+        assert!(!options.no_save);
+        assert_eq!(options.config, None);
+    }
+}
@@ -1,50 +1,170 @@
-use dirs::home_dir;
+use dirs::{config_dir, home_dir};
 use libc::chown;
 use std::ffi::CString;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs::{create_dir_all, metadata, read, read_to_string, rename, write, File};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant, SystemTime};
 
-use rusb::{Device, GlobalContext};
+use regex::RegexBuilder;
 use serde::{Deserialize, Serialize};
-use users::{get_current_gid, get_current_uid};
+use serde_json::json;
+use users::{get_current_gid, get_current_uid, get_effective_uid};
 
+use crate::ambient::{grab_screen, strip_colours};
+use crate::battery::{battery_colours, pulse, read_charge, Charge, POWER_SUPPLY_DIR};
+use crate::calibration::{load_calibrations, parse_white_point, save_white_point, white_point};
+use crate::cli::parse_command;
+use crate::config::config;
+use crate::daemon::run_daemon;
+use crate::effects::{
+    alternate_colours, blink_colours, cross_fade_colours, pulse_level, Effect, Fire, Matrix,
+    Sparkle, MIN_BLINK,
+};
 use crate::g213_keyboard::{
-    self, limit_speed, set_breathe, set_cycle, set_keyboard_colour, set_region_colour, show_info,
-    KeyboardRegions,
+    calibration_serial, catch_interrupt, device_info, hold_device, interrupted, let_go_of_device,
+    limit_speed, list_g213_keyboards, num_regions, previewing, reload_calibration, reopen_device,
+    replay, run_wave, scale_colour, send_raw, sent_lighting, set_breathe, set_cycle,
+    set_keyboard_colour, set_lighting, set_regions, unplugged, DeviceInfo, G213Error, G213Result,
+    Keyboard, KeyboardRegions, MAX_BRIGHTNESS, MIN_SPEED, NUM_REGIONS, REGION_NAMES,
+};
+use crate::hidpp::{feature_name, is_lighting_feature};
+use crate::history::{add_to_history, forget_current, load_history, previous_command};
+use crate::hotplug::watch;
+use crate::install::{install_service, install_sleep_hook, install_udev, ServiceKind};
+use crate::load::{load_colours, Load};
+use crate::models::{models, Lighting, Model};
+use crate::music::{band_levels, Audio, Levels};
+use crate::network::{network_colours, parse_rate, Throughput};
+use crate::openrgb::{
+    command_controller, controller_command, find_g213, parse_profile, write_profile,
+};
+use crate::palette::{load_palette, parse_gpl, save_palette};
+use crate::profiles::{get_profile, load_profiles, save_profile, save_profiles};
+use crate::script::{parse_script, parse_step, Step};
+use crate::themes::{pick_colours, read_xresources, wal_colours, wal_file, xresources_colours};
+use crate::timers::{countdown_colours, parse_duration, pomodoro_colours, pomodoro_phase, Phase};
+use crate::trace::parse_trace;
+use crate::transport::FakeTransport;
+use crate::tui::{run_tui, TuiState};
+use crate::typing::{find_key_events, key_region, watch_keys, Glow};
+use crate::x11_colours::{
+    close_colour, get_x11_colour, get_x11_colour_list, get_x11_colours, gradient_colours,
+    hsv_to_rgb, hue_family, mix_colours, nearest_x11_colour, rainbow_colours, rgb_to_hsl,
+    similar_colour_names, x11_colour_names, HUE_FAMILIES, RANDOM, RANDOM_X11,
 };
-use crate::x11_colours::{get_x11_colour, get_x11_colours, x11_colour_names};
 
+/// Outcome of running a [`Command`], also used as the process exit code.
+///
+/// Successful commands exit with 0, failures with their value below.
 #[repr(u8)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Status {
     Success = 0,
-    Failure,
-    SuccessNoSave,
+    Failure = 1,
+    SuccessNoSave = 2,
+    BadArguments = 3,
+    NotFound = 4,
+    PermissionDenied = 5,
+    UsbError = 6,
 }
 
 pub trait Successful {
     fn successful(&self) -> bool;
 }
 
+impl From<u8> for Status {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Status::Success,
+            2 => Status::SuccessNoSave,
+            3 => Status::BadArguments,
+            4 => Status::NotFound,
+            5 => Status::PermissionDenied,
+            6 => Status::UsbError,
+            _ => Status::Failure,
+        }
+    }
+}
+
+impl From<&G213Error> for Status {
+    fn from(err: &G213Error) -> Self {
+        match err {
+            G213Error::NotFound => Status::NotFound,
+            G213Error::Permission => Status::PermissionDenied,
+            _ => Status::UsbError,
+        }
+    }
+}
+
 impl Successful for Status {
     fn successful(&self) -> bool {
         Status::Success == *self || Status::SuccessNoSave == *self
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A `g213-cols` command and its arguments, as typed on the command line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Command {
     Colour(Vec<String>),
     Region(Vec<String>),
     Regions(Vec<String>),
+    Gradient(Vec<String>),
+    Rainbow(Vec<String>),
     Breathe(Vec<String>),
     Cycle(Vec<String>),
+    Wave(Vec<String>),
+    Ambient(Vec<String>),
+    Music(Vec<String>),
+    Typing(Vec<String>),
+    Load(Vec<String>),
+    Monitor(Vec<String>),
+    Blink(Vec<String>),
+    Alternate(Vec<String>),
+    Fire(Vec<String>),
+    Matrix(Vec<String>),
+    Sparkle(Vec<String>),
+    CycleList(Vec<String>),
+    Pulse(Vec<String>),
+    Pomodoro(Vec<String>),
+    Timer(Vec<String>),
+    Wal(Vec<String>),
+    Xresources(Vec<String>),
+    Off,
+    On,
+    Brightness(Vec<String>),
+    Calibrate(Vec<String>),
+    Daemon,
+    Devices,
+    Watch,
+    InstallService(Vec<String>),
+    InstallUdev(Vec<String>),
+    InstallSleepHook,
+    Raw(Vec<String>),
+    Replay(Vec<String>),
+    Script(Vec<String>),
+    Pipe,
+    Profile(Vec<String>),
+    Apply(Vec<String>),
+    Palette(Vec<String>),
+    ImportOpenrgb(Vec<String>),
+    ExportOpenrgb(Vec<String>),
     List(Vec<String>),
+    Nearest(Vec<String>),
+    Preview(Vec<String>),
+    Tui,
     Info,
     Saved,
+    Get(Vec<String>),
+    History,
+    Undo,
+    Toggle,
     Help(Vec<String>),
-    Unknown(Vec<String>),
 }
 
 impl Display for Command {
@@ -53,54 +173,185 @@ impl Display for Command {
             Command::Colour(args) => write!(f, "colour {}", args.join(" ")),
             Command::Region(args) => write!(f, "region {}", args.join(" ")),
             Command::Regions(args) => write!(f, "regions {}", args.join(" ")),
+            Command::Gradient(args) => write!(f, "gradient {}", args.join(" ")),
+            Command::Rainbow(args) => write!(f, "rainbow {}", args.join(" ")),
             Command::Breathe(args) => write!(f, "breathe {}", args.join(" ")),
             Command::Cycle(args) => write!(f, "cycle {}", args.join(" ")),
+            Command::Wave(args) => write!(f, "wave {}", args.join(" ")),
+            Command::Ambient(args) => write!(f, "ambient {}", args.join(" ")),
+            Command::Music(args) => write!(f, "music {}", args.join(" ")),
+            Command::Typing(args) => write!(f, "typing {}", args.join(" ")),
+            Command::Load(args) => write!(f, "load {}", args.join(" ")),
+            Command::Monitor(args) => write!(f, "monitor {}", args.join(" ")),
+            Command::Blink(args) => write!(f, "blink {}", args.join(" ")),
+            Command::Alternate(args) => write!(f, "alternate {}", args.join(" ")),
+            Command::Fire(args) => write!(f, "fire {}", args.join(" ")),
+            Command::Matrix(args) => write!(f, "matrix {}", args.join(" ")),
+            Command::Sparkle(args) => write!(f, "sparkle {}", args.join(" ")),
+            Command::CycleList(args) => write!(f, "cycle-list {}", args.join(" ")),
+            Command::Pulse(args) => write!(f, "pulse {}", args.join(" ")),
+            Command::Pomodoro(args) => write!(f, "pomodoro {}", args.join(" ")),
+            Command::Timer(args) => write!(f, "timer {}", args.join(" ")),
+            Command::Wal(args) => write!(f, "wal {}", args.join(" ")),
+            Command::Xresources(args) => write!(f, "xresources {}", args.join(" ")),
+            Command::Off => write!(f, "off"),
+            Command::On => write!(f, "on"),
+            Command::Brightness(args) => write!(f, "brightness {}", args.join(" ")),
+            Command::Calibrate(args) => write!(f, "calibrate {}", args.join(" ")),
+            Command::Daemon => write!(f, "daemon"),
+            Command::Devices => write!(f, "devices"),
+            Command::Watch => write!(f, "watch"),
+            Command::InstallService(args) => write!(f, "install-service {}", args.join(" ")),
+            Command::InstallUdev(args) => write!(f, "install-udev {}", args.join(" ")),
+            Command::InstallSleepHook => write!(f, "install-sleep-hook"),
+            Command::Raw(args) => write!(f, "raw {}", args.join(" ")),
+            Command::Replay(args) => write!(f, "replay {}", args.join(" ")),
+            Command::Script(args) => write!(f, "run {}", args.join(" ")),
+            Command::Pipe => write!(f, "pipe"),
+            Command::Profile(args) => write!(f, "profile {}", args.join(" ")),
+            Command::Apply(args) => write!(f, "apply {}", args.join(" ")),
+            Command::Palette(args) => write!(f, "palette {}", args.join(" ")),
+            Command::ImportOpenrgb(args) => write!(f, "import-openrgb {}", args.join(" ")),
+            Command::ExportOpenrgb(args) => write!(f, "export-openrgb {}", args.join(" ")),
             Command::List(args) => write!(f, "list {}", args.join(" ")),
+            Command::Nearest(args) => write!(f, "nearest {}", args.join(" ")),
+            Command::Preview(args) => write!(f, "preview {}", args.join(" ")),
+            Command::Tui => write!(f, "tui"),
             Command::Info => write!(f, "info"),
             Command::Saved => write!(f, "saved"),
+            Command::Get(args) => write!(f, "get {}", args.join(" ")),
+            Command::History => write!(f, "history"),
+            Command::Undo => write!(f, "undo"),
+            Command::Toggle => write!(f, "toggle"),
             Command::Help(args) => write!(f, "help {}", args.join(" ")),
-            Command::Unknown(args) => write!(f, "unknown {}", args.join(" ")),
         }
     }
 }
 
-pub fn get_command(args: &[String]) -> Command {
-    let cmd = if args.is_empty() { "" } else { &args[0] };
+impl Command {
+    /// `profile load name` and `apply slot` stand for the profile's own command,
+    /// which is what should be run, forwarded to the daemon and saved, as
+    /// `apply` on its own does for the saved command. `undo`
+    /// stands for the command saved before the current one, which is only
+    /// forgotten, by [`forget_current`], once that's been run and saved,
+    /// and `toggle` for `off` or `on`.
+    pub fn resolve(self) -> Result<Command, String> {
+        let name = match &self {
+            Command::Undo => {
+                return previous_command().ok_or_else(|| "Nothing to undo".to_string())
+            }
+            Command::Toggle if lights_off() => return Ok(Command::On),
+            Command::Toggle => return Ok(Command::Off),
+            Command::Apply(args) if args.is_empty() => {
+                return get_saved_command().ok_or_else(|| NO_SAVED_COMMAND.to_string())
+            }
+            Command::Profile(args) if args.len() == 2 && args[0] == PROFILE_LOAD => &args[1],
+            Command::Apply(args) if args.len() == 1 => &args[0],
+            _ => return Ok(self),
+        };
+
+        get_profile(name).ok_or_else(|| no_such_profile(name))
+    }
+
+    /// The command with any 'random' colours picked from the X11 ones, which
+    /// is kept in the command so it's the same when saved or sent to the daemon.
+    pub fn with_x11_randoms(self) -> Command {
+        let x11 = |args: Vec<String>| {
+            args.into_iter()
+                .map(|arg| match arg.eq_ignore_ascii_case(RANDOM) {
+                    true => RANDOM_X11.to_string(),
+                    false => arg,
+                })
+                .collect()
+        };
 
-    match cmd.to_lowercase().as_str() {
-        "colour" | "c" => Command::Colour(args[1..].to_vec()),
-        "region" | "r" => Command::Region(args[1..].to_vec()),
-        "regions" | "rs" => Command::Regions(args[1..].to_vec()),
-        "breathe" | "b" => Command::Breathe(args[1..].to_vec()),
-        "cycle" | "cy" => Command::Cycle(args[1..].to_vec()),
-        "list" | "l" => Command::List(args[1..].to_vec()),
-        "info" | "i" => Command::Info,
-        "saved" | "s" => Command::Saved,
-        "help" | "h" | "?" => Command::Help(args[1..].to_vec()),
-        _ => Command::Unknown(args.to_vec()),
+        match self {
+            Command::Colour(args) => Command::Colour(x11(args)),
+            Command::Region(args) => Command::Region(x11(args)),
+            Command::Regions(args) => Command::Regions(x11(args)),
+            Command::Gradient(args) => Command::Gradient(x11(args)),
+            Command::Breathe(args) => Command::Breathe(x11(args)),
+            command => command,
+        }
     }
 }
 
+/// Runs a [`Command`] against a G213 keyboard.
 pub trait Run {
-    fn run(&self, device: &Device<GlobalContext>) -> Status;
+    fn run(&self, device: &Keyboard) -> Status;
     fn has_args(&self) -> bool;
+    fn uses_device(&self) -> bool;
 }
 
 impl Run for Command {
-    fn run(&self, device: &Device<GlobalContext>) -> Status {
-        match self {
+    fn run(&self, device: &Keyboard) -> Status {
+        let result = match self {
             Command::Colour(args) => colour_command(device, args),
             Command::Region(args) => region_command(device, args),
             Command::Regions(args) => regions_command(device, args),
+            Command::Gradient(args) => gradient_command(device, args),
+            Command::Rainbow(args) => rainbow_command(device, args),
             Command::Breathe(args) => breathe_command(device, args),
             Command::Cycle(args) => cycle_command(device, args),
-            Command::List(args) => list_command(args),
+            Command::Wave(args) => wave_command(device, args),
+            Command::Ambient(args) => ambient_command(device, args),
+            Command::Music(args) => music_command(device, args),
+            Command::Typing(args) => typing_command(device, args),
+            Command::Load(args) => load_command(device, args),
+            Command::Monitor(args) => monitor_command(device, args),
+            Command::Blink(args) => blink_command(device, args),
+            Command::Alternate(args) => alternate_command(device, args),
+            Command::Fire(args) => fire_command(device, args),
+            Command::Matrix(args) => matrix_command(device, args),
+            Command::Sparkle(args) => sparkle_command(device, args),
+            Command::CycleList(args) => cycle_list_command(device, args),
+            Command::Pulse(args) => pulse_command(device, args),
+            Command::Pomodoro(args) => pomodoro_command(device, args),
+            Command::Timer(args) => timer_command(device, args),
+            Command::Wal(args) => wal_command(device, args),
+            Command::Xresources(args) => xresources_command(device, args),
+            Command::Off => off_command(device),
+            Command::On => on_command(device),
+            Command::Toggle if lights_off() => on_command(device),
+            Command::Toggle => off_command(device),
+            Command::Brightness(args) => brightness_command(device, args),
+            Command::Calibrate(args) => calibrate_command(device, args),
+            Command::Daemon => run_daemon(device),
+            Command::Devices => devices_command(),
+            Command::Watch => Ok(watch()),
+            Command::InstallService(args) => Ok(install_service_command(args)),
+            Command::InstallUdev(args) => Ok(install_udev_command(args)),
+            Command::InstallSleepHook => Ok(install_sleep_hook()),
+            Command::Raw(args) => raw_command(device, args),
+            Command::Replay(args) => replay_command(device, args),
+            Command::Script(args) => script_command(device, args),
+            Command::Pipe => pipe_command(device),
+            Command::Profile(args) => profile_command(device, args),
+            Command::Apply(args) => apply_command(device, args),
+            Command::Palette(args) => Ok(palette_command(args)),
+            Command::ImportOpenrgb(args) => Ok(import_openrgb_command(args)),
+            Command::ExportOpenrgb(args) => Ok(export_openrgb_command(args)),
+            Command::List(args) => Ok(list_command(args)),
+            Command::Nearest(args) => Ok(nearest_command(args)),
+            Command::Preview(args) => Ok(preview_command(args)),
+            Command::Tui => Ok(tui_command(device)),
             Command::Info => info_command(device),
-            Command::Saved => saved_command(),
-            Command::Help(args) => help_command(args),
-            Command::Unknown(args) => {
-                eprintln!("Unknown command: {}", args.join(" "));
-                Status::SuccessNoSave
+            Command::Saved => Ok(saved_command()),
+            Command::Get(args) => Ok(get_command(args)),
+            Command::History => Ok(history_command()),
+            Command::Undo => Ok(undo_command(device)),
+            Command::Help(args) => Ok(help_command(args)),
+        };
+
+        // Keyboard errors end up here, as a message rather than a panic
+        match result {
+            Ok(status) => status,
+            Err(err) => {
+                let status = Status::from(&err);
+
+                report_error(&err, status);
+
+                status
             }
         }
     }
@@ -110,293 +361,4538 @@ impl Run for Command {
             Command::Colour(args) => !args.is_empty(),
             Command::Region(args) => !args.is_empty(),
             Command::Regions(args) => !args.is_empty(),
+            Command::Gradient(args) => !args.is_empty(),
+            Command::Rainbow(args) => !args.is_empty(),
             Command::Breathe(args) => !args.is_empty(),
             Command::Cycle(args) => !args.is_empty(),
+            Command::Wave(args) => !args.is_empty(),
+            Command::Ambient(args) => !args.is_empty(),
+            Command::Music(args) => !args.is_empty(),
+            Command::Typing(args) => !args.is_empty(),
+            Command::Load(args) => !args.is_empty(),
+            Command::Monitor(args) => !args.is_empty(),
+            Command::Blink(args) => !args.is_empty(),
+            Command::Alternate(args) => !args.is_empty(),
+            Command::Fire(args) => !args.is_empty(),
+            Command::Matrix(args) => !args.is_empty(),
+            Command::Sparkle(args) => !args.is_empty(),
+            Command::CycleList(args) => !args.is_empty(),
+            Command::Pulse(args) => !args.is_empty(),
+            Command::Pomodoro(args) => !args.is_empty(),
+            Command::Timer(args) => !args.is_empty(),
+            Command::Wal(args) => !args.is_empty(),
+            Command::Xresources(args) => !args.is_empty(),
+            Command::Brightness(args) => !args.is_empty(),
+            Command::Calibrate(args) => !args.is_empty(),
+            Command::InstallService(args) => !args.is_empty(),
+            Command::InstallUdev(args) => !args.is_empty(),
+            Command::Raw(args) => !args.is_empty(),
+            Command::Replay(args) => !args.is_empty(),
+            Command::Script(args) => !args.is_empty(),
+            Command::Profile(args) => !args.is_empty(),
+            Command::Apply(args) => !args.is_empty(),
+            Command::Palette(args) => !args.is_empty(),
+            Command::ImportOpenrgb(args) => !args.is_empty(),
+            Command::ExportOpenrgb(args) => !args.is_empty(),
             Command::List(args) => !args.is_empty(),
+            Command::Nearest(args) => !args.is_empty(),
+            Command::Preview(args) => !args.is_empty(),
             Command::Help(args) => !args.is_empty(),
-            Command::Unknown(args) => !args.is_empty(),
+            Command::Get(args) => !args.is_empty(),
             _ => false,
         }
     }
+
+    fn uses_device(&self) -> bool {
+        if let Command::Profile(args) = self {
+            return args.first().is_some_and(|action| action == PROFILE_LOAD);
+        }
+
+        !matches!(
+            self,
+            Command::List(_)
+                | Command::Nearest(_)
+                | Command::Preview(_)
+                | Command::Palette(_)
+                | Command::ImportOpenrgb(_)
+                | Command::ExportOpenrgb(_)
+                | Command::Saved
+                | Command::Get(_)
+                | Command::History
+                | Command::Help(_)
+                | Command::Daemon
+                | Command::Devices
+                | Command::Watch
+                | Command::InstallService(_)
+                | Command::InstallUdev(_)
+                | Command::InstallSleepHook
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+// Set by '--json', output is for scripts rather than people
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::SeqCst);
+}
+
+fn json_output() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+fn print_json(value: &impl Serialize) {
+    println!("{}", serde_json::to_string(value).unwrap());
+}
+
+/// Reports why a command failed, on stderr, or with `--json` as
+/// `{"error": "...", "status": 3}` on stdout.
+pub fn report_error(message: &dyn Display, status: Status) {
+    if json_output() {
+        print_json(&json!({ "error": message.to_string(), "status": status as u8 }));
+    } else {
+        eprintln!("{}", message);
+    }
 }
 
 // ----------------------------------------------------------------------------
 
-const CONFIG_FILE: &str = ".g213-cols.json";
+const CONFIG_DIR: &str = "g213-cols";
+const CONFIG_FILE: &str = "config.json";
+const BRIGHTNESS_FILE: &str = "brightness";
+const COLOURS_FILE: &str = "colours.json";
+const OFF_FILE: &str = "off";
+pub(crate) const MODELS_FILE: &str = "models.toml";
+pub(crate) const PROFILES_FILE: &str = "profiles.json";
+pub(crate) const HISTORY_FILE: &str = "history.json";
+pub(crate) const CALIBRATION_FILE: &str = "calibration.json";
+
+// Where each file used to be kept, directly in ~
+const OLD_CONFIG_FILES: [(&str, &str); 4] = [
+    (CONFIG_FILE, ".g213-cols.json"),
+    (BRIGHTNESS_FILE, ".g213-cols-brightness"),
+    (MODELS_FILE, ".g213-cols-models.toml"),
+    (PROFILES_FILE, ".g213-cols-profiles.json"),
+];
 
-fn config_file_path() -> String {
-    match home_dir() {
-        Some(path) => format!("{}/{}", path.to_string_lossy(), CONFIG_FILE),
-        None => String::new(),
+// Set by '--config', replaces the saved command's file
+static CONFIG_PATH: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn set_config_path(path: Option<String>) {
+    *CONFIG_PATH.lock().unwrap() = path;
+}
+
+// Moves a file from ~ to the config directory, unless it's already there
+fn migrate_old_file(old: &Path, new: &Path) -> std::io::Result<bool> {
+    if new.exists() || !old.exists() {
+        return Ok(false);
     }
+
+    create_config_dir(new)?;
+    rename(old, new)?;
+
+    Ok(true)
 }
 
-pub fn get_saved_command() -> Option<Command> {
-    let path = config_file_path();
+fn create_config_dir(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.exists() => {
+            create_dir_all(dir)?;
+
+            set_file_ownership_to_me(dir.to_string_lossy().to_string());
+
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
 
-    let f = File::open(path);
+/// Where `file` is kept, in `$XDG_CONFIG_HOME/g213-cols`, which is usually
+/// `~/.config/g213-cols`. Files from older versions are moved there first.
+pub(crate) fn config_file_path(file: &str) -> std::io::Result<String> {
+    if file == CONFIG_FILE {
+        if let Some(path) = CONFIG_PATH.lock().unwrap().as_ref() {
+            return Ok(path.clone());
+        }
+    }
 
-    if let Ok(mut fh) = f {
-        let mut saved_cmd = String::new();
+    let no_home = || {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no home directory to keep config in",
+        )
+    };
 
-        fh.read_to_string(&mut saved_cmd)
-            .expect("Unable to read saved command");
+    let path = config_dir()
+        .ok_or_else(no_home)?
+        .join(CONFIG_DIR)
+        .join(file);
 
-        let command = serde_json::from_str(&saved_cmd).expect("Unable to use saved command");
+    if let Some((_, old)) = OLD_CONFIG_FILES.iter().find(|(new, _)| *new == file) {
+        let old = home_dir().ok_or_else(no_home)?.join(old);
 
-        return Some(command);
+        match migrate_old_file(&old, &path) {
+            Ok(true) => eprintln!("Moved {} to {}", old.display(), path.display()),
+            Ok(false) => (),
+            Err(err) => eprintln!(
+                "Unable to move {} to {}: {}",
+                old.display(),
+                path.display(),
+                err
+            ),
+        }
     }
 
-    None
+    Ok(path.to_string_lossy().to_string())
+}
+
+// As above, making sure the directory is there to save into
+pub(crate) fn config_file_for_saving(file: &str) -> std::io::Result<String> {
+    let path = config_file_path(file)?;
+
+    create_config_dir(Path::new(&path))?;
+
+    Ok(path)
+}
+
+/// The last successful command, saved by [`save_command`].
+pub fn get_saved_command() -> Option<Command> {
+    let path = config_file_path(CONFIG_FILE).ok()?;
+
+    let saved_cmd = read_to_string(&path).ok()?;
+
+    // A half written or hand edited file shouldn't stop everything else working
+    serde_json::from_str(&saved_cmd)
+        .map_err(|err| eprintln!("Ignoring {}: {}", path, err))
+        .ok()
 }
 
+// Only needed when running setuid root, not when the udev rule gives us access
 pub fn set_file_ownership_to_me(path: String) {
+    if get_effective_uid() == get_current_uid() {
+        return;
+    }
+
     unsafe {
         let c_path = CString::new(path).unwrap();
         chown(c_path.as_ptr(), get_current_uid(), get_current_gid());
     }
 }
 
+/// Saves a command to `~/.config/g213-cols/config.json`, or the `--config` file,
+/// for later use.
 pub fn save_command(command: &Command) {
     let ser_command = serde_json::to_string(&command).unwrap();
-    let path = config_file_path();
+    let path = match config_file_for_saving(CONFIG_FILE) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Unable to save command: {}", err);
+
+            return;
+        }
+    };
 
     let mut f = File::create(&path).expect("Unable to open config file for saving");
 
     Write::write_all(&mut f, ser_command.as_bytes()).expect("Unable to save command");
 
     set_file_ownership_to_me(path);
+
+    add_to_history(command);
+}
+
+// Brightness is kept apart from the saved command, which holds the full brightness colours
+pub fn get_brightness() -> u8 {
+    let brightness = config_file_path(BRIGHTNESS_FILE).and_then(std::fs::read_to_string);
+
+    match brightness {
+        Ok(brightness) => brightness
+            .trim()
+            .parse::<u8>()
+            .unwrap_or(MAX_BRIGHTNESS)
+            .min(MAX_BRIGHTNESS),
+        Err(_) => MAX_BRIGHTNESS,
+    }
+}
+
+pub fn save_brightness(brightness: u8) {
+    let path = match config_file_for_saving(BRIGHTNESS_FILE) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Unable to save brightness: {}", err);
+
+            return;
+        }
+    };
+
+    let mut f = File::create(&path).expect("Unable to open brightness file for saving");
+
+    Write::write_all(&mut f, brightness.to_string().as_bytes()).expect("Unable to save brightness");
+
+    set_file_ownership_to_me(path);
+}
+
+/// Each region's colour as last set, for '--fade' to start from.
+pub fn get_last_colours() -> Option<Vec<u32>> {
+    let colours = config_file_path(COLOURS_FILE)
+        .and_then(std::fs::read_to_string)
+        .ok()?;
+
+    serde_json::from_str(&colours).ok()
+}
+
+// Forgotten when they're not known, after an effect, so the next fade doesn't
+// start from the wrong colours
+fn save_last_colours(colours: Option<Vec<u32>>) {
+    let path = match config_file_for_saving(COLOURS_FILE) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Unable to save colours: {}", err);
+
+            return;
+        }
+    };
+
+    match colours {
+        Some(colours) => {
+            write(&path, serde_json::to_string(&colours).unwrap()).expect("Unable to save colours");
+
+            set_file_ownership_to_me(path);
+        }
+        None => {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Whether the lighting was last turned off, for 'toggle'.
+pub fn lights_off() -> bool {
+    config_file_path(OFF_FILE).is_ok_and(|path| Path::new(&path).exists())
+}
+
+// Just being there means off
+fn save_lights_off(off: bool) {
+    let path = match config_file_for_saving(OFF_FILE) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Unable to save lights off: {}", err);
+
+            return;
+        }
+    };
+
+    if off {
+        write(&path, "").expect("Unable to save lights off");
+
+        set_file_ownership_to_me(path);
+    } else {
+        let _ = std::fs::remove_file(path);
+    }
 }
 
 // ----------------------------------------------------------------------------
 
 const RED: u32 = 0xff1010;
+const WHITE: u32 = 0xffffff;
+const BLUE: u32 = 0x1010ff;
+pub(crate) const RAW_CONFIRM: &str = "--yes-i-know";
+pub(crate) const LIST_FORMAT: &str = "--format";
+pub(crate) const LIST_NO_COLOUR: &str = "--no-color";
+pub(crate) const LIST_SORT: &str = "--sort";
+pub(crate) const LIST_REGEX: &str = "--regex";
+pub(crate) const LIST_RANGE: &str = "--range";
+pub(crate) const PROFILE_LOAD: &str = "load";
+pub(crate) const WAL_WATCH: &str = "--watch";
+pub(crate) const REGIONS_MIRROR: &str = "--mirror";
+pub(crate) const FRAMES_FPS: &str = "--fps";
+pub(crate) const FRAMES_CPU: &str = "--cpu";
+pub(crate) const TYPING_DECAY: &str = "--decay";
+pub(crate) const TYPING_INPUT: &str = "--input";
+pub(crate) const FIRE_INTENSITY: &str = "--intensity";
+pub(crate) const FIRE_SPEED: &str = "--speed";
+pub(crate) const LOAD_USAGE: &str = "--usage";
+pub(crate) const MONITOR_EVERY: &str = "--every";
+pub(crate) const MONITOR_BAR: &str = "--bar";
+pub(crate) const MONITOR_LOW: &str = "--low";
+pub(crate) const MONITOR_BATTERY: &str = "battery";
+pub(crate) const MONITOR_NETWORK: &str = "network";
+pub(crate) const NETWORK_INTERFACE: &str = "--interface";
+pub(crate) const NETWORK_MAX: &str = "--max";
+pub(crate) const NETWORK_WHOLE: &str = "--whole";
+const WAL_POLL_MS: u64 = 1000;
+pub(crate) const XRESOURCES_FILE: &str = "--file";
+pub(crate) const CALIBRATE_RESET: &str = "--reset";
+const UDEV_GROUP: &str = "plugdev";
+const OFF: u32 = 0x000000;
 
-fn get_colour_or_red(args: &[String]) -> (u32, Status) {
-    match get_x11_colour(args) {
-        Some(col) => (col, Status::Success),
-        None => (RED, Status::Failure),
+// No colour means the one from config.toml, if there is one, otherwise white
+fn or_default_colour(args: &[String]) -> Vec<String> {
+    match &config().defaults.colour {
+        Some(colour) if args.is_empty() => colour.split_whitespace().map(String::from).collect(),
+        _ => args.to_vec(),
     }
 }
 
-fn get_colours_or_red(args: &[String], num: u8) -> (Vec<u32>, Status) {
-    match get_x11_colours(args, num) {
-        Some(cols) => (cols, Status::Success),
-        None => (vec![RED; num as usize], Status::Failure),
+fn unknown_colour(name: &str) -> String {
+    let similar = similar_colour_names(name);
+
+    match similar.split_last() {
+        None => format!("Unknown colour '{}', see 'list'", name),
+        Some((last, [])) => format!("Unknown colour '{}', did you mean '{}'?", name, last),
+        Some((last, rest)) => format!(
+            "Unknown colour '{}', did you mean '{}' or '{}'?",
+            name,
+            rest.join("', '"),
+            last
+        ),
     }
 }
 
-fn colour_command(device: &Device<GlobalContext>, args: &[String]) -> Status {
-    let (colour, status) = get_colour_or_red(args);
+// A mistyped name is used as the one colour it's close to, or failing that,
+// the colours it might have been are suggested
+fn get_close_colour(name: &str) -> Option<u32> {
+    match close_colour(name) {
+        Some(close) => {
+            eprintln!("Unknown colour '{}', using '{}'", name, close);
+
+            get_x11_colour(&[close])
+        }
+        None => {
+            report_error(&unknown_colour(name), Status::BadArguments);
+
+            None
+        }
+    }
+}
 
-    set_keyboard_colour(device, colour);
+fn get_colour_or_red(args: &[String]) -> (u32, Status) {
+    let brightness = get_brightness() as u32;
+    let args = or_default_colour(args);
 
-    status
+    match get_x11_colour(&args).or_else(|| get_close_colour(&args.join(" "))) {
+        Some(col) => (scale_colour(col, brightness), Status::Success),
+        None => (scale_colour(RED, brightness), Status::BadArguments),
+    }
 }
 
-fn region_command(device: &Device<GlobalContext>, args: &[String]) -> Status {
-    let mut status = Status::Failure;
+fn scale_colours(colours: &[u32]) -> Vec<u32> {
+    let brightness = get_brightness() as u32;
 
-    if !args.is_empty() {
-        let region: KeyboardRegions = args[0].parse::<u8>().unwrap().into();
+    colours
+        .iter()
+        .map(|colour| scale_colour(*colour, brightness))
+        .collect()
+}
 
-        let (colour, col_status) = get_colour_or_red(&args[1..]);
+// Each word that isn't a colour by itself, or part of one, is tried as a typo
+fn get_close_colours(args: &[String], num: u8) -> Option<Vec<u32>> {
+    let close: Vec<Option<String>> = args
+        .iter()
+        .map(|arg| match get_x11_colour(std::slice::from_ref(arg)) {
+            Some(_) => None,
+            None => close_colour(arg),
+        })
+        .collect();
 
-        set_region_colour(device, region as u8, colour);
+    let corrected: Vec<String> = args
+        .iter()
+        .zip(&close)
+        .map(|(arg, close)| close.clone().unwrap_or_else(|| arg.clone()))
+        .collect();
 
-        status = col_status;
+    let colours = match close.iter().any(Option::is_some) {
+        true => get_x11_colours(&corrected, num),
+        false => None,
+    };
+
+    if colours.is_some() {
+        for (arg, close) in args.iter().zip(&close) {
+            if let Some(close) = close {
+                eprintln!("Unknown colour '{}', using '{}'", arg, close);
+            }
+        }
     } else {
-        eprintln!("At least one - 'region' ['colour'] - argument needed for 'region' command");
+        let not_colours: Vec<&String> = args
+            .iter()
+            .filter(|arg| get_x11_colour(std::slice::from_ref(*arg)).is_none())
+            .collect();
+
+        // Suggest for a word that has suggestions, rather than part of a name
+        let unknown = not_colours
+            .iter()
+            .find(|arg| !similar_colour_names(arg).is_empty())
+            .or(not_colours.first())
+            .map_or_else(|| args.join(" "), |arg| arg.to_string());
+
+        report_error(&unknown_colour(&unknown), Status::BadArguments);
     }
 
-    status
+    colours
 }
 
-fn regions_command(device: &Device<GlobalContext>, args: &[String]) -> Status {
-    let (colours, status) = get_colours_or_red(args, g213_keyboard::NUM_REGIONS);
+fn get_colours_or_red(args: &[String], num: u8) -> (Vec<u32>, Status) {
+    let args = or_default_colour(args);
 
-    colours
-        .iter()
-        .enumerate()
-        .for_each(|(region, colour)| set_region_colour(device, (region + 1) as u8, *colour));
+    match get_x11_colours(&args, num).or_else(|| get_close_colours(&args, num)) {
+        Some(cols) => (scale_colours(&cols), Status::Success),
+        None => (
+            scale_colours(&vec![RED; num as usize]),
+            Status::BadArguments,
+        ),
+    }
+}
 
-    status
+fn colour_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (colour, status) = get_colour_or_red(args);
+
+    set_keyboard_colour(device, colour)?;
+
+    Ok(status)
+}
+
+// '1-3', '1,5' or 'wasd,numpad', names are tried first as some have a '-'
+fn region_list(regions: &str) -> Result<Vec<u8>, String> {
+    let mut list = Vec::new();
+
+    for part in regions.split(',') {
+        if let Ok(region) = part.parse::<KeyboardRegions>() {
+            list.push(region as u8);
+
+            continue;
+        }
+
+        let region = |region: &str| region.parse::<u8>().ok().filter(|r| *r <= NUM_REGIONS);
+
+        let range = part
+            .split_once('-')
+            .and_then(|(first, last)| Some((region(first)?, region(last)?)))
+            .filter(|(first, last)| first <= last);
+
+        match range {
+            Some((first, last)) => list.extend(first..=last),
+            None => return Err(part.parse::<KeyboardRegions>().err().unwrap_or_default()),
+        }
+    }
+
+    Ok(list)
 }
 
-fn breathe_command(device: &Device<GlobalContext>, args: &[String]) -> Status {
-    let mut status = Status::Failure;
+fn region_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let mut status = Status::BadArguments;
 
     if !args.is_empty() {
-        let speed = limit_speed(args[0].parse::<u16>().unwrap());
+        let regions = match region_list(&args[0]) {
+            Ok(regions) => regions,
+            Err(err) => {
+                report_error(&err, Status::BadArguments);
+
+                return Ok(Status::BadArguments);
+            }
+        };
+
+        // Smaller keyboards, and the mouse, have fewer
+        let num = num_regions(device);
+
+        if let Some(region) = regions.iter().find(|region| **region > num) {
+            report_error(
+                &format!("No region {}, the keyboard has 1 to {}", region, num),
+                Status::BadArguments,
+            );
+
+            return Ok(Status::BadArguments);
+        }
 
         let (colour, col_status) = get_colour_or_red(&args[1..]);
 
-        set_breathe(device, speed, colour);
+        let colours: Vec<(u8, u32)> = regions.into_iter().map(|region| (region, colour)).collect();
+
+        set_regions(device, &colours)?;
 
         status = col_status;
     } else {
-        eprintln!("At least one - 'speed' ['colour'] - argument needed for 'breathe' command");
+        report_error(
+            &"At least one - 'region' ['colour'] - argument needed for 'region' command",
+            Status::BadArguments,
+        );
     }
 
-    status
+    Ok(status)
+}
+
+// Region 1 gets the first colour, and so on
+pub(crate) fn set_region_colours(device: &Keyboard, colours: &[u32]) -> G213Result<()> {
+    let colours: Vec<(u8, u32)> = colours
+        .iter()
+        .enumerate()
+        .map(|(region, colour)| ((region + 1) as u8, *colour))
+        .collect();
+
+    set_regions(device, &colours)
+}
+
+// The first colour at both ends, the next ones working inwards
+fn mirror_colours(colours: &[u32], num: u8) -> Vec<u32> {
+    let last = num as usize - 1;
+
+    (0..=last)
+        .map(|region| colours[region.min(last - region)])
+        .collect()
 }
 
-fn cycle_command(device: &Device<GlobalContext>, args: &[String]) -> Status {
-    let mut status = Status::Failure;
+fn regions_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let num = num_regions(device);
 
-    if args.len() == 1 {
-        let speed = limit_speed(args[0].parse::<u16>().unwrap());
+    if args.iter().any(|arg| arg == REGIONS_MIRROR) {
+        let args: Vec<String> = args
+            .iter()
+            .filter(|arg| *arg != REGIONS_MIRROR)
+            .cloned()
+            .collect();
+        let (colours, status) = get_colours_or_red(&args, num.div_ceil(2));
 
-        set_cycle(device, speed);
+        set_region_colours(device, &mirror_colours(&colours, num))?;
 
-        status = Status::Success;
-    } else {
-        eprintln!("One 'speed' argument needed for 'cycle' command");
+        return Ok(status);
     }
 
-    status
+    let (colours, status) = get_colours_or_red(args, num);
+
+    set_region_colours(device, &colours)?;
+
+    Ok(status)
 }
 
-fn list_command(args: &[String]) -> Status {
-    let mut status = Status::Failure;
+fn gradient_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (ends, status) = get_colours_or_red(args, 2);
 
-    let names = x11_colour_names();
-    let target: String = if args.is_empty() {
-        "".to_string()
-    } else {
-        args[0].to_ascii_lowercase()
-    };
+    let colours = gradient_colours(ends[0], ends[1], num_regions(device));
 
-    for name in &names {
-        if target.is_empty() || name.contains(&target) {
-            println!(
-                "{} {:#08x}",
-                name,
-                get_x11_colour(&[name.to_string()]).unwrap()
-            );
-            status = Status::SuccessNoSave;
-        }
-    }
+    set_region_colours(device, &colours)?;
 
-    status
+    Ok(status)
 }
 
-fn saved_command() -> Status {
-    let command = get_saved_command();
+// The starting hue, 0 to 359, then the saturation and value, 0 to 100
+fn rainbow_args(args: &[String]) -> Result<(f64, f64, f64), String> {
+    let number = |index: usize, default: u16, max: u16, name: &str| match args.get(index) {
+        None => Ok(default as f64),
+        Some(arg) => match arg.parse::<u16>() {
+            Ok(number) if number <= max => Ok(number as f64),
+            _ => Err(format!(
+                "'rainbow' needs a {} from 0 to {}, not '{}'",
+                name, max, arg
+            )),
+        },
+    };
 
-    match command {
-        Some(cmd) => println!("Saved command: {}", cmd),
-        None => println!("No currently saved command"),
+    if args.len() > 3 {
+        return Err(
+            "At most three - ['hue'] ['saturation'] ['value'] - arguments for 'rainbow' command"
+                .to_string(),
+        );
     }
 
-    Status::SuccessNoSave
+    Ok((
+        number(0, 0, 359, "hue")?,
+        number(1, 100, 100, "saturation")?,
+        number(2, 100, 100, "value")?,
+    ))
 }
 
-fn info_command(device: &Device<GlobalContext>) -> Status {
-    println!("Device bus:   {}", device.bus_number());
-    println!("Device #:     {}", device.address());
-    println!("Device speed: {:?}", device.speed());
+fn rainbow_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (hue, saturation, value) = match rainbow_args(args) {
+        Ok(hsv) => hsv,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
 
-    // Bit hacky, directly outputs info
-    show_info(device);
+            return Ok(Status::BadArguments);
+        }
+    };
 
-    Status::SuccessNoSave
-}
+    let colours = rainbow_colours(hue, saturation, value, num_regions(device));
 
-fn help_command(_args: &[String]) -> Status {
-    const VERSION: &str = env!("CARGO_PKG_VERSION");
+    set_region_colours(device, &scale_colours(&colours))?;
 
-    println!(
-        "\ng213-cols - version {} ― Found a G213 keyboard ✅",
-        VERSION
-    );
+    Ok(Status::Success)
+}
 
-    println!(
-        r#"
-+ ----------------------------------------------------- + ------------------------------------------------------------- +
-| Command                                               | Details                                                       |
-| ----------------------------------------------------- + ------------------------------------------------------------- |
-| Set whole keyboard colour                             |                                                               |
-| `g213-cols colour`                                    | sets the default 'white'                                      |
-| `g213-cols colour ffff00`                             | sets the specified hex colour, eg yellow                      |
-|                                                       | NOTE: 3 digit hex colour conversion - 1af -> 11aaff           |
-| `g213-cols colour lawn green`                         | sets the named X11 colour                                     |
-| `g213-cols colour "alice blue"`                       | sets the named X11 colour - as a single argument              |
-| `g213-cols colour dark_slate_blue`                    | sets the named X11 colour - underscores become spaces         |
-| `g213-cols colour random`                             | sets a random colour                                          |
-| Set the colour of a specific keyboard region - 1 to 5 |                                                               |
-| `g213-cols region 2 [colour]`                         | sets the region to the [colour] as defined above              |
-| Set the colours of all 5 keyboard regions             |                                                               |
-| `g213-cols regions [colour colour ...]`               | sets the regions to the [colours] as defined above            |
-|                                                       | last supplied colour is repeated to set all 5 regions         |
-| Set 'breathe' mode                                    |                                                               |
-| `g213-cols breathe 1000 [colour]`                     | sets the breathe time (in ms) for the [colour]                |
-| Set 'cycle' mode                                      |                                                               |
-| `g213-cols cycle 1000`                                | sets the cycle time (in ms) all colours                       |
-| Use last successful saved command                     |                                                               |
-| `g213-cols`                                           | Runs the last successful saved command from ~/.g213-cols.json |
-| Show saved command                                    |                                                               |
-| `g213-cols saved`                                     | Display the last successful saved command                     |
-| Show keyboard info                                    |                                                               |
-| `g213-cols info`                                      | Show USB info for keyboard                                    |
-| List X11 colours                                      |                                                               |
-| `g213-cols list [name]`                               | Show X11 colours that contain optional [name] or all colours  |
-+ ----------------------------------------------------- + ------------------------------------------------------------- +
+// '5s', '1.5s', '500ms' or just '500', in milliseconds
+fn speed_millis(speed: &str) -> Option<f64> {
+    let speed = speed.trim().to_ascii_lowercase();
 
-+ ------- + ------------ +
-| Command | Abbreviation |
-| ------- + ------------ |
-| colour  | c            |
-| region  | r            |
-| regions | rs           |
-| breathe | b            |
-| cycle   | cy           |
-| saved   | s            |
-| info    | i            |
-| list    | l            |
-| help    | h or ?       |
-+ ------- + ------------ +
-"#
-    );
+    let (number, scale) = match speed.strip_suffix("ms") {
+        Some(number) => (number, 1.0),
+        None => match speed.strip_suffix('s') {
+            Some(number) => (number, 1000.0),
+            None => (speed.as_str(), 1.0),
+        },
+    };
 
-    Status::SuccessNoSave
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite() && *number >= 0.0)
+        .map(|number| (number * scale).round())
 }
 
-#[cfg(test)]
-mod commands_tests {
+/// A speed in milliseconds, like 5000, 5s, 1.5s or 500ms, or 'slow', 'medium'
+/// or 'fast' as set in config.toml.
+///
+/// Plain numbers below the keyboard's fastest are left for `limit_speed`, anything
+/// else has to be one it can do.
+pub fn parse_speed(speed: &str) -> Result<u16, String> {
+    if let Some(speed) = config().speeds.speed(speed) {
+        return Ok(speed);
+    }
 
-    use super::*;
+    if let Ok(speed) = speed.parse::<u16>() {
+        return Ok(speed);
+    }
 
-    fn to_string_vec(words: Vec<&str>) -> Vec<String> {
-        words.iter().map(|s| s.to_string()).collect()
+    let Some(millis) = speed_millis(speed) else {
+        return Err(format!(
+            "'{}' isn't a speed, use {}ms to {}ms, like 5000, 5s or 500ms, or slow, medium or fast",
+            speed,
+            MIN_SPEED,
+            u16::MAX
+        ));
+    };
+
+    match (f64::from(MIN_SPEED)..=f64::from(u16::MAX)).contains(&millis) {
+        true => Ok(millis as u16),
+        false => Err(format!(
+            "'{}' is {}ms, speeds go from {}ms to {}ms",
+            speed,
+            millis,
+            MIN_SPEED,
+            u16::MAX
+        )),
     }
+}
 
-    #[test]
-    fn colour_command() {
-        let args = to_string_vec(vec!["colour"]);
+// A speed the keyboard can do, or the command fails saying why
+fn command_speed(speed: &str, name: &str) -> Result<u16, Status> {
+    parse_speed(speed).map(limit_speed).map_err(|err| {
+        report_error(
+            &format!("Bad speed for '{}': {}", name, err),
+            Status::BadArguments,
+        );
 
-        let cmd = get_command(&args);
+        Status::BadArguments
+    })
+}
 
-        assert!(match cmd {
-            Command::Colour(_) => true,
-            _ => false,
-        });
+fn breathe_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let mut status = Status::BadArguments;
 
-        assert!(!cmd.has_args());
-    }
+    if !args.is_empty() {
+        let speed = match command_speed(&args[0], "breathe") {
+            Ok(speed) => speed,
+            Err(status) => return Ok(status),
+        };
 
-    #[test]
-    fn colour_command_with_args() {
-        let args = to_string_vec(vec!["colour", "0xff00ff"]);
+        let (colour, col_status) = get_colour_or_red(&args[1..]);
 
-        let cmd = get_command(&args);
+        set_breathe(device, speed, colour)?;
 
-        assert!(match cmd {
-            Command::Colour(_) => true,
-            _ => false,
-        });
+        status = col_status;
+    } else {
+        report_error(
+            &"At least one - 'speed' ['colour'] - argument needed for 'breathe' command",
+            Status::BadArguments,
+        );
+    }
 
-        assert!(cmd.has_args());
+    Ok(status)
+}
+
+fn cycle_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let mut status = Status::BadArguments;
+
+    let speed = match args {
+        [] => config().defaults.speed.map(limit_speed),
+        [speed] => match command_speed(speed, "cycle") {
+            Ok(speed) => Some(speed),
+            Err(status) => return Ok(status),
+        },
+        _ => None,
+    };
+
+    if let Some(speed) = speed {
+        set_cycle(device, speed, get_brightness())?;
+
+        status = Status::Success;
+    } else {
+        report_error(
+            &"One 'speed' argument, or a default one in config.toml, needed for 'cycle' command",
+            Status::BadArguments,
+        );
+    }
+
+    Ok(status)
+}
+
+fn wave_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let mut status = Status::BadArguments;
+
+    if !args.is_empty() {
+        let speed = match command_speed(&args[0], "wave") {
+            Ok(speed) => speed,
+            Err(status) => return Ok(status),
+        };
+
+        let (colour, col_status) = get_colour_or_red(&args[1..]);
+
+        eprintln!("Running wave, press Ctrl-C to stop");
+
+        run_wave(device, speed, colour)?;
+
+        status = col_status;
+    } else {
+        report_error(
+            &"At least one - 'speed' ['colour'] - argument needed for 'wave' command",
+            Status::BadArguments,
+        );
+    }
+
+    Ok(status)
+}
+
+#[derive(PartialEq, Debug)]
+struct Frames {
+    fps: u32,
+    cpu: u8,
+}
+
+// '--fps' and '--cpu' for effects that stream colours, and the other arguments
+fn frame_args(args: &[String], fps: u32) -> Result<(Frames, Vec<String>), String> {
+    let mut frames = Frames { fps, cpu: 100 };
+    let mut others = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let value = || {
+            args.clone()
+                .next()
+                .and_then(|value| value.parse::<u32>().ok())
+        };
+
+        if arg == FRAMES_FPS {
+            frames.fps = value()
+                .filter(|fps| (1..=60).contains(fps))
+                .ok_or("'--fps' needs 1 to 60 frames a second")?;
+        } else if arg == FRAMES_CPU {
+            frames.cpu = value()
+                .filter(|cpu| (1..=100).contains(cpu))
+                .ok_or("'--cpu' needs a budget of 1 to 100%")? as u8;
+        } else {
+            others.push(arg.clone());
+
+            continue;
+        }
+
+        args.next();
+    }
+
+    Ok((frames, others))
+}
+
+// The rest of the frame, or longer to keep the time spent working within the budget
+fn frame_wait(frames: &Frames, took: Duration) -> Duration {
+    let frame = Duration::from_secs_f64(1.0 / frames.fps as f64);
+    let budget = took.mul_f64(100.0 / frames.cpu as f64 - 1.0);
+
+    frame.saturating_sub(took).max(budget)
+}
+
+// Sends each frame's colours, when they change, until Ctrl-C or an error
+fn stream_colours(
+    device: &Keyboard,
+    frames: &Frames,
+    mut frame: impl FnMut(u8) -> Result<Vec<u32>, String>,
+) -> G213Result<Status> {
+    stream_colours_until(device, frames, |regions| frame(regions).map(Some))
+}
+
+// Until the held keyboard's plugged back in, false if it's Ctrl-C first
+fn wait_for_keyboard() -> bool {
+    eprintln!("Keyboard unplugged, waiting for it to come back");
+
+    while !interrupted() {
+        pause(Duration::from_secs(1));
+
+        if reopen_device().is_ok() {
+            eprintln!("Keyboard plugged back in");
+
+            return true;
+        }
+    }
+
+    false
+}
+
+// Runs a software effect until it's finished or Ctrl-C, then puts the
+// saved command back
+fn run_effect(device: &Keyboard, frames: &Frames, mut effect: impl Effect) -> G213Result<Status> {
+    let start = Instant::now();
+
+    // Still held for the saved command, in case it was plugged back in
+    hold_device(device)?;
+
+    let status = stream_colours_until(device, frames, |regions| {
+        Ok(effect.frame(start.elapsed(), regions))
+    })
+    .and_then(|status| on_command(device).map(|_| status));
+
+    // The effect's error matters more than one giving the keyboard back
+    let released = let_go_of_device();
+
+    let status = status?;
+
+    released.map(|_| status)
+}
+
+// As stream_colours, also stopping when there are no more frames
+fn stream_colours_until(
+    device: &Keyboard,
+    frames: &Frames,
+    mut frame: impl FnMut(u8) -> Result<Option<Vec<u32>>, String>,
+) -> G213Result<Status> {
+    let regions = num_regions(device);
+    let mut last = None;
+    let mut status = Status::SuccessNoSave;
+
+    hold_device(device)?;
+    catch_interrupt();
+
+    while !interrupted() {
+        let start = SystemTime::now();
+
+        let colours = match frame(regions) {
+            Ok(Some(colours)) => scale_colours(&colours),
+            Ok(None) => break,
+            Err(err) => {
+                status = Status::Failure;
+
+                report_error(&err, status);
+
+                break;
+            }
+        };
+
+        if last.as_ref() != Some(&colours) {
+            match set_region_colours(device, &colours) {
+                Ok(()) => last = Some(colours),
+                // The frame's sent again once it's back
+                Err(err) if unplugged(&err) && wait_for_keyboard() => (),
+                Err(err) => {
+                    let _ = let_go_of_device();
+
+                    return Err(err);
+                }
+            }
+        }
+
+        pause(frame_wait(frames, start.elapsed().unwrap_or_default()));
+    }
+
+    let_go_of_device()?;
+
+    Ok(status)
+}
+
+// Bias lighting, the keyboard following the colours on the screen above it
+fn ambient_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let frames = match frame_args(args, 5) {
+        Ok((frames, others)) if others.is_empty() => Ok(frames),
+        Ok(_) => Err("Only '--fps' and '--cpu' can be given to 'ambient'".to_string()),
+        Err(err) => Err(err),
+    };
+
+    let frames = match frames {
+        Ok(frames) => frames,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    eprintln!("Following the screen, press Ctrl-C to stop");
+
+    stream_colours(device, &frames, |regions| {
+        grab_screen().map(|image| strip_colours(&image, regions as usize))
+    })
+}
+
+// Bass to treble, red through to blue, unless the regions are given colours
+fn music_colours(args: &[String], regions: u8) -> Option<Vec<u32>> {
+    if args.is_empty() {
+        let last = (regions as f64 - 1.0).max(1.0);
+
+        return Some(
+            (0..regions)
+                .map(|region| hsv_to_rgb(240.0 * region as f64 / last, 100.0, 100.0))
+                .collect(),
+        );
+    }
+
+    get_x11_colours(args, regions).or_else(|| get_close_colours(args, regions))
+}
+
+// A region for each frequency band, as bright as the music playing in it
+fn music_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (frames, colours) = match frame_args(args, 30) {
+        Ok((frames, others)) => (frames, music_colours(&others, num_regions(device))),
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let Some(colours) = colours else {
+        return Ok(Status::BadArguments);
+    };
+
+    let mut audio = match Audio::start() {
+        Ok(audio) => audio,
+        Err(err) => {
+            report_error(&err, Status::Failure);
+
+            return Ok(Status::Failure);
+        }
+    };
+
+    let mut levels = Levels::new(colours.len());
+
+    eprintln!("Following the music, press Ctrl-C to stop");
+
+    stream_colours(device, &frames, |regions| {
+        let bands = band_levels(&audio.samples()?, regions as usize);
+
+        Ok(colours
+            .iter()
+            .zip(levels.update(&bands))
+            .map(|(colour, level)| scale_colour(*colour, (level * 100.0).round() as u32))
+            .collect())
+    })
+}
+
+#[derive(PartialEq, Debug)]
+struct TypingArgs {
+    decay: Duration,
+    input: Option<String>,
+    colour: Vec<String>,
+}
+
+fn typing_args(args: &[String]) -> Result<TypingArgs, String> {
+    let mut typing = TypingArgs {
+        decay: Duration::from_millis(500),
+        input: None,
+        colour: Vec::new(),
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == TYPING_DECAY {
+            typing.decay = args
+                .next()
+                .and_then(|ms| ms.parse::<u64>().ok())
+                .filter(|ms| (50..=10000).contains(ms))
+                .map(Duration::from_millis)
+                .ok_or("'--decay' needs 50 to 10000 ms")?;
+        } else if arg == TYPING_INPUT {
+            typing.input = Some(
+                args.next()
+                    .ok_or("'--input' needs an evdev device")?
+                    .clone(),
+            );
+        } else {
+            typing.colour.push(arg.clone());
+        }
+    }
+
+    Ok(typing)
+}
+
+// Each region lights up in the colour as its keys are pressed, then fades
+// back to the saved colours, or off
+fn typing_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let typing =
+        frame_args(args, 30).and_then(|(frames, others)| Ok((frames, typing_args(&others)?)));
+
+    let (frames, typing) = match typing {
+        Ok(typing) => typing,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let colour_args = or_default_colour(&typing.colour);
+
+    let glow = match colour_args.is_empty() {
+        true => Some(WHITE),
+        false => get_x11_colour(&colour_args).or_else(|| get_close_colour(&colour_args.join(" "))),
+    };
+
+    let Some(glow) = glow else {
+        return Ok(Status::BadArguments);
+    };
+
+    let keys = match typing.input {
+        Some(input) => Ok(input),
+        None => find_key_events(device.model()),
+    }
+    .and_then(|input| watch_keys(&input));
+
+    let keys = match keys {
+        Ok(keys) => keys,
+        Err(err) => {
+            report_error(&err, Status::Failure);
+
+            return Ok(Status::Failure);
+        }
+    };
+
+    let regions = num_regions(device);
+    let base = get_saved_command()
+        .and_then(|command| lighting_colours(&command))
+        .unwrap_or_else(|| vec![OFF; regions as usize]);
+    let mut glowing = Glow::new(regions, typing.decay);
+
+    eprintln!("Lighting up as keys are pressed, press Ctrl-C to stop");
+
+    stream_colours(device, &frames, |regions| {
+        let now = Instant::now();
+
+        loop {
+            match keys.try_recv() {
+                Ok(key) => {
+                    if let Some(region) = key_region(key, regions) {
+                        glowing.press(region, now);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err("The keyboard has gone".to_string()),
+            }
+        }
+
+        Ok(base
+            .iter()
+            .zip(glowing.levels(now))
+            .map(|(base, level)| mix_colours(*base, glow, level * 100.0))
+            .collect())
+    })
+}
+
+#[derive(PartialEq, Debug)]
+struct LoadArgs {
+    every: Duration,
+    usage: bool,
+    bar: bool,
+    colours: Vec<String>,
+}
+
+// How often a monitor checks, from 1 second to an hour, eg '--every 5'
+fn every_arg(value: Option<&String>) -> Result<Duration, String> {
+    value
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .filter(|secs| (1..=3600).contains(secs))
+        .map(Duration::from_secs)
+        .ok_or(format!("'{}' needs 1 to 3600 seconds", MONITOR_EVERY))
+}
+
+fn load_args(args: &[String]) -> Result<LoadArgs, String> {
+    let mut load = LoadArgs {
+        every: Duration::from_secs(2),
+        usage: false,
+        bar: false,
+        colours: Vec::new(),
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == MONITOR_EVERY {
+            load.every = every_arg(args.next())?;
+        } else if arg == LOAD_USAGE {
+            load.usage = true;
+        } else if arg == MONITOR_BAR {
+            load.bar = true;
+        } else {
+            load.colours.push(arg.clone());
+        }
+    }
+
+    Ok(load)
+}
+
+// A glanceable system monitor, checking the load every few seconds
+fn load_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let load_args = match load_args(args) {
+        Ok(load_args) => load_args,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let colours = &load_args.colours;
+
+    let ends = match colours.is_empty() {
+        true => None,
+        false => match get_x11_colours(colours, 2).or_else(|| get_close_colours(colours, 2)) {
+            Some(ends) => Some((ends[0], ends[1])),
+            None => return Ok(Status::BadArguments),
+        },
+    };
+
+    let mut load = Load::new(load_args.usage);
+    let mut checked: Option<(Instant, Vec<u32>)> = None;
+    let frames = Frames { fps: 1, cpu: 100 };
+
+    eprintln!("Showing the system load, press Ctrl-C to stop");
+
+    stream_colours(device, &frames, |regions| {
+        if let Some((at, colours)) = &checked {
+            if at.elapsed() < load_args.every {
+                return Ok(colours.clone());
+            }
+        }
+
+        let colours = load_colours(load.level()?, regions, load_args.bar, ends);
+
+        checked = Some((Instant::now(), colours.clone()));
+
+        Ok(colours)
+    })
+}
+
+#[derive(PartialEq, Debug)]
+struct BatteryArgs {
+    every: Duration,
+    low: u8,
+    bar: bool,
+}
+
+fn battery_args(args: &[String]) -> Result<BatteryArgs, String> {
+    let mut battery = BatteryArgs {
+        every: Duration::from_secs(10),
+        low: 20,
+        bar: false,
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == MONITOR_EVERY {
+            battery.every = every_arg(args.next())?;
+        } else if arg == MONITOR_LOW {
+            battery.low = args
+                .next()
+                .and_then(|low| low.parse::<u8>().ok())
+                .filter(|low| *low <= 100)
+                .ok_or("'--low' needs a charge of 0 to 100%")?;
+        } else if arg == MONITOR_BAR {
+            battery.bar = true;
+        } else {
+            return Err(format!("Unknown option '{}' for 'monitor battery'", arg));
+        }
+    }
+
+    Ok(battery)
+}
+
+// The charge, pulsing while it's low or charging
+fn battery_monitor(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let battery = match battery_args(args) {
+        Ok(battery) => battery,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let mut checked: Option<(Instant, Charge)> = None;
+    let started = Instant::now();
+    let frames = Frames { fps: 20, cpu: 100 };
+
+    eprintln!("Showing the battery charge, press Ctrl-C to stop");
+
+    stream_colours(device, &frames, |regions| {
+        let charge = match checked {
+            Some((at, charge)) if at.elapsed() < battery.every => charge,
+            _ => {
+                let charge = read_charge(Path::new(POWER_SUPPLY_DIR))?;
+
+                checked = Some((Instant::now(), charge));
+
+                charge
+            }
+        };
+
+        let colours = battery_colours(&charge, regions, battery.bar);
+
+        if !charge.charging && charge.percent >= battery.low {
+            return Ok(colours);
+        }
+
+        let brightness = (pulse(started.elapsed()) * 100.0).round() as u32;
+
+        Ok(colours
+            .iter()
+            .map(|colour| scale_colour(*colour, brightness))
+            .collect())
+    })
+}
+
+#[derive(PartialEq, Debug)]
+struct NetworkArgs {
+    every: Duration,
+    interface: Option<String>,
+    max: f64,
+    whole: bool,
+    colours: Vec<String>,
+}
+
+fn network_args(args: &[String]) -> Result<NetworkArgs, String> {
+    let mut network = NetworkArgs {
+        every: Duration::from_secs(1),
+        interface: None,
+        max: 1e7,
+        whole: false,
+        colours: Vec::new(),
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == MONITOR_EVERY {
+            network.every = every_arg(args.next())?;
+        } else if arg == NETWORK_INTERFACE {
+            network.interface = Some(args.next().ok_or("'--interface' needs a name")?.clone());
+        } else if arg == NETWORK_MAX {
+            network.max = args
+                .next()
+                .and_then(|max| parse_rate(max))
+                .ok_or("'--max' needs a rate in bytes a second, eg 500K or 10M")?;
+        } else if arg == NETWORK_WHOLE {
+            network.whole = true;
+        } else {
+            network.colours.push(arg.clone());
+        }
+    }
+
+    Ok(network)
+}
+
+// Downloads and uploads, brighter the faster they're going
+fn network_monitor(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let network = match network_args(args) {
+        Ok(network) => network,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let colours = &network.colours;
+
+    let (rx_colour, tx_colour) = match colours.is_empty() {
+        true => (0x00ff00, 0x0080ff),
+        false => match get_x11_colours(colours, 2).or_else(|| get_close_colours(colours, 2)) {
+            Some(ends) => (ends[0], ends[1]),
+            None => return Ok(Status::BadArguments),
+        },
+    };
+
+    let mut throughput = Throughput::new(network.interface.clone());
+    let mut checked: Option<(Instant, Vec<u32>)> = None;
+    let frames = Frames { fps: 1, cpu: 100 };
+
+    eprintln!("Showing the network traffic, press Ctrl-C to stop");
+
+    stream_colours(device, &frames, |regions| {
+        if let Some((at, colours)) = &checked {
+            if at.elapsed() < network.every {
+                return Ok(colours.clone());
+            }
+        }
+
+        let colours = network_colours(
+            throughput.rates()?,
+            network.max,
+            regions,
+            network.whole,
+            (rx_colour, tx_colour),
+        );
+
+        checked = Some((Instant::now(), colours.clone()));
+
+        Ok(colours)
+    })
+}
+
+// Things to keep an eye on, shown until Ctrl-C
+fn monitor_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    match args.split_first() {
+        Some((kind, args)) if kind == MONITOR_BATTERY => battery_monitor(device, args),
+        Some((kind, args)) if kind == MONITOR_NETWORK => network_monitor(device, args),
+        _ => {
+            report_error(
+                &"'battery' or 'network' needed for 'monitor' command",
+                Status::BadArguments,
+            );
+
+            Ok(Status::BadArguments)
+        }
+    }
+}
+
+// Minutes of work and of each break, 25 and 5 by default
+fn pomodoro_args(args: &[String]) -> Result<(Duration, Duration), String> {
+    let minutes = |arg: Option<&String>, default: u64, most: u64, what: &str| match arg {
+        None => Ok(Duration::from_secs(default * 60)),
+        Some(arg) => arg
+            .parse::<u64>()
+            .ok()
+            .filter(|minutes| (1..=most).contains(minutes))
+            .map(|minutes| Duration::from_secs(minutes * 60))
+            .ok_or(format!("The {} needs 1 to {} minutes", what, most)),
+    };
+
+    if args.len() > 2 {
+        return Err("Only the minutes of work and of each break can be given".to_string());
+    }
+
+    Ok((
+        minutes(args.first(), 25, 180, "work")?,
+        minutes(args.get(1), 5, 60, "break")?,
+    ))
+}
+
+// Work and breaks until Ctrl-C, then back to the saved command
+fn pomodoro_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (work, rest) = match pomodoro_args(args) {
+        Ok(minutes) => minutes,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let frames = Frames { fps: 20, cpu: 100 };
+    let mut phase = None;
+
+    eprintln!("Pomodoro timer running, press Ctrl-C to stop");
+
+    run_effect(device, &frames, |elapsed, regions| {
+        let (now, _) = pomodoro_phase(elapsed, work, rest);
+
+        if phase.replace(now) != Some(now) {
+            match now {
+                Phase::Work => eprintln!("Work for {} minutes", work.as_secs() / 60),
+                Phase::Break => eprintln!("Break for {} minutes", rest.as_secs() / 60),
+            }
+        }
+
+        Some(pomodoro_colours(elapsed, work, rest, regions))
+    })
+}
+
+// Counts down on the regions, then flashes, and goes back to the saved command
+fn timer_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let Some((length, colour)) = args.split_first() else {
+        report_error(
+            &"At least one - 'duration' ['colour'] - argument needed for 'timer' command",
+            Status::BadArguments,
+        );
+
+        return Ok(Status::BadArguments);
+    };
+
+    let Some(length) = parse_duration(length) else {
+        report_error(
+            &format!(
+                "Bad duration '{}', use eg 90s, 25m, 1h30m, up to 24h",
+                length
+            ),
+            Status::BadArguments,
+        );
+
+        return Ok(Status::BadArguments);
+    };
+
+    let colour = match colour {
+        [] => WHITE,
+        colour => match get_x11_colour(colour).or_else(|| get_close_colour(&colour.join(" "))) {
+            Some(colour) => colour,
+            None => return Ok(Status::BadArguments),
+        },
+    };
+
+    let frames = Frames { fps: 10, cpu: 100 };
+    let mut up = false;
+
+    eprintln!("Timer running, press Ctrl-C to stop");
+
+    run_effect(device, &frames, |elapsed, regions| {
+        if elapsed >= length && !up {
+            up = true;
+
+            eprintln!("Time's up");
+        }
+
+        countdown_colours(elapsed, length, colour, regions)
+    })
+}
+
+// Milliseconds between changes for effects that flash
+fn flash_interval(interval: &str, name: &str) -> Result<Duration, String> {
+    millis_interval(interval.parse::<u64>().ok(), name)
+}
+
+// 'cycle-list' and 'pulse' take a speed, so 'slow', 'medium' and 'fast' too
+fn speed_interval(speed: &str, name: &str) -> Result<Duration, String> {
+    millis_interval(parse_speed(speed).ok().map(u64::from), name)
+}
+
+fn millis_interval(interval: Option<u64>, name: &str) -> Result<Duration, String> {
+    interval
+        .map(Duration::from_millis)
+        .filter(|interval| (MIN_BLINK..=Duration::from_secs(60)).contains(interval))
+        .ok_or(format!(
+            "'{}' needs an interval of {} to 60000ms, faster flashing can trigger seizures",
+            name,
+            MIN_BLINK.as_millis()
+        ))
+}
+
+#[derive(PartialEq, Debug)]
+struct BlinkArgs {
+    frames: Frames,
+    interval: Duration,
+    colour: Vec<String>,
+    count: Option<u32>,
+}
+
+// The interval, the colour, and how many blinks if the last word's a number
+fn blink_args(args: &[String]) -> Result<BlinkArgs, String> {
+    let (frames, args) = frame_args(args, 20)?;
+
+    let Some((interval, rest)) = args.split_first() else {
+        return Err(
+            "At least two - 'interval' 'colour' ['count'] - arguments needed for 'blink' command"
+                .to_string(),
+        );
+    };
+
+    let interval = flash_interval(interval, "blink")?;
+
+    let (colour, count) = match rest.split_last() {
+        Some((count, colour)) if !colour.is_empty() && count.parse::<u32>().is_ok() => {
+            let count = count.parse::<u32>().ok().filter(|count| *count > 0);
+
+            (
+                colour.to_vec(),
+                Some(count.ok_or("'blink' needs at least 1 blink")?),
+            )
+        }
+        _ => (rest.to_vec(), None),
+    };
+
+    Ok(BlinkArgs {
+        frames,
+        interval,
+        colour,
+        count,
+    })
+}
+
+// Blinks in software, then goes back to the saved command
+fn blink_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let blink = match blink_args(args) {
+        Ok(blink) => blink,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let colour = or_default_colour(&blink.colour);
+
+    let colour = match get_x11_colour(&colour).or_else(|| get_close_colour(&colour.join(" "))) {
+        Some(colour) => colour,
+        None => return Ok(Status::BadArguments),
+    };
+
+    if blink.count.is_none() {
+        eprintln!("Blinking, press Ctrl-C to stop");
+    }
+
+    run_effect(device, &blink.frames, |elapsed, regions| {
+        blink_colours(elapsed, blink.interval, blink.count, colour, regions)
+    })
+}
+
+// Police lights, red and blue unless other colours are given
+fn alternate_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (frames, args) = match frame_args(args, 20) {
+        Ok(frames) => frames,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let interval = match args
+        .first()
+        .map(|interval| flash_interval(interval, "alternate"))
+    {
+        Some(Ok(interval)) => interval,
+        Some(Err(err)) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+        None => {
+            report_error(
+                &"At least one - 'interval' ['colour1' 'colour2'] - argument needed for 'alternate' command",
+                Status::BadArguments,
+            );
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let colours = match &args[1..] {
+        [] => (RED, BLUE),
+        names => match get_x11_colours(names, 2).or_else(|| get_close_colours(names, 2)) {
+            Some(colours) => (colours[0], colours[1]),
+            None => return Ok(Status::BadArguments),
+        },
+    };
+
+    eprintln!("Alternating, press Ctrl-C to stop");
+
+    run_effect(device, &frames, |elapsed, regions| {
+        Some(alternate_colours(elapsed, interval, colours, regions))
+    })
+}
+
+// A number from 1 to 100 after an option, eg '--speed 50'
+fn percent_arg(value: Option<&String>, option: &str) -> Result<u8, String> {
+    value
+        .and_then(|value| value.parse::<u8>().ok())
+        .filter(|value| (1..=100).contains(value))
+        .ok_or(format!("'{}' needs 1 to 100", option))
+}
+
+// '--intensity' and '--speed', both 70 by default, as well as '--fps' and '--cpu'
+fn fire_args(args: &[String]) -> Result<(Frames, u8, u8), String> {
+    let (frames, others) = frame_args(args, 15)?;
+    let (mut intensity, mut speed) = (70, 70);
+    let mut others = others.iter();
+
+    while let Some(arg) = others.next() {
+        if arg == FIRE_INTENSITY {
+            intensity = percent_arg(others.next(), FIRE_INTENSITY)?;
+        } else if arg == FIRE_SPEED {
+            speed = percent_arg(others.next(), FIRE_SPEED)?;
+        } else {
+            return Err(format!("Unknown option '{}' for 'fire'", arg));
+        }
+    }
+
+    Ok((frames, intensity, speed))
+}
+
+fn fire_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (frames, intensity, speed) = match fire_args(args) {
+        Ok(fire) => fire,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    eprintln!("Burning, press Ctrl-C to stop");
+
+    run_effect(
+        device,
+        &frames,
+        Fire::new(num_regions(device), intensity, speed),
+    )
+}
+
+// The colour, and how often regions sparkle if the last word's a number
+fn sparkle_args(args: &[String]) -> Result<(Frames, Vec<String>, u8), String> {
+    let (frames, args) = frame_args(args, 10)?;
+
+    match args.split_last() {
+        Some((density, colour)) if density.parse::<u8>().is_ok() => Ok((
+            frames,
+            colour.to_vec(),
+            percent_arg(Some(density), "density")?,
+        )),
+        _ => Ok((frames, args, 20)),
+    }
+}
+
+fn sparkle_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (frames, colour, density) = match sparkle_args(args) {
+        Ok(sparkle) => sparkle,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let colour = match or_default_colour(&colour) {
+        colour if colour.is_empty() => WHITE,
+        colour => match get_x11_colour(&colour).or_else(|| get_close_colour(&colour.join(" "))) {
+            Some(colour) => colour,
+            None => return Ok(Status::BadArguments),
+        },
+    };
+
+    eprintln!("Sparkling, press Ctrl-C to stop");
+
+    run_effect(device, &frames, Sparkle::new(colour, density))
+}
+
+// Fades through the colours in software, as 'cycle' can't be given them
+fn cycle_list_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (frames, args) = match frame_args(args, 30) {
+        Ok(frames) => frames,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let Some((interval, names)) = args.split_first() else {
+        report_error(
+            &"At least three - 'speed' 'colour' 'colour' ... - arguments needed for 'cycle-list' command",
+            Status::BadArguments,
+        );
+
+        return Ok(Status::BadArguments);
+    };
+
+    let colours = speed_interval(interval, "cycle-list").and_then(|interval| {
+        match get_x11_colour_list(names) {
+            Some(colours) if colours.len() >= 2 => Ok((interval, colours)),
+            Some(_) => Err("'cycle-list' needs at least two colours".to_string()),
+            None => Err(format!("Unable to read the colours '{}'", names.join(" "))),
+        }
+    });
+
+    let (interval, colours) = match colours {
+        Ok(colours) => colours,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    eprintln!("Cycling, press Ctrl-C to stop");
+
+    run_effect(device, &frames, |elapsed, regions| {
+        Some(cross_fade_colours(elapsed, interval, &colours, regions))
+    })
+}
+
+#[derive(PartialEq, Debug)]
+struct PulseArgs {
+    frames: Frames,
+    period: Duration,
+    colour: Vec<String>,
+    min: u8,
+    max: u8,
+}
+
+// The speed, the colour, then the lowest and highest brightness if the last
+// words are numbers, 20 and 100 by default
+fn pulse_args(args: &[String]) -> Result<PulseArgs, String> {
+    let (frames, args) = frame_args(args, 30)?;
+
+    let Some((period, rest)) = args.split_first() else {
+        return Err(
+            "At least one - 'speed' ['colour'] ['min%'] ['max%'] - argument needed for 'pulse' command"
+                .to_string(),
+        );
+    };
+
+    let period = speed_interval(period, "pulse")?;
+
+    let numbers = rest
+        .iter()
+        .rev()
+        .take(2)
+        .take_while(|arg| arg.parse::<u8>().is_ok())
+        .count();
+
+    let (colour, levels) = rest.split_at(rest.len() - numbers);
+    let levels: Vec<u8> = levels
+        .iter()
+        .filter_map(|level| level.parse().ok())
+        .collect();
+
+    let (min, max) = match levels[..] {
+        [min, max] => (min, max),
+        [min] => (min, 100),
+        _ => (20, 100),
+    };
+
+    if min >= max || max > 100 {
+        return Err("'pulse' needs a lowest brightness below the highest, up to 100%".to_string());
+    }
+
+    Ok(PulseArgs {
+        frames,
+        period,
+        colour: colour.to_vec(),
+        min,
+        max,
+    })
+}
+
+// Breathing that never goes dark, unlike the keyboard's own
+fn pulse_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let pulse = match pulse_args(args) {
+        Ok(pulse) => pulse,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    let colour = match or_default_colour(&pulse.colour) {
+        colour if colour.is_empty() => WHITE,
+        colour => match get_x11_colour(&colour).or_else(|| get_close_colour(&colour.join(" "))) {
+            Some(colour) => colour,
+            None => return Ok(Status::BadArguments),
+        },
+    };
+
+    eprintln!("Pulsing, press Ctrl-C to stop");
+
+    run_effect(device, &pulse.frames, |elapsed, regions| {
+        let level = pulse_level(elapsed, pulse.period, pulse.min, pulse.max);
+
+        Some(vec![scale_colour(colour, level); regions as usize])
+    })
+}
+
+// Digital rain, ticking 10 times a second unless '--fps' says otherwise
+fn matrix_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let frames = match frame_args(args, 10) {
+        Ok((frames, others)) if others.is_empty() => Ok(frames),
+        Ok(_) => Err("Only '--fps' and '--cpu' can be given to 'matrix'".to_string()),
+        Err(err) => Err(err),
+    };
+
+    let frames = match frames {
+        Ok(frames) => frames,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    eprintln!("Raining, press Ctrl-C to stop");
+
+    run_effect(device, &frames, Matrix::default())
+}
+
+fn modified_time(file: &str) -> Option<SystemTime> {
+    metadata(file).and_then(|meta| meta.modified()).ok()
+}
+
+fn apply_wal(device: &Keyboard, file: &str) -> G213Result<Status> {
+    let colours = match read_to_string(file) {
+        Ok(theme) => wal_colours(&theme, num_regions(device)),
+        Err(err) => Err(format!("Unable to read {}: {}", file, err)),
+    };
+
+    match colours {
+        Ok(colours) => {
+            set_region_colours(device, &scale_colours(&colours))?;
+
+            Ok(Status::Success)
+        }
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            Ok(Status::BadArguments)
+        }
+    }
+}
+
+// Watching never finishes, so isn't saved, just like 'wave'
+fn wal_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let watching = args.iter().any(|arg| arg == WAL_WATCH);
+    let file = match args.iter().find(|arg| *arg != WAL_WATCH) {
+        Some(file) => file.clone(),
+        None => wal_file(),
+    };
+
+    let status = apply_wal(device, &file)?;
+
+    if !watching || !status.successful() {
+        return Ok(status);
+    }
+
+    eprintln!("Watching {}, press Ctrl-C to stop", file);
+
+    catch_interrupt();
+
+    let mut modified = modified_time(&file);
+
+    while !interrupted() {
+        sleep(Duration::from_millis(WAL_POLL_MS));
+
+        let now = modified_time(&file);
+
+        // A half written theme is reported, and picked up once it's finished
+        if now != modified {
+            modified = now;
+
+            apply_wal(device, &file)?;
+        }
+    }
+
+    Ok(Status::SuccessNoSave)
+}
+
+// The colour names to use, and '--file' to read instead of asking xrdb
+fn xresources_args(args: &[String]) -> (Vec<String>, Option<String>) {
+    let mut names = Vec::new();
+    let mut file = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == XRESOURCES_FILE {
+            file = args.next().cloned();
+        } else {
+            names.push(arg.clone());
+        }
+    }
+
+    if names.is_empty() {
+        names = (1..=5).map(|n| format!("color{}", n)).collect();
+    }
+
+    (names, file)
+}
+
+fn xresources_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (names, file) = xresources_args(args);
+
+    let colours = read_xresources(file.as_deref()).and_then(|resources| {
+        pick_colours(&xresources_colours(&resources), &names, num_regions(device))
+    });
+
+    match colours {
+        Ok(colours) => {
+            set_region_colours(device, &scale_colours(&colours))?;
+
+            Ok(Status::Success)
+        }
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            Ok(Status::BadArguments)
+        }
+    }
+}
+
+// Not saved, so the saved command still holds the state for 'on' to restore
+fn off_command(device: &Keyboard) -> G213Result<Status> {
+    set_keyboard_colour(device, OFF)?;
+
+    Ok(Status::SuccessNoSave)
+}
+
+fn on_command(device: &Keyboard) -> G213Result<Status> {
+    match get_saved_command() {
+        Some(cmd) => Ok(match cmd.run(device) {
+            Status::Success => Status::SuccessNoSave,
+            status => status,
+        }),
+        None => {
+            let (colour, _) = get_colour_or_red(&[]);
+
+            set_keyboard_colour(device, colour)?;
+
+            Ok(Status::SuccessNoSave)
+        }
+    }
+}
+
+fn brightness_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let mut status = Status::BadArguments;
+
+    if args.is_empty() {
+        println!("Brightness: {}%", get_brightness());
+
+        status = Status::SuccessNoSave;
+    } else if let Ok(brightness) = args[0].parse::<u8>() {
+        save_brightness(brightness.min(MAX_BRIGHTNESS));
+
+        // Re-apply the saved command at the new brightness
+        status = reapply_saved_command(device);
+    } else {
+        report_error(
+            &"A 'brightness' (0 to 100) argument is needed for 'brightness' command",
+            Status::BadArguments,
+        );
+    }
+
+    Ok(status)
+}
+
+fn reapply_saved_command(device: &Keyboard) -> Status {
+    match get_saved_command() {
+        Some(cmd) => match cmd.run(device) {
+            Status::Success => Status::SuccessNoSave,
+            status => status,
+        },
+        None => Status::SuccessNoSave,
+    }
+}
+
+fn calibrate_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let serial = calibration_serial(device);
+
+    let white = match args {
+        [] => {
+            let [red, green, blue] = white_point(&load_calibrations(), Some(&serial));
+
+            if json_output() {
+                print_json(&json!({ "serial": serial, "white": [red, green, blue] }));
+            } else {
+                println!("{}: {}% red, {}% green, {}% blue", serial, red, green, blue);
+            }
+
+            return Ok(Status::SuccessNoSave);
+        }
+        [reset] if reset == CALIBRATE_RESET => None,
+        levels => match parse_white_point(levels) {
+            Ok(white) => Some(white),
+            Err(err) => {
+                report_error(&err, Status::BadArguments);
+
+                return Ok(Status::BadArguments);
+            }
+        },
+    };
+
+    save_white_point(&serial, white);
+    reload_calibration();
+
+    // Re-apply the saved command to see the difference
+    Ok(reapply_saved_command(device))
+}
+
+// Hex may be split across arguments, eg 11ff0c3a 0001 ff0000 ...
+fn raw_hex(args: &[String]) -> (String, bool) {
+    let confirmed = args.iter().any(|arg| arg == RAW_CONFIRM);
+
+    let hex = args
+        .iter()
+        .filter(|arg| *arg != RAW_CONFIRM)
+        .map(|arg| arg.replace(' ', ""))
+        .collect();
+
+    (hex, confirmed)
+}
+
+// Never saved, the keyboard may not be in a state worth restoring
+fn raw_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let (hex, confirmed) = raw_hex(args);
+
+    if hex.is_empty() {
+        report_error(
+            &"40 hex digits are needed for 'raw' command",
+            Status::BadArguments,
+        );
+
+        return Ok(Status::BadArguments);
+    }
+
+    if !confirmed {
+        report_error(
+            &format!(
+                "Raw commands can confuse the keyboard, add '{}' to send it anyway",
+                RAW_CONFIRM
+            ),
+            Status::BadArguments,
+        );
+
+        return Ok(Status::BadArguments);
+    }
+
+    let reply = send_raw(device, &hex)?;
+
+    println!(
+        "Reply: {}",
+        reply
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+
+    Ok(Status::SuccessNoSave)
+}
+
+fn replay_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    if args.len() != 1 {
+        report_error(
+            &"One 'file' argument, written by '--trace', needed for 'replay' command",
+            Status::BadArguments,
+        );
+
+        return Ok(Status::BadArguments);
+    }
+
+    let transfers = match std::fs::read_to_string(&args[0]) {
+        Ok(trace) => parse_trace(&trace),
+        Err(err) => Err(format!("Unable to read {}: {}", args[0], err)),
+    };
+
+    match transfers {
+        Ok(transfers) => {
+            replay(device, &transfers)?;
+
+            Ok(Status::SuccessNoSave)
+        }
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            Ok(Status::BadArguments)
+        }
+    }
+}
+
+// Waits between the steps of a script, stopping early for Ctrl-C
+fn pause(wait: Duration) {
+    let start = SystemTime::now();
+    let poll = Duration::from_millis(100);
+
+    while !interrupted() {
+        match start
+            .elapsed()
+            .ok()
+            .and_then(|elapsed| wait.checked_sub(elapsed))
+        {
+            Some(left) if !left.is_zero() => sleep(left.min(poll)),
+            _ => break,
+        }
+    }
+}
+
+// Keeps hold of the keyboard for the whole script, rather than for each command
+fn script_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    if args.len() != 1 {
+        report_error(
+            &"One 'file' argument, of commands to run, needed for 'run' command",
+            Status::BadArguments,
+        );
+
+        return Ok(Status::BadArguments);
+    }
+
+    let steps = match read_to_string(&args[0]) {
+        Ok(script) => parse_script(&script),
+        Err(err) => Err(format!("Unable to read {}: {}", args[0], err)),
+    };
+
+    let steps = match steps {
+        Ok(steps) => steps,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Ok(Status::BadArguments);
+        }
+    };
+
+    hold_device(device)?;
+    catch_interrupt();
+
+    let mut status = Status::SuccessNoSave;
+
+    for step in &steps {
+        if interrupted() {
+            break;
+        }
+
+        match step {
+            Step::Run(command) => {
+                let ran = command.run(device);
+
+                if !ran.successful() {
+                    status = ran;
+
+                    break;
+                }
+            }
+            Step::Sleep(wait) => pause(*wait),
+        }
+    }
+
+    let_go_of_device()?;
+
+    Ok(status)
+}
+
+// As 'run' for commands sent by another program, carrying on after bad ones
+fn pipe_command(device: &Keyboard) -> G213Result<Status> {
+    hold_device(device)?;
+    catch_interrupt();
+
+    // Read on another thread, so Ctrl-C isn't stuck waiting for the next line
+    let (sender, lines) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut status = Status::SuccessNoSave;
+    let mut number = 0;
+
+    while !interrupted() {
+        let line = match lines.recv_timeout(Duration::from_millis(100)) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        number += 1;
+
+        match parse_step(&line) {
+            Ok(Some(Step::Run(command))) => {
+                let ran = command.run(device);
+
+                if !ran.successful() {
+                    status = ran;
+                }
+            }
+            Ok(Some(Step::Sleep(wait))) => pause(wait),
+            Ok(None) => (),
+            Err(err) => {
+                status = Status::BadArguments;
+
+                report_error(
+                    &format!("Bad line {}: {}, {}", number, line.trim(), err),
+                    status,
+                );
+            }
+        }
+    }
+
+    let_go_of_device()?;
+
+    Ok(status)
+}
+
+const NO_SAVED_COMMAND: &str = "No saved command yet, see 'g213-cols help'";
+
+fn no_such_profile(name: &str) -> String {
+    format!("No profile or slot called '{}', see 'profile list'", name)
+}
+
+fn profile_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    let action = args.first().map(String::as_str);
+    let name = args.get(1);
+
+    let status = match (action, name) {
+        (Some("list"), None) => profile_list(),
+        (Some("save"), Some(name)) => profile_save(name),
+        (Some(PROFILE_LOAD), Some(name)) => run_profile(device, name),
+        (Some("delete"), Some(name)) => profile_delete(name),
+        _ => {
+            report_error(
+                &"'save name', 'load name', 'delete name' or 'list' needed for 'profile' command",
+                Status::BadArguments,
+            );
+
+            Status::BadArguments
+        }
+    };
+
+    Ok(status)
+}
+
+// Like 'on', the profile's command isn't saved when run from here
+fn run_profile(device: &Keyboard, name: &str) -> Status {
+    match get_profile(name) {
+        Some(cmd) => match cmd.run(device) {
+            Status::Success => Status::SuccessNoSave,
+            status => status,
+        },
+        None => {
+            report_error(&no_such_profile(name), Status::BadArguments);
+
+            Status::BadArguments
+        }
+    }
+}
+
+fn apply_command(device: &Keyboard, args: &[String]) -> G213Result<Status> {
+    if args.is_empty() {
+        return Ok(match get_saved_command() {
+            Some(command) => match command.run(device) {
+                Status::Success => Status::SuccessNoSave,
+                status => status,
+            },
+            None => {
+                report_error(&NO_SAVED_COMMAND, Status::Failure);
+
+                Status::Failure
+            }
+        });
+    }
+
+    if args.len() != 1 {
+        report_error(
+            &"At most one 'slot' argument, saved to with '--slot', for 'apply' command",
+            Status::BadArguments,
+        );
+
+        return Ok(Status::BadArguments);
+    }
+
+    Ok(run_profile(device, &args[0]))
+}
+
+fn profile_list() -> Status {
+    let profiles = load_profiles();
+
+    if json_output() {
+        let profiles: serde_json::Map<String, serde_json::Value> = profiles
+            .iter()
+            .map(|(name, cmd)| (name.clone(), json!(cmd.to_string())))
+            .collect();
+
+        print_json(&profiles);
+    } else if profiles.is_empty() {
+        println!("No profiles saved yet, see 'profile save'");
+    } else {
+        for (name, cmd) in &profiles {
+            println!("{}: {}", name, cmd);
+        }
+    }
+
+    Status::SuccessNoSave
+}
+
+fn profile_save(name: &str) -> Status {
+    let command = match get_saved_command() {
+        Some(command) => command,
+        None => {
+            report_error(
+                &"No saved command to keep as a profile yet",
+                Status::Failure,
+            );
+
+            return Status::Failure;
+        }
+    };
+
+    eprintln!("Saved profile {}: {}", name, command);
+
+    save_profile(name, &command);
+
+    Status::SuccessNoSave
+}
+
+fn profile_delete(name: &str) -> Status {
+    let mut profiles = load_profiles();
+
+    if profiles.remove(name).is_none() {
+        report_error(&no_such_profile(name), Status::BadArguments);
+
+        return Status::BadArguments;
+    }
+
+    save_profiles(&profiles);
+
+    Status::SuccessNoSave
+}
+
+fn palette_command(args: &[String]) -> Status {
+    match args {
+        [action, file] if action == "import" => palette_import(file),
+        [action] if action == "list" => palette_list(),
+        _ => {
+            report_error(
+                &"'import file.gpl' or 'list' needed for 'palette' command",
+                Status::BadArguments,
+            );
+
+            Status::BadArguments
+        }
+    }
+}
+
+// Colours with the same name as ones already imported replace them
+fn palette_import(file: &str) -> Status {
+    let imported = match read_to_string(file) {
+        Ok(gpl) => parse_gpl(&gpl),
+        Err(err) => Err(format!("Unable to read {}: {}", file, err)),
+    };
+
+    match imported {
+        Ok(imported) => {
+            let mut palette = load_palette();
+
+            eprintln!("Imported {} colours from {}", imported.len(), file);
+
+            palette.extend(imported);
+            save_palette(&palette);
+
+            Status::SuccessNoSave
+        }
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            Status::BadArguments
+        }
+    }
+}
+
+fn palette_list() -> Status {
+    let palette = load_palette();
+
+    if json_output() {
+        let colours: Vec<_> = palette
+            .iter()
+            .map(|(name, colour)| json!({ "name": name, "colour": format!("{:#08x}", colour) }))
+            .collect();
+
+        print_json(&colours);
+    } else if palette.is_empty() {
+        println!("No palette colours imported yet, see 'palette import'");
+    } else {
+        for (name, colour) in &palette {
+            println!("{} {:#08x}", name, colour);
+        }
+    }
+
+    Status::SuccessNoSave
+}
+
+// Kept as a profile, named after the file unless a name is given
+fn import_openrgb_command(args: &[String]) -> Status {
+    let (file, name) = match args {
+        [file] => (
+            file,
+            Path::new(file)
+                .file_stem()
+                .map_or(file.clone(), |stem| stem.to_string_lossy().to_string()),
+        ),
+        [file, name] => (file, name.clone()),
+        _ => {
+            report_error(
+                &"'file.orp' ['name'] needed for 'import-openrgb' command",
+                Status::BadArguments,
+            );
+
+            return Status::BadArguments;
+        }
+    };
+
+    let imported = read(file)
+        .map_err(|err| format!("Unable to read {}: {}", file, err))
+        .and_then(|orp| parse_profile(&orp))
+        .and_then(|controllers| controller_command(find_g213(&controllers)?));
+
+    match imported {
+        Ok(command) => {
+            save_profile(&name, &command);
+
+            eprintln!("Imported profile {}: {}", name, command);
+
+            Status::SuccessNoSave
+        }
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            Status::BadArguments
+        }
+    }
+}
+
+// The saved command, or a profile's
+fn export_openrgb_command(args: &[String]) -> Status {
+    let (file, command) = match args {
+        [file] => (
+            file,
+            get_saved_command().ok_or_else(|| "No saved command to export".to_string()),
+        ),
+        [file, name] => (file, get_profile(name).ok_or_else(|| no_such_profile(name))),
+        _ => {
+            report_error(
+                &"'file.orp' ['profile'] needed for 'export-openrgb' command",
+                Status::BadArguments,
+            );
+
+            return Status::BadArguments;
+        }
+    };
+
+    let exported = command
+        .and_then(|command| command_controller(&command))
+        .and_then(|controller| {
+            write(file, write_profile(&controller))
+                .map_err(|err| format!("Unable to write {}: {}", file, err))
+        });
+
+    match exported {
+        Ok(()) => {
+            set_file_ownership_to_me(file.clone());
+
+            Status::SuccessNoSave
+        }
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            Status::BadArguments
+        }
+    }
+}
+
+fn install_service_command(args: &[String]) -> Status {
+    let kind = if args.is_empty() { "user" } else { &args[0] };
+
+    match kind.to_lowercase().as_str() {
+        "user" => install_service(ServiceKind::User),
+        "system" => install_service(ServiceKind::System),
+        _ => {
+            report_error(
+                &"Only 'user' or 'system' services can be installed",
+                Status::BadArguments,
+            );
+
+            Status::BadArguments
+        }
+    }
+}
+
+fn install_udev_command(args: &[String]) -> Status {
+    let group = if args.is_empty() {
+        UDEV_GROUP
+    } else {
+        &args[0]
+    };
+
+    install_udev(group)
+}
+
+#[derive(PartialEq, Debug)]
+enum ListFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(PartialEq, Debug)]
+enum ListSort {
+    Hue,
+    Lightness,
+    Name,
+}
+
+#[derive(PartialEq, Debug, Default)]
+struct ListArgs {
+    terms: Vec<String>,
+    regex: bool,
+    range: Option<String>,
+    format: Option<ListFormat>,
+    sort: Option<ListSort>,
+    no_colour: bool,
+}
+
+// The names to look for, if any, '--regex', '--range', '--format json|csv',
+// '--sort' and '--no-color'
+fn list_args(args: &[String]) -> ListArgs {
+    let mut list = ListArgs::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == LIST_FORMAT {
+            list.format = match args.next().map(|f| f.as_str()) {
+                Some("json") => Some(ListFormat::Json),
+                Some("csv") => Some(ListFormat::Csv),
+                _ => Some(ListFormat::Text),
+            };
+        } else if arg == LIST_SORT {
+            list.sort = match args.next().map(|s| s.as_str()) {
+                Some("hue") => Some(ListSort::Hue),
+                Some("lightness") => Some(ListSort::Lightness),
+                _ => Some(ListSort::Name),
+            };
+        } else if arg == LIST_NO_COLOUR {
+            list.no_colour = true;
+        } else if arg == LIST_REGEX {
+            list.regex = true;
+        } else if arg == LIST_RANGE {
+            list.range = args.next().cloned();
+        } else {
+            list.terms.push(arg.to_ascii_lowercase());
+        }
+    }
+
+    list
+}
+
+// A block of the colour, for terminals that can show 24 bit colour
+fn swatch(colour: u32, width: usize) -> String {
+    format!(
+        "\x1b[48;2;{};{};{}m{:width$}\x1b[0m",
+        colour >> 16,
+        (colour >> 8) & 0xff,
+        colour & 0xff,
+        ""
+    )
+}
+
+// Only for people, not pipes, and not if they've asked for no colour
+fn show_swatches(no_colour: bool) -> bool {
+    !no_colour && std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+// By hue family then hue, or lightness, darkest first, either way the names
+// of the same colour stay together. With no sort they're in rgb.txt's order
+fn sort_colours(colours: &mut [(&String, u32)], sort: Option<&ListSort>) {
+    match sort {
+        Some(ListSort::Hue) => colours.sort_by(|(a_name, a), (b_name, b)| {
+            let (a_family, a_hue) = hue_family(*a);
+            let (b_family, b_hue) = hue_family(*b);
+
+            (a_family, a_hue, rgb_to_hsl(*a).2, a_name)
+                .partial_cmp(&(b_family, b_hue, rgb_to_hsl(*b).2, b_name))
+                .unwrap()
+        }),
+        Some(ListSort::Lightness) => colours.sort_by(|(a_name, a), (b_name, b)| {
+            (rgb_to_hsl(*a).2, a_name)
+                .partial_cmp(&(rgb_to_hsl(*b).2, b_name))
+                .unwrap()
+        }),
+        Some(ListSort::Name) => colours.sort(),
+        None => (),
+    }
+}
+
+// 'from..to', where each of red, green and blue must be between the two
+fn colour_range(range: &str) -> Result<(u32, u32), String> {
+    let bad_range = || format!("Bad range '{}', eg 808080..ffffff for light colours", range);
+    let (from, to) = range.split_once("..").ok_or_else(bad_range)?;
+    let colour = |colour: &str| get_x11_colour(&[colour.trim().to_string()]).ok_or_else(bad_range);
+
+    Ok((colour(from)?, colour(to)?))
+}
+
+fn in_range(colour: u32, (from, to): (u32, u32)) -> bool {
+    [16, 8, 0].iter().all(|shift| {
+        let channel = |colour: u32| (colour >> shift) & 0xff;
+        let (low, high) = (
+            channel(from).min(channel(to)),
+            channel(from).max(channel(to)),
+        );
+
+        (low..=high).contains(&channel(colour))
+    })
+}
+
+// The colours whose names match every term, and that are in the range
+fn matching_colours(list: &ListArgs) -> Result<Vec<(&'static String, u32)>, String> {
+    let range = list.range.as_deref().map(colour_range).transpose()?;
+
+    let regexes = match list.regex {
+        true => list
+            .terms
+            .iter()
+            .map(|term| {
+                RegexBuilder::new(term)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|err| format!("Bad regex '{}': {}", term, err))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        false => vec![],
+    };
+
+    let matches = |name: &str| match list.regex {
+        true => regexes.iter().all(|regex| regex.is_match(name)),
+        false => list.terms.iter().all(|term| name.contains(term)),
+    };
+
+    Ok(x11_colour_names()
+        .into_iter()
+        .filter(|name| matches(name))
+        .map(|name| (name, get_x11_colour(&[name.to_string()]).unwrap()))
+        .filter(|(_, colour)| range.is_none_or(|range| in_range(*colour, range)))
+        .collect())
+}
+
+fn list_command(args: &[String]) -> Status {
+    let list = list_args(args);
+
+    let format = list.format.as_ref().unwrap_or(if json_output() {
+        &ListFormat::Json
+    } else {
+        &ListFormat::Text
+    });
+
+    let mut colours = match matching_colours(&list) {
+        Ok(colours) => colours,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Status::BadArguments;
+        }
+    };
+
+    let ListArgs {
+        sort, no_colour, ..
+    } = list;
+
+    sort_colours(&mut colours, sort.as_ref());
+
+    match format {
+        ListFormat::Text => {
+            let swatches = show_swatches(no_colour);
+            let mut family = None;
+
+            for (name, colour) in &colours {
+                // Hue families get a heading each, to find them in the 750 or so
+                let colour_family = hue_family(*colour).0;
+
+                if sort == Some(ListSort::Hue) && family != Some(colour_family) {
+                    if family.is_some() {
+                        println!();
+                    }
+
+                    println!("{}:", HUE_FAMILIES[colour_family]);
+                    family = Some(colour_family);
+                }
+
+                match swatches {
+                    true => println!("{} {} {:#08x}", swatch(*colour, 4), name, colour),
+                    false => println!("{} {:#08x}", name, colour),
+                }
+            }
+        }
+        ListFormat::Json => {
+            let colours: Vec<_> = colours
+                .iter()
+                .map(|(name, colour)| json!({ "name": name, "colour": format!("{:#08x}", colour) }))
+                .collect();
+
+            print_json(&colours);
+        }
+        // X11 names never have commas or quotes in them
+        ListFormat::Csv => {
+            println!("name,colour");
+
+            for (name, colour) in &colours {
+                println!("{},{:#08x}", name, colour);
+            }
+        }
+    }
+
+    if colours.is_empty() {
+        Status::Failure
+    } else {
+        Status::SuccessNoSave
+    }
+}
+
+fn nearest_command(args: &[String]) -> Status {
+    let colour = match get_x11_colour(args) {
+        Some(colour) if !args.is_empty() => colour,
+        _ => {
+            report_error(
+                &format!("Not a colour: '{}'", args.join(" ")),
+                Status::BadArguments,
+            );
+
+            return Status::BadArguments;
+        }
+    };
+
+    let (name, x11, distance) = nearest_x11_colour(colour);
+
+    if json_output() {
+        print_json(&json!({
+            "colour": format!("{:#08x}", colour),
+            "name": name,
+            "x11": format!("{:#08x}", x11),
+            "delta_e": (distance * 100.0).round() / 100.0,
+        }));
+    } else {
+        println!(
+            "{} {:#08x}, ΔE {:.2} from {:#08x}",
+            name, x11, distance, colour
+        );
+    }
+
+    Status::SuccessNoSave
+}
+
+// Commands that set the lighting and then finish, without side effects
+fn previewable(command: &Command) -> bool {
+    match command {
+        Command::Wal(args) => !args.iter().any(|arg| arg == WAL_WATCH),
+        // An effect saved would never finish
+        Command::On => get_saved_command().is_none_or(|saved| previewable(&saved)),
+        command => matches!(
+            command,
+            Command::Colour(_)
+                | Command::Region(_)
+                | Command::Regions(_)
+                | Command::Gradient(_)
+                | Command::Rainbow(_)
+                | Command::Breathe(_)
+                | Command::Cycle(_)
+                | Command::Xresources(_)
+                | Command::Off
+        ),
+    }
+}
+
+// Commands a 'run' script can use, those that set the lighting and finish
+pub(crate) fn scriptable(command: &Command) -> bool {
+    previewable(command) || matches!(command, Command::Brightness(_))
+}
+
+/// Each region's colour after running `command`, unless it's an effect or
+/// leaves some of them unknown.
+pub(crate) fn lighting_colours(command: &Command) -> Option<Vec<u32>> {
+    if !previewable(command) {
+        return None;
+    }
+
+    let preview = preview(command).ok()?;
+
+    match preview.effect {
+        Some(_) => None,
+        None => preview.regions.into_iter().collect(),
+    }
+}
+
+// The regions the command leaves alone keep their last colours
+fn applied_colours(preview: Preview, last: Option<&[u32]>) -> Option<Vec<u32>> {
+    if preview.effect.is_some() {
+        return None;
+    }
+
+    preview
+        .regions
+        .iter()
+        .enumerate()
+        .map(|(region, colour)| colour.or_else(|| last?.get(region).copied()))
+        .collect()
+}
+
+/// Keeps track of the colours a successful command has left the regions in,
+/// from what it sent to the keyboard, and whether it turned them off.
+pub fn track_lighting(command: &Command) {
+    // Effects light the keyboard too, putting the saved command back after
+    if command.uses_device() && !matches!(command, Command::Info) {
+        save_lights_off(matches!(command, Command::Off));
+    }
+
+    if !previewable(command) {
+        return;
+    }
+
+    // Nothing sent from here, eg it was passed on to the daemon, which keeps track itself
+    let Some((model, sent)) = sent_lighting() else {
+        return;
+    };
+
+    let last = get_last_colours();
+
+    save_last_colours(applied_colours(
+        preview_lighting(model, &sent),
+        last.as_deref(),
+    ));
+}
+
+const FADE_FPS: u32 = 30;
+
+/// Runs `command`, fading from the last colours to those it sets over
+/// `length`, or just running it when either isn't known.
+pub fn fade_into(device: &Keyboard, command: &Command, length: Duration) -> Status {
+    if !previewable(command) {
+        return command.run(device);
+    }
+
+    // Run once, on a pretend keyboard, so what it sets is known before it's
+    // shown, and then sent to the real one
+    let model = device.model().unwrap_or_else(pretend_g213);
+
+    let (sent, status) = match pretend_run(model, command) {
+        Ok(ran) => ran,
+        Err(status) => return status,
+    };
+
+    let result = fade(device, preview_lighting(model, &sent), length)
+        .and_then(|_| set_lighting(device, &sent));
+
+    match result {
+        Ok(()) => status,
+        Err(err) => {
+            let status = Status::from(&err);
+
+            report_error(&err, status);
+
+            status
+        }
+    }
+}
+
+fn fade(device: &Keyboard, preview: Preview, length: Duration) -> G213Result<()> {
+    let from = get_last_colours();
+    let regions = num_regions(device) as usize;
+
+    let to = applied_colours(preview, from.as_deref());
+
+    let (Some(from), Some(to)) = (from, to) else {
+        return Ok(());
+    };
+
+    if from.len() != regions || to.len() != regions {
+        return Ok(());
+    }
+
+    let frame = Duration::from_secs_f64(1.0 / FADE_FPS as f64);
+    let start = Instant::now();
+
+    hold_device(device)?;
+    catch_interrupt();
+
+    // The command's own lighting is sent after
+    while start.elapsed() < length && !interrupted() {
+        let percent = start.elapsed().as_secs_f64() / length.as_secs_f64() * 100.0;
+
+        let colours: Vec<u32> = from
+            .iter()
+            .zip(&to)
+            .map(|(from, to)| mix_colours(*from, *to, percent))
+            .collect();
+
+        if let Err(err) = set_region_colours(device, &colours) {
+            let _ = let_go_of_device();
+
+            return Err(err);
+        }
+
+        pause(frame);
+    }
+
+    let_go_of_device()
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+struct Preview {
+    regions: Vec<Option<u32>>,
+    effect: Option<String>,
+}
+
+// What the keyboard would show after being sent this lighting, regions
+// not set by it are unknown
+fn preview_lighting(model: &Model, sent: &[Lighting]) -> Preview {
+    let mut preview = Preview {
+        regions: vec![None; model.num_regions() as usize],
+        effect: None,
+    };
+
+    for lighting in sent {
+        match *lighting {
+            Lighting::Colour { region: 0, colour } => {
+                preview.regions.fill(Some(colour));
+                preview.effect = None;
+            }
+            Lighting::Colour { region, colour } => {
+                if let Some(slot) = preview.regions.get_mut(region as usize - 1) {
+                    *slot = Some(colour);
+                }
+                preview.effect = None;
+            }
+            Lighting::Breathe { speed, colour } => {
+                preview.regions.fill(Some(colour));
+                preview.effect = Some(format!("breathing, {}ms per breath", speed));
+            }
+            Lighting::Cycle { speed, .. } => {
+                preview.regions.fill(None);
+                preview.effect = Some(format!("cycling through all colours every {}ms", speed));
+            }
+        }
+    }
+
+    preview
+}
+
+fn pretend_g213() -> &'static Model {
+    Model::from_name("G213").unwrap_or(&models()[0])
+}
+
+/// How `command` would go, without a keyboard, for '--save-only'. Only
+/// commands that set the lighting and finish can be checked.
+pub fn check_command(command: &Command) -> Status {
+    if !previewable(command) {
+        report_error(
+            &format!(
+                "'{}' can't be checked without a keyboard, so can't be saved on its own",
+                command.to_string().trim()
+            ),
+            Status::BadArguments,
+        );
+
+        return Status::BadArguments;
+    }
+
+    match pretend_run(pretend_g213(), command) {
+        Ok((_, status)) | Err(status) => status,
+    }
+}
+
+// Runs the command against a pretend keyboard, rather than a real one, for
+// the lighting it sends
+fn pretend_run(
+    model: &'static Model,
+    command: &Command,
+) -> Result<(Vec<Lighting>, Status), Status> {
+    let fake = Arc::new(FakeTransport::default());
+    let status = previewing(|| command.run(&Keyboard::Other(model, fake.clone())));
+
+    if !status.successful() {
+        return Err(status);
+    }
+
+    let sent = fake
+        .sent()
+        .iter()
+        .filter_map(|sent| model.decode(&hex::encode(sent)))
+        .collect();
+
+    Ok((sent, status))
+}
+
+// What the command would show on a G213
+fn preview(command: &Command) -> Result<Preview, Status> {
+    let model = pretend_g213();
+
+    pretend_run(model, command).map(|(sent, _)| preview_lighting(model, &sent))
+}
+
+fn show_preview(preview: &Preview) {
+    const WIDTH: usize = 10;
+
+    let numbers: Vec<String> = (1..=preview.regions.len())
+        .map(|region| format!("{:<WIDTH$}", region))
+        .collect();
+
+    println!("{}", numbers.join(" ").trim_end());
+
+    if show_swatches(false) {
+        let blocks: Vec<String> = preview
+            .regions
+            .iter()
+            .map(|colour| match colour {
+                Some(colour) => swatch(*colour, WIDTH),
+                None => format!("{:<WIDTH$}", "?".repeat(WIDTH)),
+            })
+            .collect();
+
+        println!("{}", blocks.join(" "));
+    }
+
+    let colours: Vec<String> = preview
+        .regions
+        .iter()
+        .map(|colour| match colour {
+            Some(colour) => format!("{:<WIDTH$}", format!("{:#08x}", colour)),
+            None => format!("{:<WIDTH$}", "?"),
+        })
+        .collect();
+
+    println!("{}", colours.join(" ").trim_end());
+
+    if let Some(effect) = &preview.effect {
+        println!("{}", effect);
+    }
+}
+
+// To see what a command would look like without a keyboard, eg over ssh
+fn preview_command(args: &[String]) -> Status {
+    let command = match args.is_empty() {
+        true => get_saved_command().ok_or_else(|| "No saved command to preview".to_string()),
+        false => parse_command(args.iter().map(String::as_str))
+            .and_then(|command| command.ok_or_else(|| "No command to preview".to_string())),
+    };
+
+    let command = match command.and_then(Command::resolve) {
+        Ok(command) if previewable(&command) => command,
+        Ok(command) => {
+            report_error(
+                &format!("'{}' can't be previewed", command.to_string().trim()),
+                Status::BadArguments,
+            );
+
+            return Status::BadArguments;
+        }
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Status::BadArguments;
+        }
+    };
+
+    let preview = match preview(&command) {
+        Ok(preview) => preview,
+        Err(status) => return status,
+    };
+
+    if json_output() {
+        print_json(&json!({
+            "command": command.to_string().trim(),
+            "regions": preview.regions.iter().map(|colour| colour.map(|colour| format!("{:#08x}", colour))).collect::<Vec<_>>(),
+            "effect": preview.effect,
+        }));
+    } else {
+        show_preview(&preview);
+    }
+
+    Status::SuccessNoSave
+}
+
+// Starts from the saved lighting, where it can be worked out
+fn tui_command(device: &Keyboard) -> Status {
+    if !std::io::stdout().is_terminal() || !std::io::stdin().is_terminal() {
+        report_error(&"The tui needs a terminal", Status::BadArguments);
+
+        return Status::BadArguments;
+    }
+
+    let model = device.model().unwrap_or(&models()[0]);
+
+    let saved = get_saved_command()
+        .filter(previewable)
+        .and_then(|command| preview(&command).ok());
+
+    let colours: Vec<u32> = (0..model.num_regions() as usize)
+        .map(|region| {
+            saved
+                .as_ref()
+                .and_then(|saved| saved.regions.get(region).copied().flatten())
+                .unwrap_or(0xffffff)
+        })
+        .collect();
+
+    let mut state = TuiState::new(&colours, config().defaults.speed.unwrap_or(2000));
+
+    // Packets printed by --dry-run would be drawn over the screen
+    let device = match device {
+        Keyboard::DryRun(model) => Keyboard::Other(model, Arc::new(FakeTransport::default())),
+        device => device.clone(),
+    };
+
+    // Held for the whole session, so each change is just sent
+    if let Err(err) = hold_device(&device) {
+        let status = Status::from(&err);
+
+        report_error(&err, status);
+
+        return status;
+    }
+
+    let mut terminal = ratatui::init();
+    let result = run_tui(&mut terminal, &device, &mut state);
+
+    ratatui::restore();
+
+    let _ = let_go_of_device();
+
+    match result {
+        Ok(Some(command)) => {
+            save_command(&command);
+
+            Status::SuccessNoSave
+        }
+        Ok(None) => Status::SuccessNoSave,
+        Err(err) => {
+            report_error(&err, Status::Failure);
+
+            Status::Failure
+        }
+    }
+}
+
+fn devices_command() -> G213Result<Status> {
+    let keyboards = list_g213_keyboards()?;
+
+    println!("Bus Address Serial           Model");
+
+    for keyboard in &keyboards {
+        println!(
+            "{:03} {:03}     {:16} {}",
+            keyboard.bus,
+            keyboard.address,
+            keyboard.serial.as_deref().unwrap_or("-"),
+            keyboard.model
+        );
+    }
+
+    Ok(Status::SuccessNoSave)
+}
+
+fn saved_command() -> Status {
+    let command = get_saved_command();
+
+    if json_output() {
+        print_json(&json!({ "saved": command.map(|cmd| cmd.to_string()) }));
+    } else {
+        match command {
+            Some(cmd) => println!("Saved command: {}", cmd),
+            None => println!("No currently saved command"),
+        }
+    }
+
+    Status::SuccessNoSave
+}
+
+// The tracked colours of `regions`, or all of them, 0 is the whole keyboard too
+fn region_colours(colours: &[u32], regions: Option<&str>) -> Result<Vec<(u8, u32)>, String> {
+    let all = (1..=colours.len() as u8).collect();
+
+    let regions = match regions.map(region_list).transpose()? {
+        Some(regions) if !regions.contains(&0) => regions,
+        _ => all,
+    };
+
+    regions
+        .into_iter()
+        .map(|region| match colours.get(region as usize - 1) {
+            Some(colour) => Ok((region, *colour)),
+            None => Err(format!(
+                "No region {}, the keyboard has {}",
+                region,
+                colours.len()
+            )),
+        })
+        .collect()
+}
+
+fn get_command(args: &[String]) -> Status {
+    let Some(colours) = get_last_colours() else {
+        report_error(
+            &"The region colours aren't known, after an effect or before any colours are set",
+            Status::Failure,
+        );
+
+        return Status::Failure;
+    };
+
+    let regions = match region_colours(&colours, args.first().map(String::as_str)) {
+        Ok(regions) => regions,
+        Err(err) => {
+            report_error(&err, Status::BadArguments);
+
+            return Status::BadArguments;
+        }
+    };
+
+    if json_output() {
+        let regions: serde_json::Map<String, serde_json::Value> = regions
+            .iter()
+            .map(|(region, colour)| (region.to_string(), json!(format!("{:#08x}", colour))))
+            .collect();
+
+        print_json(&json!({ "regions": regions }));
+    } else {
+        for (region, colour) in regions {
+            println!("{}  {:#08x}", region, colour);
+        }
+    }
+
+    Status::SuccessNoSave
+}
+
+fn history_command() -> Status {
+    let history: Vec<String> = load_history()
+        .iter()
+        .rev()
+        .map(Command::to_string)
+        .collect();
+
+    if json_output() {
+        print_json(&json!({ "history": history }));
+    } else if history.is_empty() {
+        println!("No commands saved yet");
+    } else {
+        for (age, command) in history.iter().enumerate() {
+            println!("{:>3}  {}", age, command);
+        }
+    }
+
+    Status::SuccessNoSave
+}
+
+// Normally resolved before it's run, see Command::resolve
+fn undo_command(device: &Keyboard) -> Status {
+    let command = match Command::Undo.resolve() {
+        Ok(command) => command,
+        Err(err) => {
+            report_error(&err, Status::Failure);
+
+            return Status::Failure;
+        }
+    };
+
+    eprintln!("Back to: {}", command);
+
+    let status = command.run(device);
+
+    if status == Status::Success {
+        forget_current();
+        save_command(&command);
+
+        return Status::SuccessNoSave;
+    }
+
+    status
+}
+
+fn info_command(device: &Keyboard) -> G213Result<Status> {
+    let info = device_info(device)?;
+
+    if json_output() {
+        print_json(&info);
+    } else {
+        show_info(&info);
+    }
+
+    Ok(Status::SuccessNoSave)
+}
+
+fn show_info(info: &DeviceInfo) {
+    let show = |label: &str, value: Option<String>| {
+        if let Some(value) = value {
+            println!("{:<14}{}", format!("{}:", label), value);
+        }
+    };
+
+    show("Device bus", info.bus.map(|bus| bus.to_string()));
+    show("Device #", info.address.map(|address| address.to_string()));
+    show("Device speed", info.speed.clone());
+    show(
+        "USB id",
+        info.vendor_id
+            .zip(info.product_id)
+            .map(|(vendor, product)| format!("{:04x}:{:04x}", vendor, product)),
+    );
+    show("Manufacturer", info.manufacturer.clone());
+    show("Product", info.product.clone());
+    show("Serial", info.serial.clone());
+
+    println!(
+        "Model:        {}{}",
+        info.model,
+        if info.dry_run { " (dry run)" } else { "" }
+    );
+
+    for config in &info.configurations {
+        show(
+            &format!("Config {}", config.number),
+            Some(format!("{} mA", config.max_power_ma)),
+        );
+        println!("  Interface  Alt  Class     Endpoint  Dir  Type         Max  Interval");
+
+        for interface in &config.interfaces {
+            let class = format!(
+                "{:02x}/{:02x}/{:02x}",
+                interface.class, interface.sub_class, interface.protocol
+            );
+
+            if interface.endpoints.is_empty() {
+                println!(
+                    "  {:<9}  {:<3}  {}",
+                    interface.number, interface.alt_setting, class
+                );
+            }
+
+            for endpoint in &interface.endpoints {
+                println!(
+                    "  {:<9}  {:<3}  {:<8}  {:#04x}      {:<3}  {:<11}  {:<3}  {}",
+                    interface.number,
+                    interface.alt_setting,
+                    class,
+                    endpoint.address,
+                    endpoint.direction,
+                    endpoint.transfer_type,
+                    endpoint.max_packet_size,
+                    endpoint.interval
+                );
+            }
+        }
+    }
+
+    if let Some(err) = &info.hidpp_error {
+        println!("HID++:        unavailable, {}", err);
+    }
+
+    let hidpp = match &info.hidpp {
+        Some(hidpp) => hidpp,
+        None => return,
+    };
+
+    println!("HID++:        {}.{}", hidpp.version.0, hidpp.version.1);
+
+    show("Name", hidpp.name.clone());
+
+    for version in &hidpp.firmware {
+        println!("{:<14}{}", format!("{}:", version.kind_name()), version);
+    }
+
+    println!("Features:     {}", hidpp.features.len());
+
+    for feature in &hidpp.features {
+        println!(
+            "  {:#04x}  {:#06x}  {}{}",
+            feature.index,
+            feature.id,
+            feature_name(feature.id),
+            if is_lighting_feature(feature.id) {
+                " (lighting)"
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+fn region_help() {
+    println!("Region  Names");
+
+    for (number, names) in REGION_NAMES {
+        println!("{:<7} {}", number, names.join(", "));
+    }
+}
+
+fn help_command(args: &[String]) -> Status {
+    match args.first().map(String::as_str) {
+        None => (),
+        Some("region" | "regions" | "r") => {
+            region_help();
+
+            return Status::SuccessNoSave;
+        }
+        Some(topic) => {
+            report_error(
+                &format!("No help on '{}', try 'g213-cols help region'", topic),
+                Status::BadArguments,
+            );
+
+            return Status::BadArguments;
+        }
+    }
+
+    const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+    println!(
+        "\ng213-cols - version {} ― Found a G213 keyboard ✅",
+        VERSION
+    );
+
+    println!(
+        r#"
++ ----------------------------------------------------- + ------------------------------------------------------------- +
+| Command                                               | Details                                                       |
+| ----------------------------------------------------- + ------------------------------------------------------------- |
+| Set whole keyboard colour                             |                                                               |
+| `g213-cols colour`                                    | sets the default 'white'                                      |
+| `g213-cols colour ffff00`                             | sets the specified hex colour, eg yellow                      |
+|                                                       | NOTE: 3 digit hex colour conversion - 1af -> 11aaff           |
+| `g213-cols colour 0xffff00`                           | the same, as is 0xff0                                         |
+| `g213-cols colour '#ff0'`                             | the same, CSS style # hex is quoted for the shell             |
+| `g213-cols colour 'hsl(210,80%,50%)'`                 | CSS rgb() and hsl() colours, as copied from dev tools         |
+| `g213-cols colour 'hsv(210,80,50)'`                   | hue 0-360, saturation and value 0-100, % optional             |
+| `g213-cols colour 'mix(red,blue,30%)'`                | 30% of the way from red to blue, half way by default          |
+| `g213-cols colour steelblue+20`                       | 20% lighter, or steelblue-40 for 40% darker                   |
+| `g213-cols colour lawn green`                         | sets the named X11 colour                                     |
+| `g213-cols colour "alice blue"`                       | sets the named X11 colour - as a single argument              |
+| `g213-cols colour dark_slate_blue`                    | sets the named X11 colour - underscores become spaces         |
+| `g213-cols colour random`                             | sets a random colour                                          |
+| `g213-cols regions random`                            | a different random colour for each region                     |
+| Set the colour of a specific keyboard region - 1 to 5 |                                                               |
+| `g213-cols region 2 [colour]`                         | sets the region to the [colour] as defined above              |
+| `g213-cols region numpad [colour]`                    | or by name, `g213-cols help region` lists them                |
+| `g213-cols region 1-3 [colour]`                       | several regions at once, or a list like 1,5 or wasd,4         |
+| Set the colours of all 5 keyboard regions             |                                                               |
+| `g213-cols regions [colour colour ...]`               | sets the regions to the [colours] as defined above            |
+|                                                       | last supplied colour is repeated to set all 5 regions         |
+| `g213-cols regions --mirror c1 c2 c3`                 | the same from both ends inwards - c1 c2 c3 c2 c1              |
+| Blend two colours across all 5 keyboard regions       |                                                               |
+| `g213-cols gradient [colour colour]`                  | sets the regions to a gradient between the two [colours]      |
+| Set the regions to a rainbow                          |                                                               |
+| `g213-cols rainbow [hue] [sat] [value]`               | evenly spaced hues from [hue], 0 by default - red,            |
+|                                                       | [sat]uration and [value] 0-100, both 100 by default           |
+| Set 'breathe' mode                                    |                                                               |
+| `g213-cols breathe 1000 [colour]`                     | sets the breathe time (in ms) for the [colour]                |
+| Set 'cycle' mode                                      |                                                               |
+| `g213-cols cycle 1000`                                | sets the cycle time (in ms) all colours                       |
+| `g213-cols breathe slow [colour]`                     | slow, medium or fast work for any speed, eg 10000ms,          |
+|                                                       | 5000ms and 2000ms, see [speeds] in config.toml                |
+| `g213-cols breathe 1.5s [colour]`                     | speeds can be in seconds too, eg 5s, or 500ms,                |
+|                                                       | from 32ms to 65535ms                                          |
+| Run a 'wave' effect until Ctrl-C                      |                                                               |
+| `g213-cols wave 1000 [colour]`                        | sweeps the [colour] across the regions every 1000ms           |
+| Follow the screen's colours until Ctrl-C              |                                                               |
+| `g213-cols ambient [--fps 5] [--cpu 25]`              | each region the main colour of the screen above it            |
+|                                                       | at most 25% of the time spent taking screenshots              |
+| Light up with the music until Ctrl-C                  |                                                               |
+| `g213-cols music [--fps 30] [colours]`                | regions bright with the bass through to the treble            |
+|                                                       | red to blue, or [colours] for each region                     |
+| Light up keys as they're typed until Ctrl-C           |                                                               |
+| `g213-cols typing [--decay 500] [colour]`             | regions flash [colour] as their keys are pressed              |
+|                                                       | fading back to the saved colours over 500ms                   |
+| Show the system load until Ctrl-C                     |                                                               |
+| `g213-cols load [--usage] [--bar] [--every 2]`        | green to red with the 1 minute load for each CPU              |
+|                                                       | --usage for CPU usage, --bar to light more regions            |
+| Show the laptop's battery until Ctrl-C                |                                                               |
+| `g213-cols monitor battery [--low 20] [--bar]`        | green when full to red when empty, or as a bar                |
+|                                                       | pulses when below 20% or while charging                       |
+| Show the network traffic until Ctrl-C                 |                                                               |
+| `g213-cols monitor network [--max 10M] [--whole]`     | downloads on the left, uploads on the right                   |
+|                                                       | brighter the faster, at full brightness at --max              |
+| Blink a colour until Ctrl-C, or [count] times         |                                                               |
+| `g213-cols blink 500 colour [count]`                  | [colour] for 500ms then off, 200ms at the fastest             |
+|                                                       | then restores the saved command                               |
+| Alternate two colours, like police lights             |                                                               |
+| `g213-cols alternate 400 [colour1 colour2]`           | swaps the colours between the halves every 400ms              |
+|                                                       | red and blue by default, 200ms at the fastest                 |
+| Burn like a fire until Ctrl-C                         |                                                               |
+| `g213-cols fire [--intensity 70] [--speed 70]`        | regions flicker from red through orange to yellow             |
+|                                                       | hotter with --intensity, faster with --speed                  |
+| Digital rain until Ctrl-C                             |                                                               |
+| `g213-cols matrix [--fps 10]`                         | drops of green land on the regions and fade                   |
+|                                                       | ticking 10 times a second                                     |
+| Sparkle like starlight until Ctrl-C                   |                                                               |
+| `g213-cols sparkle [colour] [density]`                | regions glint at random over a dim [colour]                   |
+|                                                       | each with a 20% or [density]% chance a tick                   |
+| Fade through a list of colours until Ctrl-C           |                                                               |
+| `g213-cols cycle-list 2000 colour colour ...`         | fades the keyboard to each colour in turn, taking             |
+|                                                       | 2000ms for each, then back round to the first                 |
+| Breathe without going dark until Ctrl-C               |                                                               |
+| `g213-cols pulse 3000 [colour] [min%] [max%]`         | breathes between 20% and 100% of [colour] every 3000ms        |
+|                                                       | or between [min%] and [max%]                                  |
+| Run a Pomodoro focus timer until Ctrl-C               |                                                               |
+| `g213-cols pomodoro [25] [5]`                         | 25 minutes of work in tomato red, then a 5 minute             |
+|                                                       | break breathing green, flashing white at each change          |
+| Count down on the regions                             |                                                               |
+| `g213-cols timer 4m [colour]`                         | regions go out right to left over 4 minutes                   |
+|                                                       | then flash for 10 seconds, eg 90s, 25m or 1h30m               |
+| Turn the lighting off and on again                    |                                                               |
+| `g213-cols off`                                       | turns all regions off, the saved command is kept              |
+| `g213-cols on`                                        | restores the saved command, or 'white' if there is none       |
+| `g213-cols toggle`                                    | off if it's on, otherwise on, eg bound to a hotkey            |
+| Set the brightness of all colours - 0 to 100          |                                                               |
+| `g213-cols brightness [50]`                           | scales colours to 50% and re-applies the saved command        |
+|                                                       | shows the current brightness if no value is given             |
+| Make white look white on this keyboard - 0 to 100%    |                                                               |
+| `g213-cols calibrate [100 100 90]`                    | less blue, for this keyboard only, re-applying the saved      |
+|                                                       | command, or shows its calibration if none are given           |
+| `g213-cols calibrate --reset`                         | forgets this keyboard's calibration                           |
+| Use last successful saved command                     |                                                               |
+| `g213-cols`                                           | Runs the last successful saved command                        |
+| `g213-cols apply`                                     | The same, failing if nothing's saved, eg at boot              |
+| Show saved command                                    |                                                               |
+| `g213-cols saved`                                     | Display the last successful saved command                     |
+| Go back to an earlier command                         |                                                               |
+| `g213-cols history`                                   | The last 20 saved commands, the current one first             |
+| `g213-cols undo`                                      | Runs and saves the one before the current one again           |
+| Show each region's colour                             |                                                               |
+| `g213-cols get [regions]`                             | The colour each region was last set to, eg `get numpad`       |
+|                                                       | or as JSON with `--json`, for other tools                     |
+| Preview a command without a keyboard, eg over ssh     |                                                               |
+| `g213-cols preview regions red blue`                  | Draws the 5 regions in the terminal, with their colours       |
+| `g213-cols preview`                                   | The same for the saved command                                |
+| Pick colours interactively                            |                                                               |
+| `g213-cols tui`                                       | Regions, colour sliders and effects, saved with s or enter    |
+| Show keyboard info                                    |                                                               |
+| `g213-cols info`                                      | USB ids, interfaces and endpoints, and HID++ features         |
+| List keyboards                                        |                                                               |
+| `g213-cols devices`                                   | Show the bus:address and serial of every G213 keyboard        |
+| Run as a daemon                                       |                                                               |
+| `g213-cols daemon`                                    | Keeps the keyboard open and runs commands sent to its socket  |
+|                                                       | other `g213-cols` commands are sent to it when it is running  |
+|                                                       | the saved command is re-applied if the keyboard is re-plugged |
+| Re-apply the saved command on re-plugging             |                                                               |
+| `g213-cols watch`                                     | Waits for the keyboard to be re-plugged until Ctrl-C          |
+| Restore the saved command at login or boot            |                                                               |
+| `g213-cols install-service [user or system]`          | Writes and enables a systemd unit, 'user' by default          |
+| Allow access to the keyboard without root             |                                                               |
+| `sudo g213-cols install-udev [group]`                 | Writes a udev rule for the seat user and [group] - 'plugdev'  |
+| Restore the lighting after suspend                    |                                                               |
+| `sudo g213-cols install-sleep-hook`                   | Writes a systemd sleep hook that runs the saved command       |
+|                                                       | the daemon does this itself when it is running                |
+| Send a raw 20 byte command, for protocol exploration  |                                                               |
+| `g213-cols raw 11ff0c3a... --yes-i-know`              | Sends the 40 hex digits as is and shows the reply             |
+|                                                       | can confuse the keyboard, so needs `--yes-i-know`             |
+| Re-send USB transfers recorded with `--trace file`    |                                                               |
+| `g213-cols replay file`                               | Sends the recorded commands again, with the same timing       |
+| Run a file of commands, eg a light show               |                                                               |
+| `g213-cols run file`                                  | One command per line, as typed after `g213-cols`              |
+|                                                       | with `sleep ms` to wait and `#` for comments                  |
+| `g213-cols -`                                         | The same for commands read from stdin, until it's closed      |
+|                                                       | `g213-cols pipe` too, bad lines are reported and skipped      |
+| Themes                                                |                                                               |
+| `g213-cols wal [file]`                                | Set the regions to the pywal theme's colours                  |
+| `g213-cols wal --watch`                               | The same, and again when the theme changes, until Ctrl-C      |
+| `g213-cols xresources [names]`                        | Set the regions to the terminal colours, by default color1-5  |
+| `g213-cols xresources --file f`                       | The same, from Xresources file [f] rather than xrdb           |
+| Named profiles                                        |                                                               |
+| `g213-cols profile save name`                         | Keep the saved command as profile [name]                      |
+| `g213-cols profile load name`                         | Run profile [name], which is then saved as usual              |
+| `g213-cols profile list`                              | Show the profiles and their commands                          |
+| `g213-cols profile delete name`                       | Forget profile [name]                                         |
+| `g213-cols apply slot`                                | Run the command saved with `--slot`, then save it as usual    |
+| Palettes                                              |                                                               |
+| `g213-cols palette import file.gpl`                   | Use the colours in a GIMP palette by name, eg `colour sky`    |
+| `g213-cols palette list`                              | Show the imported colours                                     |
+| OpenRGB profiles                                      |                                                               |
+| `g213-cols import-openrgb file.orp [name]`            | Keep the G213 in an OpenRGB profile as profile [name]         |
+|                                                       | named after the file by default                               |
+| `g213-cols export-openrgb file.orp [profile]`         | Write the saved command, or [profile], for OpenRGB            |
+| List X11 colours                                      |                                                               |
+| `g213-cols list [names]`                              | Show X11 colours that contain all of [names], or all colours  |
+|                                                       | with a block of each colour when printing to a terminal       |
+| `g213-cols list [names] --no-color`                   | The same, without the blocks, as does setting NO_COLOR        |
+| `g213-cols list [names] --format csv`                 | The same, as csv or json, for other tools to read             |
+| `g213-cols list --regex '^dark.*blue$'`               | Names matching case insensitive regular expressions           |
+| `g213-cols list --range 808080..ffffff`               | Colours with red, green and blue in a range, eg light ones    |
+| `g213-cols list --sort hue`                           | Grouped by hue family, reds to pinks then greys               |
+|                                                       | or `--sort lightness` darkest first, or `--sort name`         |
+| `g213-cols nearest 4682b5`                            | The closest X11 colour, and its CIE76 ΔE difference           |
+|                                                       | 2.3 is just noticeable, eg for colours from a screenshot      |
++ ----------------------------------------------------- + ------------------------------------------------------------- +
+
++ ---------- + ------------ +
+| Command    | Abbreviation |
+| ---------- + ------------ |
+| colour     | c            |
+| region     | r            |
+| regions    | rs           |
+| gradient   | g            |
+| breathe    | b            |
+| cycle      | cy           |
+| wave       | w            |
+| off        | o            |
+| toggle     | t            |
+| brightness | br           |
+| devices    | d            |
+| saved      | s            |
+| info       | i            |
+| list       | l            |
+| profile    | p            |
+| help       | h or ?       |
++ ---------- + ------------ +
+
+Options: `--device bus:address|serial` or `--all` - choose the keyboard(s), see README
+         `--dry-run` - print the USB commands instead of sending them
+         `--trace file` - record every USB transfer to the file, for `replay`
+         `--no-save` - don't save the command for re-applying later
+         `--save-only` - save the command without sending it to the keyboard
+         `--slot n` - save the command to slot n, for `apply n`, instead
+         `--config file` - keep the saved command in this file, not ~/.config/g213-cols
+         `--verbose` or `-v` - show the USB commands as they are sent
+         `--json` - print `list`, `saved`, `get`, `info` and errors as JSON
+         `--random x11` - make `random` an X11 named colour, rather than any colour
+         `--fade ms` - fade from the last colours to the new ones, rather than snapping
+         `--gamma 2.2` - correct the colours sent for the LEDs, or 2.2,2.0,2.4 for each channel
+         `--help` - a summary of the commands and options, `g213-cols <command> --help` for each
+
++ ---- + ------------------------------------------------- +
+| Exit | Meaning                                           |
+| ---- + ------------------------------------------------- |
+| 0    | Success                                           |
+| 1    | Failure - eg no colours matched by `list`         |
+| 3    | Bad arguments - eg an unknown colour name         |
+| 4    | No G213 keyboard found                            |
+| 5    | Permission denied - run as root or `install-udev` |
+| 6    | USB error talking to the keyboard                 |
++ ---- + ------------------------------------------------- +
+"#
+    );
+
+    Status::SuccessNoSave
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod commands_tests {
+
+    use super::*;
+
+    fn to_string_vec(words: Vec<&str>) -> Vec<String> {
+        words.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn fake_keyboard(name: &str) -> (Keyboard, Arc<FakeTransport>) {
+        let fake = Arc::new(FakeTransport::default());
+        let keyboard = Keyboard::Other(Model::from_name(name).unwrap(), fake.clone());
+
+        (keyboard, fake)
+    }
+
+    #[test]
+    fn off_sends_black() {
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        assert_eq!(Command::Off.run(&keyboard), Status::SuccessNoSave);
+        assert_eq!(
+            fake.sent_hex(),
+            vec!["11ff0c3a00010000000200000000000000000000".to_string()]
+        );
+    }
+
+    #[test]
+    fn regions_sends_every_region() {
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        Command::Regions(to_string_vec(vec!["red"])).run(&keyboard);
+
+        let regions: Vec<String> = fake
+            .sent_hex()
+            .iter()
+            .map(|s| s[8..10].to_string())
+            .collect();
+
+        assert_eq!(regions, to_string_vec(vec!["01", "02", "03", "04", "05"]));
+    }
+
+    #[test]
+    fn speeds() {
+        assert_eq!(parse_speed("5000"), Ok(5000));
+        assert_eq!(parse_speed("10"), Ok(10));
+        assert_eq!(parse_speed("5s"), Ok(5000));
+        assert_eq!(parse_speed("1.5s"), Ok(1500));
+        assert_eq!(parse_speed("500ms"), Ok(500));
+        assert_eq!(parse_speed("0.25S"), Ok(250));
+
+        assert!(parse_speed("10ms").is_err());
+        assert!(parse_speed("70s").is_err());
+        assert!(parse_speed("70000").is_err());
+        assert!(parse_speed("5m").is_err());
+        assert!(parse_speed("-1s").is_err());
+        assert!(parse_speed("infs").is_err());
+    }
+
+    #[test]
+    fn bad_speeds_are_bad_arguments() {
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        for command in [
+            Command::Breathe(to_string_vec(vec!["fast-ish", "red"])),
+            Command::Cycle(to_string_vec(vec!["90s"])),
+            Command::Wave(to_string_vec(vec!["-5", "red"])),
+        ] {
+            assert_eq!(command.run(&keyboard), Status::BadArguments);
+        }
+
+        assert!(fake.sent_hex().is_empty());
+    }
+
+    #[test]
+    fn region_ranges_and_lists() {
+        assert_eq!(region_list("1-3"), Ok(vec![1, 2, 3]));
+        assert_eq!(region_list("1,5"), Ok(vec![1, 5]));
+        assert_eq!(region_list("wasd,centre-left,4-5"), Ok(vec![1, 2, 4, 5]));
+        assert!(region_list("3-1").is_err());
+        assert!(region_list("1,,2").is_err());
+        assert!(region_list("1-x").is_err());
+        assert!(region_list("9").is_err());
+        assert!(region_list("3-9").is_err());
+    }
+
+    #[test]
+    fn regions_beyond_the_keyboard_fail() {
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        let region = |args: Vec<&str>| Command::Region(to_string_vec(args)).run(&keyboard);
+
+        assert_eq!(region(vec!["9", "red"]), Status::BadArguments);
+        assert_eq!(region(vec!["4-7", "red"]), Status::BadArguments);
+        assert!(fake.sent_hex().is_empty());
+    }
+
+    #[test]
+    fn tracked_region_colours() {
+        let colours = [1, 2, 3, 4, 5];
+
+        assert_eq!(region_colours(&colours, Some("numpad")), Ok(vec![(5, 5)]));
+        assert_eq!(
+            region_colours(&colours, Some("1,3")),
+            Ok(vec![(1, 1), (3, 3)])
+        );
+        assert_eq!(region_colours(&colours, None).unwrap().len(), 5);
+        assert_eq!(region_colours(&colours, Some("0")).unwrap().len(), 5);
+        assert!(region_colours(&colours[..1], Some("2")).is_err());
+    }
+
+    #[test]
+    fn region_by_name() {
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        let status = Command::Region(to_string_vec(vec!["numpad", "red"])).run(&keyboard);
+
+        assert_eq!(status, Status::Success);
+        assert_eq!(
+            fake.sent_hex(),
+            vec!["11ff0c3a0501ff00000200000000000000000000".to_string()]
+        );
+
+        let status = Command::Region(to_string_vec(vec!["middle", "red"])).run(&keyboard);
+
+        assert_eq!(status, Status::BadArguments);
+        assert_eq!(fake.sent().len(), 1);
+    }
+
+    #[test]
+    fn regions_mirrored() {
+        assert_eq!(mirror_colours(&[1, 2, 3], 5), [1, 2, 3, 2, 1]);
+        assert_eq!(mirror_colours(&[1, 2], 4), [1, 2, 2, 1]);
+        assert_eq!(mirror_colours(&[1], 1), [1]);
+
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        Command::Regions(to_string_vec(vec!["--mirror", "red", "blue"])).run(&keyboard);
+
+        let colours: Vec<String> = fake
+            .sent_hex()
+            .iter()
+            .map(|s| s[12..18].to_string())
+            .collect();
+
+        assert_eq!(
+            colours,
+            to_string_vec(vec!["ff0000", "0000ff", "0000ff", "0000ff", "ff0000"])
+        );
+    }
+
+    #[test]
+    fn single_region_keyboard() {
+        let (keyboard, fake) = fake_keyboard("G512");
+
+        Command::Regions(to_string_vec(vec!["red"])).run(&keyboard);
+
+        assert_eq!(fake.sent().len(), 1);
+        assert!(fake.sent_hex()[0].starts_with("11ff0d3c0001"));
+
+        let status = Command::Region(to_string_vec(vec!["3", "red"])).run(&keyboard);
+
+        assert_eq!(status, Status::BadArguments);
+        assert_eq!(fake.sent().len(), 1);
+    }
+
+    #[test]
+    fn raw_sends_exact_bytes() {
+        let (keyboard, fake) = fake_keyboard("G213");
+        let hex = "11ff0c3a0001123456020000000000000000000a";
+
+        let status = Command::Raw(to_string_vec(vec![hex, "--yes-i-know"])).run(&keyboard);
+
+        assert_eq!(status, Status::SuccessNoSave);
+        assert_eq!(fake.sent_hex(), vec![hex.to_string()]);
+    }
+
+    #[test]
+    fn glitches_are_tried_again() {
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        fake.queue_error(rusb::Error::Pipe);
+        fake.queue_error(rusb::Error::Busy);
+
+        assert_eq!(Command::Off.run(&keyboard), Status::SuccessNoSave);
+        assert_eq!(fake.sent().len(), 1);
+
+        for _ in 0..4 {
+            fake.queue_error(rusb::Error::Pipe);
+        }
+
+        assert_eq!(Command::Off.run(&keyboard), Status::UsbError);
+
+        fake.queue_error(rusb::Error::NoDevice);
+
+        assert_eq!(Command::Off.run(&keyboard), Status::UsbError);
+        assert_eq!(fake.sent().len(), 1);
+    }
+
+    #[test]
+    fn rejected_command_fails() {
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        fake.queue_reply(&hex::decode("11ffff0c3a050000000000000000000000000000").unwrap());
+
+        assert_eq!(Command::Off.run(&keyboard), Status::UsbError);
+    }
+
+    #[test]
+    fn commands_that_use_no_device() {
+        for cmd in [
+            Command::Daemon,
+            Command::Devices,
+            Command::Watch,
+            Command::InstallService(vec![]),
+            Command::InstallUdev(vec![]),
+            Command::InstallSleepHook,
+            Command::List(vec![]),
+            Command::Nearest(vec![]),
+            Command::Saved,
+            Command::Get(vec![]),
+            Command::History,
+            Command::Help(vec![]),
+        ] {
+            assert!(!cmd.uses_device(), "{}", cmd);
+        }
+
+        assert!(Command::Raw(vec![]).uses_device());
+        assert!(Command::Replay(vec![]).uses_device());
+    }
+
+    #[test]
+    fn info_as_json() {
+        let (keyboard, _) = fake_keyboard("G413");
+        let info = serde_json::to_value(device_info(&keyboard).unwrap()).unwrap();
+
+        assert_eq!(info["model"], "G413");
+        assert_eq!(info["dry_run"], false);
+        assert!(info["serial"].is_null());
+        assert_eq!(info["configurations"], json!([]));
+    }
+
+    #[test]
+    fn frames_and_their_budget() {
+        let (frames, others) = frame_args(
+            &to_string_vec(vec!["--fps", "20", "bass", "--cpu", "10"]),
+            5,
+        )
+        .unwrap();
+
+        assert_eq!(frames, Frames { fps: 20, cpu: 10 });
+        assert_eq!(others, ["bass"]);
+        assert!(frame_args(&to_string_vec(vec!["--fps", "0"]), 5).is_err());
+        assert!(frame_args(&to_string_vec(vec!["--cpu"]), 5).is_err());
+
+        let ms = Duration::from_millis;
+
+        assert_eq!(frame_wait(&Frames { fps: 5, cpu: 100 }, ms(50)), ms(150));
+        assert_eq!(frame_wait(&Frames { fps: 5, cpu: 10 }, ms(50)), ms(450));
+        assert_eq!(frame_wait(&Frames { fps: 5, cpu: 100 }, ms(300)), ms(0));
+    }
+
+    #[test]
+    fn typing_decay_and_input() {
+        let typing = typing_args(&to_string_vec(vec![
+            "--decay",
+            "300",
+            "lawn",
+            "--input",
+            "/dev/input/event5",
+            "green",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            typing,
+            TypingArgs {
+                decay: Duration::from_millis(300),
+                input: Some("/dev/input/event5".to_string()),
+                colour: to_string_vec(vec!["lawn", "green"]),
+            }
+        );
+        assert_eq!(typing_args(&[]).unwrap().decay, Duration::from_millis(500));
+        assert!(typing_args(&to_string_vec(vec!["--decay", "10"])).is_err());
+        assert!(typing_args(&to_string_vec(vec!["--input"])).is_err());
+    }
+
+    #[test]
+    fn load_options() {
+        let load = load_args(&to_string_vec(vec![
+            "--bar", "--every", "5", "green", "red",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            load,
+            LoadArgs {
+                every: Duration::from_secs(5),
+                usage: false,
+                bar: true,
+                colours: to_string_vec(vec!["green", "red"]),
+            }
+        );
+        assert!(load_args(&to_string_vec(vec!["--usage"])).unwrap().usage);
+        assert!(load_args(&to_string_vec(vec!["--every", "0"])).is_err());
+    }
+
+    #[test]
+    fn blink_options() {
+        assert_eq!(
+            blink_args(&to_string_vec(vec!["500", "dark", "red", "3"])),
+            Ok(BlinkArgs {
+                frames: Frames { fps: 20, cpu: 100 },
+                interval: Duration::from_millis(500),
+                colour: to_string_vec(vec!["dark", "red"]),
+                count: Some(3),
+            })
+        );
+        assert_eq!(
+            blink_args(&to_string_vec(vec!["500", "red"])).map(|blink| blink.count),
+            Ok(None)
+        );
+        assert!(blink_args(&to_string_vec(vec!["50", "red"])).is_err());
+        assert!(blink_args(&to_string_vec(vec!["500", "red", "0"])).is_err());
+        assert!(blink_args(&[]).is_err());
+        assert_eq!(
+            blink_args(&to_string_vec(vec!["--fps", "5", "500", "red"]))
+                .map(|blink| blink.frames.fps),
+            Ok(5)
+        );
+    }
+
+    #[test]
+    fn fire_options() {
+        let (frames, intensity, speed) =
+            fire_args(&to_string_vec(vec!["--speed", "20", "--fps", "30"])).unwrap();
+
+        assert_eq!((frames.fps, intensity, speed), (30, 70, 20));
+        assert!(fire_args(&to_string_vec(vec!["--intensity", "0"])).is_err());
+        assert!(fire_args(&to_string_vec(vec!["red"])).is_err());
+    }
+
+    #[test]
+    fn sparkle_options() {
+        let sparkle =
+            |args| sparkle_args(&to_string_vec(args)).map(|(_, colour, density)| (colour, density));
+
+        assert_eq!(
+            sparkle(vec!["light", "blue", "40"]),
+            Ok((to_string_vec(vec!["light", "blue"]), 40))
+        );
+        assert_eq!(sparkle(vec![]), Ok((vec![], 20)));
+        assert!(sparkle(vec!["blue", "0"]).is_err());
+    }
+
+    #[test]
+    fn rainbow_options() {
+        let rainbow = |args| rainbow_args(&to_string_vec(args));
+
+        assert_eq!(rainbow(vec![]), Ok((0.0, 100.0, 100.0)));
+        assert_eq!(rainbow(vec!["120", "50"]), Ok((120.0, 50.0, 100.0)));
+        assert!(rainbow(vec!["360"]).is_err());
+        assert!(rainbow(vec!["0", "101"]).is_err());
+        assert!(rainbow(vec!["0", "100", "100", "1"]).is_err());
+    }
+
+    #[test]
+    fn pulse_options() {
+        let pulse = |args| {
+            pulse_args(&to_string_vec(args)).map(|pulse| (pulse.colour, pulse.min, pulse.max))
+        };
+
+        assert_eq!(
+            pulse(vec!["3000", "dark", "red", "10", "60"]),
+            Ok((to_string_vec(vec!["dark", "red"]), 10, 60))
+        );
+        assert_eq!(
+            pulse(vec!["3000", "red", "5"]),
+            Ok((to_string_vec(vec!["red"]), 5, 100))
+        );
+        assert_eq!(pulse(vec!["3000"]), Ok((vec![], 20, 100)));
+        assert!(pulse(vec!["3000", "red", "60", "10"]).is_err());
+        assert!(pulse(vec!["3000", "red", "10", "200"]).is_err());
+        assert!(pulse(vec![]).is_err());
+    }
+
+    #[test]
+    fn pomodoro_options() {
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+
+        assert_eq!(pomodoro_args(&[]), Ok((minutes(25), minutes(5))));
+        assert_eq!(
+            pomodoro_args(&to_string_vec(vec!["50", "10"])),
+            Ok((minutes(50), minutes(10)))
+        );
+        assert!(pomodoro_args(&to_string_vec(vec!["0"])).is_err());
+        assert!(pomodoro_args(&to_string_vec(vec!["25", "5", "3"])).is_err());
+    }
+
+    #[test]
+    fn battery_options() {
+        let battery = battery_args(&to_string_vec(vec!["--low", "30", "--bar"])).unwrap();
+
+        assert_eq!(
+            battery,
+            BatteryArgs {
+                every: Duration::from_secs(10),
+                low: 30,
+                bar: true,
+            }
+        );
+        assert!(battery_args(&to_string_vec(vec!["--low", "101"])).is_err());
+        assert!(battery_args(&to_string_vec(vec!["red"])).is_err());
+    }
+
+    #[test]
+    fn network_options() {
+        let network = network_args(&to_string_vec(vec![
+            "--interface",
+            "wlan0",
+            "--max",
+            "500K",
+            "red",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            network,
+            NetworkArgs {
+                every: Duration::from_secs(1),
+                interface: Some("wlan0".to_string()),
+                max: 5e5,
+                whole: false,
+                colours: to_string_vec(vec!["red"]),
+            }
+        );
+        assert!(network_args(&to_string_vec(vec!["--max", "fast"])).is_err());
+    }
+
+    #[test]
+    fn music_rainbow_or_colours() {
+        assert_eq!(
+            music_colours(&[], 5).unwrap(),
+            [0xff0000, 0xffff00, 0x00ff00, 0x00ffff, 0x0000ff]
+        );
+        assert_eq!(
+            music_colours(&to_string_vec(vec!["red", "blue"]), 3).unwrap(),
+            [0xff0000, 0x0000ff, 0x0000ff]
+        );
+    }
+
+    #[test]
+    fn list_args_and_format() {
+        assert_eq!(list_args(&[]), ListArgs::default());
+        assert_eq!(
+            list_args(&to_string_vec(vec!["Navy", "--format", "csv"])),
+            ListArgs {
+                terms: to_string_vec(vec!["navy"]),
+                format: Some(ListFormat::Csv),
+                ..ListArgs::default()
+            }
+        );
+        assert_eq!(
+            list_args(&to_string_vec(vec!["--format", "json", "--no-color"])),
+            ListArgs {
+                format: Some(ListFormat::Json),
+                no_colour: true,
+                ..ListArgs::default()
+            }
+        );
+    }
+
+    #[test]
+    fn matching_list_colours() {
+        let names = |args: Vec<&str>| {
+            matching_colours(&list_args(&to_string_vec(args))).map(|colours| {
+                colours
+                    .iter()
+                    .map(|(name, _)| name.to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            names(vec!["navy"]).unwrap(),
+            ["navy", "navy blue", "navyblue"]
+        );
+        assert_eq!(
+            names(vec!["Slate", "dark", "blue"]).unwrap(),
+            ["dark slate blue", "darkslateblue"]
+        );
+        assert_eq!(
+            names(vec!["--regex", "^(lawn|forest) ?green$"]).unwrap(),
+            ["lawn green", "lawngreen", "forest green", "forestgreen"]
+        );
+        assert_eq!(
+            names(vec!["--range", "f0f0f0..white", "snow"]).unwrap(),
+            ["snow", "snow1"]
+        );
+        assert!(names(vec!["--range", "000000..101010"])
+            .unwrap()
+            .contains(&"black".to_string()));
+        assert!(names(vec!["--regex", "("]).is_err());
+        assert!(names(vec!["--range", "black"]).is_err());
+    }
+
+    #[test]
+    fn colour_ranges() {
+        assert!(in_range(0x808080, (0x000000, 0xffffff)));
+        assert!(in_range(0x808080, (0xffffff, 0x808080)));
+        assert!(!in_range(0x7f8080, (0x808080, 0xffffff)));
+        assert_eq!(colour_range("red..0x00f"), Ok((0xff0000, 0x0000ff)));
+    }
+
+    #[test]
+    fn sorted_colours() {
+        let names = to_string_vec(vec![
+            "white",
+            "navy",
+            "red",
+            "lawn green",
+            "black",
+            "maroon",
+        ]);
+        let colours: Vec<(&String, u32)> = names
+            .iter()
+            .map(|name| (name, get_x11_colour(std::slice::from_ref(name)).unwrap()))
+            .collect();
+
+        let sorted = |sort| {
+            let mut colours = colours.clone();
+
+            sort_colours(&mut colours, Some(&sort));
+            colours
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sorted(ListSort::Name),
+            ["black", "lawn green", "maroon", "navy", "red", "white"]
+        );
+        assert_eq!(
+            sorted(ListSort::Lightness),
+            ["black", "navy", "maroon", "lawn green", "red", "white"]
+        );
+        assert_eq!(
+            sorted(ListSort::Hue),
+            ["maroon", "red", "lawn green", "navy", "black", "white"]
+        );
+        assert_eq!(
+            list_args(&to_string_vec(vec!["--sort", "hue"])).sort,
+            Some(ListSort::Hue)
+        );
+    }
+
+    #[test]
+    fn swatches() {
+        assert_eq!(swatch(0x4682b4, 4), "\x1b[48;2;70;130;180m    \x1b[0m");
+        assert!(!show_swatches(true));
+    }
+
+    #[test]
+    fn profile_commands_use_device_to_load() {
+        let profile = |args| Command::Profile(to_string_vec(args));
+
+        assert!(profile(vec!["load", "work"]).uses_device());
+        assert!(!profile(vec!["list"]).uses_device());
+        assert!(!profile(vec!["save", "work"]).uses_device());
+        assert!(Command::Apply(to_string_vec(vec!["2"])).uses_device());
+    }
+
+    #[test]
+    fn other_commands_resolve_to_themselves() {
+        let command = Command::Colour(to_string_vec(vec!["red"])).resolve();
+
+        assert_eq!(command.unwrap().to_string(), "colour red");
+        assert!(Command::Profile(to_string_vec(vec!["save", "x"]))
+            .resolve()
+            .is_ok());
+    }
+
+    #[test]
+    fn old_config_files_are_moved() {
+        let dir = std::env::temp_dir().join(format!("g213-cols-test-{}", std::process::id()));
+        let old = dir.join(".g213-cols.json");
+        let new = dir.join("g213-cols").join("config.json");
+
+        create_dir_all(&dir).unwrap();
+        std::fs::write(&old, "{}").unwrap();
+
+        assert!(migrate_old_file(&old, &new).unwrap());
+        assert!(!old.exists());
+        assert_eq!(std::fs::read_to_string(&new).unwrap(), "{}");
+
+        // Nothing left to move, and the new file isn't replaced
+        assert!(!migrate_old_file(&old, &new).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn x11_randoms() {
+        let command = Command::Regions(to_string_vec(vec!["red", "Random", "randomx11"]));
+
+        assert_eq!(
+            command.with_x11_randoms().to_string(),
+            "regions red randomx11 randomx11"
+        );
+
+        let command = Command::Profile(to_string_vec(vec!["save", "random"]));
+
+        assert_eq!(
+            command.with_x11_randoms().to_string(),
+            "profile save random"
+        );
+    }
+
+    #[test]
+    fn xresources_names_and_file() {
+        let (names, file) = xresources_args(&[]);
+
+        assert_eq!(names.len(), 5);
+        assert_eq!(names[0], "color1");
+        assert_eq!(file, None);
+
+        let (names, file) = xresources_args(&to_string_vec(vec![
+            "background",
+            "--file",
+            "x.res",
+            "color4",
+        ]));
+
+        assert_eq!(names, ["background", "color4"]);
+        assert_eq!(file, Some("x.res".to_string()));
+    }
+
+    #[test]
+    fn close_colours_are_used() {
+        let (keyboard, fake) = fake_keyboard("G213");
+
+        let status = Command::Colour(to_string_vec(vec!["lawn", "gren"])).run(&keyboard);
+
+        assert_eq!(status, Status::Success);
+        assert_eq!(&fake.sent_hex()[0][12..18], "7cfc00");
+
+        let status = Command::Regions(to_string_vec(vec!["red", "bleu"])).run(&keyboard);
+
+        assert_eq!(status, Status::Success);
+        assert_eq!(&fake.sent_hex()[2][12..18], "0000ff");
+    }
+
+    #[test]
+    fn unknown_colours_are_suggested() {
+        assert_eq!(
+            unknown_colour("gren"),
+            "Unknown colour 'gren', did you mean 'green' or 'grey'?"
+        );
+        assert_eq!(
+            unknown_colour("xyzzy"),
+            "Unknown colour 'xyzzy', see 'list'"
+        );
+
+        let (keyboard, _) = fake_keyboard("G213");
+        let status = Command::Regions(to_string_vec(vec!["red", "gren"])).run(&keyboard);
+
+        assert_eq!(status, Status::BadArguments);
+    }
+
+    #[test]
+    fn previews() {
+        let model = Model::from_name("G213").unwrap();
+        let sent = |commands: Vec<String>| {
+            commands
+                .iter()
+                .filter_map(|command| model.decode(command))
+                .collect::<Vec<_>>()
+        };
+
+        let preview = preview_lighting(
+            model,
+            &sent(vec![
+                model.colour_command(0, 0x0000ff),
+                model.colour_command(2, 0xff0000),
+            ]),
+        );
+
+        assert_eq!(
+            preview.regions,
+            [
+                Some(0xff),
+                Some(0xff0000),
+                Some(0xff),
+                Some(0xff),
+                Some(0xff)
+            ]
+        );
+        assert_eq!(preview.effect, None);
+
+        let preview = preview_lighting(model, &sent(vec![model.colour_command(5, 0xff)]));
+
+        assert_eq!(preview.regions, [None, None, None, None, Some(0xff)]);
+
+        let preview = preview_lighting(model, &sent(vec![model.cycle_command(3000, 100)]));
+
+        assert_eq!(preview.regions, [None; 5]);
+        assert_eq!(
+            preview.effect.as_deref(),
+            Some("cycling through all colours every 3000ms")
+        );
+    }
+
+    #[test]
+    fn colours_left_applied() {
+        let preview = |regions: Vec<Option<u32>>, effect: Option<&str>| Preview {
+            regions,
+            effect: effect.map(str::to_string),
+        };
+        let last = [1, 2, 3];
+
+        assert_eq!(
+            applied_colours(preview(vec![None, Some(0xff), None], None), Some(&last)),
+            Some(vec![1, 0xff, 3])
+        );
+        assert_eq!(
+            applied_colours(preview(vec![None, Some(0xff), None], None), None),
+            None
+        );
+        assert_eq!(
+            applied_colours(preview(vec![Some(0xff); 3], Some("breathing")), Some(&last)),
+            None
+        );
+    }
+
+    #[test]
+    fn fading_runs_the_command_once() {
+        let _global = crate::global_state();
+        let (keyboard, fake) = fake_keyboard("G213");
+        let command = Command::Regions(to_string_vec(vec!["red", "gren"]));
+
+        assert_eq!(
+            fade_into(&keyboard, &command, Duration::ZERO),
+            Status::BadArguments
+        );
+        assert!(fake.sent_hex().is_empty());
+
+        let command = Command::Regions(to_string_vec(vec!["random"]));
+
+        assert_eq!(
+            fade_into(&keyboard, &command, Duration::ZERO),
+            Status::Success
+        );
+        assert_eq!(fake.sent_hex().len(), 5);
+
+        // What's tracked is what was really sent
+        let (_, sent) = sent_lighting().unwrap();
+
+        assert_eq!(
+            preview_lighting(Model::from_name("G213").unwrap(), &sent).regions,
+            fake.sent()
+                .iter()
+                .map(|sent| Some(u32::from_be_bytes([0, sent[6], sent[7], sent[8]])))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn checked_without_a_keyboard() {
+        let check = |args: Vec<&str>| check_command(&parse_command(args).unwrap().unwrap());
+
+        assert_eq!(check(vec!["breathe", "5000", "teal"]), Status::Success);
+        assert_eq!(check(vec!["colour", "qqqq"]), Status::BadArguments);
+        assert_eq!(check(vec!["off"]), Status::SuccessNoSave);
+        assert_eq!(check(vec!["wave", "1000"]), Status::BadArguments);
+    }
+
+    #[test]
+    fn what_can_be_previewed() {
+        assert!(previewable(&Command::Regions(vec![])));
+        assert!(previewable(&Command::Wal(vec![])));
+        assert!(!previewable(&Command::Wal(to_string_vec(vec!["--watch"]))));
+        assert!(!previewable(&Command::Wave(vec![])));
+        assert!(!previewable(&Command::Brightness(to_string_vec(vec![
+            "50"
+        ]))));
+        assert!(!previewable(&Command::Preview(vec![])));
+        assert_eq!(
+            preview_command(&to_string_vec(vec!["wave", "1000"])),
+            Status::BadArguments
+        );
+        assert_eq!(
+            preview_command(&to_string_vec(vec!["regions", "red", "blue"])),
+            Status::SuccessNoSave
+        );
+    }
+
+    #[test]
+    fn raw_hex_args() {
+        let args = to_string_vec(vec!["11ff", "0c3a", "--yes-i-know"]);
+
+        assert_eq!(raw_hex(&args), ("11ff0c3a".to_string(), true));
+        assert_eq!(
+            raw_hex(&to_string_vec(vec!["11ff 0c3a"])),
+            ("11ff0c3a".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn status_from_u8() {
+        assert!(Status::from(Status::SuccessNoSave as u8) == Status::SuccessNoSave);
+        assert!(Status::from(42) == Status::Failure);
+    }
+
+    #[test]
+    fn status_from_g213_error() {
+        assert_eq!(Status::from(&G213Error::NotFound), Status::NotFound);
+        assert_eq!(
+            Status::from(&G213Error::Permission),
+            Status::PermissionDenied
+        );
+        assert_eq!(
+            Status::from(&G213Error::WriteControl(rusb::Error::Timeout)),
+            Status::UsbError
+        );
+    }
+
+    #[test]
+    fn status_round_trips_through_u8() {
+        assert_eq!(
+            Status::from(Status::BadArguments as u8),
+            Status::BadArguments
+        );
+        assert_eq!(Status::from(Status::NotFound as u8), Status::NotFound);
+        assert_eq!(
+            Status::from(Status::PermissionDenied as u8),
+            Status::PermissionDenied
+        );
+        assert_eq!(Status::from(Status::UsbError as u8), Status::UsbError);
     }
 
     #[test]
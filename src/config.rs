@@ -0,0 +1,496 @@
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::calibration::{Correction, Gamma};
+use crate::commands::config_file_path;
+use crate::g213_keyboard::RetryPolicy;
+
+pub(crate) const SETTINGS_FILE: &str = "config.toml";
+
+/// Settings from `~/.config/g213-cols/config.toml`, command line options win.
+///
+/// ```toml
+/// [defaults]
+/// colour = "lawn green"   # when a command isn't given one
+/// speed = 2000            # for 'cycle' without a speed
+///
+/// [device]
+/// select = "1:5"          # like '--device', or "all" like '--all'
+/// gamma = 2.2             # like '--gamma', or [2.2, 2.0, 2.4] for each channel
+/// retries = 3             # when the keyboard isn't ready, eg just plugged in
+/// retry_delay = 20        # ms before the first, doubling each time
+///
+/// [device.regions]        # red, green and blue factors, to even out regions
+/// numpad = [1.2, 1.2, 1.2]
+/// 1 = [1.0, 0.9, 1.0]
+///
+/// [speeds]                # ms for 'breathe slow', 'cycle fast' and so on
+/// slow = 10000
+/// medium = 5000
+/// fast = 2000
+///
+/// [colours]
+/// desk = "0x20c0ff"       # usable anywhere a colour is
+/// warning = "orange red"
+///
+/// [daemon]
+/// socket = "/run/user/1000/g213.sock"
+/// dbus = false
+/// http = "127.0.0.1:8213" # serve a REST API
+///
+/// [sun]                   # for the daemon to switch at sunrise and sunset
+/// latitude = 51.5
+/// longitude = -0.13
+/// day = "colour white"
+/// night = "colour darkred"
+/// transition = 30         # minutes to blend between them
+///
+/// [night]                 # for the daemon to warm the colours at night
+/// start = "20:00"
+/// end = "07:00"
+/// temperature = 3400      # in Kelvin, from day_temperature, 6500 by default
+/// transition = 60         # minutes to warm up or cool down
+///
+/// [idle]                  # for the daemon to dim the keyboard when not used
+/// timeout = 10            # minutes
+/// level = 20              # percent of the usual brightness, 0 for off
+///
+/// [locks]                 # for the daemon to show Caps Lock and Num Lock
+/// caps = "1 red"          # the region and colour while it's on
+/// num = "5 lawn green"
+///
+/// [notifications]         # for the daemon to flash the keyboard
+/// colour = "orange"
+/// urgency = "critical"    # or "normal", or "low" for every notification
+/// flashes = 3
+///
+/// [mqtt]                  # for the daemon to be a Home Assistant light
+/// host = "homeassistant.local"
+/// port = 1883
+/// username = "g213"
+/// password = "secret"
+/// topic = "g213-cols"     # for its commands and state
+/// discovery = "homeassistant"
+/// name = "G213 keyboard"
+///
+/// [dmx]                   # for the daemon to be lit by a lighting console
+/// protocol = "sacn"       # or "artnet"
+/// universe = 1
+/// address = 1             # the first of 3 channels for each region
+/// timeout = 5             # seconds without any before going back
+///
+/// [[apps]]                # for the daemon to show a profile while an
+/// class = "kitty"         # application's focused, the first that matches
+/// title = "prod"          # part of the window title, either will do
+/// profile = "alert"
+///
+/// [game]                  # for the daemon to show a profile while gaming
+/// profile = "gaming"
+/// fullscreen = true       # when a fullscreen window's focused
+/// processes = ["dota2"]   # or while any of these are running
+///
+/// [schedule]              # run by the daemon every day
+/// "22:00" = "breathe 4000 darkred"
+/// "08:00" = "colour white"
+/// ```
+#[derive(Deserialize, Default, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub defaults: Defaults,
+    pub device: DeviceConfig,
+    pub speeds: SpeedsConfig,
+    pub colours: BTreeMap<String, String>,
+    pub daemon: DaemonConfig,
+    pub sun: SunConfig,
+    pub night: NightConfig,
+    pub idle: IdleConfig,
+    pub locks: LocksConfig,
+    pub notifications: NotificationsConfig,
+    pub mqtt: MqttConfig,
+    pub dmx: DmxConfig,
+    pub apps: Vec<AppConfig>,
+    pub game: GameConfig,
+    pub schedule: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Default, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Defaults {
+    pub colour: Option<String>,
+    pub speed: Option<u16>,
+}
+
+#[derive(Deserialize, Default, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    pub select: Option<String>,
+    pub gamma: Option<Gamma>,
+    pub regions: BTreeMap<String, Correction>,
+    pub retries: Option<u8>,
+    pub retry_delay: Option<u64>,
+}
+
+impl DeviceConfig {
+    /// `retries` and `retry_delay`, in ms, or the usual ones.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let usual = RetryPolicy::default();
+
+        RetryPolicy {
+            retries: self.retries.unwrap_or(usual.retries),
+            delay: self.retry_delay.map_or(usual.delay, Duration::from_millis),
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeedsConfig {
+    pub slow: u16,
+    pub medium: u16,
+    pub fast: u16,
+}
+
+impl Default for SpeedsConfig {
+    fn default() -> Self {
+        SpeedsConfig {
+            slow: 10000,
+            medium: 5000,
+            fast: 2000,
+        }
+    }
+}
+
+impl SpeedsConfig {
+    /// The milliseconds for 'slow', 'medium' or 'fast'.
+    pub fn speed(&self, name: &str) -> Option<u16> {
+        match name.to_lowercase().as_str() {
+            "slow" => Some(self.slow),
+            "medium" => Some(self.medium),
+            "fast" => Some(self.fast),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SunConfig {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub day: Option<String>,
+    pub night: Option<String>,
+    pub transition: u32,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NightConfig {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub temperature: Option<u32>,
+    pub day_temperature: u32,
+    pub transition: u32,
+}
+
+impl Default for NightConfig {
+    fn default() -> Self {
+        NightConfig {
+            start: None,
+            end: None,
+            temperature: None,
+            day_temperature: 6500,
+            transition: 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdleConfig {
+    pub timeout: Option<u32>,
+    pub level: u8,
+}
+
+#[derive(Deserialize, Default, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocksConfig {
+    pub caps: Option<String>,
+    pub num: Option<String>,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    pub colour: Option<String>,
+    pub urgency: String,
+    pub flashes: u8,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig {
+            colour: None,
+            urgency: "critical".to_string(),
+            flashes: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: Option<String>,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic: String,
+    pub discovery: String,
+    pub name: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: None,
+            port: 1883,
+            username: None,
+            password: None,
+            topic: "g213-cols".to_string(),
+            discovery: "homeassistant".to_string(),
+            name: "G213 keyboard".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DmxConfig {
+    pub protocol: Option<String>,
+    pub universe: u16,
+    pub address: u16,
+    pub timeout: u32,
+}
+
+impl Default for DmxConfig {
+    fn default() -> Self {
+        DmxConfig {
+            protocol: None,
+            universe: 1,
+            address: 1,
+            timeout: 5,
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AppConfig {
+    pub class: Option<String>,
+    pub title: Option<String>,
+    pub profile: String,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub profile: Option<String>,
+    pub fullscreen: bool,
+    pub processes: Vec<String>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            profile: None,
+            fullscreen: true,
+            processes: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    pub socket: Option<String>,
+    pub dbus: bool,
+    pub http: Option<String>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            socket: None,
+            dbus: true,
+            http: None,
+        }
+    }
+}
+
+fn parse_config(config: &str) -> Result<Config, toml::de::Error> {
+    toml::from_str(config)
+}
+
+// A broken file shouldn't stop the keyboard being set, so say why and carry on
+fn load_config() -> Config {
+    let path = match config_file_path(SETTINGS_FILE) {
+        Ok(path) => path,
+        Err(_) => return Config::default(),
+    };
+
+    match read_to_string(&path) {
+        Ok(config) => parse_config(&config).unwrap_or_else(|err| {
+            eprintln!("Ignoring {}: {}", path, err);
+
+            Config::default()
+        }),
+        Err(_) => Config::default(),
+    }
+}
+
+lazy_static! {
+    static ref CONFIG: Config = load_config();
+}
+
+pub fn config() -> &'static Config {
+    &CONFIG
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn empty_config() {
+        let config = parse_config("").unwrap();
+
+        assert_eq!(config, Config::default());
+        assert!(config.daemon.dbus);
+    }
+
+    #[test]
+    fn all_sections() {
+        let config = parse_config(
+            r#"
+[defaults]
+colour = "lawn green"
+speed = 2000
+
+[device]
+select = "all"
+gamma = [2.2, 2.0, 2.4]
+retries = 5
+
+[device.regions]
+numpad = [1.2, 1.2, 1.2]
+
+[speeds]
+fast = 1000
+
+[colours]
+desk = "0x20c0ff"
+"my warning" = "orange red"
+
+[daemon]
+socket = "/tmp/g213.sock"
+dbus = false
+http = "127.0.0.1:8213"
+
+[sun]
+latitude = 51.5
+longitude = -0.13
+day = "colour white"
+night = "colour darkred"
+transition = 30
+
+[night]
+start = "20:00"
+end = "07:00"
+temperature = 3400
+
+[idle]
+timeout = 10
+level = 20
+
+[locks]
+caps = "1 red"
+
+[notifications]
+colour = "orange"
+
+[mqtt]
+host = "localhost"
+
+[dmx]
+protocol = "artnet"
+universe = 0
+
+[[apps]]
+class = "steam"
+profile = "games"
+
+[[apps]]
+title = "prod"
+profile = "alert"
+
+[game]
+profile = "gaming"
+processes = ["dota2"]
+
+[schedule]
+"08:00" = "colour white"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.defaults.colour.as_deref(), Some("lawn green"));
+        assert_eq!(config.defaults.speed, Some(2000));
+        assert_eq!(config.device.select.as_deref(), Some("all"));
+        assert_eq!(config.device.gamma, Some(Gamma::Channels([2.2, 2.0, 2.4])));
+        assert_eq!(config.device.regions["numpad"], [1.2, 1.2, 1.2]);
+        assert_eq!(config.device.retry_policy().retries, 5);
+        assert_eq!(
+            config.device.retry_policy().delay,
+            Duration::from_millis(20)
+        );
+        assert_eq!(config.speeds.speed("Fast"), Some(1000));
+        assert_eq!(config.speeds.speed("slow"), Some(10000));
+        assert_eq!(config.speeds.speed("quick"), None);
+        assert_eq!(config.colours["my warning"], "orange red");
+        assert_eq!(config.daemon.socket.as_deref(), Some("/tmp/g213.sock"));
+        assert!(!config.daemon.dbus);
+        assert_eq!(config.daemon.http.as_deref(), Some("127.0.0.1:8213"));
+        assert_eq!(config.sun.latitude, Some(51.5));
+        assert_eq!(config.sun.transition, 30);
+        assert_eq!(config.night.temperature, Some(3400));
+        assert_eq!(config.night.day_temperature, 6500);
+        assert_eq!(config.idle.timeout, Some(10));
+        assert_eq!(config.idle.level, 20);
+        assert_eq!(config.locks.caps.as_deref(), Some("1 red"));
+        assert_eq!(config.notifications.colour.as_deref(), Some("orange"));
+        assert_eq!(config.notifications.urgency, "critical");
+        assert_eq!(config.notifications.flashes, 3);
+        assert_eq!(config.mqtt.host.as_deref(), Some("localhost"));
+        assert_eq!(config.mqtt.port, 1883);
+        assert_eq!(config.mqtt.topic, "g213-cols");
+        assert_eq!(config.dmx.protocol.as_deref(), Some("artnet"));
+        assert_eq!(config.dmx.universe, 0);
+        assert_eq!(config.dmx.address, 1);
+        assert_eq!(config.apps.len(), 2);
+        assert_eq!(config.apps[0].class.as_deref(), Some("steam"));
+        assert_eq!(config.apps[1].profile, "alert");
+        assert_eq!(config.game.profile.as_deref(), Some("gaming"));
+        assert!(config.game.fullscreen);
+        assert_eq!(config.game.processes, ["dota2"]);
+        assert_eq!(config.schedule["08:00"], "colour white");
+    }
+
+    #[test]
+    fn mistakes_are_errors() {
+        assert!(parse_config("[defaults]\ncolor = \"red\"\n").is_err());
+        assert!(parse_config("[defaults]\nspeed = \"fast\"\n").is_err());
+        assert!(parse_config("[device]\ngamma = [2.2]\n").is_err());
+    }
+}
@@ -0,0 +1,624 @@
+use std::env;
+use std::fs::remove_file;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::thread::sleep;
+use std::time::Duration;
+
+use serde_json::json;
+use users::get_current_uid;
+
+use crate::apps::{AppChange, Apps};
+use crate::commands::{
+    get_saved_command, lighting_colours, save_command, set_file_ownership_to_me,
+    set_region_colours, track_lighting, Command, Run, Status,
+};
+use crate::config::config;
+use crate::dbus::{serve_dbus, watch_for_resume};
+use crate::dmx::{Dmx, DmxChange};
+use crate::g213_keyboard::{
+    catch_interrupt, forget_device, hold_device, interrupted, let_go_of_device, num_regions,
+    scale_colour, G213Result, Keyboard,
+};
+use crate::hotplug::{replay_saved_command, HotplugEvent, HotplugWatch};
+use crate::http::HttpServer;
+use crate::idle::{Idle, IdleChange};
+use crate::locks::Locks;
+use crate::mqtt::Mqtt;
+use crate::night::Night;
+use crate::notifications::Notifications;
+use crate::schedule::{local_time, Schedule};
+use crate::sun::{Sun, SunChange};
+
+const SOCKET_FILE: &str = "g213-cols.sock";
+const POLL_MS: u64 = 100;
+// A client that connects but never sends its request mustn't stall the loop
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub fn socket_path() -> String {
+    if let Some(socket) = &config().daemon.socket {
+        return socket.clone();
+    }
+
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => format!("{}/{}", dir, SOCKET_FILE),
+        _ => format!("/tmp/{}-{}", get_current_uid(), SOCKET_FILE),
+    }
+}
+
+// Commands that never finish would stop the daemon serving anyone else,
+// raw replies, 'info' and the calibration would be printed by the daemon
+// rather than the caller, and files are best read by the caller, who knows
+// where they are
+fn can_forward(command: &Command) -> bool {
+    match command {
+        // Setting it, or '--reset', prints nothing
+        Command::Calibrate(args) => !args.is_empty(),
+        command => !matches!(
+            command,
+            Command::Wave(_)
+                | Command::Ambient(_)
+                | Command::Music(_)
+                | Command::Typing(_)
+                | Command::Load(_)
+                | Command::Monitor(_)
+                | Command::Blink(_)
+                | Command::Alternate(_)
+                | Command::Fire(_)
+                | Command::Matrix(_)
+                | Command::Sparkle(_)
+                | Command::CycleList(_)
+                | Command::Pulse(_)
+                | Command::Pomodoro(_)
+                | Command::Timer(_)
+                | Command::Watch
+                | Command::Raw(_)
+                | Command::Info
+                | Command::Replay(_)
+                | Command::Script(_)
+                | Command::Pipe
+                | Command::Wal(_)
+                | Command::Xresources(_)
+                | Command::Tui
+        ),
+    }
+}
+
+// Commands that use the keyboard are sent to a running daemon, if there is one
+pub fn forward_command(command: &Command) -> Option<Status> {
+    if !command.uses_device() || !can_forward(command) {
+        return None;
+    }
+
+    let mut stream = UnixStream::connect(socket_path()).ok()?;
+
+    let request = serde_json::to_string(command).unwrap();
+
+    writeln!(stream, "{}", request).ok()?;
+
+    let mut reply = String::new();
+
+    BufReader::new(stream).read_line(&mut reply).ok()?;
+
+    reply.trim().parse::<u8>().ok().map(Status::from)
+}
+
+// Only what could have been run by the caller
+fn run_request(device: &Keyboard, command: &Command) -> Status {
+    if !command.uses_device() || !can_forward(command) {
+        eprintln!("Refusing: {}", command);
+
+        return Status::Failure;
+    }
+
+    eprintln!("Running: {}", command);
+
+    command.run(device)
+}
+
+// The command, when it's been run successfully
+fn handle_client(device: &Keyboard, stream: UnixStream) -> Option<Command> {
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+
+    if reader.read_line(&mut request).is_err() {
+        return None;
+    }
+
+    let (status, ran) = match serde_json::from_str::<Command>(&request) {
+        Ok(command) => {
+            let status = run_request(device, &command);
+
+            (status, Some(command).filter(|_| status == Status::Success))
+        }
+        Err(_) => {
+            eprintln!("Bad request: {}", request.trim());
+
+            (Status::Failure, None)
+        }
+    };
+
+    let _ = writeln!(&stream, "{}", status as u8);
+
+    ran
+}
+
+// Config mistakes are reported, and that part left out
+fn or_report<T>(automatic: Result<Option<T>, String>) -> Option<T> {
+    automatic.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+
+        None
+    })
+}
+
+fn show_colours(device: &Keyboard, colours: &[u32]) {
+    if let Err(err) = set_region_colours(device, colours) {
+        eprintln!("{}", err);
+    }
+}
+
+// Effects can't be dimmed, so they're turned off
+fn dim(device: &Keyboard, level: u8) {
+    let colours = get_saved_command()
+        .and_then(|command| lighting_colours(&command))
+        .filter(|_| level > 0)
+        .unwrap_or_else(|| vec![0; num_regions(device) as usize]);
+
+    let dimmed: Vec<u32> = colours
+        .iter()
+        .map(|colour| scale_colour(*colour, level as u32))
+        .collect();
+
+    show_colours(device, &dimmed);
+}
+
+// The daemon's own changes to the lighting, set up in the config
+struct Automatic {
+    schedule: Schedule,
+    sun: Option<Sun>,
+    night: Option<Night>,
+    idle: Option<Idle>,
+    locks: Option<Locks>,
+    notifications: Option<Notifications>,
+    mqtt: Option<Mqtt>,
+    http: Option<HttpServer>,
+    dmx: Option<Dmx>,
+    apps: Option<Apps>,
+}
+
+impl Automatic {
+    fn new() -> Automatic {
+        let (schedule, errors) = Schedule::new(&config().schedule);
+
+        for err in errors {
+            eprintln!("{}", err);
+        }
+
+        let mut automatic = Automatic {
+            schedule,
+            sun: or_report(Sun::new(&config().sun)),
+            night: or_report(Night::new(&config().night)),
+            idle: or_report(Idle::new(&config().idle)),
+            locks: or_report(Locks::new(&config().locks)),
+            notifications: or_report(Notifications::new(&config().notifications)),
+            mqtt: or_report(Mqtt::new(&config().mqtt)),
+            http: or_report(HttpServer::new(config().daemon.http.as_deref())),
+            dmx: or_report(Dmx::new(&config().dmx)),
+            apps: or_report(Apps::new(&config().apps, &config().game)),
+        };
+
+        // Warming whatever's saved, until something else is shown
+        if let Some(command) = get_saved_command() {
+            automatic.shown(&command);
+        }
+
+        automatic
+    }
+
+    // After a command has set the lighting
+    fn shown(&mut self, command: &Command) {
+        track_lighting(command);
+
+        if let Some(night) = self.night.as_mut() {
+            night.set_base(command);
+        }
+
+        if let Some(locks) = self.locks.as_mut() {
+            locks.refresh();
+        }
+
+        if let Some(mqtt) = self.mqtt.as_mut() {
+            mqtt.shown(command);
+        }
+
+        if let Some(http) = self.http.as_mut() {
+            http.broadcast(&json!({ "event": "applied", "command": command.to_string() }));
+        }
+    }
+
+    // For WebSocket clients, when something happens to the keyboard
+    fn announce(&mut self, event: &str) {
+        if let Some(http) = self.http.as_mut() {
+            http.broadcast(&json!({ "event": event }));
+        }
+    }
+
+    // After the saved command has been run again
+    fn refresh(&mut self) {
+        if let Some(night) = self.night.as_mut() {
+            night.refresh();
+        }
+
+        if let Some(locks) = self.locks.as_mut() {
+            locks.refresh();
+        }
+
+        // Put back over the saved command if its application's still focused
+        if let Some(apps) = self.apps.as_mut() {
+            apps.forget();
+        }
+    }
+
+    // Lit indicators over the lighting, effects are turned off while they're
+    // on, and the lighting is restored once they're all off
+    fn show_locks(&mut self, device: &Keyboard) {
+        let Some(lit) = self.locks.as_mut().and_then(Locks::due) else {
+            return;
+        };
+
+        if !lit.contains(&true) {
+            Command::On.run(device);
+
+            if let Some(night) = self.night.as_mut() {
+                night.refresh();
+            }
+
+            return;
+        }
+
+        let base = self
+            .night
+            .as_ref()
+            .and_then(Night::colours)
+            .or_else(|| get_saved_command().and_then(|command| lighting_colours(&command)))
+            .unwrap_or_else(|| vec![0; num_regions(device) as usize]);
+
+        if let Some(locks) = self.locks.as_ref() {
+            show_colours(device, &locks.colours(&base, &lit));
+        }
+    }
+
+    fn run(&mut self, device: &Keyboard) {
+        let ran = self
+            .http
+            .as_mut()
+            .map(|http| http.handle(|command| run_request(device, command)))
+            .unwrap_or_default();
+
+        for command in ran {
+            self.shown(&command);
+        }
+
+        match self.idle.as_mut().and_then(Idle::due) {
+            Some(IdleChange::Dim(level)) => {
+                eprintln!("Idle, dimming to {}%", level);
+
+                dim(device, level);
+            }
+            Some(IdleChange::Restore) => {
+                Command::On.run(device);
+
+                self.refresh();
+            }
+            None => (),
+        }
+
+        // Changes while idle are only saved, to be shown when it's used again
+        let dimmed = self.idle.as_ref().is_some_and(Idle::dimmed);
+        let now = local_time();
+
+        let command = match self.sun.as_mut().and_then(|sun| sun.due(&now)) {
+            Some(SunChange::Run(command)) => Some(command),
+            Some(SunChange::Colours(colours)) => {
+                if !dimmed {
+                    show_colours(device, &colours);
+                }
+
+                if let Some(night) = self.night.as_mut() {
+                    night.set_base_colours(Some(colours));
+                }
+
+                None
+            }
+            None => self.schedule.due(now.minutes as u32).cloned(),
+        };
+
+        // Saved, so it's what comes back after a resume or being plugged in again
+        if let Some(command) = command {
+            eprintln!("Changing to: {}", command);
+
+            if dimmed {
+                save_command(&command);
+            } else {
+                if command.run(device) == Status::Success {
+                    save_command(&command);
+                }
+
+                self.shown(&command);
+            }
+        }
+
+        // From Home Assistant, run as if sent to the daemon
+        for command in self.mqtt.as_mut().map(Mqtt::due).unwrap_or_default() {
+            eprintln!("From MQTT: {}", command);
+
+            if command.run(device) == Status::Success {
+                save_command(&command);
+            }
+
+            self.shown(&command);
+        }
+
+        if let Some(mqtt) = self.mqtt.as_mut() {
+            mqtt.publish_state();
+        }
+
+        // Missed while idle, rather than flashed when it's used again
+        let flash = self.notifications.as_mut().is_some_and(Notifications::due);
+        let dmx = self
+            .dmx
+            .as_mut()
+            .and_then(|dmx| dmx.due(num_regions(device)));
+
+        if dimmed {
+            return;
+        }
+
+        if let Some(notifications) = self.notifications.as_ref().filter(|_| flash) {
+            if let Err(err) = notifications.flash(device) {
+                eprintln!("{}", err);
+            }
+
+            Command::On.run(device);
+
+            self.refresh();
+        }
+
+        // Shown, but not saved, so the saved command comes back afterwards
+        match self.apps.as_mut().and_then(Apps::due) {
+            Some(AppChange::Run(command)) => {
+                eprintln!("Focused application: {}", command);
+
+                command.run(device);
+
+                self.shown(&command);
+            }
+            Some(AppChange::Restore) => {
+                Command::On.run(device);
+
+                if let Some(command) = get_saved_command() {
+                    self.shown(&command);
+                }
+            }
+            None => (),
+        }
+
+        match dmx {
+            Some(DmxChange::Colours(colours)) => show_colours(device, &colours),
+            Some(DmxChange::Stopped) => {
+                eprintln!("DMX stopped");
+
+                Command::On.run(device);
+
+                self.refresh();
+            }
+            None => (),
+        }
+
+        if let Some(colours) = self.night.as_mut().and_then(|night| night.due(now.minutes)) {
+            show_colours(device, &colours);
+
+            if let Some(locks) = self.locks.as_mut() {
+                locks.refresh();
+            }
+        }
+
+        self.show_locks(device);
+    }
+}
+
+pub fn run_daemon(device: &Keyboard) -> G213Result<Status> {
+    let path = socket_path();
+
+    if UnixStream::connect(&path).is_ok() {
+        eprintln!("A daemon is already listening on {}", path);
+
+        return Ok(Status::Failure);
+    }
+
+    // Left behind by a daemon that didn't exit cleanly
+    let _ = remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Unable to listen on {}: {}", path, err);
+
+            return Ok(Status::Failure);
+        }
+    };
+
+    set_file_ownership_to_me(path.clone());
+
+    listener
+        .set_nonblocking(true)
+        .expect("Unable to set up daemon socket");
+
+    if let Err(err) = hold_device(device) {
+        let _ = remove_file(&path);
+
+        return Err(err);
+    }
+
+    catch_interrupt();
+
+    // The socket still works without a session bus, eg when started at boot
+    let dbus = match config().daemon.dbus {
+        true => serve_dbus(device).map(Some),
+        false => Ok(None),
+    };
+
+    let _dbus = match dbus {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("D-Bus interface unavailable: {}", err);
+
+            None
+        }
+    };
+
+    let hotplug = HotplugWatch::new();
+
+    let resumed = match watch_for_resume() {
+        Ok(receiver) => Some(receiver),
+        Err(err) => {
+            eprintln!("Unable to watch for resume from suspend: {}", err);
+
+            None
+        }
+    };
+    let mut automatic = Automatic::new();
+
+    let mut device = device.clone();
+
+    eprintln!("Listening on {}, press Ctrl-C to stop", path);
+
+    while !interrupted() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let ran = handle_client(&device, stream);
+
+                if let Some(command) = ran {
+                    automatic.shown(&command);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                let timeout = Duration::from_millis(POLL_MS);
+
+                // Waiting on hotplug events doubles as our pause between polls
+                match hotplug.as_ref().map(|watcher| watcher.poll(timeout)) {
+                    Some(Some(HotplugEvent::Arrived(arrived))) => {
+                        device = Keyboard::Usb(arrived);
+
+                        eprintln!("Keyboard plugged in");
+
+                        automatic.announce("plugged");
+
+                        forget_device();
+
+                        match hold_device(&device) {
+                            Ok(()) => {
+                                replay_saved_command(&device);
+
+                                automatic.refresh();
+                            }
+                            Err(err) => eprintln!("{}", err),
+                        }
+                    }
+                    Some(Some(HotplugEvent::Left)) => {
+                        forget_device();
+
+                        automatic.announce("unplugged");
+                    }
+                    Some(None) => {}
+                    None => sleep(timeout),
+                }
+            }
+            Err(err) => eprintln!("Connection failed: {}", err),
+        }
+
+        // The keyboard forgets its colours while suspended
+        if let Some(Ok(())) = resumed.as_ref().map(|receiver| receiver.try_recv()) {
+            eprintln!("Resumed from suspend");
+
+            automatic.announce("resumed");
+
+            replay_saved_command(&device);
+
+            automatic.refresh();
+        }
+
+        automatic.run(&device);
+    }
+
+    let _ = remove_file(&path);
+
+    let_go_of_device()?;
+
+    Ok(Status::SuccessNoSave)
+}
+
+#[cfg(test)]
+mod daemon_tests {
+    use super::*;
+    use crate::models::Model;
+    use crate::transport::FakeTransport;
+    use std::sync::Arc;
+
+    #[test]
+    fn socket_in_runtime_dir() {
+        assert!(socket_path().ends_with(SOCKET_FILE));
+    }
+
+    #[test]
+    fn silent_clients_time_out() {
+        let keyboard = Keyboard::Other(
+            Model::from_name("G213").unwrap(),
+            Arc::new(FakeTransport::default()),
+        );
+        let (stream, _client) = UnixStream::pair().unwrap();
+
+        assert!(handle_client(&keyboard, stream).is_none());
+    }
+
+    #[test]
+    fn wave_is_not_forwarded() {
+        assert!(!can_forward(&Command::Wave(vec!["1000".to_string()])));
+    }
+
+    #[test]
+    fn wal_is_not_forwarded() {
+        assert!(!can_forward(&Command::Wal(vec![])));
+        assert!(!can_forward(&Command::Xresources(vec![])));
+    }
+
+    #[test]
+    fn watch_is_not_forwarded() {
+        assert!(!can_forward(&Command::Watch));
+    }
+
+    #[test]
+    fn raw_is_not_forwarded() {
+        assert!(!can_forward(&Command::Raw(vec![])));
+    }
+
+    #[test]
+    fn what_only_prints_is_not_forwarded() {
+        assert!(!can_forward(&Command::Info));
+        assert!(!can_forward(&Command::Calibrate(vec![])));
+        assert!(can_forward(&Command::Calibrate(vec![
+            "100".to_string(),
+            "90".to_string(),
+            "80".to_string()
+        ])));
+    }
+
+    #[test]
+    fn colour_is_forwarded() {
+        assert!(can_forward(&Command::Colour(vec![])));
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use zbus::blocking::connection::Builder;
+use zbus::blocking::{Connection, MessageIterator, Proxy};
+use zbus::zvariant::OwnedValue;
+use zbus::{interface, Message};
+
+use crate::commands::{get_saved_command, save_command, Command, Run, Status, Successful};
+use crate::g213_keyboard::Keyboard;
+
+const BUS_NAME: &str = "org.g213cols.Controller";
+const OBJECT_PATH: &str = "/org/g213cols/Controller";
+
+const LOGIND_NAME: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+
+// GNOME, on X11 or Wayland, in ms
+const MUTTER_IDLE_NAME: &str = "org.gnome.Mutter.IdleMonitor";
+const MUTTER_IDLE_PATH: &str = "/org/gnome/Mutter/IdleMonitor/Core";
+
+// KDE and others, in seconds
+const SCREENSAVER_NAME: &str = "org.freedesktop.ScreenSaver";
+const SCREENSAVER_PATH: &str = "/org/freedesktop/ScreenSaver";
+
+const DBUS_NAME: &str = "org.freedesktop.DBus";
+const DBUS_PATH: &str = "/org/freedesktop/DBus";
+const DBUS_MONITORING: &str = "org.freedesktop.DBus.Monitoring";
+
+const NOTIFICATIONS_NAME: &str = "org.freedesktop.Notifications";
+
+// When a notification doesn't say how urgent it is
+const NORMAL_URGENCY: u8 = 1;
+
+// The arguments to Notify, from the notification spec
+type Notify = (
+    String,
+    u32,
+    String,
+    String,
+    String,
+    Vec<String>,
+    HashMap<String, OwnedValue>,
+    i32,
+);
+
+struct Controller {
+    device: Keyboard,
+}
+
+fn colour_args(colour: &str) -> Vec<String> {
+    colour.split_whitespace().map(|s| s.to_string()).collect()
+}
+
+impl Controller {
+    // Runs and saves the command exactly as if it came from the command line
+    fn run(&self, command: Command) -> bool {
+        let status = command.run(&self.device);
+
+        if Status::Success == status {
+            save_command(&command);
+        }
+
+        status.successful()
+    }
+}
+
+#[interface(name = "org.g213cols.Controller")]
+impl Controller {
+    fn set_colour(&self, colour: &str) -> bool {
+        self.run(Command::Colour(colour_args(colour)))
+    }
+
+    fn set_region(&self, region: u8, colour: &str) -> bool {
+        let mut args = vec![region.to_string()];
+        args.extend(colour_args(colour));
+
+        self.run(Command::Region(args))
+    }
+
+    fn breathe(&self, speed: u16, colour: &str) -> bool {
+        let mut args = vec![speed.to_string()];
+        args.extend(colour_args(colour));
+
+        self.run(Command::Breathe(args))
+    }
+
+    fn cycle(&self, speed: u16) -> bool {
+        self.run(Command::Cycle(vec![speed.to_string()]))
+    }
+
+    #[zbus(property)]
+    fn saved(&self) -> String {
+        match get_saved_command() {
+            Some(cmd) => cmd.to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+// The connection must be kept alive for the interface to stay on the bus
+pub fn serve_dbus(device: &Keyboard) -> zbus::Result<Connection> {
+    let controller = Controller {
+        device: device.clone(),
+    };
+
+    Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, controller)?
+        .build()
+}
+
+// logind sends PrepareForSleep(true) before suspending and PrepareForSleep(false) on resume
+pub fn watch_for_resume() -> zbus::Result<Receiver<()>> {
+    let connection = Connection::system()?;
+    let (resumed, receiver) = channel();
+
+    // Subscribe before returning, so a failure is reported to the caller
+    let proxy = Proxy::new(&connection, LOGIND_NAME, LOGIND_PATH, LOGIND_MANAGER)?;
+    let signals = proxy.receive_signal("PrepareForSleep")?;
+
+    thread::spawn(move || {
+        let _connection = connection;
+
+        for signal in signals {
+            if let Ok(false) = signal.body().deserialize::<bool>() {
+                if resumed.send(()).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// How long since the keyboard or mouse was last used, from the desktop,
+/// when it can tell us.
+pub fn session_idle_time(connection: &Connection) -> Option<Duration> {
+    let mutter = Proxy::new(
+        connection,
+        MUTTER_IDLE_NAME,
+        MUTTER_IDLE_PATH,
+        MUTTER_IDLE_NAME,
+    )
+    .and_then(|proxy| proxy.call::<_, _, u64>("GetIdletime", &()));
+
+    if let Ok(ms) = mutter {
+        return Some(Duration::from_millis(ms));
+    }
+
+    Proxy::new(
+        connection,
+        SCREENSAVER_NAME,
+        SCREENSAVER_PATH,
+        SCREENSAVER_NAME,
+    )
+    .and_then(|proxy| proxy.call::<_, _, u32>("GetSessionIdleTime", &()))
+    .ok()
+    .map(|seconds| Duration::from_secs(seconds as u64))
+}
+
+// 0 for low to 2 for critical, when it's a notification being sent
+fn notification_urgency(message: &Message) -> Option<u8> {
+    let header = message.header();
+
+    if header.interface()?.as_str() != NOTIFICATIONS_NAME || header.member()?.as_str() != "Notify" {
+        return None;
+    }
+
+    let (_, _, _, _, _, _, hints, _): Notify = message.body().deserialize().ok()?;
+
+    Some(
+        hints
+            .get("urgency")
+            .and_then(|urgency| urgency.downcast_ref::<u8>().ok())
+            .unwrap_or(NORMAL_URGENCY),
+    )
+}
+
+/// The urgency of each desktop notification as it's sent, by watching the
+/// session bus as a monitor, as `dbus-monitor` does.
+pub fn watch_notifications() -> zbus::Result<Receiver<u8>> {
+    let connection = Connection::session()?;
+    let rule = format!(
+        "type='method_call',interface='{}',member='Notify'",
+        NOTIFICATIONS_NAME
+    );
+
+    // Subscribe before returning, so a failure is reported to the caller
+    connection.call_method(
+        Some(DBUS_NAME),
+        DBUS_PATH,
+        Some(DBUS_MONITORING),
+        "BecomeMonitor",
+        &(&[rule.as_str()] as &[&str], 0u32),
+    )?;
+
+    let (sender, receiver) = channel();
+
+    thread::spawn(move || {
+        for message in MessageIterator::from(connection).flatten() {
+            if let Some(urgency) = notification_urgency(&message) {
+                if sender.send(urgency).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod dbus_tests {
+    use super::*;
+
+    #[test]
+    fn colour_words_become_args() {
+        assert_eq!(
+            colour_args(" alice  blue "),
+            vec!["alice".to_string(), "blue".to_string()]
+        );
+    }
+
+    #[test]
+    fn notifications_and_their_urgency() {
+        let notify = |hints: HashMap<&str, zbus::zvariant::Value>| {
+            let body = (
+                "app",
+                0u32,
+                "",
+                "Summary",
+                "Body",
+                Vec::<&str>::new(),
+                hints,
+                -1i32,
+            );
+
+            Message::method("/org/freedesktop/Notifications", "Notify")
+                .unwrap()
+                .interface(NOTIFICATIONS_NAME)
+                .unwrap()
+                .build(&body)
+                .unwrap()
+        };
+
+        let critical = HashMap::from([("urgency", 2u8.into())]);
+
+        assert_eq!(notification_urgency(&notify(critical)), Some(2));
+        assert_eq!(notification_urgency(&notify(HashMap::new())), Some(1));
+
+        let other = Message::method("/org/freedesktop/Notifications", "GetServerInformation")
+            .unwrap()
+            .interface(NOTIFICATIONS_NAME)
+            .unwrap()
+            .build(&())
+            .unwrap();
+
+        assert_eq!(notification_urgency(&other), None);
+    }
+}
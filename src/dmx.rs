@@ -0,0 +1,287 @@
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::config::DmxConfig;
+
+const SACN_PORT: u16 = 5568;
+const ARTNET_PORT: u16 = 6454;
+
+const SACN_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const SACN_DATA: u32 = 0x04;
+const SACN_PREVIEW: u8 = 0x80;
+const SACN_TERMINATED: u8 = 0x40;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_DMX: u16 = 0x5000;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Protocol {
+    Sacn,
+    Artnet,
+}
+
+/// What a DMX packet says for a universe.
+#[derive(PartialEq, Debug)]
+pub enum Frame<'a> {
+    Levels(u16, &'a [u8]),
+    Stopped(u16),
+}
+
+fn be16(packet: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(packet.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// An E1.31 data packet's universe and channel levels.
+pub fn parse_sacn(packet: &[u8]) -> Option<Frame<'_>> {
+    if packet.get(4..16)? != SACN_ID
+        || u32::from_be_bytes(packet.get(18..22)?.try_into().ok()?) != SACN_DATA
+    {
+        return None;
+    }
+
+    let options = *packet.get(112)?;
+    let universe = be16(packet, 113)?;
+
+    // Previews are for the console's own visualiser
+    if options & SACN_PREVIEW != 0 {
+        return None;
+    }
+
+    if options & SACN_TERMINATED != 0 {
+        return Some(Frame::Stopped(universe));
+    }
+
+    // The first value is the start code, 0 for levels
+    let count = be16(packet, 123)? as usize;
+
+    match packet.get(125)? {
+        0 => Some(Frame::Levels(universe, packet.get(126..125 + count)?)),
+        _ => None,
+    }
+}
+
+/// An ArtDmx packet's universe and channel levels.
+pub fn parse_artnet(packet: &[u8]) -> Option<Frame<'_>> {
+    if packet.get(..8)? != ARTNET_ID
+        || u16::from_le_bytes(packet.get(8..10)?.try_into().ok()?) != ARTNET_DMX
+    {
+        return None;
+    }
+
+    // The 15 bit port address, from the net and sub-net and universe
+    let universe = (*packet.get(15)? as u16 & 0x7f) << 8 | *packet.get(14)? as u16;
+    let length = be16(packet, 16)? as usize;
+
+    Some(Frame::Levels(universe, packet.get(18..18 + length)?))
+}
+
+/// Each region's red, green and blue from 3 channels, starting at channel
+/// `address`, counting from 1 as consoles do. Missing channels are off.
+pub fn dmx_colours(levels: &[u8], address: u16, regions: u8) -> Vec<u32> {
+    let level = |channel: usize| *levels.get(channel).unwrap_or(&0) as u32;
+    let first = address.saturating_sub(1) as usize;
+
+    (0..regions as usize)
+        .map(|region| {
+            let channel = first + region * 3;
+
+            level(channel) << 16 | level(channel + 1) << 8 | level(channel + 2)
+        })
+        .collect()
+}
+
+#[derive(PartialEq, Debug)]
+pub enum DmxChange {
+    Colours(Vec<u32>),
+    Stopped,
+}
+
+/// A DMX universe from a lighting console, from the config's `[dmx]`, which
+/// the daemon shows on the keyboard while it's being sent.
+pub struct Dmx {
+    socket: UdpSocket,
+    protocol: Protocol,
+    universe: u16,
+    address: u16,
+    timeout: Duration,
+    received: Option<Instant>,
+    shown: Option<Vec<u32>>,
+}
+
+impl Dmx {
+    /// Nothing if `[dmx]` isn't set up.
+    pub fn new(config: &DmxConfig) -> Result<Option<Dmx>, String> {
+        let protocol = match config.protocol.as_deref() {
+            None => return Ok(None),
+            Some("sacn") => Protocol::Sacn,
+            Some("artnet") => Protocol::Artnet,
+            Some(other) => {
+                return Err(format!(
+                    "Bad [dmx] protocol '{}', use sacn or artnet",
+                    other
+                ))
+            }
+        };
+
+        if !(1..=512).contains(&config.address) {
+            return Err(format!(
+                "Bad [dmx] address {}, use 1 to 512",
+                config.address
+            ));
+        }
+
+        let port = match protocol {
+            Protocol::Sacn => SACN_PORT,
+            Protocol::Artnet => ARTNET_PORT,
+        };
+
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+            .map_err(|err| format!("Unable to listen for DMX on port {}: {}", port, err))?;
+
+        socket
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+
+        // Consoles multicast sACN to a group for each universe
+        if protocol == Protocol::Sacn {
+            let [high, low] = config.universe.to_be_bytes();
+
+            if let Err(err) = socket
+                .join_multicast_v4(&Ipv4Addr::new(239, 255, high, low), &Ipv4Addr::UNSPECIFIED)
+            {
+                eprintln!("Only unicast sACN will be seen: {}", err);
+            }
+        }
+
+        Ok(Some(Dmx {
+            socket,
+            protocol,
+            universe: config.universe,
+            address: config.address,
+            timeout: Duration::from_secs(config.timeout.max(1) as u64),
+            received: None,
+            shown: None,
+        }))
+    }
+
+    // The latest levels for our universe, from all the packets waiting
+    fn receive(&mut self, regions: u8) -> Option<DmxChange> {
+        let mut buffer = [0u8; 1024];
+        let mut change = None;
+
+        while let Ok(length) = self.socket.recv(&mut buffer) {
+            let packet = &buffer[..length];
+            let frame = match self.protocol {
+                Protocol::Sacn => parse_sacn(packet),
+                Protocol::Artnet => parse_artnet(packet),
+            };
+
+            change = match frame {
+                Some(Frame::Levels(universe, levels)) if universe == self.universe => Some(
+                    DmxChange::Colours(dmx_colours(levels, self.address, regions)),
+                ),
+                Some(Frame::Stopped(universe)) if universe == self.universe => {
+                    Some(DmxChange::Stopped)
+                }
+                _ => change,
+            };
+        }
+
+        change
+    }
+
+    /// New colours to show, or that the console has stopped sending and the
+    /// lighting should be put back.
+    pub fn due(&mut self, regions: u8) -> Option<DmxChange> {
+        let change = match self.receive(regions) {
+            Some(change) => change,
+            None => match self.received {
+                Some(received) if received.elapsed() > self.timeout => DmxChange::Stopped,
+                _ => return None,
+            },
+        };
+
+        match change {
+            DmxChange::Colours(colours) => {
+                self.received = Some(Instant::now());
+
+                // Consoles keep resending, even when nothing's changed
+                if self.shown.as_ref() == Some(&colours) {
+                    return None;
+                }
+
+                self.shown = Some(colours.clone());
+
+                Some(DmxChange::Colours(colours))
+            }
+            DmxChange::Stopped => {
+                self.received = None;
+
+                self.shown.take().map(|_| DmxChange::Stopped)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod dmx_tests {
+    use super::*;
+
+    fn sacn(universe: u16, options: u8, levels: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; 126];
+
+        packet[4..16].copy_from_slice(SACN_ID);
+        packet[18..22].copy_from_slice(&SACN_DATA.to_be_bytes());
+        packet[112] = options;
+        packet[113..115].copy_from_slice(&universe.to_be_bytes());
+        packet[123..125].copy_from_slice(&(levels.len() as u16 + 1).to_be_bytes());
+        packet.extend(levels);
+        packet
+    }
+
+    fn artnet(universe: u16, levels: &[u8]) -> Vec<u8> {
+        let mut packet = ARTNET_ID.to_vec();
+
+        packet.extend(ARTNET_DMX.to_le_bytes());
+        packet.extend([0, 14, 0, 0]);
+        packet.extend([(universe & 0xff) as u8, (universe >> 8) as u8]);
+        packet.extend((levels.len() as u16).to_be_bytes());
+        packet.extend(levels);
+        packet
+    }
+
+    #[test]
+    fn sacn_packets() {
+        assert_eq!(
+            parse_sacn(&sacn(1, 0, &[1, 2, 3])),
+            Some(Frame::Levels(1, &[1, 2, 3][..]))
+        );
+        assert_eq!(
+            parse_sacn(&sacn(2, SACN_TERMINATED, &[])),
+            Some(Frame::Stopped(2))
+        );
+        assert_eq!(parse_sacn(&sacn(1, SACN_PREVIEW, &[1])), None);
+        assert_eq!(parse_sacn(&sacn(1, 0, &[1])[..100]), None);
+        assert_eq!(parse_sacn(&artnet(1, &[1])), None);
+    }
+
+    #[test]
+    fn artnet_packets() {
+        assert_eq!(
+            parse_artnet(&artnet(0x0102, &[9, 8])),
+            Some(Frame::Levels(0x0102, &[9, 8][..]))
+        );
+        assert_eq!(parse_artnet(&sacn(1, 0, &[1])), None);
+    }
+
+    #[test]
+    fn channels_to_regions() {
+        let levels: Vec<u8> = (1..=16).collect();
+
+        assert_eq!(
+            dmx_colours(&levels, 1, 5),
+            [0x010203, 0x040506, 0x070809, 0x0a0b0c, 0x0d0e0f]
+        );
+        assert_eq!(dmx_colours(&levels, 14, 2), [0x0e0f10, 0]);
+    }
+}
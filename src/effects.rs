@@ -0,0 +1,377 @@
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use rand::random;
+
+use crate::g213_keyboard::scale_colour;
+use crate::x11_colours::mix_colours;
+
+// Embers glow dark red, flames burn orange, and the hottest are yellow
+const EMBER: u32 = 0x300000;
+const FLAME: u32 = 0xff2800;
+const BLAZE: u32 = 0xffb020;
+
+// Digital rain, with pale heads on the drops that fade to green
+const RAIN: u32 = 0x00ff41;
+const RAIN_HEAD: u32 = 0xc0ffc0;
+const RAIN_FADE: f64 = 0.75;
+const RAIN_CHANCE: f64 = 0.15;
+
+// Sparkles glint whiter than the colour, over a dim version of it
+const SPARKLE_BASE: u32 = 15;
+const SPARKLE_GLINT: f64 = 50.0;
+const SPARKLE_FADE: f64 = 0.6;
+const WHITE: u32 = 0xffffff;
+
+// At most 2.5 flashes a second, below the 3 that can trigger seizures
+pub const MIN_BLINK: Duration = Duration::from_millis(200);
+
+/// A lighting effect drawn in software, a frame at a time.
+pub trait Effect {
+    /// Each region's colour `elapsed` after the effect started, or nothing
+    /// once it's finished.
+    fn frame(&mut self, elapsed: Duration, regions: u8) -> Option<Vec<u32>>;
+}
+
+impl<F: FnMut(Duration, u8) -> Option<Vec<u32>>> Effect for F {
+    fn frame(&mut self, elapsed: Duration, regions: u8) -> Option<Vec<u32>> {
+        self(elapsed, regions)
+    }
+}
+
+fn noise(regions: u8) -> Vec<f64> {
+    (0..regions).map(|_| random()).collect()
+}
+
+/// The colour and off in turn, each for `interval`, `count` times or for
+/// ever. Nothing once they've all been shown.
+pub fn blink_colours(
+    elapsed: Duration,
+    interval: Duration,
+    count: Option<u32>,
+    colour: u32,
+    regions: u8,
+) -> Option<Vec<u32>> {
+    let step = elapsed.as_millis() / interval.as_millis().max(1);
+
+    if count.is_some_and(|count| step >= 2 * count as u128) {
+        return None;
+    }
+
+    match step % 2 {
+        0 => Some(vec![colour; regions as usize]),
+        _ => Some(vec![0; regions as usize]),
+    }
+}
+
+/// One colour on the left half and the other on the right, swapping every
+/// `interval`. The middle region of an odd number is left off.
+pub fn alternate_colours(
+    elapsed: Duration,
+    interval: Duration,
+    (first, second): (u32, u32),
+    regions: u8,
+) -> Vec<u32> {
+    let (left, right) = match (elapsed.as_millis() / interval.as_millis().max(1)) % 2 {
+        0 => (first, second),
+        _ => (second, first),
+    };
+
+    (0..regions)
+        .map(|region| match (region * 2 + 1).cmp(&regions) {
+            Ordering::Less => left,
+            Ordering::Equal => 0,
+            Ordering::Greater => right,
+        })
+        .collect()
+}
+
+/// The colour of a fire at `heat`, from 0 to 1.
+pub fn fire_colour(heat: f64) -> u32 {
+    match heat.clamp(0.0, 1.0) {
+        heat if heat < 0.5 => mix_colours(EMBER, FLAME, heat * 200.0),
+        heat => mix_colours(FLAME, BLAZE, (heat - 0.5) * 200.0),
+    }
+}
+
+/// Each region's heat, flickering at random around how fierce the fire is.
+pub struct Fire {
+    heat: Vec<f64>,
+    intensity: f64,
+    speed: f64,
+}
+
+impl Fire {
+    /// `intensity` and `speed` from 1 to 100, for a fierce or a fast fire.
+    pub fn new(regions: u8, intensity: u8, speed: u8) -> Fire {
+        let intensity = intensity as f64 / 100.0;
+
+        Fire {
+            heat: vec![intensity; regions as usize],
+            intensity,
+            speed: speed as f64 / 100.0,
+        }
+    }
+
+    /// The colours after each region's heat takes a step, at random with
+    /// `noise` from 0 to 1 for each, drifting back towards the intensity.
+    pub fn step(&mut self, noise: &[f64]) -> Vec<u32> {
+        for (heat, noise) in self.heat.iter_mut().zip(noise) {
+            let flicker = (noise - 0.5) * self.speed * 0.6;
+            let drift = (self.intensity - *heat) * self.speed * 0.3;
+
+            *heat = (*heat + flicker + drift).clamp(0.0, 1.0);
+        }
+
+        // A little of each region's neighbours, so flames spread
+        let heat = self.heat.clone();
+
+        for (region, heat_here) in self.heat.iter_mut().enumerate() {
+            let left = heat[region.saturating_sub(1)];
+            let right = *heat.get(region + 1).unwrap_or(&heat[region]);
+
+            *heat_here = 0.8 * *heat_here + 0.1 * (left + right);
+        }
+
+        self.heat.iter().map(|heat| fire_colour(*heat)).collect()
+    }
+}
+
+impl Effect for Fire {
+    fn frame(&mut self, _: Duration, regions: u8) -> Option<Vec<u32>> {
+        Some(self.step(&noise(regions)))
+    }
+}
+
+/// Drops of digital rain landing on the regions at random, each fading
+/// a little every tick.
+#[derive(Default)]
+pub struct Matrix {
+    levels: Vec<f64>,
+}
+
+impl Matrix {
+    /// The colours after a tick, with a new drop on each region where
+    /// `drops` is true.
+    pub fn step(&mut self, drops: &[bool]) -> Vec<u32> {
+        self.levels.resize(drops.len(), 0.0);
+
+        self.levels
+            .iter_mut()
+            .zip(drops)
+            .map(|(level, drop)| {
+                *level = match drop {
+                    true => 1.0,
+                    false => *level * RAIN_FADE,
+                };
+
+                match *level {
+                    1.0 => RAIN_HEAD,
+                    level => scale_colour(RAIN, (level * 100.0).round() as u32),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Effect for Matrix {
+    fn frame(&mut self, _: Duration, regions: u8) -> Option<Vec<u32>> {
+        let drops: Vec<bool> = noise(regions)
+            .iter()
+            .map(|noise| *noise < RAIN_CHANCE)
+            .collect();
+
+        Some(self.step(&drops))
+    }
+}
+
+/// Regions glinting at random, and fading back, over a dim colour.
+pub struct Sparkle {
+    colour: u32,
+    chance: f64,
+    levels: Vec<f64>,
+}
+
+impl Sparkle {
+    /// `density` from 1 to 100, the chance of each region glinting each tick.
+    pub fn new(colour: u32, density: u8) -> Sparkle {
+        Sparkle {
+            colour,
+            chance: density as f64 / 100.0,
+            levels: Vec::new(),
+        }
+    }
+
+    /// The colours after a tick, with the regions where `glints` is true
+    /// glinting.
+    pub fn step(&mut self, glints: &[bool]) -> Vec<u32> {
+        let base = scale_colour(self.colour, SPARKLE_BASE);
+        let glint = mix_colours(self.colour, WHITE, SPARKLE_GLINT);
+
+        self.levels.resize(glints.len(), 0.0);
+
+        self.levels
+            .iter_mut()
+            .zip(glints)
+            .map(|(level, glints)| {
+                *level = match glints {
+                    true => 1.0,
+                    false => *level * SPARKLE_FADE,
+                };
+
+                mix_colours(base, glint, *level * 100.0)
+            })
+            .collect()
+    }
+}
+
+impl Effect for Sparkle {
+    fn frame(&mut self, _: Duration, regions: u8) -> Option<Vec<u32>> {
+        let glints: Vec<bool> = noise(regions)
+            .iter()
+            .map(|noise| *noise < self.chance)
+            .collect();
+
+        Some(self.step(&glints))
+    }
+}
+
+/// The whole keyboard fading from each colour to the next, taking
+/// `interval` for each, and back round to the first.
+pub fn cross_fade_colours(
+    elapsed: Duration,
+    interval: Duration,
+    colours: &[u32],
+    regions: u8,
+) -> Vec<u32> {
+    let interval = interval.as_millis().max(1);
+    let step = elapsed.as_millis() / interval;
+    let into = (elapsed.as_millis() % interval) as f64 / interval as f64;
+
+    let from = colours[step as usize % colours.len()];
+    let to = colours[(step as usize + 1) % colours.len()];
+
+    vec![mix_colours(from, to, into * 100.0); regions as usize]
+}
+
+/// The brightness `elapsed` into breathing, from `min` to `max` percent
+/// and back over `period`, starting at `min`.
+pub fn pulse_level(elapsed: Duration, period: Duration, min: u8, max: u8) -> u32 {
+    let angle = 2.0 * PI * elapsed.as_secs_f64() / period.as_secs_f64().max(0.001);
+    let (min, max) = (min as f64, max as f64);
+
+    (min + (max - min) * (0.5 - 0.5 * angle.cos())).round() as u32
+}
+
+#[cfg(test)]
+mod effects_tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn blinking() {
+        let blink = |elapsed, count| blink_colours(ms(elapsed), ms(500), count, 0xff0000, 2);
+
+        assert_eq!(blink(0, Some(2)), Some(vec![0xff0000; 2]));
+        assert_eq!(blink(600, Some(2)), Some(vec![0; 2]));
+        assert_eq!(blink(1100, Some(2)), Some(vec![0xff0000; 2]));
+        assert_eq!(blink(2000, Some(2)), None);
+        assert_eq!(blink(1_000_000, None), Some(vec![0xff0000; 2]));
+    }
+
+    #[test]
+    fn alternating() {
+        let colours = (0xff0000, 0x0000ff);
+
+        assert_eq!(
+            alternate_colours(ms(0), ms(300), colours, 5),
+            [0xff0000, 0xff0000, 0, 0x0000ff, 0x0000ff]
+        );
+        assert_eq!(
+            alternate_colours(ms(400), ms(300), colours, 4),
+            [0x0000ff, 0x0000ff, 0xff0000, 0xff0000]
+        );
+    }
+
+    #[test]
+    fn fire_colours() {
+        assert_eq!(fire_colour(0.0), EMBER);
+        assert_eq!(fire_colour(0.5), FLAME);
+        assert_eq!(fire_colour(1.0), BLAZE);
+        assert_eq!(fire_colour(2.0), BLAZE);
+    }
+
+    #[test]
+    fn flickering() {
+        let mut fire = Fire::new(3, 50, 100);
+
+        // Without any noise it stays as fierce as it was asked to be
+        assert_eq!(fire.step(&[0.5; 3]), [FLAME; 3]);
+
+        let colours = fire.step(&[1.0, 0.5, 0.0]);
+
+        assert!(colours[0] > colours[1] && colours[1] > colours[2]);
+
+        // And settles back down
+        for _ in 0..50 {
+            fire.step(&[0.5; 3]);
+        }
+
+        assert_eq!(fire.step(&[0.5; 3]), [FLAME; 3]);
+    }
+
+    #[test]
+    fn raining() {
+        let mut matrix = Matrix::default();
+
+        assert_eq!(matrix.step(&[true, false]), [RAIN_HEAD, 0]);
+        assert_eq!(
+            matrix.step(&[false, true]),
+            [scale_colour(RAIN, 75), RAIN_HEAD]
+        );
+        assert_eq!(
+            matrix.step(&[false, false]),
+            [scale_colour(RAIN, 56), scale_colour(RAIN, 75)]
+        );
+    }
+
+    #[test]
+    fn effects_from_closures() {
+        let mut effect =
+            |elapsed: Duration, regions| Some(vec![elapsed.as_millis() as u32; regions as usize]);
+
+        assert_eq!(effect.frame(ms(5), 2), Some(vec![5, 5]));
+    }
+
+    #[test]
+    fn sparkling() {
+        let mut sparkle = Sparkle::new(0x0000ff, 20);
+
+        assert_eq!(sparkle.step(&[true, false]), [0x8080ff, 0x000026]);
+        assert_eq!(sparkle.step(&[false, false]), [0x4d4da8, 0x000026]);
+    }
+
+    #[test]
+    fn cross_fading() {
+        let colours = [0xff0000, 0x0000ff, 0x00ff00];
+        let fade = |elapsed| cross_fade_colours(ms(elapsed), ms(1000), &colours, 2);
+
+        assert_eq!(fade(0), [0xff0000; 2]);
+        assert_eq!(fade(500), [0x800080; 2]);
+        assert_eq!(fade(1000), [0x0000ff; 2]);
+        assert_eq!(fade(2500), [0x808000; 2]);
+        assert_eq!(fade(3000), [0xff0000; 2]);
+    }
+
+    #[test]
+    fn pulsing() {
+        assert_eq!(pulse_level(ms(0), ms(2000), 20, 90), 20);
+        assert_eq!(pulse_level(ms(500), ms(2000), 20, 90), 55);
+        assert_eq!(pulse_level(ms(1000), ms(2000), 20, 90), 90);
+        assert_eq!(pulse_level(ms(2000), ms(2000), 0, 100), 0);
+    }
+}
@@ -1,24 +1,276 @@
-use rusb::{devices, Device, DeviceDescriptor, DeviceHandle, Error, GlobalContext};
-use std::time::Duration;
+use rusb::{
+    devices, Context, Device, DeviceDescriptor, DeviceHandle, Error, GlobalContext, UsbContext,
+};
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::calibration::{
+    calibrate_colour, load_calibrations, regions_corrected, white_point, WhitePoint, NO_SERIAL,
+    WHITE,
+};
+use crate::hidpp::{check_reply, hidpp_info, HidppInfo};
+use crate::models::{models, supported_device, Lighting, Model};
+use crate::trace::{record, Direction, Transfer};
+use crate::transport::{DryRun, Transport};
 
 pub const NUM_REGIONS: u8 = 5;
 
 pub const LOGITECH: u16 = 0x046d; // Vendor
-pub const G213: u16 = 0xc336; // Device
-
-const ENDPOINT: u8 = 0x82; // Read Interrupt
 
-const REQ_TYPE: u8 = 0x21;
-const REQ: u8 = 0x09;
-const VALUE: u16 = 0x0211;
-const INDEX: u16 = 0x0001;
-const CMD_LEN: usize = 20;
+pub(crate) const CMD_LEN: usize = 20;
 const TIMEOUT_MS: u64 = 50;
+const MAX_READS: usize = 3;
+
+pub const MIN_SPEED: u16 = 32;
+
+pub const MAX_BRIGHTNESS: u8 = 100;
+
+#[derive(Debug, Error)]
+pub enum G213Error {
+    #[error("No supported keyboard found, sorry!")]
+    NotFound,
+    #[error("Permission denied, run as root or see 'install-udev'")]
+    Permission,
+    #[error("Unable to open device: {0}")]
+    Open(Error),
+    #[error("Unable to detach kernel USB driver: {0}")]
+    Detach(Error),
+    #[error("Unable to claim interface for device: {0}")]
+    Claim(Error),
+    #[error("Unable to release interface for device: {0}")]
+    Release(Error),
+    #[error("Unable to attach kernel USB driver: {0}")]
+    Attach(Error),
+    #[error("Unable to send command to keyboard: {0}")]
+    WriteControl(Error),
+    #[error("No reply from keyboard: {0}")]
+    ReadInterrupt(Error),
+    #[error("Unable to read keyboard info: {0}")]
+    Info(Error),
+    #[error("The {0} has no region {1}")]
+    NoSuchRegion(&'static str, u8),
+    #[error("Keyboard rejected the request with HID++ error {0}")]
+    Hidpp(u8),
+    #[error("Keyboard didn't reply to the command")]
+    UnexpectedReply,
+    #[error("Badly formed keyboard command: {0}")]
+    Command(#[from] hex::FromHexError),
+}
+
+pub type G213Result<T> = Result<T, G213Error>;
+
+// Access errors mean the same thing whichever step they happen in
+fn usb_error(wrap: fn(Error) -> G213Error) -> impl Fn(Error) -> G213Error + Copy {
+    move |err| match err {
+        Error::Access => G213Error::Permission,
+        err => wrap(err),
+    }
+}
+
+// Brightness (%) of the regions either side of the wave's crest
+const WAVE_TRAIL: [u32; 3] = [100, 30, 5];
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Set by '--verbose', shows each command as it is sent
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::SeqCst);
+}
+
+/// How often to try a command again when the keyboard isn't ready, eg just
+/// after it's plugged in, waiting `delay` and then twice as long each time.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u8,
+    pub delay: Duration,
+}
+
+const USUAL_RETRY_POLICY: RetryPolicy = RetryPolicy {
+    retries: 3,
+    delay: Duration::from_millis(20),
+};
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        USUAL_RETRY_POLICY
+    }
+}
+
+// Set once at startup, from config.toml
+static RETRY_POLICY: Mutex<RetryPolicy> = Mutex::new(USUAL_RETRY_POLICY);
+
+pub fn set_retry_policy(policy: RetryPolicy) {
+    *RETRY_POLICY.lock().unwrap() = policy;
+}
+
+// Glitches worth trying again, rather than a keyboard that's gone or saying no
+fn transient(err: &G213Error) -> bool {
+    matches!(
+        err,
+        G213Error::WriteControl(Error::Pipe | Error::Busy | Error::Interrupted | Error::Timeout)
+            | G213Error::ReadInterrupt(Error::Pipe | Error::Busy | Error::Interrupted)
+    )
+}
+
+/// A keyboard to send commands to, or just print them for with `--dry-run`.
+///
+/// `Other` talks to the model over any [`Transport`], eg a [`FakeTransport`](crate::transport::FakeTransport) in tests.
+#[derive(Clone)]
+pub enum Keyboard {
+    Usb(Device<GlobalContext>),
+    DryRun(&'static Model),
+    Other(&'static Model, Arc<dyn Transport>),
+}
+
+impl Keyboard {
+    pub fn model(&self) -> Option<&'static Model> {
+        match self {
+            Keyboard::Usb(device) => model_of(device),
+            Keyboard::DryRun(model) | Keyboard::Other(model, _) => Some(model),
+        }
+    }
+}
+
+// Giving a claimed interface back to the kernel, apart from the handle so
+// tests can see it happen
+trait Release {
+    fn release_interface(&mut self, interface: u8) -> rusb::Result<()>;
+    fn attach_kernel_driver(&mut self, interface: u8) -> rusb::Result<()>;
+}
+
+impl Release for DeviceHandle<GlobalContext> {
+    fn release_interface(&mut self, interface: u8) -> rusb::Result<()> {
+        DeviceHandle::release_interface(self, interface)
+    }
+
+    fn attach_kernel_driver(&mut self, interface: u8) -> rusb::Result<()> {
+        DeviceHandle::attach_kernel_driver(self, interface)
+    }
+}
+
+// A keyboard's claimed interface, shared by everything using the keyboard.
+// Its handle is locked for each transfer, so replies can't go to the wrong
+// command, and whoever lets go of it last gives it back to the kernel
+struct Claimed<H: Release = DeviceHandle<GlobalContext>> {
+    handle: Mutex<H>,
+    interface: u8,
+    kernel_driver_detached: bool,
+    released: bool,
+}
+
+impl<H: Release> Claimed<H> {
+    fn new(handle: H, interface: u8, kernel_driver_detached: bool) -> Claimed<H> {
+        Claimed {
+            handle: Mutex::new(handle),
+            interface,
+            kernel_driver_detached,
+            released: false,
+        }
+    }
+
+    fn give_back(&mut self) -> G213Result<()> {
+        self.released = true;
+
+        let handle = self.handle.get_mut().unwrap();
+
+        handle
+            .release_interface(self.interface)
+            .map_err(usb_error(G213Error::Release))?;
+
+        // Let the kernel take over again
+        if self.kernel_driver_detached {
+            handle
+                .attach_kernel_driver(self.interface)
+                .map_err(usb_error(G213Error::Attach))?;
+        }
+
+        Ok(())
+    }
+
+    fn release(mut self) -> G213Result<()> {
+        self.give_back()
+    }
+}
+
+// When a command that didn't hold the keyboard was the last to use it
+impl<H: Release> Drop for Claimed<H> {
+    fn drop(&mut self) {
+        if !self.released {
+            let _ = self.give_back();
+        }
+    }
+}
+
+// Only a real keyboard has an interface to give back to the kernel. It's
+// shared, so a held keyboard can be used without keeping it locked
+#[derive(Clone)]
+enum Link {
+    Usb(Arc<Claimed>),
+    Other(Arc<dyn Transport>),
+}
 
-const MIN_SPEED: u16 = 32;
+// A keyboard with its interface claimed, ready for commands
+#[derive(Clone)]
+pub(crate) struct OpenKeyboard {
+    link: Link,
+    model: &'static Model,
+    // Previews and tests see the colours as given, the keyboard, and
+    // '--dry-run', what's really sent
+    white_point: Option<WhitePoint>,
+}
+
+impl OpenKeyboard {
+    // A write and the reads for its reply are never split up by another's
+    fn transfer<T>(&self, transfer: impl FnOnce(&dyn Transport) -> T) -> T {
+        match &self.link {
+            Link::Usb(claimed) => transfer(&*claimed.handle.lock().unwrap()),
+            Link::Other(transport) => transfer(transport.as_ref()),
+        }
+    }
+
+    fn calibrate(&self, region: u8, colour: u32) -> u32 {
+        match &self.white_point {
+            Some(white) => calibrate_colour(colour, white, region),
+            None => colour,
+        }
+    }
+
+    // Corrected regions need colours of their own, rather than one for them all
+    fn corrects_regions(&self) -> bool {
+        self.white_point.is_some() && self.model.num_regions() > 1 && regions_corrected()
+    }
+}
+
+// Kept open by long-running modes such as the daemon, until all of them, eg
+// a script and the effects it runs, have let go
+struct Held {
+    kb: OpenKeyboard,
+    holds: usize,
+    // To find it again after it's been unplugged
+    serial: Option<String>,
+}
+
+static HELD_HANDLE: Mutex<Option<Held>> = Mutex::new(None);
+
+thread_local! {
+    // The lighting sent to a keyboard, as asked for rather than calibrated, so
+    // what a command did can be looked back on. Kept for each thread, so
+    // previews and tests running alongside don't get mixed in
+    static SENT_LIGHTING: RefCell<Option<(&'static Model, Vec<Lighting>)>> =
+        const { RefCell::new(None) };
+    static PREVIEWING: Cell<bool> = const { Cell::new(false) };
+}
 
 #[repr(u8)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Debug)]
 pub enum KeyboardRegions {
     WholeKeyboard = 0,
     Region1 = 1,
@@ -42,6 +294,71 @@ impl From<u8> for KeyboardRegions {
     }
 }
 
+/// Names for the regions, left to right, as well as their numbers.
+pub const REGION_NAMES: [(u8, &[&str]); 5] = [
+    (1, &["left", "wasd"]),
+    (2, &["centre-left", "center-left"]),
+    (3, &["centre", "center"]),
+    (4, &["right", "arrows"]),
+    (5, &["numpad", "keypad"]),
+];
+
+impl FromStr for KeyboardRegions {
+    type Err = String;
+
+    // 'Centre Left' and 'centre_left' are 'centre-left' too, and 0 is the
+    // whole keyboard
+    fn from_str(region: &str) -> Result<Self, Self::Err> {
+        let unknown = || {
+            let names: Vec<&str> = REGION_NAMES.iter().map(|(_, names)| names[0]).collect();
+
+            format!(
+                "Unknown region '{}', use 1 to {}, or {}",
+                region,
+                NUM_REGIONS,
+                names.join(", ")
+            )
+        };
+
+        if let Ok(number) = region.parse::<u8>() {
+            return match number <= NUM_REGIONS {
+                true => Ok(number.into()),
+                false => Err(unknown()),
+            };
+        }
+
+        let name = region.trim().to_lowercase().replace([' ', '_'], "-");
+
+        REGION_NAMES
+            .iter()
+            .find(|(_, names)| names.contains(&name.as_str()))
+            .map(|(number, _)| (*number).into())
+            .ok_or_else(unknown)
+    }
+}
+
+/// Which keyboard(s) to use when more than one is connected.
+#[derive(PartialEq, Debug, Clone)]
+pub enum DeviceSelector {
+    First,
+    All,
+    BusAddress(u8, u8),
+    Serial(String),
+}
+
+impl From<&str> for DeviceSelector {
+    // 'bus:address', as shown by lsusb, or anything else is a serial number
+    fn from(value: &str) -> Self {
+        if let Some((bus, address)) = value.split_once(':') {
+            if let (Ok(bus), Ok(address)) = (bus.parse::<u8>(), address.parse::<u8>()) {
+                return DeviceSelector::BusAddress(bus, address);
+            }
+        }
+
+        DeviceSelector::Serial(value.to_string())
+    }
+}
+
 pub fn limit_speed(speed: u16) -> u16 {
     if speed < MIN_SPEED {
         MIN_SPEED
@@ -50,6 +367,28 @@ pub fn limit_speed(speed: u16) -> u16 {
     }
 }
 
+pub fn scale_colour(colour: u32, percent: u32) -> u32 {
+    let scale = |c: u32| (c & 0xff) * percent / 100;
+
+    scale(colour >> 16) << 16 | scale(colour >> 8) << 8 | scale(colour)
+}
+
+// Colours for each of 'num' regions when the crest of the wave is at 'step'
+pub fn wave_colours(colour: u32, step: u8, num: u8) -> Vec<u32> {
+    let crest = step % num;
+
+    (0..num)
+        .map(|region| {
+            let distance = region.abs_diff(crest) as usize;
+
+            match WAVE_TRAIL.get(distance) {
+                Some(percent) => scale_colour(colour, *percent),
+                None => 0,
+            }
+        })
+        .collect()
+}
+
 pub trait G213DeviceDescriptor {
     fn vendor_id(&self) -> u16;
     fn product_id(&self) -> u16;
@@ -65,153 +404,924 @@ impl G213DeviceDescriptor for DeviceDescriptor {
     }
 }
 
-pub fn is_g213_keyboard(descriptor: &dyn G213DeviceDescriptor) -> bool {
-    descriptor.vendor_id() == LOGITECH && descriptor.product_id() == G213
+fn model_of(device: &Device<GlobalContext>) -> Option<&'static Model> {
+    device
+        .device_descriptor()
+        .ok()
+        .and_then(|desc| supported_device(&desc))
 }
 
-fn send_to_keyboard(
-    handle: &DeviceHandle<GlobalContext>,
+fn write_to_keyboard(
+    kb: &OpenKeyboard,
+    transport: &dyn Transport,
+    bytes: &[u8],
+) -> G213Result<usize> {
+    record(Direction::Out, bytes);
+
+    if VERBOSE.load(Ordering::SeqCst) {
+        eprintln!("{} <- {}", kb.model.name(), hex::encode(bytes));
+    }
+
+    transport
+        .write_control(
+            kb.model.request_type(),
+            kb.model.request(),
+            kb.model.value(),
+            kb.model.interface() as u16,
+            bytes,
+            Duration::from_millis(TIMEOUT_MS),
+        )
+        .map_err(usb_error(G213Error::WriteControl))
+}
+
+fn read_from_keyboard(
+    kb: &OpenKeyboard,
+    transport: &dyn Transport,
     bytes: &mut [u8],
-) -> Result<usize, Error> {
-    handle.write_control(
-        REQ_TYPE,
-        REQ,
-        VALUE,
-        INDEX,
-        bytes,
-        Duration::from_millis(TIMEOUT_MS),
-    )?;
+) -> G213Result<usize> {
+    let len = transport
+        .read_interrupt(
+            kb.model.endpoint(),
+            bytes,
+            Duration::from_millis(TIMEOUT_MS),
+        )
+        .map_err(usb_error(G213Error::ReadInterrupt))?;
+
+    record(Direction::In, &bytes[..len]);
 
-    handle.read_interrupt(ENDPOINT, bytes, Duration::from_millis(TIMEOUT_MS))
+    Ok(len)
 }
 
-fn send_command(handle: &DeviceHandle<GlobalContext>, command: &str) -> Result<usize, Error> {
+// The reply is left in 'bytes'
+pub(crate) fn send_to_keyboard(kb: &OpenKeyboard, bytes: &mut [u8]) -> G213Result<usize> {
+    let request = bytes.to_vec();
+    let policy = *RETRY_POLICY.lock().unwrap();
+
+    let mut delay = policy.delay;
+
+    for _ in 0..policy.retries {
+        match send_once(kb, bytes, &request) {
+            Err(err) if transient(&err) => {
+                if VERBOSE.load(Ordering::SeqCst) {
+                    eprintln!("{}, trying again in {}ms", err, delay.as_millis());
+                }
+
+                sleep(delay);
+                delay *= 2;
+
+                bytes.copy_from_slice(&request);
+            }
+            result => return result,
+        }
+    }
+
+    // The last try's error is the one reported
+    send_once(kb, bytes, &request)
+}
+
+fn send_once(kb: &OpenKeyboard, bytes: &mut [u8], request: &[u8]) -> G213Result<usize> {
+    kb.transfer(|transport| {
+        write_to_keyboard(kb, transport, bytes)?;
+
+        // Key presses and other notifications can arrive before our reply
+        for _ in 0..MAX_READS {
+            let len = read_from_keyboard(kb, transport, bytes)?;
+
+            match check_reply(request, &bytes[..len]) {
+                Err(G213Error::UnexpectedReply) => continue,
+                result => return result.map(|_| len),
+            }
+        }
+
+        Err(G213Error::UnexpectedReply)
+    })
+}
+
+fn send_command(kb: &OpenKeyboard, command: &str) -> G213Result<usize> {
     let mut bytes = [0u8; CMD_LEN];
 
-    hex::decode_to_slice(command, &mut bytes).unwrap();
+    hex::decode_to_slice(command, &mut bytes)?;
 
-    send_to_keyboard(handle, &mut bytes)
+    send_to_keyboard(kb, &mut bytes)
 }
 
-fn send_keyboard_colour(handle: &DeviceHandle<GlobalContext>, region: u8, colour: u32) {
-    let command = format!(
-        "11ff0c3a{:02x}01{:06x}0200000000000000000000",
-        region, colour
+fn send_keyboard_colour(kb: &OpenKeyboard, region: u8, colour: u32) -> G213Result<()> {
+    if region == KeyboardRegions::WholeKeyboard as u8 && kb.corrects_regions() {
+        return (1..=kb.model.num_regions())
+            .try_for_each(|region| send_keyboard_colour(kb, region, colour));
+    }
+
+    // Single region keyboards only know about the whole keyboard
+    let region = match kb.model.num_regions() {
+        1 if region <= 1 => KeyboardRegions::WholeKeyboard as u8,
+        num if region <= num => region,
+        _ => return Err(G213Error::NoSuchRegion(kb.model.name(), region)),
+    };
+
+    let command = kb
+        .model
+        .colour_command(region, kb.calibrate(region, colour));
+
+    send_command(kb, &command)?;
+    note_lighting(kb, Lighting::Colour { region, colour });
+
+    Ok(())
+}
+
+fn send_breathe(kb: &OpenKeyboard, speed: u16, colour: u32) -> G213Result<()> {
+    let command = kb.model.breathe_command(
+        speed,
+        kb.calibrate(KeyboardRegions::WholeKeyboard as u8, colour),
     );
 
-    let _bytes_sent = send_command(handle, &command).unwrap();
+    send_command(kb, &command)?;
+    note_lighting(kb, Lighting::Breathe { speed, colour });
+
+    Ok(())
+}
+
+fn send_cycle(kb: &OpenKeyboard, speed: u16, brightness: u8) -> G213Result<()> {
+    let command = kb.model.cycle_command(speed, brightness);
+
+    send_command(kb, &command)?;
+    note_lighting(kb, Lighting::Cycle { speed, brightness });
+
+    Ok(())
+}
+
+// Only what's still showing is kept, so a long effect doesn't pile them up
+fn note_lighting(kb: &OpenKeyboard, lighting: Lighting) {
+    if PREVIEWING.get() {
+        return;
+    }
+
+    SENT_LIGHTING.with_borrow_mut(|sent| {
+        if sent
+            .as_ref()
+            .is_some_and(|(model, _)| !std::ptr::eq(*model, kb.model))
+        {
+            *sent = None;
+        }
+
+        let (_, noted) = sent.get_or_insert_with(|| (kb.model, Vec::new()));
+
+        match lighting {
+            Lighting::Colour { region, .. } if region > 0 => noted.retain(|noted| {
+                !matches!(noted, Lighting::Colour { region: earlier, .. } if *earlier == region)
+            }),
+            _ => noted.clear(),
+        }
+
+        noted.push(lighting);
+    });
+}
+
+/// The lighting this thread has sent to a keyboard, as asked for rather than
+/// calibrated, oldest first, with the keyboard's model. Only what's still
+/// showing is included, and nothing sent by [`previewing`].
+pub fn sent_lighting() -> Option<(&'static Model, Vec<Lighting>)> {
+    SENT_LIGHTING.with_borrow(Clone::clone)
+}
+
+/// Runs `preview`, eg a command on a pretend keyboard, without its lighting
+/// being taken for the real keyboard's.
+pub fn previewing<T>(preview: impl FnOnce() -> T) -> T {
+    let was = PREVIEWING.replace(true);
+    let result = preview();
+
+    PREVIEWING.set(was);
+
+    result
+}
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// Ctrl-C stops the animation rather than the process, so the kernel driver gets reattached
+pub fn catch_interrupt() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+fn send_wave(kb: &OpenKeyboard, speed: u16, colour: u32) -> G213Result<()> {
+    let num_regions = kb.model.num_regions();
+    let step_time = Duration::from_millis((speed / num_regions as u16) as u64);
+    let mut step: u8 = 0;
+
+    catch_interrupt();
+
+    while !interrupted() {
+        for (region, colour) in wave_colours(colour, step, num_regions).iter().enumerate() {
+            send_keyboard_colour(kb, (region + 1) as u8, *colour)?;
+        }
+
+        step = (step + 1) % num_regions;
+
+        sleep(step_time);
+    }
+
+    Ok(())
 }
 
-fn send_breathe(handle: &DeviceHandle<GlobalContext>, speed: u16, colour: u32) {
-    let command = format!("11ff0c3a0002{:06x}{:04x}006400000000000000", colour, speed);
+// Re-sends the OUT transfers, with the same gaps between them as when traced
+fn send_replay(kb: &OpenKeyboard, transfers: &[Transfer]) -> G213Result<()> {
+    let mut sent = transfers
+        .iter()
+        .filter(|transfer| transfer.direction == Direction::Out)
+        .peekable();
+
+    let first = match sent.peek() {
+        Some(transfer) => transfer.at,
+        None => return Ok(()),
+    };
+
+    let start = Instant::now();
+
+    catch_interrupt();
 
-    let _bytes_sent = send_command(handle, &command).unwrap();
+    for transfer in sent {
+        if interrupted() {
+            break;
+        }
+
+        if let Some(wait) = (transfer.at - first).checked_sub(start.elapsed()) {
+            sleep(wait);
+        }
+
+        let mut bytes = [0u8; CMD_LEN];
+        let len = transfer.bytes.len().min(CMD_LEN);
+
+        bytes[..len].copy_from_slice(&transfer.bytes[..len]);
+
+        kb.transfer(|transport| {
+            write_to_keyboard(kb, transport, &bytes)?;
+            read_from_keyboard(kb, transport, &mut bytes)
+        })?;
+    }
+
+    Ok(())
 }
 
-fn send_cycle(handle: &DeviceHandle<GlobalContext>, speed: u16) {
-    let command = format!("11ff0c3a0003ffffff0000{:04x}64000000000000", speed);
+/// What `info` shows about a keyboard, from [`device_info`].
+///
+/// Only a real keyboard has USB details, and HID++ ones if it answers.
+#[derive(Serialize, Default)]
+pub struct DeviceInfo {
+    pub model: String,
+    pub dry_run: bool,
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+    pub bus: Option<u8>,
+    pub address: Option<u8>,
+    pub speed: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial: Option<String>,
+    pub configurations: Vec<ConfigurationInfo>,
+    pub hidpp: Option<HidppInfo>,
+    pub hidpp_error: Option<String>,
+}
 
-    let _bytes_sent = send_command(handle, &command).unwrap();
+#[derive(Serialize)]
+pub struct ConfigurationInfo {
+    pub number: u8,
+    pub max_power_ma: u16,
+    pub interfaces: Vec<InterfaceInfo>,
 }
 
-fn do_show_info(descriptor: &DeviceDescriptor, handle: &DeviceHandle<GlobalContext>) {
+#[derive(Serialize)]
+pub struct InterfaceInfo {
+    pub number: u8,
+    pub alt_setting: u8,
+    pub class: u8,
+    pub sub_class: u8,
+    pub protocol: u8,
+    pub endpoints: Vec<EndpointInfo>,
+}
+
+#[derive(Serialize)]
+pub struct EndpointInfo {
+    pub address: u8,
+    pub direction: String,
+    pub transfer_type: String,
+    pub max_packet_size: u16,
+    pub interval: u8,
+}
+
+// Just the descriptors, so it doesn't need the device opening
+fn usb_topology(
+    device: &Device<GlobalContext>,
+    descriptor: &DeviceDescriptor,
+) -> G213Result<Vec<ConfigurationInfo>> {
+    let mut configurations = Vec::new();
+
+    for index in 0..descriptor.num_configurations() {
+        let config = device
+            .config_descriptor(index)
+            .map_err(usb_error(G213Error::Info))?;
+
+        let interfaces = config
+            .interfaces()
+            .flat_map(|interface| interface.descriptors())
+            .map(|interface| InterfaceInfo {
+                number: interface.interface_number(),
+                alt_setting: interface.setting_number(),
+                class: interface.class_code(),
+                sub_class: interface.sub_class_code(),
+                protocol: interface.protocol_code(),
+                endpoints: interface
+                    .endpoint_descriptors()
+                    .map(|endpoint| EndpointInfo {
+                        address: endpoint.address(),
+                        direction: format!("{:?}", endpoint.direction()).to_uppercase(),
+                        transfer_type: format!("{:?}", endpoint.transfer_type()),
+                        max_packet_size: endpoint.max_packet_size(),
+                        interval: endpoint.interval(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        configurations.push(ConfigurationInfo {
+            number: config.number(),
+            max_power_ma: config.max_power(),
+            interfaces,
+        });
+    }
+
+    Ok(configurations)
+}
+
+fn read_usb_info(
+    device: &Device<GlobalContext>,
+    descriptor: &DeviceDescriptor,
+    kb: &OpenKeyboard,
+) -> G213Result<DeviceInfo> {
+    let mut info = DeviceInfo {
+        model: kb.model.name().to_string(),
+        vendor_id: Some(descriptor.vendor_id()),
+        product_id: Some(descriptor.product_id()),
+        bus: Some(device.bus_number()),
+        address: Some(device.address()),
+        speed: Some(format!("{:?}", device.speed())),
+        configurations: usb_topology(device, descriptor)?,
+        ..Default::default()
+    };
+
+    let handle = match &kb.link {
+        Link::Usb(claimed) => claimed.handle.lock().unwrap(),
+        Link::Other(_) => return Ok(info),
+    };
     let timeout = std::time::Duration::from_millis(TIMEOUT_MS);
-    let lang = handle.read_languages(timeout).unwrap()[0];
+    let info_error = usb_error(G213Error::Info);
 
-    println!(
-        "Manufacturer: {}",
+    let lang = match handle.read_languages(timeout).map_err(info_error)?.first() {
+        Some(lang) => *lang,
+        None => return Err(G213Error::Info(Error::NotFound)),
+    };
+
+    info.manufacturer = Some(
         handle
             .read_manufacturer_string(lang, descriptor, timeout)
-            .unwrap()
+            .map_err(info_error)?,
     );
-
-    println!(
-        "Product:      {}",
+    info.product = Some(
         handle
             .read_product_string(lang, descriptor, timeout)
-            .unwrap()
+            .map_err(info_error)?,
     );
-
-    println!(
-        "Serial:       {}",
+    info.serial = Some(
         handle
             .read_serial_number_string(lang, descriptor, timeout)
-            .unwrap()
+            .map_err(info_error)?,
     );
+
+    // HID++ takes the handle for each of its own transfers
+    drop(handle);
+
+    // Not every unit speaks HID++ 2.0, which is worth knowing when debugging
+    match hidpp_info(kb) {
+        Ok(hidpp) => info.hidpp = Some(hidpp),
+        Err(err) => info.hidpp_error = Some(err.to_string()),
+    }
+
+    Ok(info)
 }
 
-pub fn find_g213_keyboard() -> Option<Device<GlobalContext>> {
-    devices().unwrap().iter().find(|device| {
-        let desc = device.device_descriptor().unwrap();
-        is_g213_keyboard(&desc)
-    })
+// Only the descriptors are read, so serials can't be matched, and there may
+// not be a USB bus at all, eg in CI
+fn dry_run_models(selector: &DeviceSelector) -> Vec<&'static Model> {
+    let devices = match Context::new().and_then(|context| context.devices()) {
+        Ok(devices) => devices,
+        Err(_) => return vec![],
+    };
+
+    let found = devices.iter().filter_map(|device| {
+        let model = supported_device(&device.device_descriptor().ok()?)?;
+
+        match selector {
+            DeviceSelector::BusAddress(bus, address)
+                if device.bus_number() != *bus || device.address() != *address =>
+            {
+                None
+            }
+            _ => Some(model),
+        }
+    });
+
+    match selector {
+        DeviceSelector::All => found.collect(),
+        _ => found.take(1).collect(),
+    }
 }
 
-fn send_command_wrapper(
-    device: &Device<GlobalContext>,
-    cmd_fn: impl Fn(&DeviceHandle<GlobalContext>),
-) {
-    let mut handle = device.open().expect("Unable to open device!");
+pub fn find_g213_keyboard() -> G213Result<Device<GlobalContext>> {
+    find_g213_keyboards(&DeviceSelector::First)?
+        .pop()
+        .ok_or(G213Error::NotFound)
+}
+
+fn read_serial(device: &Device<GlobalContext>) -> Option<String> {
+    let timeout = Duration::from_millis(TIMEOUT_MS);
+
+    let descriptor = device.device_descriptor().ok()?;
+    let handle = device.open().ok()?;
+    let lang = *handle.read_languages(timeout).ok()?.first()?;
+
+    handle
+        .read_serial_number_string(lang, &descriptor, timeout)
+        .ok()
+}
+
+/// Where to find a keyboard, as shown by the `devices` command.
+pub struct KeyboardInfo {
+    pub bus: u8,
+    pub address: u8,
+    pub serial: Option<String>,
+    pub model: String,
+}
+
+pub fn list_g213_keyboards() -> G213Result<Vec<KeyboardInfo>> {
+    let keyboards = match find_g213_keyboards(&DeviceSelector::All) {
+        Ok(keyboards) => keyboards,
+        Err(G213Error::NotFound) => vec![],
+        Err(err) => return Err(err),
+    };
+
+    Ok(keyboards
+        .iter()
+        .map(|device| KeyboardInfo {
+            bus: device.bus_number(),
+            address: device.address(),
+            serial: read_serial(device),
+            model: model_of(device)
+                .map_or("?", |model| model.name())
+                .to_string(),
+        })
+        .collect())
+}
+
+fn selected(selector: &DeviceSelector, device: &Device<GlobalContext>) -> bool {
+    match selector {
+        DeviceSelector::First | DeviceSelector::All => true,
+        DeviceSelector::BusAddress(bus, address) => {
+            device.bus_number() == *bus && device.address() == *address
+        }
+        DeviceSelector::Serial(serial) => read_serial(device).as_ref() == Some(serial),
+    }
+}
+
+pub fn find_g213_keyboards(selector: &DeviceSelector) -> G213Result<Vec<Device<GlobalContext>>> {
+    let devices = devices().map_err(usb_error(G213Error::Open))?;
+
+    let mut keyboards = devices
+        .iter()
+        .filter(|device| model_of(device).is_some() && selected(selector, device));
+
+    let found: Vec<_> = match selector {
+        DeviceSelector::All => keyboards.collect(),
+        _ => keyboards.next().into_iter().collect(),
+    };
+
+    if found.is_empty() {
+        Err(G213Error::NotFound)
+    } else {
+        Ok(found)
+    }
+}
+
+// Serials take a moment to read, so only when there's a calibration to find
+fn device_white_point(device: &Device<GlobalContext>) -> WhitePoint {
+    let calibrations = load_calibrations();
+
+    match calibrations.is_empty() {
+        true => WHITE,
+        false => white_point(&calibrations, read_serial(device).as_deref()),
+    }
+}
+
+fn claim_device(device: &Device<GlobalContext>) -> G213Result<OpenKeyboard> {
+    let model = model_of(device).ok_or(G213Error::NotFound)?;
+    let interface = model.interface();
+
+    let mut handle = device.open().map_err(usb_error(G213Error::Open))?;
 
     let mut kernel_driver_detached = false;
 
     // Then we detach the kernel driver so that we can access the device
-    if handle.kernel_driver_active(INDEX as u8).unwrap() {
+    if handle
+        .kernel_driver_active(interface)
+        .map_err(usb_error(G213Error::Detach))?
+    {
         handle
-            .detach_kernel_driver(INDEX as u8)
-            .expect("Unable to detach kernel USB driver");
+            .detach_kernel_driver(interface)
+            .map_err(usb_error(G213Error::Detach))?;
 
         kernel_driver_detached = true;
     }
 
     // Now we claim the interface
-    handle
-        .claim_interface(INDEX as u8)
-        .expect("Unable to claim interface for device");
+    if let Err(err) = handle.claim_interface(interface) {
+        if kernel_driver_detached {
+            let _ = handle.attach_kernel_driver(interface);
+        }
 
-    // Do our thing
-    cmd_fn(&handle);
+        return Err(usb_error(G213Error::Claim)(err));
+    }
 
-    handle
-        .release_interface(INDEX as u8)
-        .expect("Unable to release interface for device");
+    Ok(OpenKeyboard {
+        link: Link::Usb(Arc::new(Claimed::new(
+            handle,
+            interface,
+            kernel_driver_detached,
+        ))),
+        model,
+        white_point: Some(device_white_point(device)),
+    })
+}
 
-    // Let the kernel take over again
-    if kernel_driver_detached {
-        handle
-            .attach_kernel_driver(INDEX as u8)
-            .expect("Unable to attach kernel USB driver");
+fn open_keyboard(keyboard: &Keyboard) -> G213Result<OpenKeyboard> {
+    match keyboard {
+        Keyboard::Usb(device) => claim_device(device),
+        Keyboard::DryRun(model) => Ok(OpenKeyboard {
+            link: Link::Other(Arc::new(DryRun { name: model.name() })),
+            model,
+            white_point: Some(white_point(&load_calibrations(), None)),
+        }),
+        Keyboard::Other(model, transport) => Ok(OpenKeyboard {
+            link: Link::Other(transport.clone()),
+            model,
+            white_point: None,
+        }),
     }
 }
 
-pub fn set_keyboard_colour(device: &Device<GlobalContext>, color: u32) {
-    send_command_wrapper(device, |h| {
-        send_keyboard_colour(h, KeyboardRegions::WholeKeyboard as u8, color);
-    });
+fn release_device(kb: OpenKeyboard) -> G213Result<()> {
+    match kb.link {
+        Link::Usb(claimed) => match Arc::try_unwrap(claimed) {
+            Ok(claimed) => claimed.release(),
+            // Still in use by a command that didn't hold it, which gives it
+            // back when it's done
+            Err(_) => Ok(()),
+        },
+        Link::Other(_) => Ok(()),
+    }
 }
 
-pub fn set_region_colour(device: &Device<GlobalContext>, region: u8, color: u32) {
-    send_command_wrapper(device, |h| {
-        send_keyboard_colour(h, region, color);
-    });
+fn send_command_wrapper<T>(
+    keyboard: &Keyboard,
+    cmd_fn: impl Fn(&OpenKeyboard) -> G213Result<T>,
+) -> G213Result<T> {
+    // Not locked while the command runs, which may be a long effect, so the
+    // keyboard can still be reopened or held again meanwhile
+    let held = HELD_HANDLE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|held| held.kb.clone());
+
+    if let Some(held) = held {
+        // Other transports, eg a preview's, are never the keyboard being held
+        let same = match (keyboard, &held.link) {
+            (Keyboard::Other(_, transport), Link::Other(held)) => Arc::ptr_eq(transport, held),
+            (Keyboard::Other(..), _) => false,
+            _ => true,
+        };
+
+        if same {
+            return cmd_fn(&held);
+        }
+    }
+
+    let kb = open_keyboard(keyboard)?;
+
+    // Do our thing, but always give the keyboard back
+    let result = cmd_fn(&kb);
+
+    let released = release_device(kb);
+
+    let value = result?;
+
+    released.map(|_| value)
 }
 
-pub fn set_breathe(device: &Device<GlobalContext>, speed: u16, color: u32) {
-    send_command_wrapper(device, |h| {
-        send_breathe(h, speed, color);
-    });
+/// Lighting regions on the keyboard, 1 if it can only be set as a whole.
+pub fn num_regions(keyboard: &Keyboard) -> u8 {
+    keyboard
+        .model()
+        .map_or(NUM_REGIONS, |model| model.num_regions())
 }
 
-pub fn set_cycle(device: &Device<GlobalContext>, speed: u16) {
-    send_command_wrapper(device, |h| {
-        send_cycle(h, speed);
-    });
+// Keep the device open and claimed until 'let_go_of_device', avoiding a
+// kernel driver detach/attach round trip for every command
+pub fn hold_device(keyboard: &Keyboard) -> G213Result<()> {
+    let mut held = HELD_HANDLE.lock().unwrap();
+
+    match held.as_mut() {
+        Some(held) => held.holds += 1,
+        None => {
+            *held = Some(Held {
+                kb: open_keyboard(keyboard)?,
+                holds: 1,
+                serial: match keyboard {
+                    Keyboard::Usb(device) => read_serial(device),
+                    _ => None,
+                },
+            })
+        }
+    }
+
+    Ok(())
+}
+
+pub fn let_go_of_device() -> G213Result<()> {
+    let mut held = HELD_HANDLE.lock().unwrap();
+
+    let holds = match held.as_mut() {
+        Some(held) => {
+            held.holds -= 1;
+            held.holds
+        }
+        None => return Ok(()),
+    };
+
+    match (holds, held.take()) {
+        (0, Some(held)) => release_device(held.kb),
+        (_, still_held) => {
+            *held = still_held;
+
+            Ok(())
+        }
+    }
 }
 
-pub fn show_info(device: &Device<GlobalContext>) {
-    let descriptor = device.device_descriptor().unwrap();
+/// Whether `err` is because the keyboard has been unplugged.
+pub fn unplugged(err: &G213Error) -> bool {
+    matches!(
+        err,
+        G213Error::WriteControl(Error::NoDevice) | G213Error::ReadInterrupt(Error::NoDevice)
+    )
+}
+
+/// Opens the held keyboard again once it's been plugged back in, found by its
+/// serial number, or as the first keyboard if it hasn't got one.
+pub fn reopen_device() -> G213Result<()> {
+    let mut held = HELD_HANDLE.lock().unwrap();
+
+    let Some(held) = held.as_mut() else {
+        return Err(G213Error::NotFound);
+    };
+
+    if let Link::Other(_) = held.kb.link {
+        return Ok(());
+    }
+
+    let selector = match &held.serial {
+        Some(serial) => DeviceSelector::Serial(serial.clone()),
+        None => DeviceSelector::First,
+    };
+
+    let device = find_g213_keyboards(&selector)?
+        .pop()
+        .ok_or(G213Error::NotFound)?;
+
+    // The old handle has nothing left to give back
+    held.kb = claim_device(&device)?;
+
+    Ok(())
+}
+
+// After 'calibrate', so a held keyboard doesn't keep the old white point
+pub fn reload_calibration() {
+    if let Some(Held { kb: held, .. }) = HELD_HANDLE.lock().unwrap().as_mut() {
+        held.white_point = match (&held.link, held.white_point) {
+            (_, None) => None,
+            (Link::Usb(claimed), _) => {
+                Some(device_white_point(&claimed.handle.lock().unwrap().device()))
+            }
+            (Link::Other(_), _) => Some(white_point(&load_calibrations(), None)),
+        };
+    }
+}
+
+/// The serial number `calibrate` keeps the keyboard's white point under.
+pub fn calibration_serial(keyboard: &Keyboard) -> String {
+    match keyboard {
+        Keyboard::Usb(device) => read_serial(device),
+        _ => None,
+    }
+    .unwrap_or_else(|| NO_SERIAL.to_string())
+}
+
+// For when the keyboard has been unplugged and there's nothing left to release
+pub fn forget_device() {
+    HELD_HANDLE.lock().unwrap().take();
+}
+
+pub fn set_keyboard_colour(keyboard: &Keyboard, color: u32) -> G213Result<()> {
+    send_command_wrapper(keyboard, |h| {
+        send_keyboard_colour(h, KeyboardRegions::WholeKeyboard as u8, color)
+    })
+}
+
+pub fn set_region_colour(keyboard: &Keyboard, region: u8, color: u32) -> G213Result<()> {
+    send_command_wrapper(keyboard, |h| send_keyboard_colour(h, region, color))
+}
+
+// Opens, and gives back, the keyboard once for all of them, rather than for each
+pub fn set_regions(keyboard: &Keyboard, colours: &[(u8, u32)]) -> G213Result<()> {
+    send_command_wrapper(keyboard, |h| {
+        colours
+            .iter()
+            .try_for_each(|(region, colour)| send_keyboard_colour(h, *region, *colour))
+    })
+}
+
+/// Sends lighting worked out beforehand, eg by running a command on a pretend
+/// keyboard, in order.
+pub fn set_lighting(keyboard: &Keyboard, lighting: &[Lighting]) -> G213Result<()> {
+    send_command_wrapper(keyboard, |h| {
+        lighting.iter().try_for_each(|lighting| match *lighting {
+            Lighting::Colour { region, colour } => send_keyboard_colour(h, region, colour),
+            Lighting::Breathe { speed, colour } => send_breathe(h, speed, colour),
+            Lighting::Cycle { speed, brightness } => send_cycle(h, speed, brightness),
+        })
+    })
+}
+
+pub fn set_breathe(keyboard: &Keyboard, speed: u16, color: u32) -> G213Result<()> {
+    send_command_wrapper(keyboard, |h| send_breathe(h, speed, color))
+}
+
+pub fn set_cycle(keyboard: &Keyboard, speed: u16, brightness: u8) -> G213Result<()> {
+    send_command_wrapper(keyboard, |h| send_cycle(h, speed, brightness))
+}
+
+pub fn run_wave(keyboard: &Keyboard, speed: u16, color: u32) -> G213Result<()> {
+    send_command_wrapper(keyboard, |h| send_wave(h, speed, color))
+}
+
+pub fn device_info(keyboard: &Keyboard) -> G213Result<DeviceInfo> {
+    let device = match keyboard {
+        Keyboard::Usb(device) => device,
+        Keyboard::DryRun(model) => {
+            return Ok(DeviceInfo {
+                model: model.name().to_string(),
+                dry_run: true,
+                ..Default::default()
+            })
+        }
+        Keyboard::Other(model, _) => {
+            return Ok(DeviceInfo {
+                model: model.name().to_string(),
+                ..Default::default()
+            })
+        }
+    };
+
+    let descriptor = device
+        .device_descriptor()
+        .map_err(usb_error(G213Error::Info))?;
+
+    send_command_wrapper(keyboard, |kb| read_usb_info(device, &descriptor, kb))
+}
+
+// Sends 20 bytes of hex as is, returning whatever the keyboard replies with
+pub fn send_raw(keyboard: &Keyboard, command: &str) -> G213Result<Vec<u8>> {
+    let mut bytes = [0u8; CMD_LEN];
+
+    hex::decode_to_slice(command, &mut bytes)?;
+
+    send_command_wrapper(keyboard, |kb| {
+        let mut reply = bytes;
+
+        let len = kb.transfer(|transport| {
+            write_to_keyboard(kb, transport, &reply)?;
+            read_from_keyboard(kb, transport, &mut reply)
+        })?;
+
+        Ok(reply[..len].to_vec())
+    })
+}
 
-    send_command_wrapper(device, |h| do_show_info(&descriptor, h));
+pub fn replay(keyboard: &Keyboard, transfers: &[Transfer]) -> G213Result<()> {
+    send_command_wrapper(keyboard, |kb| send_replay(kb, transfers))
+}
+
+/// A connected G213 keyboard, for driving it from other Rust programs.
+///
+/// Each call opens the keyboard, sends its command and hands the keyboard
+/// back to the kernel, unless a long-running mode is holding it open.
+pub struct G213 {
+    keyboard: Keyboard,
+}
+
+impl G213 {
+    /// Finds the first G213 keyboard on the USB bus.
+    pub fn find() -> G213Result<G213> {
+        find_g213_keyboard().map(|device| G213 {
+            keyboard: Keyboard::Usb(device),
+        })
+    }
+
+    /// Finds the G213 keyboards picked out by `selector`.
+    pub fn select(selector: &DeviceSelector) -> G213Result<Vec<G213>> {
+        let devices = find_g213_keyboards(selector)?;
+
+        Ok(devices
+            .into_iter()
+            .map(|device| G213 {
+                keyboard: Keyboard::Usb(device),
+            })
+            .collect())
+    }
+
+    /// Like [`G213::select`], but commands are printed rather than sent. The
+    /// keyboards are never opened, and a G213 is assumed if none are found.
+    pub fn dry_run(selector: &DeviceSelector) -> Vec<G213> {
+        let mut found = dry_run_models(selector);
+
+        if found.is_empty() {
+            found.push(&models()[0]);
+        }
+
+        found
+            .into_iter()
+            .map(|model| G213 {
+                keyboard: Keyboard::DryRun(model),
+            })
+            .collect()
+    }
+
+    /// The keyboard, as used by [`crate::commands::Run`].
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    /// Sets the whole keyboard to a `0xRRGGBB` colour.
+    pub fn set_colour(&self, colour: u32) -> G213Result<()> {
+        set_keyboard_colour(&self.keyboard, colour)
+    }
+
+    /// Sets one region, 1 to [`NUM_REGIONS`], to a `0xRRGGBB` colour.
+    pub fn set_region_colour(&self, region: u8, colour: u32) -> G213Result<()> {
+        set_region_colour(&self.keyboard, region, colour)
+    }
+
+    /// Sets several regions, with their `0xRRGGBB` colours, in one go.
+    pub fn set_regions(&self, colours: &[(u8, u32)]) -> G213Result<()> {
+        set_regions(&self.keyboard, colours)
+    }
+
+    /// Breathes a `0xRRGGBB` colour, taking `speed` ms per breath.
+    pub fn set_breathe(&self, speed: u16, colour: u32) -> G213Result<()> {
+        set_breathe(&self.keyboard, limit_speed(speed), colour)
+    }
+
+    /// Cycles through all colours, taking `speed` ms per cycle, at a brightness of 0 to 100.
+    pub fn set_cycle(&self, speed: u16, brightness: u8) -> G213Result<()> {
+        set_cycle(
+            &self.keyboard,
+            limit_speed(speed),
+            brightness.min(MAX_BRIGHTNESS),
+        )
+    }
+
+    /// Sweeps a `0xRRGGBB` colour across the regions every `speed` ms, until Ctrl-C.
+    pub fn wave(&self, speed: u16, colour: u32) -> G213Result<()> {
+        run_wave(&self.keyboard, limit_speed(speed), colour)
+    }
 }
 
 #[cfg(test)]
@@ -219,6 +1329,8 @@ mod g213_keyboard_tests {
     // use rusb::{ffi::libusb_device_descriptor, DeviceDescriptor};
 
     use super::*;
+    use crate::transport::FakeTransport;
+    use std::sync::atomic::AtomicUsize;
 
     // NOTE: A lot of work to test a one line function...
 
@@ -260,20 +1372,244 @@ mod g213_keyboard_tests {
     fn a_g213_keyboard() {
         let descriptor = GoodG213DeviceDescriptor {};
 
-        assert_eq!(is_g213_keyboard(&descriptor), true);
+        assert_eq!(supported_device(&descriptor).map(Model::name), Some("G213"));
     }
 
     #[test]
     fn not_a_logitech_device() {
         let descriptor = NonLogitechDeviceDescriptor {};
 
-        assert_eq!(is_g213_keyboard(&descriptor), false);
+        assert_eq!(supported_device(&descriptor), None);
     }
 
     #[test]
     fn not_a_g213_keyboard() {
         let descriptor = NonG213DeviceDescriptor {};
 
-        assert_eq!(is_g213_keyboard(&descriptor), false);
+        assert_eq!(supported_device(&descriptor), None);
+    }
+
+    #[test]
+    fn access_error_is_permission() {
+        let err = usb_error(G213Error::Open)(Error::Access);
+
+        assert!(matches!(err, G213Error::Permission));
+    }
+
+    #[test]
+    fn other_errors_are_wrapped() {
+        let err = usb_error(G213Error::WriteControl)(Error::Timeout);
+
+        assert!(matches!(err, G213Error::WriteControl(Error::Timeout)));
+    }
+
+    #[test]
+    fn selector_bus_address() {
+        assert_eq!(
+            DeviceSelector::from("001:012"),
+            DeviceSelector::BusAddress(1, 12)
+        );
+    }
+
+    #[test]
+    fn selector_serial() {
+        assert_eq!(
+            DeviceSelector::from("1234ABCD"),
+            DeviceSelector::Serial("1234ABCD".to_string())
+        );
+    }
+
+    #[test]
+    fn selector_bad_bus_address_is_serial() {
+        assert_eq!(
+            DeviceSelector::from("1:999"),
+            DeviceSelector::Serial("1:999".to_string())
+        );
+    }
+
+    #[test]
+    fn regions_by_number_or_name() {
+        assert_eq!("2".parse(), Ok(KeyboardRegions::Region2));
+        assert_eq!("WASD".parse(), Ok(KeyboardRegions::Region1));
+        assert_eq!("Centre Left".parse(), Ok(KeyboardRegions::Region2));
+        assert_eq!("center_left".parse(), Ok(KeyboardRegions::Region2));
+        assert_eq!("numpad".parse(), Ok(KeyboardRegions::Region5));
+        assert!("middle".parse::<KeyboardRegions>().is_err());
+        assert!("9".parse::<KeyboardRegions>().is_err());
+    }
+
+    #[test]
+    fn regions_set_together() {
+        let fake = Arc::new(FakeTransport::default());
+        let keyboard = Keyboard::Other(Model::from_name("G213").unwrap(), fake.clone());
+
+        set_regions(&keyboard, &[(1, 0xff0000), (5, 0x0000ff)]).unwrap();
+
+        assert_eq!(
+            fake.sent_hex(),
+            [
+                "11ff0c3a0101ff00000200000000000000000000",
+                "11ff0c3a05010000ff0200000000000000000000"
+            ]
+        );
+        assert!(matches!(
+            set_regions(&keyboard, &[(6, 0xff0000)]),
+            Err(G213Error::NoSuchRegion(_, 6))
+        ));
+    }
+
+    #[test]
+    fn holds_nest() {
+        let _global = crate::global_state();
+        let fake = Arc::new(FakeTransport::default());
+        let keyboard = Keyboard::Other(Model::from_name("G213").unwrap(), fake.clone());
+
+        let holding = || {
+            HELD_HANDLE
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|held| match &held.kb.link {
+                    Link::Other(transport) => {
+                        Arc::ptr_eq(transport, &(fake.clone() as Arc<dyn Transport>))
+                    }
+                    Link::Usb(_) => false,
+                })
+        };
+
+        hold_device(&keyboard).unwrap();
+        hold_device(&keyboard).unwrap();
+        let_go_of_device().unwrap();
+
+        assert_eq!(holding(), Some(true));
+
+        let_go_of_device().unwrap();
+
+        assert_eq!(holding(), None);
+    }
+
+    #[test]
+    fn held_keyboard_is_not_locked_while_used() {
+        let _global = crate::global_state();
+        let fake = Arc::new(FakeTransport::default());
+        let keyboard = Keyboard::Other(Model::from_name("G213").unwrap(), fake.clone());
+
+        hold_device(&keyboard).unwrap();
+
+        // Holding it again from inside a command needs the lock
+        send_command_wrapper(&keyboard, |kb| {
+            hold_device(&keyboard)?;
+            send_keyboard_colour(kb, 0, 0xff0000)
+        })
+        .unwrap();
+
+        let_go_of_device().unwrap();
+        let_go_of_device().unwrap();
+
+        assert_eq!(fake.sent_hex().len(), 1);
+        assert!(HELD_HANDLE.lock().unwrap().is_none());
+    }
+
+    // Counts how often the interface is given back, and the kernel driver reattached
+    #[derive(Clone, Default)]
+    struct CountingHandle {
+        released: Arc<AtomicUsize>,
+        attached: Arc<AtomicUsize>,
+    }
+
+    impl Release for CountingHandle {
+        fn release_interface(&mut self, _interface: u8) -> rusb::Result<()> {
+            self.released.fetch_add(1, Ordering::SeqCst);
+
+            Ok(())
+        }
+
+        fn attach_kernel_driver(&mut self, _interface: u8) -> rusb::Result<()> {
+            self.attached.fetch_add(1, Ordering::SeqCst);
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keyboard_given_back_once() {
+        let counts = CountingHandle::default();
+
+        Claimed::new(counts.clone(), 1, true).release().unwrap();
+
+        assert_eq!(counts.released.load(Ordering::SeqCst), 1);
+        assert_eq!(counts.attached.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn last_user_gives_keyboard_back() {
+        let counts = CountingHandle::default();
+        let claimed = Arc::new(Claimed::new(counts.clone(), 1, true));
+        let in_use = claimed.clone();
+
+        // Let go of while a command's still using it
+        assert!(Arc::try_unwrap(claimed).is_err());
+        assert_eq!(counts.released.load(Ordering::SeqCst), 0);
+
+        drop(in_use);
+
+        assert_eq!(counts.released.load(Ordering::SeqCst), 1);
+        assert_eq!(counts.attached.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn only_lighting_still_showing_is_noted() {
+        let fake = Arc::new(FakeTransport::default());
+        let model = Model::from_name("G213").unwrap();
+        let keyboard = Keyboard::Other(model, fake);
+
+        set_breathe(&keyboard, 1000, 0xff).unwrap();
+        set_keyboard_colour(&keyboard, 0xff0000).unwrap();
+        set_region_colour(&keyboard, 2, 0xff00).unwrap();
+        set_region_colour(&keyboard, 2, 0xff).unwrap();
+        previewing(|| set_cycle(&keyboard, 1000, 100)).unwrap();
+
+        let (noted, sent) = sent_lighting().unwrap();
+
+        assert_eq!(noted, model);
+        assert_eq!(
+            sent,
+            [
+                Lighting::Colour {
+                    region: 0,
+                    colour: 0xff0000
+                },
+                Lighting::Colour {
+                    region: 2,
+                    colour: 0xff
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn scale_colour_half() {
+        assert_eq!(scale_colour(0xff8040, 50), 0x7f4020);
+    }
+
+    #[test]
+    fn scale_colour_full() {
+        assert_eq!(scale_colour(0x123456, 100), 0x123456);
+    }
+
+    #[test]
+    fn wave_crest_at_first_region() {
+        assert_eq!(
+            wave_colours(0xffffff, 0, NUM_REGIONS),
+            vec![0xffffff, 0x4c4c4c, 0x0c0c0c, 0, 0]
+        );
+    }
+
+    #[test]
+    fn wave_crest_wraps_around() {
+        assert_eq!(
+            wave_colours(0xff0000, 7, NUM_REGIONS),
+            wave_colours(0xff0000, 2, NUM_REGIONS)
+        );
     }
 }
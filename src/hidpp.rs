@@ -0,0 +1,342 @@
+use std::fmt;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::g213_keyboard::{send_to_keyboard, G213Error, G213Result, OpenKeyboard, CMD_LEN};
+
+const LONG_REPORT: u8 = 0x11;
+const DEVICE_INDEX: u8 = 0xff;
+const ERROR_REPLY: u8 = 0xff;
+
+// Lets us tell our replies apart from the lighting commands' ones
+const SW_ID: u8 = 0x0b;
+
+const ROOT: u16 = 0x0000;
+const FEATURE_SET: u16 = 0x0001;
+const FIRMWARE_VERSION: u16 = 0x0003;
+const DEVICE_NAME: u16 = 0x0005;
+const COLOUR_LED_EFFECTS: u16 = 0x8070;
+const RGB_EFFECTS: u16 = 0x8071;
+
+const FEATURE_NAMES: [(u16, &str); 14] = [
+    (ROOT, "Root"),
+    (FEATURE_SET, "Feature set"),
+    (0x0002, "Feature info"),
+    (FIRMWARE_VERSION, "Firmware version"),
+    (DEVICE_NAME, "Device name"),
+    (0x0020, "Config change"),
+    (0x00c2, "DFU control"),
+    (0x00d0, "DFU"),
+    (0x1b04, "Reprogrammable keys"),
+    (0x4522, "Disable keys"),
+    (COLOUR_LED_EFFECTS, "Colour LED effects"),
+    (RGB_EFFECTS, "RGB effects"),
+    (0x8010, "G-keys"),
+    (0x8040, "Brightness control"),
+];
+
+#[derive(serde::Serialize)]
+pub struct Feature {
+    pub index: u8,
+    pub id: u16,
+}
+
+/// What a keyboard says about itself over HID++, for `info`.
+#[derive(serde::Serialize)]
+pub struct HidppInfo {
+    pub version: (u8, u8),
+    pub name: Option<String>,
+    pub firmware: Vec<FirmwareVersion>,
+    pub features: Vec<Feature>,
+}
+
+// One of the device's firmware entities, the main application, bootloader etc
+pub struct FirmwareVersion {
+    pub kind: u8,
+    pub prefix: String,
+    pub number: u8,
+    pub revision: u8,
+    pub build: u16,
+}
+
+impl FirmwareVersion {
+    // getFwInfo replies with type, 3 character prefix, then BCD number, revision and build
+    fn from_reply(reply: &[u8]) -> FirmwareVersion {
+        FirmwareVersion {
+            kind: reply[0] & 0x0f,
+            prefix: String::from_utf8_lossy(&reply[1..4])
+                .trim_end_matches('\0')
+                .to_string(),
+            number: reply[4],
+            revision: reply[5],
+            build: u16::from_be_bytes([reply[6], reply[7]]),
+        }
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            0 => "Firmware",
+            1 => "Bootloader",
+            2 => "Hardware",
+            _ => "Other",
+        }
+    }
+}
+
+// The numbers are BCD, so print them as hex
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:02x}.{:02x}.B{:04x}",
+            self.prefix, self.number, self.revision, self.build
+        )
+    }
+}
+
+// The kind and version as shown, rather than the raw BCD fields
+impl Serialize for FirmwareVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("FirmwareVersion", 2)?;
+
+        state.serialize_field("kind", self.kind_name())?;
+        state.serialize_field("version", &self.to_string())?;
+        state.end()
+    }
+}
+
+pub fn feature_name(id: u16) -> &'static str {
+    FEATURE_NAMES
+        .iter()
+        .find(|(feature, _)| *feature == id)
+        .map_or("?", |(_, name)| name)
+}
+
+fn request_bytes(feature_index: u8, function: u8, params: &[u8]) -> [u8; CMD_LEN] {
+    let mut bytes = [0u8; CMD_LEN];
+
+    bytes[0] = LONG_REPORT;
+    bytes[1] = DEVICE_INDEX;
+    bytes[2] = feature_index;
+    bytes[3] = function << 4 | SW_ID;
+    bytes[4..4 + params.len()].copy_from_slice(params);
+
+    bytes
+}
+
+// Replies echo the request's first 4 bytes, errors look like
+// 11 ff ff <feature index> <function> <error code>
+pub(crate) fn check_reply(request: &[u8], reply: &[u8]) -> G213Result<()> {
+    if reply.len() < 6 || reply[..2] != request[..2] {
+        Err(G213Error::UnexpectedReply)
+    } else if reply[2] == ERROR_REPLY && reply[3] == request[2] && reply[4] == request[3] {
+        Err(G213Error::Hidpp(reply[5]))
+    } else if reply[2..4] != request[2..4] {
+        Err(G213Error::UnexpectedReply)
+    } else {
+        Ok(())
+    }
+}
+
+// Returns the reply's parameters
+fn request(
+    kb: &OpenKeyboard,
+    feature_index: u8,
+    function: u8,
+    params: &[u8],
+) -> G213Result<[u8; CMD_LEN - 4]> {
+    let request = request_bytes(feature_index, function, params);
+    let mut reply = request;
+
+    send_to_keyboard(kb, &mut reply)?;
+
+    let mut result = [0u8; CMD_LEN - 4];
+    result.copy_from_slice(&reply[4..]);
+
+    Ok(result)
+}
+
+// The root feature is always at index 0, and reports 0 for missing features
+pub(crate) fn feature_index(kb: &OpenKeyboard, id: u16) -> G213Result<Option<u8>> {
+    let reply = request(kb, 0, 0, &id.to_be_bytes())?;
+
+    Ok(match reply[0] {
+        0 if id != ROOT => None,
+        index => Some(index),
+    })
+}
+
+pub(crate) fn protocol_version(kb: &OpenKeyboard) -> G213Result<(u8, u8)> {
+    let reply = request(kb, 0, 1, &[])?;
+
+    Ok((reply[0], reply[1]))
+}
+
+pub(crate) fn features(kb: &OpenKeyboard) -> G213Result<Vec<Feature>> {
+    let feature_set = match feature_index(kb, FEATURE_SET)? {
+        Some(index) => index,
+        None => return Ok(vec![]),
+    };
+
+    // The count doesn't include the root feature
+    let count = request(kb, feature_set, 0, &[])?[0];
+
+    let mut features = vec![Feature { index: 0, id: ROOT }];
+
+    for index in 1..=count {
+        let reply = request(kb, feature_set, 1, &[index])?;
+
+        features.push(Feature {
+            index,
+            id: u16::from_be_bytes([reply[0], reply[1]]),
+        });
+    }
+
+    Ok(features)
+}
+
+pub(crate) fn device_name(kb: &OpenKeyboard) -> G213Result<Option<String>> {
+    let index = match feature_index(kb, DEVICE_NAME)? {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+
+    let length = request(kb, index, 0, &[])?[0] as usize;
+    let mut name = Vec::new();
+
+    while name.len() < length {
+        let reply = request(kb, index, 1, &[name.len() as u8])?;
+
+        name.extend_from_slice(&reply[..reply.len().min(length - name.len())]);
+    }
+
+    Ok(Some(String::from_utf8_lossy(&name).to_string()))
+}
+
+pub(crate) fn firmware_versions(kb: &OpenKeyboard) -> G213Result<Vec<FirmwareVersion>> {
+    let index = match feature_index(kb, FIRMWARE_VERSION)? {
+        Some(index) => index,
+        None => return Ok(vec![]),
+    };
+
+    let count = request(kb, index, 0, &[])?[0];
+    let mut versions = Vec::new();
+
+    for entity in 0..count {
+        let reply = request(kb, index, 1, &[entity])?;
+
+        versions.push(FirmwareVersion::from_reply(&reply));
+    }
+
+    Ok(versions)
+}
+
+pub(crate) fn hidpp_info(kb: &OpenKeyboard) -> G213Result<HidppInfo> {
+    Ok(HidppInfo {
+        version: protocol_version(kb)?,
+        name: device_name(kb)?,
+        firmware: firmware_versions(kb)?,
+        features: features(kb)?,
+    })
+}
+
+pub fn is_lighting_feature(id: u16) -> bool {
+    id == COLOUR_LED_EFFECTS || id == RGB_EFFECTS
+}
+
+#[cfg(test)]
+mod hidpp_tests {
+    use super::*;
+
+    #[test]
+    fn feature_request() {
+        let bytes = request_bytes(0x00, 0, &[0x80, 0x70]);
+
+        assert_eq!(&bytes[..6], &[0x11, 0xff, 0x00, 0x0b, 0x80, 0x70]);
+        assert!(bytes[6..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn matching_reply() {
+        let request = request_bytes(0x01, 1, &[3]);
+        let mut reply = request;
+        reply[4] = 0x80;
+
+        assert!(check_reply(&request, &reply).is_ok());
+    }
+
+    #[test]
+    fn error_reply() {
+        let request = request_bytes(0x01, 1, &[3]);
+        let mut reply = [0u8; CMD_LEN];
+        reply[..6].copy_from_slice(&[0x11, 0xff, 0xff, 0x01, 0x1b, 0x02]);
+
+        assert!(matches!(
+            check_reply(&request, &reply),
+            Err(G213Error::Hidpp(2))
+        ));
+    }
+
+    #[test]
+    fn short_reply() {
+        let request = request_bytes(0x01, 1, &[3]);
+
+        assert!(matches!(
+            check_reply(&request, &request[..4]),
+            Err(G213Error::UnexpectedReply)
+        ));
+    }
+
+    #[test]
+    fn lighting_command_reply() {
+        let request = hex::decode("11ff0c3a0001ff00000200000000000000000000").unwrap();
+        let reply = hex::decode("11ff0c3a00000000000000000000000000000000").unwrap();
+
+        assert!(check_reply(&request, &reply).is_ok());
+    }
+
+    #[test]
+    fn someone_elses_reply() {
+        let request = request_bytes(0x01, 1, &[3]);
+        let reply = request_bytes(0x0c, 3, &[]);
+
+        assert!(matches!(
+            check_reply(&request, &reply),
+            Err(G213Error::UnexpectedReply)
+        ));
+    }
+
+    #[test]
+    fn firmware_version() {
+        let reply = [
+            0x00, b'U', b'1', b' ', 0x12, 0x01, 0x00, 0x31, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let version = FirmwareVersion::from_reply(&reply);
+
+        assert_eq!(version.kind_name(), "Firmware");
+        assert_eq!(version.to_string(), "U1  12.01.B0031");
+    }
+
+    #[test]
+    fn bootloader_version() {
+        let reply = [
+            0x01, b'B', b'O', b'T', 0x04, 0x02, 0x00, 0x07, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let version = FirmwareVersion::from_reply(&reply);
+
+        assert_eq!(version.kind_name(), "Bootloader");
+        assert_eq!(version.to_string(), "BOT 04.02.B0007");
+        assert_eq!(
+            serde_json::to_string(&version).unwrap(),
+            r#"{"kind":"Bootloader","version":"BOT 04.02.B0007"}"#
+        );
+    }
+
+    #[test]
+    fn feature_names() {
+        assert_eq!(feature_name(0x8070), "Colour LED effects");
+        assert_eq!(feature_name(0x1234), "?");
+        assert!(is_lighting_feature(0x8071));
+        assert!(!is_lighting_feature(0x0005));
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs::{read_to_string, write};
+
+use crate::commands::{
+    config_file_for_saving, config_file_path, set_file_ownership_to_me, Command, HISTORY_FILE,
+};
+
+// Enough to get back past a few experiments
+const HISTORY_LENGTH: usize = 20;
+
+/// The saved commands, oldest first, the last being the one saved now.
+pub type History = Vec<Command>;
+
+fn parse_history(history: &str) -> Result<History, serde_json::Error> {
+    serde_json::from_str(history)
+}
+
+pub fn load_history() -> History {
+    let path = match config_file_path(HISTORY_FILE) {
+        Ok(path) => path,
+        Err(_) => return History::new(),
+    };
+
+    match read_to_string(&path) {
+        Ok(history) => parse_history(&history).unwrap_or_else(|err| {
+            eprintln!("Ignoring {}: {}", path, err);
+
+            History::new()
+        }),
+        Err(_) => History::new(),
+    }
+}
+
+fn save_history(history: &History) {
+    let path = match config_file_for_saving(HISTORY_FILE) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Unable to save history: {}", err);
+
+            return;
+        }
+    };
+
+    write(&path, serde_json::to_string_pretty(history).unwrap()).expect("Unable to save history");
+
+    set_file_ownership_to_me(path);
+}
+
+// Running the same command again isn't worth an entry of its own
+fn remember(history: &mut History, command: &Command) {
+    if history.last().map(Command::to_string) == Some(command.to_string()) {
+        return;
+    }
+
+    history.push(command.clone());
+
+    let extra = history.len().saturating_sub(HISTORY_LENGTH);
+
+    history.drain(..extra);
+}
+
+pub fn add_to_history(command: &Command) {
+    let mut history = load_history();
+
+    remember(&mut history, command);
+    save_history(&history);
+}
+
+/// The command saved before the current one, for `undo` to run again.
+pub fn previous_command() -> Option<Command> {
+    let history = load_history();
+
+    history
+        .len()
+        .checked_sub(2)
+        .map(|previous| history[previous].clone())
+}
+
+/// Forgets the current command, once `undo` has gone back past it, so
+/// undoing again goes further back. Called before the previous one is saved.
+pub fn forget_current() {
+    let mut history = load_history();
+
+    if history.pop().is_some() {
+        save_history(&history);
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+
+    fn colour(name: &str) -> Command {
+        Command::Colour(vec![name.to_string()])
+    }
+
+    #[test]
+    fn repeats_are_remembered_once() {
+        let mut history = History::new();
+
+        remember(&mut history, &colour("red"));
+        remember(&mut history, &colour("red"));
+        remember(&mut history, &colour("blue"));
+        remember(&mut history, &colour("red"));
+
+        let history: Vec<String> = history.iter().map(Command::to_string).collect();
+
+        assert_eq!(history, ["colour red", "colour blue", "colour red"]);
+    }
+
+    #[test]
+    fn oldest_are_forgotten() {
+        let mut history = History::new();
+
+        for n in 0..HISTORY_LENGTH + 5 {
+            remember(&mut history, &colour(&n.to_string()));
+        }
+
+        assert_eq!(history.len(), HISTORY_LENGTH);
+        assert_eq!(history[0].to_string(), "colour 5");
+    }
+
+    #[test]
+    fn history_round_trip() {
+        let history = vec![colour("red"), Command::Off];
+        let history = parse_history(&serde_json::to_string(&history).unwrap()).unwrap();
+
+        assert_eq!(history[1].to_string(), "off");
+    }
+}
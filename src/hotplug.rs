@@ -0,0 +1,123 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::sleep;
+use std::time::Duration;
+
+use rusb::{has_hotplug, Device, GlobalContext, Hotplug, HotplugBuilder, Registration, UsbContext};
+
+use crate::commands::{get_saved_command, Run, Status};
+use crate::g213_keyboard::{catch_interrupt, interrupted, Keyboard};
+use crate::models::supported_device;
+
+const POLL_MS: u64 = 100;
+
+// Give the keyboard a moment to finish enumerating before talking to it
+const SETTLE_MS: u64 = 500;
+
+pub enum HotplugEvent {
+    Arrived(Device<GlobalContext>),
+    Left,
+}
+
+// Only safe to queue events here, USB I/O has to wait until we're out of the callback
+struct KeyboardWatcher {
+    events: Sender<HotplugEvent>,
+}
+
+// Cached descriptors are fine to read in the callback
+fn is_supported(device: &Device<GlobalContext>) -> bool {
+    match device.device_descriptor() {
+        Ok(desc) => supported_device(&desc).is_some(),
+        Err(_) => false,
+    }
+}
+
+impl Hotplug<GlobalContext> for KeyboardWatcher {
+    fn device_arrived(&mut self, device: Device<GlobalContext>) {
+        if is_supported(&device) {
+            let _ = self.events.send(HotplugEvent::Arrived(device));
+        }
+    }
+
+    fn device_left(&mut self, device: Device<GlobalContext>) {
+        if is_supported(&device) {
+            let _ = self.events.send(HotplugEvent::Left);
+        }
+    }
+}
+
+pub struct HotplugWatch {
+    _registration: Registration<GlobalContext>,
+    events: Receiver<HotplugEvent>,
+}
+
+impl HotplugWatch {
+    pub fn new() -> Option<Self> {
+        if !has_hotplug() {
+            return None;
+        }
+
+        let (sender, events) = channel();
+
+        // Not filtered by vendor, user models may come from anyone
+        let registration = HotplugBuilder::new()
+            .register(
+                GlobalContext::default(),
+                Box::new(KeyboardWatcher { events: sender }),
+            )
+            .ok()?;
+
+        Some(HotplugWatch {
+            _registration: registration,
+            events,
+        })
+    }
+
+    // Waits up to 'timeout' for the next keyboard plug or unplug
+    pub fn poll(&self, timeout: Duration) -> Option<HotplugEvent> {
+        let _ = GlobalContext::default().handle_events(Some(timeout));
+
+        self.events.try_recv().ok()
+    }
+}
+
+pub fn replay_saved_command(device: &Keyboard) -> Status {
+    sleep(Duration::from_millis(SETTLE_MS));
+
+    match get_saved_command() {
+        Some(cmd) => {
+            eprintln!("Using saved command: {}", cmd);
+
+            cmd.run(device)
+        }
+        None => Status::SuccessNoSave,
+    }
+}
+
+pub fn watch() -> Status {
+    let watcher = match HotplugWatch::new() {
+        Some(watcher) => watcher,
+        None => {
+            eprintln!("USB hotplug isn't supported here, sorry!");
+
+            return Status::Failure;
+        }
+    };
+
+    catch_interrupt();
+
+    eprintln!("Watching for the keyboard being plugged in, press Ctrl-C to stop");
+
+    while !interrupted() {
+        match watcher.poll(Duration::from_millis(POLL_MS)) {
+            Some(HotplugEvent::Arrived(device)) => {
+                eprintln!("Keyboard plugged in");
+
+                replay_saved_command(&Keyboard::Usb(device));
+            }
+            Some(HotplugEvent::Left) => eprintln!("Keyboard unplugged"),
+            None => {}
+        }
+    }
+
+    Status::SuccessNoSave
+}
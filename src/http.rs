@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::cli::parse_command;
+use crate::commands::{
+    get_brightness, get_saved_command, lighting_colours, save_command, Command, Status, Successful,
+};
+use crate::websocket::{accept_key, WebSocket, TEXT};
+
+// So a slow client can't hold up the daemon for long
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_BODY: usize = 64 * 1024;
+
+#[derive(PartialEq, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// The request line, headers with lower case names, and the body when
+/// there's a `Content-Length`.
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request, String> {
+    let mut line = String::new();
+
+    reader.read_line(&mut line).map_err(|err| err.to_string())?;
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err("Bad request line".to_string());
+    };
+
+    let mut headers = BTreeMap::new();
+
+    loop {
+        let mut header = String::new();
+
+        reader
+            .read_line(&mut header)
+            .map_err(|err| err.to_string())?;
+
+        let header = header.trim();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length: usize = match headers.get("content-length") {
+        Some(length) => length.parse().map_err(|_| "Bad Content-Length")?,
+        None => 0,
+    };
+
+    if length > MAX_BODY {
+        return Err("Request too large".to_string());
+    }
+
+    let mut body = vec![0; length];
+
+    reader
+        .read_exact(&mut body)
+        .map_err(|err| err.to_string())?;
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.split('?').next().unwrap_or_default().to_string(),
+        headers,
+        body,
+    })
+}
+
+/// The command named by the path, eg `/breathe`, with its arguments from
+/// the body, a JSON list like `["4000", "dark red"]`.
+pub fn request_command(path: &str, body: &[u8]) -> Result<Command, String> {
+    let args: Vec<String> = match body.iter().all(u8::is_ascii_whitespace) {
+        true => vec![],
+        false => serde_json::from_slice(body)
+            .map_err(|_| "The body should be a JSON list of arguments".to_string())?,
+    };
+
+    let name = path.trim_matches('/');
+
+    if name.is_empty() || name.starts_with('-') {
+        return Err(format!("No command '{}'", name));
+    }
+
+    let words = args.iter().flat_map(|arg| arg.split_whitespace());
+
+    parse_command(std::iter::once(name).chain(words))?
+        .ok_or_else(|| format!("No command '{}'", name))
+}
+
+/// The saved command, brightness, and colours of each region when it just sets colours.
+pub fn state() -> Value {
+    let saved = get_saved_command();
+
+    json!({
+        "saved": saved.as_ref().map(Command::to_string),
+        "brightness": get_brightness(),
+        "colours": saved.as_ref().and_then(lighting_colours).map(|colours| {
+            colours
+                .iter()
+                .map(|colour| format!("{:06x}", colour))
+                .collect::<Vec<_>>()
+        }),
+    })
+}
+
+fn http_status(status: Status) -> (u16, &'static str) {
+    match status {
+        Status::Success | Status::SuccessNoSave => (200, "OK"),
+        Status::BadArguments => (400, "Bad Request"),
+        Status::PermissionDenied => (403, "Forbidden"),
+        Status::NotFound => (503, "Service Unavailable"),
+        Status::Failure | Status::UsbError => (500, "Internal Server Error"),
+    }
+}
+
+fn respond(mut stream: &TcpStream, (code, reason): (u16, &str), body: &Value) {
+    let body = body.to_string();
+
+    // Allowed from any page, for dashboards opened from a file
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Headers: Content-Type\r\n\
+         Connection: close\r\n\r\n{}",
+        code,
+        reason,
+        body.len(),
+        body
+    );
+}
+
+/// A REST API for the daemon, on the address in the config's `[daemon]`,
+/// with WebSocket clients at `/events`.
+pub struct HttpServer {
+    listener: TcpListener,
+    sockets: Vec<WebSocket>,
+}
+
+impl HttpServer {
+    /// Nothing if no address is set.
+    pub fn new(address: Option<&str>) -> Result<Option<HttpServer>, String> {
+        let Some(address) = address else {
+            return Ok(None);
+        };
+
+        let listener = TcpListener::bind(address)
+            .map_err(|err| format!("Unable to serve HTTP on {}: {}", address, err))?;
+
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+
+        eprintln!("Serving HTTP on {}", address);
+
+        Ok(Some(HttpServer {
+            listener,
+            sockets: Vec::new(),
+        }))
+    }
+
+    /// Answers a waiting request, and any WebSocket messages, with `run`
+    /// running their commands, and gives back those run successfully.
+    pub fn handle(&mut self, mut run: impl FnMut(&Command) -> Status) -> Vec<Command> {
+        let mut ran = Vec::new();
+
+        match self.listener.accept() {
+            Ok((stream, _)) => ran.extend(self.answer(stream, &mut run)),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => (),
+            Err(err) => eprintln!("HTTP connection failed: {}", err),
+        }
+
+        // Each message is a command line, as typed after 'g213-cols'
+        self.sockets.retain_mut(|socket| {
+            let Some(messages) = socket.messages() else {
+                return false;
+            };
+
+            messages.iter().all(|message| {
+                let reply = match parse_command(message.split_whitespace()) {
+                    Ok(Some(command)) => {
+                        let status = run(&command);
+
+                        if status == Status::Success {
+                            save_command(&command);
+
+                            ran.push(command);
+                        }
+
+                        json!({ "event": "result", "ok": status.successful() })
+                    }
+                    Ok(None) => json!({ "event": "result", "error": "No command" }),
+                    Err(err) => json!({ "event": "result", "error": err }),
+                };
+
+                socket.send(TEXT, reply.to_string().as_bytes()).is_ok()
+            })
+        });
+
+        ran
+    }
+
+    /// Sends an event to every WebSocket client.
+    pub fn broadcast(&mut self, event: &Value) {
+        let event = event.to_string();
+
+        self.sockets
+            .retain_mut(|socket| socket.send(TEXT, event.as_bytes()).is_ok());
+    }
+
+    fn answer(
+        &mut self,
+        stream: TcpStream,
+        run: &mut impl FnMut(&Command) -> Status,
+    ) -> Option<Command> {
+        let _ = stream.set_nonblocking(false);
+        let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+
+        let request = match read_request(&mut BufReader::new(&stream)) {
+            Ok(request) => request,
+            Err(err) => {
+                respond(&stream, (400, "Bad Request"), &json!({ "error": err }));
+
+                return None;
+            }
+        };
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/state") => respond(&stream, (200, "OK"), &state()),
+            ("GET", "/events") => self.upgrade(stream, &request),
+            // Browsers check before sending JSON from another page
+            ("OPTIONS", _) => respond(&stream, (200, "OK"), &json!({})),
+            ("POST", path) => match request_command(path, &request.body) {
+                Ok(command) => {
+                    let status = run(&command);
+
+                    respond(
+                        &stream,
+                        http_status(status),
+                        &json!({ "ok": status.successful() }),
+                    );
+
+                    if status == Status::Success {
+                        save_command(&command);
+
+                        return Some(command);
+                    }
+                }
+                Err(err) => respond(&stream, (400, "Bad Request"), &json!({ "error": err })),
+            },
+            _ => respond(
+                &stream,
+                (404, "Not Found"),
+                &json!({ "error": "Use GET /state or /events, or POST /<command>" }),
+            ),
+        }
+
+        None
+    }
+
+    fn upgrade(&mut self, mut stream: TcpStream, request: &Request) {
+        let Some(key) = request.headers.get("sec-websocket-key") else {
+            respond(
+                &stream,
+                (400, "Bad Request"),
+                &json!({ "error": "/events is a WebSocket" }),
+            );
+
+            return;
+        };
+
+        let upgraded = write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        );
+
+        match upgraded.and_then(|_| WebSocket::new(stream)) {
+            Ok(socket) => self.sockets.push(socket),
+            Err(err) => eprintln!("WebSocket connection failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod http_tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let request = "POST /breathe?x=1 HTTP/1.1\r\nHost: localhost\r\n\
+                       content-length: 7\r\n\r\n[\"400\"]extra";
+
+        assert_eq!(
+            read_request(&mut request.as_bytes()),
+            Ok(Request {
+                method: "POST".to_string(),
+                path: "/breathe".to_string(),
+                headers: BTreeMap::from([
+                    ("content-length".to_string(), "7".to_string()),
+                    ("host".to_string(), "localhost".to_string()),
+                ]),
+                body: b"[\"400\"]".to_vec(),
+            })
+        );
+
+        let request = read_request(&mut "GET /state HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+
+        assert!(request.body.is_empty());
+        assert!(read_request(&mut "\r\n".as_bytes()).is_err());
+        assert!(
+            read_request(&mut "GET / HTTP/1.1\r\nContent-Length: 9999999\r\n\r\n".as_bytes())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn commands_from_paths() {
+        let command = |path, body: &str| {
+            request_command(path, body.as_bytes()).map(|command| command.to_string())
+        };
+
+        assert_eq!(
+            command("/breathe", r#"["4000", "dark red"]"#),
+            Ok("breathe 4000 dark red".to_string())
+        );
+        assert_eq!(command("/off", ""), Ok("off".to_string()));
+        assert!(matches!(
+            request_command("/colour/", b" \n"),
+            Ok(Command::Colour(args)) if args.is_empty()
+        ));
+        assert!(command("/dance", "").is_err());
+        assert!(command("/", "").is_err());
+        assert!(command("/--all", "").is_err());
+        assert!(command("/colour", r#"{"colour": "red"}"#).is_err());
+    }
+
+    #[test]
+    fn statuses() {
+        assert_eq!(http_status(Status::SuccessNoSave).0, 200);
+        assert_eq!(http_status(Status::BadArguments).0, 400);
+    }
+}
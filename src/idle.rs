@@ -0,0 +1,146 @@
+use std::process;
+use std::time::{Duration, Instant};
+
+use zbus::blocking::Connection;
+
+use crate::config::IdleConfig;
+use crate::dbus::session_idle_time;
+
+// Asking the desktop ten times a second would be a waste
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+#[derive(PartialEq, Debug)]
+pub enum IdleChange {
+    /// To this percentage of the usual brightness, 0 for off
+    Dim(u8),
+    Restore,
+}
+
+/// Idle dimming, from the config's `[idle]`, which the daemon uses to dim
+/// the keyboard when it's not being used.
+pub struct Idle {
+    timeout: Duration,
+    level: u8,
+    dimmed: bool,
+    session: Option<Connection>,
+    checked: Option<Instant>,
+}
+
+// The XScreenSaver extension's idle time, for X11 desktops without a D-Bus way
+fn xprintidle() -> Option<Duration> {
+    let output = process::Command::new("xprintidle").output().ok()?;
+
+    match output.status.success() {
+        true => String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .ok()
+            .map(Duration::from_millis),
+        false => None,
+    }
+}
+
+impl Idle {
+    /// Nothing if `[idle]` isn't set up.
+    pub fn new(config: &IdleConfig) -> Result<Option<Idle>, String> {
+        let timeout = match config.timeout {
+            None => return Ok(None),
+            Some(0) => return Err("[idle] timeout needs to be at least a minute".to_string()),
+            Some(minutes) => Duration::from_secs(minutes as u64 * 60),
+        };
+
+        if config.level > 100 {
+            return Err(format!("[idle] level {}% is over 100%", config.level));
+        }
+
+        Ok(Some(Idle {
+            timeout,
+            level: config.level,
+            dimmed: false,
+            session: Connection::session().ok(),
+            checked: None,
+        }))
+    }
+
+    pub fn dimmed(&self) -> bool {
+        self.dimmed
+    }
+
+    /// Whether to dim or restore the keyboard, after being idle this long.
+    pub fn update(&mut self, idle: Duration) -> Option<IdleChange> {
+        match (self.dimmed, idle >= self.timeout) {
+            (false, true) => {
+                self.dimmed = true;
+
+                Some(IdleChange::Dim(self.level))
+            }
+            (true, false) => {
+                self.dimmed = false;
+
+                Some(IdleChange::Restore)
+            }
+            _ => None,
+        }
+    }
+
+    /// As above, asking the desktop how long it's been idle now and then.
+    pub fn due(&mut self) -> Option<IdleChange> {
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < CHECK_EVERY)
+        {
+            return None;
+        }
+
+        self.checked = Some(Instant::now());
+
+        let idle = self
+            .session
+            .as_ref()
+            .and_then(session_idle_time)
+            .or_else(xprintidle)?;
+
+        self.update(idle)
+    }
+}
+
+#[cfg(test)]
+mod idle_tests {
+    use super::*;
+
+    #[test]
+    fn dims_once_and_restores() {
+        let config = IdleConfig {
+            timeout: Some(5),
+            level: 20,
+        };
+
+        let mut idle = Idle::new(&config).unwrap().unwrap();
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+
+        assert_eq!(idle.update(minutes(4)), None);
+        assert_eq!(idle.update(minutes(5)), Some(IdleChange::Dim(20)));
+        assert!(idle.dimmed());
+        assert_eq!(idle.update(minutes(6)), None);
+        assert_eq!(
+            idle.update(Duration::from_secs(1)),
+            Some(IdleChange::Restore)
+        );
+        assert_eq!(idle.update(Duration::from_secs(2)), None);
+    }
+
+    #[test]
+    fn config_mistakes() {
+        assert!(Idle::new(&IdleConfig::default()).unwrap().is_none());
+        assert!(Idle::new(&IdleConfig {
+            timeout: Some(0),
+            level: 0
+        })
+        .is_err());
+        assert!(Idle::new(&IdleConfig {
+            timeout: Some(5),
+            level: 101
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use std::env::current_exe;
+use std::fs::{create_dir_all, set_permissions, File, Permissions};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command as Process;
+
+use dirs::{config_dir, home_dir};
+use users::{get_current_gid, get_current_uid};
+
+use crate::commands::{set_file_ownership_to_me, Status};
+use crate::models::models;
+
+const SERVICE_NAME: &str = "g213-cols.service";
+const SYSTEM_UNIT_DIR: &str = "/etc/systemd/system";
+
+const UDEV_RULE_PATH: &str = "/etc/udev/rules.d/70-g213-cols.rules";
+
+const SLEEP_HOOK_PATH: &str = "/usr/lib/systemd/system-sleep/g213-cols";
+
+#[derive(PartialEq, Debug)]
+pub enum ServiceKind {
+    User,
+    System,
+}
+
+// Running the binary with no arguments re-applies the saved command
+pub fn service_unit(kind: &ServiceKind, exe: &str, home: &str) -> String {
+    let (after, environment, wanted_by) = match kind {
+        ServiceKind::User => ("graphical-session.target", String::new(), "default.target"),
+        ServiceKind::System => (
+            "systemd-udev-settle.service",
+            format!("Environment=HOME={}\n", home),
+            "multi-user.target",
+        ),
+    };
+
+    format!(
+        "[Unit]
+Description=Restore G213 keyboard lighting
+After={}
+
+[Service]
+Type=oneshot
+{}ExecStart={}
+
+[Install]
+WantedBy={}
+",
+        after, environment, exe, wanted_by
+    )
+}
+
+// Any directories we create under sudo should still belong to the user
+fn create_dir_for_me(dir: &Path) -> std::io::Result<()> {
+    if !dir.exists() {
+        if let Some(parent) = dir.parent() {
+            create_dir_for_me(parent)?;
+        }
+
+        create_dir_all(dir)?;
+
+        set_file_ownership_to_me(dir.to_string_lossy().to_string());
+    }
+
+    Ok(())
+}
+
+fn write_unit(path: &Path, unit: &str, kind: &ServiceKind) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        match kind {
+            ServiceKind::User => create_dir_for_me(dir)?,
+            ServiceKind::System => create_dir_all(dir)?,
+        }
+    }
+
+    let mut f = File::create(path)?;
+
+    Write::write_all(&mut f, unit.as_bytes())?;
+
+    if ServiceKind::User == *kind {
+        set_file_ownership_to_me(path.to_string_lossy().to_string());
+    }
+
+    Ok(())
+}
+
+fn systemctl(kind: &ServiceKind, args: &[&str]) -> bool {
+    let mut systemctl = Process::new("systemctl");
+
+    // The user's systemd instance won't talk to root, so drop any setuid privileges
+    if ServiceKind::User == *kind {
+        systemctl
+            .arg("--user")
+            .uid(get_current_uid())
+            .gid(get_current_gid());
+    }
+
+    match systemctl.args(args).status() {
+        Ok(status) => status.success(),
+        Err(err) => {
+            eprintln!("Unable to run systemctl: {}", err);
+
+            false
+        }
+    }
+}
+
+pub fn install_service(kind: ServiceKind) -> Status {
+    let exe = current_exe().expect("Unable to find the g213-cols executable");
+    let home = home_dir().unwrap_or_default();
+
+    let unit_dir = match kind {
+        ServiceKind::User => config_dir().unwrap_or_default().join("systemd/user"),
+        ServiceKind::System => Path::new(SYSTEM_UNIT_DIR).to_path_buf(),
+    };
+
+    let unit_path = unit_dir.join(SERVICE_NAME);
+    let unit = service_unit(&kind, &exe.to_string_lossy(), &home.to_string_lossy());
+
+    if let Err(err) = write_unit(&unit_path, &unit, &kind) {
+        eprintln!("Unable to write {}: {}", unit_path.display(), err);
+
+        return Status::Failure;
+    }
+
+    println!("Wrote {}", unit_path.display());
+
+    if systemctl(&kind, &["daemon-reload"]) && systemctl(&kind, &["enable", SERVICE_NAME]) {
+        println!("Enabled {}", SERVICE_NAME);
+
+        Status::SuccessNoSave
+    } else {
+        eprintln!("Unable to enable {}", SERVICE_NAME);
+
+        Status::Failure
+    }
+}
+
+// 'uaccess' covers whoever is logged in at the seat, the group covers everyone else
+pub fn udev_rule(group: &str) -> String {
+    let mut rule = "# Allow non-root access to Logitech keyboards for g213-cols\n".to_string();
+
+    for model in models() {
+        rule += &format!(
+            "SUBSYSTEM==\"usb\", ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\", MODE=\"0660\", GROUP=\"{}\", TAG+=\"uaccess\"\n",
+            model.vendor_id(),
+            model.product_id(),
+            group
+        );
+    }
+
+    rule
+}
+
+fn udevadm(args: &[&str]) -> bool {
+    match Process::new("udevadm").args(args).status() {
+        Ok(status) => status.success(),
+        Err(err) => {
+            eprintln!("Unable to run udevadm: {}", err);
+
+            false
+        }
+    }
+}
+
+pub fn install_udev(group: &str) -> Status {
+    let rule = udev_rule(group);
+
+    let written = File::create(UDEV_RULE_PATH).and_then(|mut f| f.write_all(rule.as_bytes()));
+
+    if let Err(err) = written {
+        eprintln!("Unable to write {}, try with sudo: {}", UDEV_RULE_PATH, err);
+
+        return Status::Failure;
+    }
+
+    println!("Wrote {}", UDEV_RULE_PATH);
+
+    if udevadm(&["control", "--reload-rules"]) && udevadm(&["trigger"]) {
+        println!("Reloaded udev rules, re-plug the keyboard if access is still denied");
+
+        Status::SuccessNoSave
+    } else {
+        eprintln!("Unable to reload udev rules");
+
+        Status::Failure
+    }
+}
+
+// systemd runs these with 'pre' or 'post' and the kind of sleep as arguments
+pub fn sleep_hook(exe: &str, home: &str) -> String {
+    format!(
+        "#!/bin/sh
+# Restore G213 keyboard lighting after suspend or hibernate, written by g213-cols
+case \"$1\" in
+    post) HOME=\"{}\" \"{}\" ;;
+esac
+",
+        home, exe
+    )
+}
+
+pub fn install_sleep_hook() -> Status {
+    let exe = current_exe().expect("Unable to find the g213-cols executable");
+    let home = home_dir().unwrap_or_default();
+
+    let hook = sleep_hook(&exe.to_string_lossy(), &home.to_string_lossy());
+
+    let written = File::create(SLEEP_HOOK_PATH)
+        .and_then(|mut f| f.write_all(hook.as_bytes()))
+        .and_then(|_| set_permissions(SLEEP_HOOK_PATH, Permissions::from_mode(0o755)));
+
+    match written {
+        Ok(_) => {
+            println!("Wrote {}", SLEEP_HOOK_PATH);
+
+            Status::SuccessNoSave
+        }
+        Err(err) => {
+            eprintln!(
+                "Unable to write {}, try with sudo: {}",
+                SLEEP_HOOK_PATH, err
+            );
+
+            Status::Failure
+        }
+    }
+}
+
+#[cfg(test)]
+mod install_tests {
+    use super::*;
+
+    #[test]
+    fn user_unit() {
+        let unit = service_unit(&ServiceKind::User, "/home/me/bin/g213-cols", "/home/me");
+
+        assert!(unit.contains("ExecStart=/home/me/bin/g213-cols\n"));
+        assert!(unit.contains("WantedBy=default.target\n"));
+        assert!(!unit.contains("Environment="));
+    }
+
+    #[test]
+    fn system_unit_uses_users_home() {
+        let unit = service_unit(&ServiceKind::System, "/home/me/bin/g213-cols", "/home/me");
+
+        assert!(unit.contains("Environment=HOME=/home/me\n"));
+        assert!(unit.contains("WantedBy=multi-user.target\n"));
+    }
+
+    #[test]
+    fn udev_rule_for_g213() {
+        let rule = udev_rule("plugdev");
+
+        assert!(rule.contains("ATTRS{idVendor}==\"046d\", ATTRS{idProduct}==\"c336\""));
+        assert!(rule.contains("GROUP=\"plugdev\""));
+        assert!(rule.contains("TAG+=\"uaccess\""));
+    }
+
+    #[test]
+    fn udev_rule_for_every_model() {
+        let rule = udev_rule("plugdev");
+
+        assert!(rule.contains("ATTRS{idProduct}==\"c33a\""));
+        assert!(rule.contains("ATTRS{idProduct}==\"c342\""));
+        assert!(rule.contains("ATTRS{idProduct}==\"c084\""));
+    }
+
+    #[test]
+    fn sleep_hook_runs_on_resume() {
+        let hook = sleep_hook("/home/me/bin/g213-cols", "/home/me");
+
+        assert!(hook.starts_with("#!/bin/sh\n"));
+        assert!(hook.contains("post) HOME=\"/home/me\" \"/home/me/bin/g213-cols\" ;;"));
+    }
+}
@@ -1,6 +1,75 @@
+//! Control the backlight of a Logitech G213 keyboard.
+//!
+//! The `g213-cols` binary is a thin wrapper around this library, which can
+//! also be used directly:
+//!
+//! ```no_run
+//! use g213_colours::G213;
+//!
+//! fn main() -> Result<(), g213_colours::G213Error> {
+//!     let keyboard = G213::find()?;
+//!
+//!     keyboard.set_colour(0x00ff00)?;
+//!     keyboard.set_region_colour(1, 0xff0000)?;
+//!     keyboard.set_breathe(2000, 0x0000ff)
+//! }
+//! ```
+//!
+//! Colours can be looked up by X11 name or hex string with
+//! [`x11_colours::get_x11_colour`], and whole `g213-cols` command lines can
+//! be run, and saved, through [`commands`].
+
 #[macro_use]
 extern crate lazy_static;
 
+pub mod ambient;
+pub mod apps;
+pub mod battery;
+pub mod calibration;
+pub mod cli;
 pub mod commands;
+pub mod config;
+pub mod daemon;
+pub mod dbus;
+pub mod dmx;
+pub mod effects;
 pub mod g213_keyboard;
+pub mod hidpp;
+pub mod history;
+pub mod hotplug;
+pub mod http;
+pub mod idle;
+pub mod install;
+pub mod load;
+pub mod locks;
+pub mod models;
+pub mod mqtt;
+pub mod music;
+pub mod network;
+pub mod night;
+pub mod notifications;
+pub mod openrgb;
+pub mod palette;
+pub mod profiles;
+pub mod schedule;
+pub mod script;
+pub mod sun;
+pub mod themes;
+pub mod timers;
+pub mod trace;
+pub mod transport;
+pub mod tui;
+pub mod typing;
+pub mod websocket;
 pub mod x11_colours;
+
+pub use g213_keyboard::{DeviceSelector, G213Error, G213Result, G213};
+
+// Tests that change process wide state, eg the held keyboard, take turns
+#[cfg(test)]
+pub(crate) fn global_state() -> std::sync::MutexGuard<'static, ()> {
+    static GLOBAL_STATE: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    // One failing mustn't fail the rest
+    GLOBAL_STATE.lock().unwrap_or_else(|err| err.into_inner())
+}
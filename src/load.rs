@@ -0,0 +1,146 @@
+use std::fs::read_to_string;
+use std::thread::available_parallelism;
+
+use crate::x11_colours::{hsv_to_rgb, mix_colours};
+
+const LOADAVG_FILE: &str = "/proc/loadavg";
+const STAT_FILE: &str = "/proc/stat";
+
+/// The 1 minute load average, from `/proc/loadavg`.
+pub fn parse_loadavg(loadavg: &str) -> Option<f64> {
+    loadavg.split_whitespace().next()?.parse().ok()
+}
+
+/// Time the CPUs have spent busy, and in all, from the first line of `/proc/stat`.
+pub fn parse_cpu_times(stat: &str) -> Option<(u64, u64)> {
+    let times: Vec<u64> = stat
+        .lines()
+        .next()?
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .map(|time| time.parse().ok())
+        .collect::<Option<_>>()?;
+
+    // Idle and waiting for the disks
+    let idle = times.get(3)? + times.get(4).unwrap_or(&0);
+    let total = times.iter().sum::<u64>();
+
+    Some((total - idle, total))
+}
+
+/// How busy the system is, from 0 to 1.
+pub struct Load {
+    usage: bool,
+    times: Option<(u64, u64)>,
+}
+
+impl Load {
+    /// CPU usage since the last reading if `usage`, otherwise the load average
+    /// for each CPU.
+    pub fn new(usage: bool) -> Load {
+        Load { usage, times: None }
+    }
+
+    pub fn update(&mut self, busy: u64, total: u64) -> f64 {
+        let (last_busy, last_total) = self.times.replace((busy, total)).unwrap_or((0, 0));
+
+        match total.saturating_sub(last_total) {
+            0 => 0.0,
+            took => busy.saturating_sub(last_busy) as f64 / took as f64,
+        }
+    }
+
+    pub fn level(&mut self) -> Result<f64, String> {
+        let (file, parsed) = match self.usage {
+            true => (
+                STAT_FILE,
+                read_to_string(STAT_FILE).map(|stat| {
+                    parse_cpu_times(&stat).map(|(busy, total)| self.update(busy, total))
+                }),
+            ),
+            false => (
+                LOADAVG_FILE,
+                read_to_string(LOADAVG_FILE).map(|loadavg| {
+                    let cpus = available_parallelism().map_or(1, |cpus| cpus.get());
+
+                    parse_loadavg(&loadavg).map(|load| load / cpus as f64)
+                }),
+            ),
+        };
+
+        match parsed {
+            Ok(Some(level)) => Ok(level.clamp(0.0, 1.0)),
+            Ok(None) => Err(format!("Unable to understand {}", file)),
+            Err(err) => Err(format!("Unable to read {}: {}", file, err)),
+        }
+    }
+}
+
+// From green when idle, through yellow, to red when flat out, or between the given colours
+fn level_colour(level: f64, ends: Option<(u32, u32)>) -> u32 {
+    match ends {
+        Some((from, to)) => mix_colours(from, to, level * 100.0),
+        None => hsv_to_rgb(120.0 * (1.0 - level), 100.0, 100.0),
+    }
+}
+
+/// The whole keyboard in a colour for the level, or as a `bar` with more
+/// regions lit, left to right, the busier it is.
+pub fn load_colours(level: f64, regions: u8, bar: bool, ends: Option<(u32, u32)>) -> Vec<u32> {
+    if !bar {
+        return vec![level_colour(level, ends); regions as usize];
+    }
+
+    // Always at least one, so it's clear the bar is there
+    let lit = ((level * regions as f64).ceil() as u8).max(1);
+    let last = (regions as f64 - 1.0).max(1.0);
+
+    (0..regions)
+        .map(|region| match region < lit {
+            true => level_colour(region as f64 / last, ends),
+            false => 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod load_tests {
+    use super::*;
+
+    #[test]
+    fn proc_files() {
+        assert_eq!(parse_loadavg("0.52 0.58 0.59 1/467 12345\n"), Some(0.52));
+        assert_eq!(parse_loadavg(""), None);
+
+        let stat = "cpu  100 0 50 800 50 0 0 0 0 0\ncpu0 50 0 25 400 25 0 0 0 0 0\n";
+
+        assert_eq!(parse_cpu_times(stat), Some((150, 1000)));
+        assert_eq!(parse_cpu_times("intr 1 2 3\n"), None);
+    }
+
+    #[test]
+    fn usage_since_last_time() {
+        let mut load = Load::new(true);
+
+        assert_eq!(load.update(150, 1000), 0.15);
+        assert_eq!(load.update(250, 1200), 0.5);
+        assert_eq!(load.update(250, 1200), 0.0);
+    }
+
+    #[test]
+    fn whole_keyboard_or_bar() {
+        assert_eq!(load_colours(0.0, 5, false, None), [0x00ff00; 5]);
+        assert_eq!(load_colours(1.0, 2, false, None), [0xff0000; 2]);
+        assert_eq!(
+            load_colours(0.5, 5, false, Some((0x000000, 0xfefefe))),
+            [0x7f7f7f; 5]
+        );
+
+        assert_eq!(
+            load_colours(0.5, 5, true, None),
+            [0x00ff00, 0x80ff00, 0xffff00, 0, 0]
+        );
+        assert_eq!(load_colours(0.0, 5, true, None), [0x00ff00, 0, 0, 0, 0]);
+        assert_eq!(load_colours(1.0, 5, true, None)[4], 0xff0000);
+    }
+}
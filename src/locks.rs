@@ -0,0 +1,195 @@
+use std::fs::{read_dir, read_to_string};
+use std::time::{Duration, Instant};
+
+use crate::commands::get_brightness;
+use crate::config::LocksConfig;
+use crate::g213_keyboard::scale_colour;
+use crate::x11_colours::get_x11_colour;
+
+const LEDS_DIR: &str = "/sys/class/leds";
+
+// Quick enough to feel like the keyboard's own lights
+const CHECK_EVERY: Duration = Duration::from_millis(200);
+
+struct Indicator {
+    led: &'static str,
+    region: u8,
+    colour: u32,
+}
+
+/// Lock indicators, from the config's `[locks]`, which the daemon uses to
+/// colour a region while Caps Lock or Num Lock is on.
+pub struct Locks {
+    indicators: Vec<Indicator>,
+    last: Option<Vec<bool>>,
+    checked: Option<Instant>,
+}
+
+// Whether any keyboard has this LED lit, eg 'input3::capslock'
+fn led_on(led: &str) -> bool {
+    let Ok(leds) = read_dir(LEDS_DIR) else {
+        return false;
+    };
+
+    leds.flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(&format!("::{}", led))
+        })
+        .any(|entry| {
+            read_to_string(entry.path().join("brightness"))
+                .is_ok_and(|brightness| brightness.trim() != "0")
+        })
+}
+
+// "1 red" is region 1 in red, like the 'region' command
+fn parse_indicator(lock: &str, led: &'static str, setting: &str) -> Result<Indicator, String> {
+    let bad = || format!("Bad [locks] {} '{}', use eg \"1 red\"", lock, setting);
+
+    let words: Vec<String> = setting.split_whitespace().map(String::from).collect();
+    let (region, colour) = words.split_first().ok_or_else(bad)?;
+
+    let region = region
+        .parse::<u8>()
+        .ok()
+        .filter(|region| (1..=5).contains(region))
+        .ok_or_else(bad)?;
+
+    Ok(Indicator {
+        led,
+        region,
+        colour: get_x11_colour(colour).ok_or_else(bad)?,
+    })
+}
+
+impl Locks {
+    /// Nothing if `[locks]` isn't set up, and why not if it's wrong.
+    pub fn new(config: &LocksConfig) -> Result<Option<Locks>, String> {
+        let locks = [
+            ("caps", "capslock", &config.caps),
+            ("num", "numlock", &config.num),
+        ];
+
+        let indicators = locks
+            .into_iter()
+            .filter_map(|(lock, led, setting)| {
+                setting
+                    .as_ref()
+                    .map(|setting| parse_indicator(lock, led, setting))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((!indicators.is_empty()).then_some(Locks {
+            indicators,
+            last: None,
+            checked: None,
+        }))
+    }
+
+    /// After the lighting has been changed, so lit indicators are shown again.
+    pub fn refresh(&mut self) {
+        self.last = None;
+    }
+
+    /// Which indicators are lit, when that's changed, or they need showing again.
+    pub fn update(&mut self, lit: Vec<bool>) -> Option<Vec<bool>> {
+        let nothing_to_show = self.last.is_none() && !lit.contains(&true);
+
+        match self.last.replace(lit.clone()) {
+            Some(last) if last == lit => None,
+            _ if nothing_to_show => None,
+            _ => Some(lit),
+        }
+    }
+
+    /// As above, checking the keyboard's LEDs now and then.
+    pub fn due(&mut self) -> Option<Vec<bool>> {
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < CHECK_EVERY)
+        {
+            return None;
+        }
+
+        self.checked = Some(Instant::now());
+
+        let lit = self.indicators.iter().map(|i| led_on(i.led)).collect();
+
+        self.update(lit)
+    }
+
+    /// The colours with the lit indicators' regions changed.
+    pub fn colours(&self, base: &[u32], lit: &[bool]) -> Vec<u32> {
+        let mut colours = base.to_vec();
+        let brightness = get_brightness() as u32;
+
+        for (indicator, _) in self.indicators.iter().zip(lit).filter(|(_, lit)| **lit) {
+            if let Some(colour) = colours.get_mut(indicator.region as usize - 1) {
+                *colour = scale_colour(indicator.colour, brightness);
+            }
+        }
+
+        colours
+    }
+}
+
+#[cfg(test)]
+mod locks_tests {
+    use super::*;
+
+    fn locks() -> Locks {
+        let config = LocksConfig {
+            caps: Some("1 red".to_string()),
+            num: Some("5 lawn green".to_string()),
+        };
+
+        Locks::new(&config).unwrap().unwrap()
+    }
+
+    #[test]
+    fn only_changes() {
+        let mut locks = locks();
+
+        assert_eq!(locks.update(vec![false, false]), None);
+        assert_eq!(locks.update(vec![true, false]), Some(vec![true, false]));
+        assert_eq!(locks.update(vec![true, false]), None);
+        assert_eq!(locks.update(vec![false, false]), Some(vec![false, false]));
+
+        locks.refresh();
+
+        assert_eq!(locks.update(vec![false, false]), None);
+        assert_eq!(locks.update(vec![false, true]), Some(vec![false, true]));
+
+        locks.refresh();
+
+        assert_eq!(locks.update(vec![false, true]), Some(vec![false, true]));
+    }
+
+    #[test]
+    fn indicator_regions() {
+        let colours = locks().colours(&[0x0000ff; 5], &[true, true]);
+
+        assert_eq!(colours[1..4], [0x0000ff; 3]);
+        assert_ne!(colours[0], 0x0000ff);
+        assert_ne!(colours[4], 0x0000ff);
+
+        // A smaller keyboard has no region 5
+        assert_eq!(locks().colours(&[0; 3], &[false, true]), [0; 3]);
+    }
+
+    #[test]
+    fn config_mistakes() {
+        let config = |caps: &str| LocksConfig {
+            caps: Some(caps.to_string()),
+            num: None,
+        };
+
+        assert!(Locks::new(&LocksConfig::default()).unwrap().is_none());
+        assert!(Locks::new(&config("red")).is_err());
+        assert!(Locks::new(&config("6 red")).is_err());
+        assert!(Locks::new(&config("1 not a colour")).is_err());
+        assert!(Locks::new(&config("1 dark red")).unwrap().is_some());
+    }
+}
@@ -0,0 +1,441 @@
+use std::fs::read_to_string;
+
+use serde::Deserialize;
+
+use crate::commands::{config_file_path, MODELS_FILE};
+use crate::g213_keyboard::{G213DeviceDescriptor, LOGITECH};
+
+const BUILT_IN_MODELS: &str = include_str!("models.toml");
+
+/// A Logitech keyboard, or mouse, we know how to talk to, see `models.toml`.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct Model {
+    name: String,
+    #[serde(default = "default_vendor_id")]
+    vendor_id: u16,
+    product_id: u16,
+    #[serde(default = "default_regions")]
+    regions: u8,
+    #[serde(default = "default_interface")]
+    interface: u8,
+    #[serde(default = "default_request_type")]
+    request_type: u8,
+    #[serde(default = "default_request")]
+    request: u8,
+    #[serde(default = "default_value")]
+    value: u16,
+    #[serde(default = "default_endpoint")]
+    endpoint: u8,
+    colour: String,
+    breathe: String,
+    cycle: String,
+}
+
+fn default_vendor_id() -> u16 {
+    LOGITECH
+}
+
+fn default_regions() -> u8 {
+    1
+}
+
+fn default_interface() -> u8 {
+    1
+}
+
+// HID Set_Report, output report 0x11
+fn default_request_type() -> u8 {
+    0x21
+}
+
+fn default_request() -> u8 {
+    0x09
+}
+
+fn default_value() -> u16 {
+    0x0211
+}
+
+// Read Interrupt
+fn default_endpoint() -> u8 {
+    0x82
+}
+
+#[derive(Deserialize)]
+struct ModelTable {
+    #[serde(default)]
+    model: Vec<Model>,
+}
+
+fn parse_models(table: &str) -> Result<Vec<Model>, String> {
+    let models = toml::from_str::<ModelTable>(table)
+        .map_err(|err| err.to_string())?
+        .model;
+
+    // Everything that splits the keyboard into regions divides by this
+    if let Some(model) = models.iter().find(|model| model.regions == 0) {
+        return Err(format!(
+            "{} has no regions, it needs at least 1",
+            model.name
+        ));
+    }
+
+    Ok(models)
+}
+
+// User models replace built in ones with the same ids
+fn merge_models(models: &mut Vec<Model>, extra: Vec<Model>) {
+    for model in extra {
+        match models
+            .iter_mut()
+            .find(|m| m.vendor_id == model.vendor_id && m.product_id == model.product_id)
+        {
+            Some(existing) => *existing = model,
+            None => models.push(model),
+        }
+    }
+}
+
+fn load_models() -> Vec<Model> {
+    let mut models = parse_models(BUILT_IN_MODELS).expect("Built in models.toml is broken");
+
+    let path = match config_file_path(MODELS_FILE) {
+        Ok(path) => path,
+        Err(_) => return models,
+    };
+
+    if let Ok(table) = read_to_string(&path) {
+        match parse_models(&table) {
+            Ok(extra) => merge_models(&mut models, extra),
+            Err(err) => eprintln!("Ignoring {}: {}", path, err),
+        }
+    }
+
+    models
+}
+
+lazy_static! {
+    static ref MODELS: Vec<Model> = load_models();
+}
+
+pub fn models() -> &'static [Model] {
+    &MODELS
+}
+
+fn fill(template: &str, placeholder: &str, value: String) -> String {
+    template.replace(placeholder, &value)
+}
+
+impl Model {
+    pub fn from_ids(vendor_id: u16, product_id: u16) -> Option<&'static Model> {
+        models()
+            .iter()
+            .find(|model| model.vendor_id == vendor_id && model.product_id == product_id)
+    }
+
+    pub fn from_name(name: &str) -> Option<&'static Model> {
+        models()
+            .iter()
+            .find(|model| model.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    pub fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Lighting regions that can be set individually, 1 means the whole keyboard only
+    pub fn num_regions(&self) -> u8 {
+        self.regions
+    }
+
+    // USB interface that takes the HID++ lighting reports
+    pub fn interface(&self) -> u8 {
+        self.interface
+    }
+
+    pub fn request_type(&self) -> u8 {
+        self.request_type
+    }
+
+    pub fn request(&self) -> u8 {
+        self.request
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    pub fn endpoint(&self) -> u8 {
+        self.endpoint
+    }
+
+    // 'region' 0 is the whole keyboard
+    pub fn colour_command(&self, region: u8, colour: u32) -> String {
+        let command = fill(&self.colour, "{region}", format!("{:02x}", region));
+
+        fill(&command, "{colour}", format!("{:06x}", colour))
+    }
+
+    pub fn breathe_command(&self, speed: u16, colour: u32) -> String {
+        let command = fill(&self.breathe, "{speed}", format!("{:04x}", speed));
+
+        fill(&command, "{colour}", format!("{:06x}", colour))
+    }
+
+    pub fn cycle_command(&self, speed: u16, brightness: u8) -> String {
+        let command = fill(&self.cycle, "{speed}", format!("{:04x}", speed));
+
+        fill(&command, "{brightness}", format!("{:02x}", brightness))
+    }
+
+    /// Works out what a command sent to this model does, the reverse of
+    /// [`Model::colour_command`] and friends.
+    pub fn decode(&self, command: &str) -> Option<Lighting> {
+        let value = |values: &[(String, u32)], name: &str| {
+            values
+                .iter()
+                .find(|(placeholder, _)| placeholder == name)
+                .map_or(0, |(_, value)| *value)
+        };
+
+        if let Some(values) = match_template(&self.colour, command) {
+            return Some(Lighting::Colour {
+                region: value(&values, "region") as u8,
+                colour: value(&values, "colour"),
+            });
+        }
+
+        if let Some(values) = match_template(&self.breathe, command) {
+            return Some(Lighting::Breathe {
+                speed: value(&values, "speed") as u16,
+                colour: value(&values, "colour"),
+            });
+        }
+
+        match_template(&self.cycle, command).map(|values| Lighting::Cycle {
+            speed: value(&values, "speed") as u16,
+            brightness: value(&values, "brightness") as u8,
+        })
+    }
+}
+
+/// What a command built from a model's templates does to the lighting.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Lighting {
+    Colour { region: u8, colour: u32 },
+    Breathe { speed: u16, colour: u32 },
+    Cycle { speed: u16, brightness: u8 },
+}
+
+// The values of a template's placeholders in a command made from it
+fn match_template(template: &str, command: &str) -> Option<Vec<(String, u32)>> {
+    let mut values = Vec::new();
+    let mut template = template;
+    let mut command = command;
+
+    while !template.is_empty() {
+        if let Some(rest) = template.strip_prefix('{') {
+            let (name, rest) = rest.split_once('}')?;
+            let width = match name {
+                "region" | "brightness" => 2,
+                "speed" => 4,
+                "colour" => 6,
+                _ => return None,
+            };
+
+            let value = u32::from_str_radix(command.get(..width)?, 16).ok()?;
+
+            values.push((name.to_string(), value));
+            template = rest;
+            command = &command[width..];
+        } else {
+            let literal = template.find('{').unwrap_or(template.len());
+
+            if command.get(..literal)? != &template[..literal] {
+                return None;
+            }
+
+            template = &template[literal..];
+            command = &command[literal..];
+        }
+    }
+
+    command.is_empty().then_some(values)
+}
+
+pub fn supported_device(descriptor: &dyn G213DeviceDescriptor) -> Option<&'static Model> {
+    Model::from_ids(descriptor.vendor_id(), descriptor.product_id())
+}
+
+#[cfg(test)]
+mod models_tests {
+    use super::*;
+
+    fn built_in(name: &str) -> Model {
+        parse_models(BUILT_IN_MODELS)
+            .unwrap()
+            .into_iter()
+            .find(|model| model.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn model_from_ids() {
+        let name = |product_id| Model::from_ids(LOGITECH, product_id).map(Model::name);
+
+        assert_eq!(name(0xc336), Some("G213"));
+        assert_eq!(name(0xc33a), Some("G413"));
+        assert_eq!(name(0xc342), Some("G512"));
+        assert_eq!(name(0xc084), Some("G203"));
+        assert_eq!(name(0x1234), None);
+    }
+
+    #[test]
+    fn defaults_are_filled_in() {
+        let model = built_in("G413");
+
+        assert_eq!(model.vendor_id(), LOGITECH);
+        assert_eq!(model.interface(), 1);
+        assert_eq!(model.request_type(), 0x21);
+        assert_eq!(model.request(), 0x09);
+        assert_eq!(model.value(), 0x0211);
+        assert_eq!(model.endpoint(), 0x82);
+    }
+
+    #[test]
+    fn g213_colour_command() {
+        assert_eq!(
+            built_in("G213").colour_command(2, 0x123456),
+            "11ff0c3a02011234560200000000000000000000"
+        );
+    }
+
+    #[test]
+    fn g213_cycle_command() {
+        assert_eq!(
+            built_in("G213").cycle_command(0x1234, 100),
+            "11ff0c3a0003ffffff0000123464000000000000"
+        );
+    }
+
+    #[test]
+    fn commands_are_20_bytes() {
+        for model in parse_models(BUILT_IN_MODELS).unwrap() {
+            assert_eq!(model.colour_command(0, 0xffffff).len(), 40);
+            assert_eq!(model.breathe_command(1000, 0xffffff).len(), 40);
+            assert_eq!(model.cycle_command(1000, 100).len(), 40);
+        }
+    }
+
+    #[test]
+    fn commands_decode() {
+        let g213 = built_in("G213");
+
+        assert_eq!(
+            g213.decode(&g213.colour_command(2, 0x123456)),
+            Some(Lighting::Colour {
+                region: 2,
+                colour: 0x123456
+            })
+        );
+        assert_eq!(
+            g213.decode(&g213.breathe_command(1000, 0xff0000)),
+            Some(Lighting::Breathe {
+                speed: 1000,
+                colour: 0xff0000
+            })
+        );
+        assert_eq!(
+            g213.decode(&g213.cycle_command(5000, 100)),
+            Some(Lighting::Cycle {
+                speed: 5000,
+                brightness: 100
+            })
+        );
+        assert_eq!(g213.decode("11ff0c3a0001"), None);
+        assert_eq!(
+            g213.decode(&built_in("G203").colour_command(0, 0xff0000)),
+            None
+        );
+    }
+
+    #[test]
+    fn g203_mouse() {
+        assert!(built_in("G203")
+            .colour_command(0, 0xff0000)
+            .starts_with("11ff0e3c0001ff0000"));
+    }
+
+    #[test]
+    fn only_the_g213_has_regions() {
+        assert_eq!(built_in("G213").num_regions(), 5);
+        assert_eq!(built_in("G413").num_regions(), 1);
+        assert_eq!(built_in("G512").num_regions(), 1);
+        assert_eq!(built_in("G203").num_regions(), 1);
+    }
+
+    #[test]
+    fn user_models_are_added_or_replace() {
+        let mut models = parse_models(BUILT_IN_MODELS).unwrap();
+        let count = models.len();
+
+        let extra = parse_models(
+            r#"
+[[model]]
+name = "G213 Custom"
+product_id = 0xc336
+regions = 5
+colour = "11ff0c3a{region}01{colour}0200000000000000000000"
+breathe = "11ff0c3a0002{colour}{speed}006400000000000000"
+cycle = "11ff0c3a0003ffffff0000{speed}{brightness}000000000000"
+
+[[model]]
+name = "G915"
+vendor_id = 0x046d
+product_id = 0xc541
+interface = 2
+colour = "11ff0f1c{region}01{colour}0200000000000000000000"
+breathe = "11ff0f1c0002{colour}{speed}006400000000000000"
+cycle = "11ff0f1c0003ffffff0000{speed}{brightness}000000000000"
+"#,
+        )
+        .unwrap();
+
+        merge_models(&mut models, extra);
+
+        assert_eq!(models.len(), count + 1);
+        assert_eq!(models[0].name(), "G213 Custom");
+        assert_eq!(models[count].interface(), 2);
+    }
+
+    #[test]
+    fn broken_user_models_are_an_error() {
+        assert!(parse_models("[[model]]\nname = \"G1\"\n").is_err());
+    }
+
+    #[test]
+    fn user_models_need_a_region() {
+        let err = parse_models(
+            r#"
+[[model]]
+name = "G1"
+product_id = 0xc001
+regions = 0
+colour = "11ff0c3a{region}01{colour}0200000000000000000000"
+breathe = "11ff0c3a0002{colour}{speed}006400000000000000"
+cycle = "11ff0c3a0003ffffff0000{speed}{brightness}000000000000"
+"#,
+        )
+        .unwrap_err();
+
+        assert!(err.contains("G1"));
+    }
+}
@@ -0,0 +1,574 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::process;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::commands::{get_brightness, get_saved_command, lighting_colours, Command};
+use crate::config::{config, MqttConfig};
+use crate::x11_colours::get_x11_colour;
+
+// MQTT 3.1.1 packet types, in the top 4 bits of the first byte
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const SUBSCRIBE: u8 = 8;
+const PINGREQ: u8 = 12;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const POLL: Duration = Duration::from_millis(100);
+const RETRY: Duration = Duration::from_secs(10);
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+const EFFECTS: [&str; 3] = ["none", "breathe", "cycle"];
+
+fn remaining_length(mut length: usize) -> Vec<u8> {
+    let mut encoded = Vec::new();
+
+    loop {
+        let byte = (length % 128) as u8;
+
+        length /= 128;
+
+        match length {
+            0 => {
+                encoded.push(byte);
+
+                return encoded;
+            }
+            _ => encoded.push(byte | 0x80),
+        }
+    }
+}
+
+fn mqtt_string(string: &str) -> Vec<u8> {
+    let mut encoded = (string.len() as u16).to_be_bytes().to_vec();
+
+    encoded.extend(string.as_bytes());
+    encoded
+}
+
+fn packet(kind: u8, flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind << 4 | flags];
+
+    packet.extend(remaining_length(body.len()));
+    packet.extend(body);
+    packet
+}
+
+/// A clean session, with a retained will so the broker says we've gone.
+pub fn connect_packet(
+    client_id: &str,
+    login: Option<(&str, &str)>,
+    (will_topic, will): (&str, &str),
+) -> Vec<u8> {
+    let mut body = mqtt_string("MQTT");
+    let mut flags = 0x02 | 0x04 | 0x20;
+
+    if let Some((_, password)) = login {
+        flags |= 0x80
+            | match password.is_empty() {
+                true => 0,
+                false => 0x40,
+            };
+    }
+
+    body.extend([4, flags]);
+    body.extend((KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    body.extend(mqtt_string(client_id));
+    body.extend(mqtt_string(will_topic));
+    body.extend(mqtt_string(will));
+
+    if let Some((username, password)) = login {
+        body.extend(mqtt_string(username));
+
+        if !password.is_empty() {
+            body.extend(mqtt_string(password));
+        }
+    }
+
+    packet(CONNECT, 0, &body)
+}
+
+pub fn publish_packet(topic: &str, payload: &str, retain: bool) -> Vec<u8> {
+    let mut body = mqtt_string(topic);
+
+    body.extend(payload.as_bytes());
+
+    packet(PUBLISH, retain as u8, &body)
+}
+
+pub fn subscribe_packet(id: u16, topic: &str) -> Vec<u8> {
+    let mut body = id.to_be_bytes().to_vec();
+
+    body.extend(mqtt_string(topic));
+    body.push(0);
+
+    packet(SUBSCRIBE, 0x02, &body)
+}
+
+/// The topic and payload of a PUBLISH packet.
+pub fn parse_publish(flags: u8, body: &[u8]) -> Option<(String, Vec<u8>)> {
+    let length = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    let topic = String::from_utf8(body.get(2..2 + length)?.to_vec()).ok()?;
+
+    // Higher QoS messages have a packet id before the payload
+    let payload = match flags & 0x06 {
+        0 => 2 + length,
+        _ => 4 + length,
+    };
+
+    Some((topic, body.get(payload..)?.to_vec()))
+}
+
+// The next packet's first byte and body, or nothing if none has arrived
+fn read_packet(stream: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut first = [0u8];
+
+    match stream.read(&mut first) {
+        Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+        Ok(_) => (),
+        Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return Ok(None)
+        }
+        Err(err) => return Err(err),
+    }
+
+    let mut length = 0;
+
+    for shift in (0..28).step_by(7) {
+        let mut byte = [0u8];
+
+        stream.read_exact(&mut byte)?;
+
+        length |= ((byte[0] & 0x7f) as usize) << shift;
+
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+
+    let mut body = vec![0; length];
+
+    stream.read_exact(&mut body)?;
+
+    Ok(Some((first[0], body)))
+}
+
+#[derive(Deserialize, Debug)]
+struct Rgb {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+// A Home Assistant JSON schema light command
+#[derive(Deserialize, Debug)]
+struct LightCommand {
+    state: Option<String>,
+    color: Option<Rgb>,
+    brightness: Option<u8>,
+    effect: Option<String>,
+}
+
+/// The commands to run for a light command from Home Assistant, when the
+/// keyboard is showing `colour`.
+pub fn light_commands(payload: &[u8], colour: u32, speed: u16) -> Result<Vec<Command>, String> {
+    let light: LightCommand =
+        serde_json::from_slice(payload).map_err(|err| format!("Bad MQTT command: {}", err))?;
+
+    if light.state.as_deref() == Some("OFF") {
+        return Ok(vec![Command::Off]);
+    }
+
+    let mut commands = Vec::new();
+
+    // Home Assistant's brightness is 0 to 255
+    if let Some(brightness) = light.brightness {
+        let percent = (brightness as u32 * 100 + 127) / 255;
+
+        commands.push(Command::Brightness(vec![percent.to_string()]));
+    }
+
+    let hex = match &light.color {
+        Some(Rgb { r, g, b }) => format!("0x{:02x}{:02x}{:02x}", r, g, b),
+        None => format!("0x{:06x}", colour),
+    };
+
+    match (light.effect.as_deref(), &light.color) {
+        (Some("breathe"), _) => commands.push(Command::Breathe(vec![speed.to_string(), hex])),
+        (Some("cycle"), _) => commands.push(Command::Cycle(vec![])),
+        (Some("none"), _) | (None, Some(_)) => commands.push(Command::Colour(vec![hex])),
+        (Some(effect), _) => return Err(format!("Unknown MQTT effect '{}'", effect)),
+        (None, None) if commands.is_empty() => commands.push(Command::On),
+        (None, None) => (),
+    }
+
+    Ok(commands)
+}
+
+/// The keyboard as a Home Assistant JSON schema light's state.
+pub fn light_state(on: bool, colour: u32, effect: &str, brightness: u8) -> String {
+    json!({
+        "state": if on { "ON" } else { "OFF" },
+        "color_mode": "rgb",
+        "color": {
+            "r": colour >> 16 & 0xff,
+            "g": colour >> 8 & 0xff,
+            "b": colour & 0xff,
+        },
+        "brightness": (brightness as u32 * 255 + 50) / 100,
+        "effect": effect,
+    })
+    .to_string()
+}
+
+// The colour a command shows, for effects too
+fn command_colour(command: &Command) -> Option<u32> {
+    match command {
+        Command::Breathe(args) => args.get(1..).and_then(get_x11_colour),
+        command => lighting_colours(command)?.first().copied(),
+    }
+}
+
+fn command_effect(command: Option<&Command>) -> &'static str {
+    match command {
+        Some(Command::Breathe(_)) => "breathe",
+        Some(Command::Cycle(_)) => "cycle",
+        _ => "none",
+    }
+}
+
+struct Topics {
+    set: String,
+    state: String,
+    availability: String,
+    discovery: String,
+}
+
+impl Topics {
+    fn new(config: &MqttConfig) -> Topics {
+        let object_id = config.topic.replace(|c: char| !c.is_alphanumeric(), "_");
+
+        Topics {
+            set: format!("{}/set", config.topic),
+            state: format!("{}/state", config.topic),
+            availability: format!("{}/availability", config.topic),
+            discovery: format!("{}/light/{}/config", config.discovery, object_id),
+        }
+    }
+
+    // So Home Assistant finds the keyboard without any setting up
+    fn discovery_config(&self, config: &MqttConfig) -> String {
+        json!({
+            "name": config.name,
+            "unique_id": self.discovery.split('/').nth(2),
+            "schema": "json",
+            "command_topic": self.set,
+            "state_topic": self.state,
+            "availability_topic": self.availability,
+            "brightness": true,
+            "supported_color_modes": ["rgb"],
+            "effect": true,
+            "effect_list": EFFECTS,
+        })
+        .to_string()
+    }
+}
+
+// One connection to the broker, until it fails, or the daemon stops
+fn session(
+    config: &MqttConfig,
+    topics: &Topics,
+    commands: &Sender<Vec<u8>>,
+    states: &Receiver<String>,
+    state: &mut Option<String>,
+) -> io::Result<()> {
+    let host = config.host.as_deref().unwrap_or_default();
+    let mut stream = TcpStream::connect((host, config.port))?;
+    let login = config
+        .username
+        .as_deref()
+        .map(|username| (username, config.password.as_deref().unwrap_or_default()));
+    let client_id = format!("g213-cols-{}", process::id());
+
+    stream.write_all(&connect_packet(
+        &client_id,
+        login,
+        (&topics.availability, "offline"),
+    ))?;
+
+    stream.set_read_timeout(Some(RETRY))?;
+
+    match read_packet(&mut stream)? {
+        Some((first, body)) if first >> 4 == CONNACK && body.get(1) == Some(&0) => (),
+        _ => return Err(io::Error::other("the broker refused to connect")),
+    }
+
+    eprintln!("Connected to MQTT broker {}:{}", host, config.port);
+
+    stream.set_read_timeout(Some(POLL))?;
+    stream.write_all(&subscribe_packet(1, &topics.set))?;
+    stream.write_all(&publish_packet(
+        &topics.discovery,
+        &topics.discovery_config(config),
+        true,
+    ))?;
+    stream.write_all(&publish_packet(&topics.availability, "online", true))?;
+
+    if let Some(state) = state {
+        stream.write_all(&publish_packet(&topics.state, state, true))?;
+    }
+
+    let mut sent = Instant::now();
+
+    loop {
+        if let Some((first, body)) = read_packet(&mut stream)? {
+            let sent_command = match parse_publish(first & 0x0f, &body) {
+                Some((topic, payload)) if first >> 4 == PUBLISH && topic == topics.set => {
+                    commands.send(payload)
+                }
+                _ => Ok(()),
+            };
+
+            if sent_command.is_err() {
+                return Ok(());
+            }
+        }
+
+        loop {
+            match states.try_recv() {
+                Ok(new_state) => {
+                    stream.write_all(&publish_packet(&topics.state, &new_state, true))?;
+
+                    *state = Some(new_state);
+                    sent = Instant::now();
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+
+        if sent.elapsed() > KEEP_ALIVE / 2 {
+            stream.write_all(&packet(PINGREQ, 0, &[]))?;
+
+            sent = Instant::now();
+        }
+    }
+}
+
+// Connecting again whenever the connection is lost
+fn run_client(config: &MqttConfig, commands: Sender<Vec<u8>>, states: Receiver<String>) {
+    let topics = Topics::new(config);
+    let mut state = None;
+    let mut last_error = String::new();
+
+    loop {
+        match session(config, &topics, &commands, &states, &mut state) {
+            Ok(()) => return,
+            Err(err) => {
+                let err = format!("MQTT connection failed: {}", err);
+
+                // Once, rather than every time it tries again
+                if err != last_error {
+                    eprintln!("{}", err);
+
+                    last_error = err;
+                }
+            }
+        }
+
+        sleep(RETRY);
+    }
+}
+
+/// The keyboard as a Home Assistant light, from the config's `[mqtt]`, which
+/// the daemon uses to take commands from the broker and report the lighting.
+pub struct Mqtt {
+    commands: Receiver<Vec<u8>>,
+    states: Sender<String>,
+    on: bool,
+    colour: u32,
+    published: Option<String>,
+    checked: Option<Instant>,
+}
+
+impl Mqtt {
+    /// Nothing if `[mqtt]` isn't set up.
+    pub fn new(config: &'static MqttConfig) -> Result<Option<Mqtt>, String> {
+        if config.host.is_none() {
+            return Ok(None);
+        }
+
+        if config.topic.is_empty() || config.topic.contains(['#', '+']) {
+            return Err(format!("Bad [mqtt] topic '{}'", config.topic));
+        }
+
+        let (command_sender, commands) = channel();
+        let (states, state_receiver) = channel();
+
+        thread::spawn(move || run_client(config, command_sender, state_receiver));
+
+        let mut mqtt = Mqtt {
+            commands,
+            states,
+            on: true,
+            colour: 0xffffff,
+            published: None,
+            checked: None,
+        };
+
+        if let Some(command) = get_saved_command() {
+            mqtt.shown(&command);
+        }
+
+        Ok(Some(mqtt))
+    }
+
+    /// After a command has set the lighting, or turned it off.
+    pub fn shown(&mut self, command: &Command) {
+        self.on = !matches!(command, Command::Off);
+
+        if let Some(colour) = command_colour(command) {
+            self.colour = colour;
+        }
+    }
+
+    /// The commands sent from the broker since last time.
+    pub fn due(&mut self) -> Vec<Command> {
+        let speed = config().defaults.speed.unwrap_or(2000);
+
+        self.commands
+            .try_iter()
+            .flat_map(|payload| {
+                light_commands(&payload, self.colour, speed).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+
+                    vec![]
+                })
+            })
+            .collect()
+    }
+
+    /// Tells the broker how the keyboard is lit now and then, when it's changed.
+    pub fn publish_state(&mut self) {
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < CHECK_EVERY)
+        {
+            return;
+        }
+
+        self.checked = Some(Instant::now());
+
+        let saved = get_saved_command();
+        let state = light_state(
+            self.on,
+            self.colour,
+            command_effect(saved.as_ref()),
+            get_brightness(),
+        );
+
+        if self.published.as_ref() != Some(&state) {
+            let _ = self.states.send(state.clone());
+
+            self.published = Some(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod mqtt_tests {
+    use super::*;
+
+    #[test]
+    fn packets() {
+        assert_eq!(remaining_length(0), [0]);
+        assert_eq!(remaining_length(127), [0x7f]);
+        assert_eq!(remaining_length(321), [0xc1, 0x02]);
+
+        assert_eq!(
+            publish_packet("a/b", "on", true),
+            [0x31, 7, 0, 3, b'a', b'/', b'b', b'o', b'n']
+        );
+        assert_eq!(subscribe_packet(1, "a"), [0x82, 6, 0, 1, 0, 1, b'a', 0]);
+
+        let connect = connect_packet("id", Some(("me", "pw")), ("w", "x"));
+
+        assert_eq!(connect[0], 0x10);
+        assert_eq!(&connect[2..10], [0, 4, b'M', b'Q', b'T', b'T', 4, 0xe6]);
+    }
+
+    #[test]
+    fn reads_publish() {
+        let packet = publish_packet("g213-cols/set", "{}", false);
+        let (first, body) = read_packet(&mut packet.as_slice()).unwrap().unwrap();
+
+        assert_eq!(first >> 4, PUBLISH);
+        assert_eq!(
+            parse_publish(first & 0x0f, &body),
+            Some(("g213-cols/set".to_string(), b"{}".to_vec()))
+        );
+
+        // QoS 1, with a packet id
+        let body = [0, 1, b't', 0, 9, b'x'];
+
+        assert_eq!(
+            parse_publish(0x02, &body),
+            Some(("t".to_string(), b"x".to_vec()))
+        );
+    }
+
+    #[test]
+    fn home_assistant_commands() {
+        let commands = |payload: &str| {
+            light_commands(payload.as_bytes(), 0x0000ff, 2000)
+                .unwrap()
+                .iter()
+                .map(Command::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(commands(r#"{"state":"OFF"}"#), ["off"]);
+        assert_eq!(commands(r#"{"state":"ON"}"#), ["on"]);
+        assert_eq!(
+            commands(r#"{"state":"ON","color":{"r":255,"g":0,"b":16}}"#),
+            ["colour 0xff0010"]
+        );
+        assert_eq!(
+            commands(r#"{"state":"ON","brightness":128,"effect":"breathe"}"#),
+            ["brightness 50", "breathe 2000 0x0000ff"]
+        );
+        assert_eq!(commands(r#"{"brightness":255}"#), ["brightness 100"]);
+        assert_eq!(commands(r#"{"effect":"none"}"#), ["colour 0x0000ff"]);
+        assert!(light_commands(br#"{"effect":"disco"}"#, 0, 2000).is_err());
+        assert!(light_commands(b"on", 0, 2000).is_err());
+    }
+
+    #[test]
+    fn home_assistant_state() {
+        let state: serde_json::Value =
+            serde_json::from_str(&light_state(true, 0xff8000, "none", 50)).unwrap();
+
+        assert_eq!(state["state"], "ON");
+        assert_eq!(state["color"], json!({"r": 255, "g": 128, "b": 0}));
+        assert_eq!(state["brightness"], 128);
+    }
+
+    #[test]
+    fn discovery() {
+        let config = MqttConfig::default();
+        let topics = Topics::new(&config);
+        let discovery: serde_json::Value =
+            serde_json::from_str(&topics.discovery_config(&config)).unwrap();
+
+        assert_eq!(topics.discovery, "homeassistant/light/g213_cols/config");
+        assert_eq!(discovery["command_topic"], "g213-cols/set");
+        assert_eq!(discovery["unique_id"], "g213_cols");
+        assert_eq!(discovery["schema"], "json");
+    }
+}
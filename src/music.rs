@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::io::Read;
+use std::process::{self, Child, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const SAMPLE_RATE: u32 = 22050;
+
+// About a tenth of a second, long enough for the bass
+const WINDOW: usize = 2048;
+
+// Bottom and top of the bands, which are spaced like octaves
+const LOWEST_HZ: f64 = 40.0;
+const HIGHEST_HZ: f64 = 8000.0;
+const TONES_PER_BAND: usize = 6;
+
+// How much a band's loudest level fades each frame, so quiet music still shows
+const PEAK_FADE: f64 = 0.995;
+const LEVEL_FADE: f64 = 0.8;
+
+/// What's being played, recorded from the default output's monitor with
+/// `parec`, which works with PulseAudio and PipeWire.
+pub struct Audio {
+    parec: Child,
+    samples: Arc<Mutex<VecDeque<f64>>>,
+}
+
+impl Audio {
+    pub fn start() -> Result<Audio, String> {
+        let mut parec = process::Command::new("parec")
+            .args([
+                "-d",
+                "@DEFAULT_MONITOR@",
+                "--format=s16le",
+                "--channels=1",
+                "--raw",
+            ])
+            .arg(format!("--rate={}", SAMPLE_RATE))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Unable to run parec to listen to the music: {}", err))?;
+
+        let samples = Arc::new(Mutex::new(VecDeque::from(vec![0.0; WINDOW])));
+        let mut stdout = parec.stdout.take().unwrap();
+        let recorded = samples.clone();
+
+        thread::spawn(move || {
+            let mut bytes = [0u8; 512];
+
+            while let Ok(read @ 1..) = stdout.read(&mut bytes) {
+                let mut samples = recorded.lock().unwrap();
+
+                for sample in bytes[..read].chunks_exact(2) {
+                    samples.push_back(i16::from_le_bytes([sample[0], sample[1]]) as f64 / 32768.0);
+                }
+
+                let extra = samples.len().saturating_sub(WINDOW);
+
+                samples.drain(..extra);
+            }
+        });
+
+        Ok(Audio { parec, samples })
+    }
+
+    /// The latest samples, or why there aren't any more.
+    pub fn samples(&mut self) -> Result<Vec<f64>, String> {
+        if let Ok(Some(status)) = self.parec.try_wait() {
+            return Err(format!("parec stopped listening to the music, {}", status));
+        }
+
+        Ok(self.samples.lock().unwrap().iter().copied().collect())
+    }
+}
+
+impl Drop for Audio {
+    fn drop(&mut self) {
+        let _ = self.parec.kill();
+        let _ = self.parec.wait();
+    }
+}
+
+// The strength of one frequency in the samples, with the Goertzel algorithm
+fn tone_power(samples: &[f64], hz: f64) -> f64 {
+    let coefficient = 2.0 * (2.0 * PI * hz / SAMPLE_RATE as f64).cos();
+    let (mut previous, mut before) = (0.0, 0.0);
+
+    for sample in samples {
+        let current = sample + coefficient * previous - before;
+
+        before = previous;
+        previous = current;
+    }
+
+    (previous * previous + before * before - coefficient * previous * before).max(0.0)
+}
+
+/// How loud each of `bands` frequency bands are, bass first.
+pub fn band_levels(samples: &[f64], bands: usize) -> Vec<f64> {
+    let tones = bands * TONES_PER_BAND;
+    let ratio = (HIGHEST_HZ / LOWEST_HZ).powf(1.0 / (tones - 1).max(1) as f64);
+
+    (0..bands)
+        .map(|band| {
+            let power: f64 = (0..TONES_PER_BAND)
+                .map(|tone| LOWEST_HZ * ratio.powi((band * TONES_PER_BAND + tone) as i32))
+                .map(|hz| tone_power(samples, hz))
+                .sum();
+
+            power.sqrt()
+        })
+        .collect()
+}
+
+/// Band levels from 0 to 1, against the loudest each has been lately, falling
+/// away smoothly rather than flickering.
+pub struct Levels {
+    peaks: Vec<f64>,
+    levels: Vec<f64>,
+}
+
+impl Levels {
+    pub fn new(bands: usize) -> Levels {
+        Levels {
+            peaks: vec![1e-3; bands],
+            levels: vec![0.0; bands],
+        }
+    }
+
+    pub fn update(&mut self, bands: &[f64]) -> &[f64] {
+        for ((band, peak), level) in bands.iter().zip(&mut self.peaks).zip(&mut self.levels) {
+            *peak = (*peak * PEAK_FADE).max(*band).max(1e-3);
+            *level = (band / *peak).max(*level * LEVEL_FADE);
+        }
+
+        &self.levels
+    }
+}
+
+#[cfg(test)]
+mod music_tests {
+    use super::*;
+
+    fn tone(hz: f64) -> Vec<f64> {
+        (0..WINDOW)
+            .map(|at| (2.0 * PI * hz * at as f64 / SAMPLE_RATE as f64).sin())
+            .collect()
+    }
+
+    fn loudest(levels: &[f64]) -> usize {
+        (0..levels.len())
+            .max_by(|a, b| levels[*a].total_cmp(&levels[*b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn bass_and_treble() {
+        assert_eq!(loudest(&band_levels(&tone(60.0), 5)), 0);
+        assert_eq!(loudest(&band_levels(&tone(440.0), 5)), 2);
+        assert_eq!(loudest(&band_levels(&tone(6000.0), 5)), 4);
+        assert!(band_levels(&[0.0; WINDOW], 5)
+            .iter()
+            .all(|level| *level == 0.0));
+    }
+
+    #[test]
+    fn levels_fall_away() {
+        let mut levels = Levels::new(2);
+
+        assert_eq!(levels.update(&[1.0, 0.5]), [1.0, 1.0]);
+        assert_eq!(levels.update(&[0.9, 0.0]), [0.9 / PEAK_FADE, 0.8]);
+        assert_eq!(levels.update(&[0.0, 0.0])[1], 0.8 * 0.8);
+    }
+}
@@ -0,0 +1,194 @@
+use std::fs::read_to_string;
+use std::time::Instant;
+
+use crate::g213_keyboard::scale_colour;
+
+const NET_DEV_FILE: &str = "/proc/net/dev";
+
+/// Bytes received and sent by each interface, from `/proc/net/dev`.
+pub fn parse_net_dev(net_dev: &str) -> Vec<(String, u64, u64)> {
+    net_dev
+        .lines()
+        .filter_map(|line| {
+            let (interface, counts) = line.split_once(':')?;
+            let counts: Vec<u64> = counts
+                .split_whitespace()
+                .map(|count| count.parse().ok())
+                .collect::<Option<_>>()?;
+
+            Some((
+                interface.trim().to_string(),
+                *counts.first()?,
+                *counts.get(8)?,
+            ))
+        })
+        .collect()
+}
+
+/// A rate in bytes a second, eg '500K' or '10M'.
+pub fn parse_rate(rate: &str) -> Option<f64> {
+    let (number, scale) = match rate.to_ascii_uppercase() {
+        rate if rate.ends_with('K') => (rate[..rate.len() - 1].to_string(), 1e3),
+        rate if rate.ends_with('M') => (rate[..rate.len() - 1].to_string(), 1e6),
+        rate if rate.ends_with('G') => (rate[..rate.len() - 1].to_string(), 1e9),
+        rate => (rate, 1.0),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|number| *number > 0.0)
+        .map(|number| number * scale)
+}
+
+/// Download and upload rates of an interface, or the one that's been used
+/// most if none is chosen.
+pub struct Throughput {
+    interface: Option<String>,
+    last: Option<(Instant, u64, u64)>,
+}
+
+impl Throughput {
+    pub fn new(interface: Option<String>) -> Throughput {
+        Throughput {
+            interface,
+            last: None,
+        }
+    }
+
+    /// Bytes a second since the last update, nothing the first time.
+    pub fn update(&mut self, net_dev: &str, now: Instant) -> Result<(f64, f64), String> {
+        let interfaces = parse_net_dev(net_dev);
+
+        let found = match &self.interface {
+            Some(chosen) => interfaces.iter().find(|(name, _, _)| name == chosen),
+            None => interfaces
+                .iter()
+                .filter(|(name, _, _)| name != "lo")
+                .max_by_key(|(_, rx, tx)| rx + tx),
+        };
+
+        let (name, rx, tx) = match found {
+            Some(found) => found.clone(),
+            None => {
+                return Err(match &self.interface {
+                    Some(chosen) => format!("No network interface '{}'", chosen),
+                    None => "No network interface found".to_string(),
+                })
+            }
+        };
+
+        // Sticking with the busiest one, so the rates aren't between two
+        self.interface = Some(name);
+
+        let rates = match self.last.replace((now, rx, tx)) {
+            Some((then, last_rx, last_tx)) => {
+                let secs = now.duration_since(then).as_secs_f64().max(1e-3);
+
+                (
+                    rx.saturating_sub(last_rx) as f64 / secs,
+                    tx.saturating_sub(last_tx) as f64 / secs,
+                )
+            }
+            None => (0.0, 0.0),
+        };
+
+        Ok(rates)
+    }
+
+    pub fn rates(&mut self) -> Result<(f64, f64), String> {
+        let net_dev = read_to_string(NET_DEV_FILE)
+            .map_err(|err| format!("Unable to read {}: {}", NET_DEV_FILE, err))?;
+
+        self.update(&net_dev, Instant::now())
+    }
+}
+
+/// Downloads on the left half and uploads on the right, each as bright as
+/// its rate is of `max`, or the whole keyboard for both together.
+pub fn network_colours(
+    (rx, tx): (f64, f64),
+    max: f64,
+    regions: u8,
+    whole: bool,
+    (rx_colour, tx_colour): (u32, u32),
+) -> Vec<u32> {
+    let level = |rate: f64| ((rate / max).min(1.0) * 100.0).round() as u32;
+
+    if whole {
+        return vec![scale_colour(rx_colour, level(rx + tx)); regions as usize];
+    }
+
+    let left = regions.div_ceil(2);
+
+    (0..regions)
+        .map(|region| match region < left {
+            true => scale_colour(rx_colour, level(rx)),
+            false => scale_colour(tx_colour, level(tx)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod network_tests {
+    use super::*;
+    use std::time::Duration;
+
+    const NET_DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 9000000    100    0    0    0     0          0         0 9000000    100    0    0    0     0       0          0
+  eth0:    1000     10    0    0    0     0          0         0     200      2    0    0    0     0       0          0
+ wlan0:    5000     50    0    0    0     0          0         0    3000     30    0    0    0     0       0          0
+";
+
+    #[test]
+    fn interfaces_and_rates() {
+        assert_eq!(parse_net_dev(NET_DEV)[2], ("wlan0".to_string(), 5000, 3000));
+
+        assert_eq!(parse_rate("500"), Some(500.0));
+        assert_eq!(parse_rate("10M"), Some(1e7));
+        assert_eq!(parse_rate("1.5k"), Some(1500.0));
+        assert_eq!(parse_rate("0"), None);
+        assert_eq!(parse_rate("fast"), None);
+    }
+
+    #[test]
+    fn busiest_or_chosen() {
+        let start = Instant::now();
+        let later = NET_DEV.replace("5000     50", "7000     50");
+        let mut busiest = Throughput::new(None);
+
+        assert_eq!(busiest.update(NET_DEV, start), Ok((0.0, 0.0)));
+        assert_eq!(
+            busiest.update(&later, start + Duration::from_secs(2)),
+            Ok((1000.0, 0.0))
+        );
+
+        let mut chosen = Throughput::new(Some("eth0".to_string()));
+
+        assert_eq!(chosen.update(NET_DEV, start), Ok((0.0, 0.0)));
+        assert_eq!(chosen.interface.as_deref(), Some("eth0"));
+        assert!(Throughput::new(Some("eth9".to_string()))
+            .update(NET_DEV, start)
+            .is_err());
+    }
+
+    #[test]
+    fn halves_or_whole() {
+        let colours = (0x00ff00, 0x0000ff);
+
+        assert_eq!(
+            network_colours((500.0, 1000.0), 1000.0, 5, false, colours),
+            [0x007f00, 0x007f00, 0x007f00, 0x0000ff, 0x0000ff]
+        );
+        assert_eq!(
+            network_colours((500.0, 1000.0), 1000.0, 5, true, colours),
+            [0x00ff00; 5]
+        );
+        assert_eq!(
+            network_colours((0.0, 0.0), 1000.0, 2, false, colours),
+            [0, 0]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use crate::commands::{lighting_colours, Command};
+use crate::config::NightConfig;
+use crate::schedule::parse_time;
+
+const MINUTES_PER_DAY: f64 = 24.0 * 60.0;
+
+/// Red, green and blue of a white light at a colour temperature, using
+/// Tanner Helland's fit to the black body colours.
+pub fn kelvin_rgb(kelvin: f64) -> (f64, f64, f64) {
+    let t = kelvin / 100.0;
+
+    let red = match t <= 66.0 {
+        true => 255.0,
+        false => 329.698727446 * (t - 60.0).powf(-0.1332047592),
+    };
+
+    let green = match t <= 66.0 {
+        true => 99.4708025861 * t.ln() - 161.1195681661,
+        false => 288.1221695283 * (t - 60.0).powf(-0.0755148492),
+    };
+
+    let blue = match t {
+        t if t >= 66.0 => 255.0,
+        t if t <= 19.0 => 0.0,
+        t => 138.5177312231 * (t - 10.0).ln() - 305.0447927307,
+    };
+
+    let clamp = |channel: f64| channel.clamp(0.0, 255.0);
+
+    (clamp(red), clamp(green), clamp(blue))
+}
+
+/// A colour as it'd look lit at `kelvin` rather than `white_point`.
+pub fn shift_colour(colour: u32, kelvin: f64, white_point: f64) -> u32 {
+    let (red, green, blue) = kelvin_rgb(kelvin);
+    let (white_red, white_green, white_blue) = kelvin_rgb(white_point);
+
+    let channel = |shift: u32, scale: f64| {
+        let value = ((colour >> shift) & 0xff) as f64 * scale;
+
+        (value.round().min(255.0) as u32) << shift
+    };
+
+    channel(16, red / white_red) | channel(8, green / white_green) | channel(0, blue / white_blue)
+}
+
+/// Night mode, from the config's `[night]`, which the daemon uses to warm
+/// the saved colours in the evening, and cool them again in the morning.
+pub struct Night {
+    start: f64,
+    end: f64,
+    transition: f64,
+    day: f64,
+    night: f64,
+    base: Option<Vec<u32>>,
+    last: Option<Vec<u32>>,
+}
+
+impl Night {
+    /// Nothing if `[night]` isn't set up, and why not if it's wrong.
+    pub fn new(config: &NightConfig) -> Result<Option<Night>, String> {
+        let (start, end, night) = match (&config.start, &config.end, config.temperature) {
+            (None, None, None) => return Ok(None),
+            (Some(start), Some(end), Some(night)) => (start, end, night),
+            _ => return Err("[night] needs start, end and temperature".to_string()),
+        };
+
+        let time = |time: &str| {
+            parse_time(time)
+                .map(|minutes| minutes as f64)
+                .ok_or_else(|| format!("Bad [night] time '{}', use HH:MM", time))
+        };
+
+        let kelvin = |kelvin: u32| match (1000..=40000).contains(&kelvin) {
+            true => Ok(kelvin as f64),
+            false => Err(format!(
+                "[night] temperature {}K isn't 1000 to 40000",
+                kelvin
+            )),
+        };
+
+        Ok(Some(Night {
+            start: time(start)?,
+            end: time(end)?,
+            transition: config.transition as f64,
+            day: kelvin(config.day_temperature)?,
+            night: kelvin(night)?,
+            base: None,
+            last: None,
+        }))
+    }
+
+    /// The colours to warm, from the command that last set them. Effects are
+    /// left alone.
+    pub fn set_base(&mut self, command: &Command) {
+        self.set_base_colours(lighting_colours(command));
+    }
+
+    pub fn set_base_colours(&mut self, colours: Option<Vec<u32>>) {
+        self.base = colours;
+        self.last = None;
+    }
+
+    /// After the base colours have been shown again as they are.
+    pub fn refresh(&mut self) {
+        self.last = None;
+    }
+
+    /// The colours as last shown, warmed or not.
+    pub fn colours(&self) -> Option<Vec<u32>> {
+        self.last.clone().or_else(|| self.base.clone())
+    }
+
+    /// The colour temperature at `now`, local minutes since midnight.
+    pub fn temperature(&self, now: f64) -> f64 {
+        let since = |time: f64| (now - time).rem_euclid(MINUTES_PER_DAY);
+        let night_length = (self.end - self.start).rem_euclid(MINUTES_PER_DAY);
+
+        let done = |minutes: f64| match self.transition > 0.0 {
+            true => (minutes / self.transition).min(1.0),
+            false => 1.0,
+        };
+
+        // Warming from the start, and cooling again from the end
+        let (from, to, minutes) = match since(self.start) < night_length {
+            true => (self.day, self.night, since(self.start)),
+            false => (self.night, self.day, since(self.end)),
+        };
+
+        from + (to - from) * done(minutes)
+    }
+
+    /// The colours to show at `now`, when they're different to those last shown.
+    pub fn due(&mut self, now: f64) -> Option<Vec<u32>> {
+        let kelvin = self.temperature(now).round();
+
+        let colours: Vec<u32> = self
+            .base
+            .as_ref()?
+            .iter()
+            .map(|colour| shift_colour(*colour, kelvin, self.day))
+            .collect();
+
+        // The base colours have just been set by their command
+        if self.last.is_none() && kelvin == self.day {
+            self.last = Some(colours);
+
+            return None;
+        }
+
+        match self.last.replace(colours.clone()) {
+            Some(last) if last == colours => None,
+            _ => Some(colours),
+        }
+    }
+}
+
+#[cfg(test)]
+mod night_tests {
+    use super::*;
+
+    fn night() -> Night {
+        let config = NightConfig {
+            start: Some("20:00".to_string()),
+            end: Some("07:00".to_string()),
+            temperature: Some(3400),
+            transition: 60,
+            ..Default::default()
+        };
+
+        Night::new(&config).unwrap().unwrap()
+    }
+
+    #[test]
+    fn temperatures() {
+        let night = night();
+
+        assert_eq!(night.temperature(12.0 * 60.0), 6500.0);
+        assert_eq!(night.temperature(20.0 * 60.0 + 30.0), 4950.0);
+        assert_eq!(night.temperature(23.0 * 60.0), 3400.0);
+        assert_eq!(night.temperature(3.0 * 60.0), 3400.0);
+        assert_eq!(night.temperature(7.0 * 60.0 + 30.0), 4950.0);
+        assert_eq!(night.temperature(8.0 * 60.0), 6500.0);
+    }
+
+    #[test]
+    fn warmer_colours() {
+        assert_eq!(shift_colour(0xffffff, 6500.0, 6500.0), 0xffffff);
+
+        let warm = shift_colour(0xffffff, 3400.0, 6500.0);
+
+        assert_eq!(warm >> 16, 0xff);
+        assert!(warm & 0xff < 0xa0);
+    }
+
+    #[test]
+    fn only_changes() {
+        let mut night = night();
+
+        assert_eq!(night.due(12.0 * 60.0), None);
+
+        night.set_base(&Command::Colour(vec!["white".to_string()]));
+
+        assert_eq!(night.due(12.0 * 60.0), None);
+        assert!(night.due(23.0 * 60.0).is_some());
+        assert_eq!(night.due(23.5 * 60.0), None);
+        assert_eq!(night.due(12.0 * 60.0), Some(vec![0xffffff; 5]));
+
+        night.set_base(&Command::Cycle(vec![]));
+
+        assert_eq!(night.due(23.0 * 60.0), None);
+    }
+
+    #[test]
+    fn config_mistakes() {
+        let config = |start: &str, temperature| NightConfig {
+            start: Some(start.to_string()),
+            end: Some("07:00".to_string()),
+            temperature: Some(temperature),
+            ..Default::default()
+        };
+
+        assert!(Night::new(&NightConfig::default()).unwrap().is_none());
+        assert!(Night::new(&config("8pm", 3400)).is_err());
+        assert!(Night::new(&config("20:00", 100)).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::mpsc::Receiver;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::commands::{get_brightness, set_region_colours};
+use crate::config::NotificationsConfig;
+use crate::dbus::watch_notifications;
+use crate::g213_keyboard::{num_regions, scale_colour, G213Result, Keyboard};
+use crate::x11_colours::get_x11_colour;
+
+const FLASH_ON: Duration = Duration::from_millis(250);
+const FLASH_OFF: Duration = Duration::from_millis(200);
+
+fn parse_urgency(urgency: &str) -> Option<u8> {
+    match urgency {
+        "low" => Some(0),
+        "normal" => Some(1),
+        "critical" => Some(2),
+        _ => None,
+    }
+}
+
+/// Notification flashes, from the config's `[notifications]`, which the
+/// daemon uses to flash the keyboard for urgent desktop notifications.
+pub struct Notifications {
+    colour: u32,
+    urgency: u8,
+    flashes: u8,
+    sent: Option<Receiver<u8>>,
+}
+
+impl Notifications {
+    // The settings, before watching for notifications
+    fn from_config(config: &NotificationsConfig) -> Result<Option<Notifications>, String> {
+        let Some(colour) = &config.colour else {
+            return Ok(None);
+        };
+
+        let words: Vec<String> = colour.split_whitespace().map(String::from).collect();
+
+        let colour = get_x11_colour(&words)
+            .ok_or_else(|| format!("Bad [notifications] colour '{}'", colour))?;
+
+        let urgency = parse_urgency(&config.urgency).ok_or_else(|| {
+            format!(
+                "Bad [notifications] urgency '{}', use low, normal or critical",
+                config.urgency
+            )
+        })?;
+
+        Ok(Some(Notifications {
+            colour,
+            urgency,
+            flashes: config.flashes.max(1),
+            sent: None,
+        }))
+    }
+
+    /// Nothing if `[notifications]` isn't set up, and why not if it's wrong
+    /// or the desktop's notifications can't be watched.
+    pub fn new(config: &NotificationsConfig) -> Result<Option<Notifications>, String> {
+        let Some(mut notifications) = Notifications::from_config(config)? else {
+            return Ok(None);
+        };
+
+        let sent = watch_notifications()
+            .map_err(|err| format!("Unable to watch for notifications: {}", err))?;
+
+        notifications.sent = Some(sent);
+
+        Ok(Some(notifications))
+    }
+
+    /// Whether any notifications urgent enough have been sent since last time.
+    pub fn due(&mut self) -> bool {
+        let Some(sent) = self.sent.as_ref() else {
+            return false;
+        };
+
+        // All of them, so they aren't left for next time
+        let urgencies: Vec<u8> = sent.try_iter().collect();
+
+        urgencies.iter().any(|urgency| *urgency >= self.urgency)
+    }
+
+    /// Flashes the whole keyboard, leaving it off, to be set again after.
+    pub fn flash(&self, device: &Keyboard) -> G213Result<()> {
+        let regions = num_regions(device) as usize;
+        let colour = scale_colour(self.colour, get_brightness() as u32);
+
+        for _ in 0..self.flashes {
+            set_region_colours(device, &vec![colour; regions])?;
+            sleep(FLASH_ON);
+            set_region_colours(device, &vec![0; regions])?;
+            sleep(FLASH_OFF);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod notifications_tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    fn config(colour: &str, urgency: &str) -> NotificationsConfig {
+        NotificationsConfig {
+            colour: Some(colour.to_string()),
+            urgency: urgency.to_string(),
+            flashes: 3,
+        }
+    }
+
+    #[test]
+    fn urgent_enough() {
+        let (sender, receiver) = channel();
+        let mut notifications = Notifications::from_config(&config("orange", "normal"))
+            .unwrap()
+            .unwrap();
+
+        notifications.sent = Some(receiver);
+
+        assert!(!notifications.due());
+
+        sender.send(0).unwrap();
+
+        assert!(!notifications.due());
+
+        sender.send(2).unwrap();
+        sender.send(0).unwrap();
+
+        assert!(notifications.due());
+        assert!(!notifications.due());
+    }
+
+    #[test]
+    fn config_mistakes() {
+        assert!(Notifications::new(&NotificationsConfig::default())
+            .unwrap()
+            .is_none());
+        assert!(Notifications::from_config(&config("orangey", "critical")).is_err());
+        assert!(Notifications::from_config(&config("orange", "urgent")).is_err());
+        assert!(Notifications::from_config(&config("dark orange", "low"))
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,500 @@
+use crate::commands::{lighting_colours, Command};
+use crate::g213_keyboard::limit_speed;
+use crate::x11_colours::get_x11_colour;
+
+// Profiles from OpenRGB 0.8, which later versions still load
+const HEADER: &[u8; 16] = b"OPENRGB_PROFILE\0";
+const VERSION: u32 = 3;
+
+const KEYBOARD: i32 = 5;
+const ZONE_LINEAR: i32 = 1;
+
+const HAS_SPEED: u32 = 1 << 0;
+const HAS_PER_LED_COLOR: u32 = 1 << 5;
+const HAS_MODE_SPECIFIC_COLOR: u32 = 1 << 6;
+
+const COLOR_MODE_NONE: u32 = 0;
+const COLOR_MODE_PER_LED: u32 = 1;
+const COLOR_MODE_SPECIFIC: u32 = 2;
+
+const STATIC: &str = "Static";
+const BREATHING: &str = "Breathing";
+const CYCLE: &str = "Spectrum Cycle";
+
+const G213_REGIONS: usize = 5;
+const DEFAULT_SPEED: u16 = 2000;
+const MAX_SPEED: u32 = 65535;
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Mode {
+    pub name: String,
+    pub speed: Option<u32>,
+    pub colours: Vec<u32>,
+}
+
+/// The parts of an OpenRGB device, as kept in a profile, that matter here.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Controller {
+    pub name: String,
+    pub modes: Vec<Mode>,
+    pub active_mode: usize,
+    pub colours: Vec<u32>,
+}
+
+// OpenRGB colours are 0x00bbggrr, so this goes either way
+fn swap_red_blue(colour: u32) -> u32 {
+    (colour & 0xff) << 16 | (colour & 0xff00) | (colour >> 16 & 0xff)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, count: usize) -> Result<&[u8], String> {
+        let bytes = self
+            .data
+            .get(self.at..self.at + count)
+            .ok_or("The profile ends too soon")?;
+
+        self.at += count;
+
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    // With a length that includes its trailing nul
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u16()? as usize;
+        let bytes = self.bytes(length)?;
+
+        Ok(String::from_utf8_lossy(bytes)
+            .trim_end_matches('\0')
+            .to_string())
+    }
+
+    fn colours(&mut self) -> Result<Vec<u32>, String> {
+        (0..self.u16()?)
+            .map(|_| self.u32().map(swap_red_blue))
+            .collect()
+    }
+}
+
+// A controller's description, as OpenRGB writes it for `version`
+fn parse_controller(reader: &mut Reader, version: u32) -> Result<Controller, String> {
+    let start = reader.at;
+    let size = reader.u32()? as usize;
+
+    reader.u32()?;
+
+    let name = reader.string()?;
+
+    // Vendor, description, version, serial and location
+    for _ in 0..match version {
+        0 => 4,
+        _ => 5,
+    } {
+        reader.string()?;
+    }
+
+    let mode_count = reader.u16()?;
+    let active_mode = reader.u32()? as usize;
+    let mut modes = Vec::new();
+
+    for _ in 0..mode_count {
+        let name = reader.string()?;
+        let _value = reader.u32()?;
+        let flags = reader.u32()?;
+
+        // Speed range, brightness range, and colour count range
+        reader.bytes(match version >= 3 {
+            true => 24,
+            false => 16,
+        })?;
+
+        let speed = reader.u32()?;
+
+        // Brightness, direction, and colour mode
+        reader.bytes(match version >= 3 {
+            true => 12,
+            false => 8,
+        })?;
+
+        modes.push(Mode {
+            name,
+            speed: Some(speed).filter(|_| flags & HAS_SPEED != 0),
+            colours: reader.colours()?,
+        });
+    }
+
+    for _ in 0..reader.u16()? {
+        reader.string()?;
+        reader.bytes(16)?;
+
+        let matrix = reader.u16()? as usize;
+
+        reader.bytes(matrix)?;
+
+        if version >= 4 {
+            for _ in 0..reader.u16()? {
+                reader.string()?;
+                reader.bytes(12)?;
+            }
+        }
+    }
+
+    for _ in 0..reader.u16()? {
+        reader.string()?;
+        reader.u32()?;
+    }
+
+    let colours = reader.colours()?;
+
+    // Anything newer versions add is skipped
+    match start + size {
+        end if end < reader.at || end > reader.data.len() => {
+            return Err(format!("'{}' in the profile is the wrong size", name))
+        }
+        end => reader.at = end,
+    }
+
+    Ok(Controller {
+        name,
+        modes,
+        active_mode,
+        colours,
+    })
+}
+
+/// The devices in an OpenRGB `.orp` profile.
+pub fn parse_profile(data: &[u8]) -> Result<Vec<Controller>, String> {
+    if !data.starts_with(HEADER) {
+        return Err("Not an OpenRGB profile".to_string());
+    }
+
+    let mut reader = Reader {
+        data,
+        at: HEADER.len(),
+    };
+
+    let version = reader.u32()?;
+    let mut controllers = Vec::new();
+
+    while reader.at < data.len() {
+        controllers.push(parse_controller(&mut reader, version)?);
+    }
+
+    Ok(controllers)
+}
+
+fn put_string(data: &mut Vec<u8>, string: &str) {
+    data.extend((string.len() as u16 + 1).to_le_bytes());
+    data.extend(string.as_bytes());
+    data.push(0);
+}
+
+fn put_colours(data: &mut Vec<u8>, colours: &[u32]) {
+    data.extend((colours.len() as u16).to_le_bytes());
+
+    for colour in colours {
+        data.extend(swap_red_blue(*colour).to_le_bytes());
+    }
+}
+
+fn put_mode(data: &mut Vec<u8>, value: u32, mode: &Mode) {
+    let (flags, colour_mode, colour_count) = match mode.name.as_str() {
+        STATIC => (HAS_PER_LED_COLOR, COLOR_MODE_PER_LED, 0),
+        BREATHING => (HAS_SPEED | HAS_MODE_SPECIFIC_COLOR, COLOR_MODE_SPECIFIC, 1),
+        _ => (HAS_SPEED, COLOR_MODE_NONE, 0),
+    };
+    let speed_range = match mode.speed {
+        Some(_) => [limit_speed(0) as u32, MAX_SPEED],
+        None => [0, 0],
+    };
+
+    put_string(data, &mode.name);
+
+    for field in [value, flags, speed_range[0], speed_range[1], 0, 0] {
+        data.extend(field.to_le_bytes());
+    }
+
+    for field in [colour_count, colour_count, mode.speed.unwrap_or(0), 0, 0] {
+        data.extend(field.to_le_bytes());
+    }
+
+    data.extend(colour_mode.to_le_bytes());
+
+    put_colours(data, &mode.colours);
+}
+
+// A G213, as OpenRGB describes one
+fn write_controller(controller: &Controller) -> Vec<u8> {
+    let mut data = KEYBOARD.to_le_bytes().to_vec();
+
+    for field in [
+        controller.name.as_str(),
+        "Logitech",
+        "Logitech G213 Keyboard Device",
+        "",
+        "",
+        "",
+    ] {
+        put_string(&mut data, field);
+    }
+
+    data.extend((controller.modes.len() as u16).to_le_bytes());
+    data.extend((controller.active_mode as u32).to_le_bytes());
+
+    for (value, mode) in controller.modes.iter().enumerate() {
+        put_mode(&mut data, value as u32, mode);
+    }
+
+    let leds = controller.colours.len() as u32;
+
+    data.extend(1u16.to_le_bytes());
+    put_string(&mut data, "Keyboard");
+    data.extend(ZONE_LINEAR.to_le_bytes());
+
+    for field in [leds, leds, leds] {
+        data.extend(field.to_le_bytes());
+    }
+
+    data.extend(0u16.to_le_bytes());
+    data.extend((leds as u16).to_le_bytes());
+
+    for led in 0..leds {
+        put_string(&mut data, &format!("Region {}", led + 1));
+        data.extend(led.to_le_bytes());
+    }
+
+    put_colours(&mut data, &controller.colours);
+
+    let mut sized = ((data.len() + 4) as u32).to_le_bytes().to_vec();
+
+    sized.extend(data);
+    sized
+}
+
+/// An OpenRGB `.orp` profile holding just `controller`.
+pub fn write_profile(controller: &Controller) -> Vec<u8> {
+    let mut data = HEADER.to_vec();
+
+    data.extend(VERSION.to_le_bytes());
+    data.extend(write_controller(controller));
+    data
+}
+
+/// The G213 in a profile, or the only device when there's just one.
+pub fn find_g213(controllers: &[Controller]) -> Result<&Controller, String> {
+    match controllers {
+        [only] => Ok(only),
+        _ => controllers
+            .iter()
+            .find(|controller| controller.name.contains("G213"))
+            .ok_or_else(|| "No G213 in the profile".to_string()),
+    }
+}
+
+fn colour_arg(colour: u32) -> String {
+    format!("0x{:06x}", colour)
+}
+
+/// The command that shows what OpenRGB would, its breathing or cycling
+/// modes, or otherwise the colour of each LED, spread over the regions.
+pub fn controller_command(controller: &Controller) -> Result<Command, String> {
+    let mode = controller.modes.get(controller.active_mode);
+    let name = mode.map_or(String::new(), |mode| mode.name.to_ascii_lowercase());
+    let speed = mode
+        .and_then(|mode| mode.speed)
+        .map_or(DEFAULT_SPEED, |speed| {
+            limit_speed(speed.min(MAX_SPEED) as u16)
+        })
+        .to_string();
+
+    if name.contains("breath") {
+        let colour = mode
+            .and_then(|mode| mode.colours.first())
+            .or(controller.colours.first())
+            .copied()
+            .unwrap_or(0xffffff);
+
+        return Ok(Command::Breathe(vec![speed, colour_arg(colour)]));
+    }
+
+    if name.contains("cycle") || name.contains("spectrum") || name.contains("rainbow") {
+        return Ok(Command::Cycle(vec![speed]));
+    }
+
+    if name == "off" {
+        return Ok(Command::Off);
+    }
+
+    let leds = &controller.colours;
+
+    if leds.is_empty() {
+        return Err(format!("'{}' has no colours to import", controller.name));
+    }
+
+    let colours = (0..G213_REGIONS)
+        .map(|region| colour_arg(leds[region * leds.len() / G213_REGIONS]))
+        .collect();
+
+    Ok(Command::Regions(colours))
+}
+
+/// A G213 showing what `command` does, for commands that set colours, and
+/// `breathe` and `cycle`.
+pub fn command_controller(command: &Command) -> Result<Controller, String> {
+    let speed = |args: &[String]| {
+        args.first()
+            .and_then(|speed| speed.parse::<u16>().ok())
+            .map(limit_speed)
+            .unwrap_or(DEFAULT_SPEED) as u32
+    };
+
+    let (mode, colours) = match command {
+        Command::Breathe(args) => {
+            let colour = args.get(1..).and_then(get_x11_colour).unwrap_or(0xff0000);
+
+            (
+                Mode {
+                    name: BREATHING.to_string(),
+                    speed: Some(speed(args)),
+                    colours: vec![colour],
+                },
+                vec![colour; G213_REGIONS],
+            )
+        }
+        Command::Cycle(args) => (
+            Mode {
+                name: CYCLE.to_string(),
+                speed: Some(speed(args)),
+                colours: vec![],
+            },
+            vec![0; G213_REGIONS],
+        ),
+        command => (
+            Mode {
+                name: STATIC.to_string(),
+                ..Mode::default()
+            },
+            lighting_colours(command).ok_or_else(|| {
+                format!(
+                    "'{}' can't be exported, only commands that set colours, breathe and cycle",
+                    command.to_string().trim()
+                )
+            })?,
+        ),
+    };
+
+    let mut modes: Vec<Mode> = [STATIC, BREATHING, CYCLE]
+        .iter()
+        .map(|name| Mode {
+            name: name.to_string(),
+            speed: Some(DEFAULT_SPEED as u32).filter(|_| *name != STATIC),
+            colours: match *name {
+                BREATHING => vec![colours[0]],
+                _ => vec![],
+            },
+        })
+        .collect();
+    let active_mode = modes.iter().position(|m| m.name == mode.name).unwrap();
+
+    modes[active_mode] = mode;
+
+    Ok(Controller {
+        name: "Logitech G213".to_string(),
+        modes,
+        active_mode,
+        colours,
+    })
+}
+
+#[cfg(test)]
+mod openrgb_tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn colours_are_bgr() {
+        assert_eq!(swap_red_blue(0x0000ff), 0xff0000);
+        assert_eq!(swap_red_blue(0x123456), 0x563412);
+    }
+
+    #[test]
+    fn round_trip() {
+        let regions = Command::Regions(strings(&[
+            "0xff0000", "0x00ff00", "0x0000ff", "0xffffff", "0x000000",
+        ]));
+        let breathe = Command::Breathe(strings(&["4000", "0x102030"]));
+        let cycle = Command::Cycle(strings(&["3000"]));
+
+        for command in [regions, breathe, cycle] {
+            let controller = command_controller(&command).unwrap();
+            let controllers = parse_profile(&write_profile(&controller)).unwrap();
+
+            assert_eq!(controllers, [controller]);
+            assert_eq!(
+                controller_command(find_g213(&controllers).unwrap())
+                    .unwrap()
+                    .to_string(),
+                command.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn imports() {
+        let controller = |mode: &str, colours: Vec<u32>| Controller {
+            name: "Logitech G213".to_string(),
+            modes: vec![Mode {
+                name: mode.to_string(),
+                speed: None,
+                colours: vec![],
+            }],
+            active_mode: 0,
+            colours,
+        };
+
+        // More LEDs than regions are spread over them
+        assert_eq!(
+            controller_command(&controller("Direct", (1..=10).collect()))
+                .unwrap()
+                .to_string(),
+            "regions 0x000001 0x000003 0x000005 0x000007 0x000009"
+        );
+        assert_eq!(
+            controller_command(&controller("Off", vec![]))
+                .unwrap()
+                .to_string(),
+            "off"
+        );
+        assert!(controller_command(&controller("Direct", vec![])).is_err());
+
+        let other = Controller {
+            name: "Corsair Vengeance".to_string(),
+            ..Controller::default()
+        };
+
+        assert!(find_g213(&[other.clone(), controller("Static", vec![1])]).is_ok());
+        assert!(find_g213(&[other.clone(), other]).is_err());
+    }
+
+    #[test]
+    fn mistakes() {
+        assert!(parse_profile(b"not a profile").is_err());
+        assert!(parse_profile(&write_profile(&Controller::default())[..40]).is_err());
+        assert!(command_controller(&Command::Wave(strings(&["1000"]))).is_err());
+    }
+}
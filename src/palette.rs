@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+
+use crate::commands::{config_file_for_saving, config_file_path, set_file_ownership_to_me};
+
+const PALETTE_FILE: &str = "palette.json";
+const GPL_HEADER: &str = "GIMP Palette";
+
+/// Colours imported from palette files, by lower case name.
+pub type Palette = BTreeMap<String, u32>;
+
+/// Reads a GIMP `.gpl` palette, whose colours are lines of `red green blue name`.
+///
+/// Unnamed colours are called after the palette and their position, eg `sunset 3`.
+pub fn parse_gpl(gpl: &str) -> Result<Palette, String> {
+    let mut lines = gpl.lines().enumerate();
+
+    match lines.next() {
+        Some((_, header)) if header.trim() == GPL_HEADER => (),
+        _ => return Err(format!("Not a GIMP palette, no '{}' line", GPL_HEADER)),
+    }
+
+    let mut palette_name = "palette".to_string();
+    let mut palette = Palette::new();
+
+    for (number, line) in lines {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix("Name:") {
+            palette_name = name.trim().to_ascii_lowercase();
+
+            continue;
+        }
+
+        let bad_line = || format!("Bad palette line {}: {}", number + 1, line);
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if fields.len() < 3 {
+            return Err(bad_line());
+        }
+
+        let mut colour = 0u32;
+
+        for field in &fields[..3] {
+            colour = colour << 8 | field.parse::<u8>().map_err(|_| bad_line())? as u32;
+        }
+
+        let name = match fields[3..].join(" ").to_ascii_lowercase() {
+            name if name.is_empty() || name == "untitled" => {
+                format!("{} {}", palette_name, palette.len() + 1)
+            }
+            name => name,
+        };
+
+        palette.insert(name, colour);
+    }
+
+    Ok(palette)
+}
+
+pub fn load_palette() -> Palette {
+    let path = match config_file_path(PALETTE_FILE) {
+        Ok(path) => path,
+        Err(_) => return Palette::new(),
+    };
+
+    match read_to_string(&path) {
+        Ok(palette) => serde_json::from_str(&palette).unwrap_or_else(|err| {
+            eprintln!("Ignoring {}: {}", path, err);
+
+            Palette::new()
+        }),
+        Err(_) => Palette::new(),
+    }
+}
+
+pub fn save_palette(palette: &Palette) {
+    let path = match config_file_for_saving(PALETTE_FILE) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Unable to save palette: {}", err);
+
+            return;
+        }
+    };
+
+    write(&path, serde_json::to_string_pretty(palette).unwrap()).expect("Unable to save palette");
+
+    set_file_ownership_to_me(path);
+}
+
+lazy_static! {
+    static ref PALETTE: Palette = load_palette();
+}
+
+/// The imported colours, as they were when first needed.
+pub fn palette() -> &'static Palette {
+    &PALETTE
+}
+
+#[cfg(test)]
+mod palette_tests {
+    use super::*;
+
+    const GPL: &str = "GIMP Palette
+Name: Sunset
+Columns: 3
+#
+255 128   0 Warm Orange
+ 32 192 255\tSky
+  0   0   0
+";
+
+    #[test]
+    fn gpl_colours() {
+        let palette = parse_gpl(GPL).unwrap();
+
+        assert_eq!(palette.len(), 3);
+        assert_eq!(palette["warm orange"], 0xff8000);
+        assert_eq!(palette["sky"], 0x20c0ff);
+        assert_eq!(palette["sunset 3"], 0x000000);
+    }
+
+    #[test]
+    fn not_a_gpl_file() {
+        assert!(parse_gpl("255 0 0 Red\n").is_err());
+    }
+
+    #[test]
+    fn bad_gpl_lines() {
+        assert_eq!(
+            parse_gpl("GIMP Palette\n300 0 0 Too red\n"),
+            Err("Bad palette line 2: 300 0 0 Too red".to_string())
+        );
+        assert!(parse_gpl("GIMP Palette\n1 2\n").is_err());
+    }
+}
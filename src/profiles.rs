@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+
+use crate::commands::{
+    config_file_for_saving, config_file_path, set_file_ownership_to_me, Command, PROFILES_FILE,
+};
+
+/// Named commands, eg 'work' and 'night', kept side by side in one file.
+///
+/// Slots saved with `--slot 2` are just profiles called '2'.
+pub type Profiles = BTreeMap<String, Command>;
+
+fn parse_profiles(profiles: &str) -> Result<Profiles, serde_json::Error> {
+    serde_json::from_str(profiles)
+}
+
+pub fn load_profiles() -> Profiles {
+    let path = match config_file_path(PROFILES_FILE) {
+        Ok(path) => path,
+        Err(_) => return Profiles::new(),
+    };
+
+    match read_to_string(&path) {
+        Ok(profiles) => parse_profiles(&profiles).unwrap_or_else(|err| {
+            eprintln!("Ignoring {}: {}", path, err);
+
+            Profiles::new()
+        }),
+        Err(_) => Profiles::new(),
+    }
+}
+
+pub fn save_profiles(profiles: &Profiles) {
+    let path = match config_file_for_saving(PROFILES_FILE) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Unable to save profiles: {}", err);
+
+            return;
+        }
+    };
+
+    write(&path, serde_json::to_string_pretty(profiles).unwrap()).expect("Unable to save profiles");
+
+    set_file_ownership_to_me(path);
+}
+
+pub fn save_profile(name: &str, command: &Command) {
+    let mut profiles = load_profiles();
+
+    profiles.insert(name.to_string(), command.clone());
+    save_profiles(&profiles);
+}
+
+pub fn get_profile(name: &str) -> Option<Command> {
+    load_profiles().remove(name)
+}
+
+#[cfg(test)]
+mod profiles_tests {
+    use super::*;
+
+    #[test]
+    fn profiles_round_trip() {
+        let mut profiles = Profiles::new();
+
+        profiles.insert(
+            "day".to_string(),
+            Command::Colour(vec!["white".to_string()]),
+        );
+        profiles.insert(
+            "night".to_string(),
+            Command::Breathe(vec!["5000".to_string(), "navy".to_string()]),
+        );
+
+        let profiles = parse_profiles(&serde_json::to_string(&profiles).unwrap()).unwrap();
+
+        assert_eq!(profiles.keys().collect::<Vec<_>>(), vec!["day", "night"]);
+        assert_eq!(profiles["night"].to_string(), "breathe 5000 navy");
+    }
+
+    #[test]
+    fn broken_profiles_are_an_error() {
+        assert!(parse_profiles("{\"day\": \"purple\"}").is_err());
+    }
+}
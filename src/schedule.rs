@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use crate::commands::Command;
+use crate::script::{parse_step, Step};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(Debug)]
+struct Entry {
+    at: u32,
+    command: Command,
+}
+
+/// The daily lighting changes from the config's `[schedule]`, eg
+/// `"22:00" = "breathe 4000 darkred"`, which the daemon runs as they come up.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    entries: Vec<Entry>,
+    last: Option<u32>,
+}
+
+// "HH:MM" as minutes since midnight
+pub(crate) fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// The local time of day, for the daemon's daily changes.
+#[derive(Clone, Copy, Debug)]
+pub struct LocalTime {
+    /// 0 for the 1st of January
+    pub day_of_year: u32,
+    /// Since midnight, with the seconds as a fraction
+    pub minutes: f64,
+    /// Ahead of UTC, in minutes
+    pub utc_offset: f64,
+}
+
+pub fn local_time() -> LocalTime {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+
+        libc::localtime_r(&now, &mut tm);
+
+        LocalTime {
+            day_of_year: tm.tm_yday as u32,
+            minutes: (tm.tm_hour * 60 + tm.tm_min) as f64 + tm.tm_sec as f64 / 60.0,
+            utc_offset: tm.tm_gmtoff as f64 / 60.0,
+        }
+    }
+}
+
+impl Schedule {
+    /// Bad entries are left out, with the reason for each.
+    pub fn new(schedule: &BTreeMap<String, String>) -> (Schedule, Vec<String>) {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+
+        for (time, command) in schedule {
+            let at = match parse_time(time) {
+                Some(at) => at,
+                None => {
+                    errors.push(format!("Bad schedule time '{}', use HH:MM", time));
+
+                    continue;
+                }
+            };
+
+            match parse_step(command) {
+                Ok(Some(Step::Run(command))) => entries.push(Entry { at, command }),
+                Ok(_) => errors.push(format!("No command to run at {}", time)),
+                Err(err) => errors.push(format!("Bad schedule command at {}: {}", time, err)),
+            }
+        }
+
+        (
+            Schedule {
+                entries,
+                last: None,
+            },
+            errors,
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The command to run at `now`, minutes since midnight, if one has come up
+    /// since last asked. The first time it's the one that should be in effect.
+    pub fn due(&mut self, now: u32) -> Option<&Command> {
+        // The whole day, to find the latest entry
+        let span = match self.last.replace(now) {
+            None => MINUTES_PER_DAY,
+            Some(last) => (now + MINUTES_PER_DAY - last) % MINUTES_PER_DAY,
+        };
+
+        // How long ago each entry was, wrapping round midnight
+        let ago = |entry: &Entry| (now + MINUTES_PER_DAY - entry.at) % MINUTES_PER_DAY;
+
+        self.entries
+            .iter()
+            .filter(|entry| ago(entry) < span)
+            .min_by_key(|entry| ago(entry))
+            .map(|entry| &entry.command)
+    }
+}
+
+#[cfg(test)]
+mod schedule_tests {
+    use super::*;
+
+    fn schedule(entries: &[(&str, &str)]) -> (Schedule, Vec<String>) {
+        Schedule::new(
+            &entries
+                .iter()
+                .map(|(time, command)| (time.to_string(), command.to_string()))
+                .collect(),
+        )
+    }
+
+    fn due(schedule: &mut Schedule, now: u32) -> Option<String> {
+        schedule.due(now).map(|command| command.to_string())
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("08:00"), Some(480));
+        assert_eq!(parse_time("8:05"), Some(485));
+        assert_eq!(parse_time("23:59"), Some(1439));
+        assert_eq!(parse_time("24:00"), None);
+        assert_eq!(parse_time("8pm"), None);
+    }
+
+    #[test]
+    fn entries_come_up_once() {
+        let (mut schedule, errors) =
+            schedule(&[("22:00", "breathe 4000 darkred"), ("08:00", "colour white")]);
+
+        assert!(errors.is_empty());
+
+        // What should be showing when the daemon starts
+        assert_eq!(
+            due(&mut schedule, 23 * 60).as_deref(),
+            Some("breathe 4000 darkred")
+        );
+        assert_eq!(due(&mut schedule, 23 * 60), None);
+        assert_eq!(due(&mut schedule, 7 * 60 + 59), None);
+        assert_eq!(due(&mut schedule, 8 * 60).as_deref(), Some("colour white"));
+        assert_eq!(due(&mut schedule, 8 * 60 + 1), None);
+    }
+
+    #[test]
+    fn latest_after_a_gap() {
+        let (mut schedule, _) = schedule(&[
+            ("07:00", "colour white"),
+            ("12:00", "colour yellow"),
+            ("22:00", "off"),
+        ]);
+
+        assert_eq!(due(&mut schedule, 6 * 60).as_deref(), Some("off"));
+
+        // eg after being suspended all morning
+        assert_eq!(
+            due(&mut schedule, 13 * 60).as_deref(),
+            Some("colour yellow")
+        );
+    }
+
+    #[test]
+    fn bad_entries() {
+        let (schedule, errors) = schedule(&[("noon", "colour white"), ("12:00", "wave 100 red")]);
+
+        assert!(schedule.is_empty());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0],
+            "Bad schedule command at 12:00: only commands that set the lighting can be used"
+        );
+        assert_eq!(errors[1], "Bad schedule time 'noon', use HH:MM");
+    }
+}
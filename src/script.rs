@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use crate::cli::parse_command;
+use crate::commands::{scriptable, Command};
+
+const SLEEP: &str = "sleep";
+
+#[derive(Debug)]
+pub enum Step {
+    Run(Command),
+    Sleep(Duration),
+}
+
+/// Reads a line of a script, a command as typed after `g213-cols`, `sleep <ms>`
+/// to wait, or nothing for a blank line or a comment starting with `#`.
+pub fn parse_step(line: &str) -> Result<Option<Step>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words[..] {
+        [] => return Ok(None),
+        [first, ..] if first.starts_with('#') => return Ok(None),
+        [SLEEP, ms] => {
+            return match ms.parse::<u64>() {
+                Ok(ms) => Ok(Some(Step::Sleep(Duration::from_millis(ms)))),
+                Err(_) => Err("the sleep isn't a number of ms".to_string()),
+            }
+        }
+        [SLEEP, ..] => return Err("sleep needs a number of ms".to_string()),
+        _ => (),
+    }
+
+    let command = parse_command(words)?
+        .ok_or_else(|| "no command".to_string())?
+        .resolve()?;
+
+    match scriptable(&command) {
+        true => Ok(Some(Step::Run(command))),
+        false => Err("only commands that set the lighting can be used".to_string()),
+    }
+}
+
+/// Reads a whole script for `run`, stopping at the first bad line.
+pub fn parse_script(script: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+
+    for (number, line) in script.lines().enumerate() {
+        match parse_step(line) {
+            Ok(Some(step)) => steps.push(step),
+            Ok(None) => (),
+            Err(err) => {
+                return Err(format!(
+                    "Bad script line {}: {}, {}",
+                    number + 1,
+                    line.trim(),
+                    err
+                ))
+            }
+        }
+    }
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod script_tests {
+    use super::*;
+
+    #[test]
+    fn commands_and_sleeps() {
+        let script = "# a slow police light\n\
+                      colour red\n\
+                      \n\
+                      sleep 500\n  \
+                      regions blue white blue\n";
+
+        let steps = parse_script(script).unwrap();
+
+        assert_eq!(steps.len(), 3);
+        assert!(matches!(&steps[0], Step::Run(Command::Colour(args)) if *args == ["red"]));
+        assert!(matches!(steps[1], Step::Sleep(wait) if wait == Duration::from_millis(500)));
+        assert!(
+            matches!(&steps[2], Step::Run(Command::Regions(args)) if *args == ["blue", "white", "blue"])
+        );
+    }
+
+    #[test]
+    fn steps() {
+        assert!(parse_step("  ").unwrap().is_none());
+        assert!(parse_step("#ff0000 isn't a command").unwrap().is_none());
+        assert!(matches!(
+            parse_step("off"),
+            Ok(Some(Step::Run(Command::Off)))
+        ));
+        assert_eq!(
+            parse_step("sleep 1 2").unwrap_err(),
+            "sleep needs a number of ms"
+        );
+    }
+
+    #[test]
+    fn bad_lines() {
+        let err = |script| parse_script(script).unwrap_err();
+
+        assert_eq!(
+            err("colour red\nsleep soon"),
+            "Bad script line 2: sleep soon, the sleep isn't a number of ms"
+        );
+        assert_eq!(
+            err("sleep"),
+            "Bad script line 1: sleep, sleep needs a number of ms"
+        );
+        assert!(err("dance red").starts_with("Bad script line 1: dance red, unrecognized"));
+        assert_eq!(
+            err("wave 1000 red"),
+            "Bad script line 1: wave 1000 red, only commands that set the lighting can be used"
+        );
+        assert_eq!(
+            err("run other.txt"),
+            "Bad script line 1: run other.txt, only commands that set the lighting can be used"
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use std::f64::consts::PI;
+
+use crate::commands::{lighting_colours, Command};
+use crate::config::SunConfig;
+use crate::schedule::LocalTime;
+use crate::script::{parse_step, Step};
+use crate::x11_colours::mix_colours;
+
+const MINUTES_PER_DAY: f64 = 24.0 * 60.0;
+
+// The sun's centre this far below the horizon, allowing for refraction and its size
+const SUNRISE_ZENITH: f64 = 90.833;
+
+#[derive(PartialEq, Debug)]
+pub enum Sky {
+    /// Local minutes since midnight
+    Rises {
+        sunrise: f64,
+        sunset: f64,
+    },
+    /// All day, or all night, near the poles
+    Up,
+    Down,
+}
+
+/// Sunrise and sunset, using NOAA's approximations, which are good to a
+/// minute or so away from the poles.
+pub fn sky(day_of_year: u32, latitude: f64, longitude: f64, utc_offset: f64) -> Sky {
+    let year = 2.0 * PI / 365.0 * day_of_year as f64;
+
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * year.cos()
+            - 0.032077 * year.sin()
+            - 0.014615 * (2.0 * year).cos()
+            - 0.040849 * (2.0 * year).sin());
+
+    let declination = 0.006918 - 0.399912 * year.cos() + 0.070257 * year.sin()
+        - 0.006758 * (2.0 * year).cos()
+        + 0.000907 * (2.0 * year).sin()
+        - 0.002697 * (3.0 * year).cos()
+        + 0.00148 * (3.0 * year).sin();
+
+    let latitude = latitude.to_radians();
+
+    let cos_hour_angle = SUNRISE_ZENITH.to_radians().cos() / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+
+    if cos_hour_angle > 1.0 {
+        return Sky::Down;
+    } else if cos_hour_angle < -1.0 {
+        return Sky::Up;
+    }
+
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    let local = |utc: f64| (utc + utc_offset).rem_euclid(MINUTES_PER_DAY);
+
+    Sky::Rises {
+        sunrise: local(720.0 - 4.0 * (longitude + hour_angle) - equation_of_time),
+        sunset: local(720.0 - 4.0 * (longitude - hour_angle) - equation_of_time),
+    }
+}
+
+/// How much it's day, from 0 at night to 1, changing over `transition`
+/// minutes centred on sunrise and sunset.
+pub fn daylight(sky: &Sky, now: f64, transition: f64) -> f64 {
+    let (sunrise, sunset) = match sky {
+        Sky::Rises { sunrise, sunset } => (*sunrise, *sunset),
+        Sky::Up => return 1.0,
+        Sky::Down => return 0.0,
+    };
+
+    let day_length = (sunset - sunrise).rem_euclid(MINUTES_PER_DAY);
+
+    // Just before sunrise is a little before the day, rather than late at night
+    let mut since_sunrise = (now - sunrise).rem_euclid(MINUTES_PER_DAY);
+
+    if since_sunrise > MINUTES_PER_DAY - transition / 2.0 {
+        since_sunrise -= MINUTES_PER_DAY;
+    }
+
+    let ramp = |minutes: f64| match transition > 0.0 {
+        true => ((minutes + transition / 2.0) / transition).clamp(0.0, 1.0),
+        false => (minutes >= 0.0) as u8 as f64,
+    };
+
+    ramp(since_sunrise).min(ramp(day_length - since_sunrise))
+}
+
+pub enum SunChange {
+    /// The day or night command, to run and save
+    Run(Command),
+    /// Part way between them
+    Colours(Vec<u32>),
+}
+
+/// The day and night commands from the config's `[sun]`, which the daemon
+/// switches between at sunrise and sunset.
+pub struct Sun {
+    latitude: f64,
+    longitude: f64,
+    transition: f64,
+    day: Command,
+    night: Command,
+    // Each region's colour for day and night, when they can be blended
+    colours: Option<(Vec<u32>, Vec<u32>)>,
+    last: Option<Vec<u32>>,
+    last_daylight: Option<f64>,
+}
+
+impl Sun {
+    /// Nothing if `[sun]` isn't set up, and why not if it's only part done.
+    pub fn new(config: &SunConfig) -> Result<Option<Sun>, String> {
+        let (latitude, longitude, day, night) = match (
+            config.latitude,
+            config.longitude,
+            &config.day,
+            &config.night,
+        ) {
+            (None, None, None, None) => return Ok(None),
+            (Some(latitude), Some(longitude), Some(day), Some(night)) => {
+                (latitude, longitude, day, night)
+            }
+            _ => return Err("[sun] needs latitude, longitude, day and night".to_string()),
+        };
+
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err("[sun] latitude or longitude out of range".to_string());
+        }
+
+        let command = |name: &str, command: &str| match parse_step(command) {
+            Ok(Some(Step::Run(command))) => Ok(command),
+            Ok(_) => Err(format!("No [sun] {} command", name)),
+            Err(err) => Err(format!("Bad [sun] {} command: {}", name, err)),
+        };
+
+        let day = command("day", day)?;
+        let night = command("night", night)?;
+
+        let colours = match config.transition {
+            0 => None,
+            _ => lighting_colours(&day).zip(lighting_colours(&night)),
+        };
+
+        Ok(Some(Sun {
+            latitude,
+            longitude,
+            transition: config.transition as f64,
+            day,
+            night,
+            colours,
+            last: None,
+            last_daylight: None,
+        }))
+    }
+
+    /// What to change the lighting to at `now`, if anything.
+    pub fn due(&mut self, now: &LocalTime) -> Option<SunChange> {
+        let sky = sky(
+            now.day_of_year,
+            self.latitude,
+            self.longitude,
+            now.utc_offset,
+        );
+        let daylight = daylight(&sky, now.minutes, self.transition);
+
+        // Commands that can't be blended switch half way through
+        let daylight = match self.colours {
+            Some(_) => daylight,
+            None => daylight.round(),
+        };
+
+        if self.last_daylight.replace(daylight) == Some(daylight) {
+            return None;
+        }
+
+        let colours: Vec<u32> = match &self.colours {
+            Some((day, night)) if daylight > 0.0 && daylight < 1.0 => day
+                .iter()
+                .zip(night)
+                .map(|(day, night)| mix_colours(*night, *day, daylight * 100.0))
+                .collect(),
+            _ => {
+                self.last = None;
+
+                return Some(SunChange::Run(match daylight == 1.0 {
+                    true => self.day.clone(),
+                    false => self.night.clone(),
+                }));
+            }
+        };
+
+        // Only when the colours sent would be different
+        match self.last.replace(colours.clone()) {
+            Some(last) if last == colours => None,
+            _ => Some(SunChange::Colours(colours)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod sun_tests {
+    use super::*;
+
+    fn hhmm(minutes: f64) -> String {
+        let minutes = minutes.round() as u32;
+
+        format!("{:02}:{:02}", minutes / 60, minutes % 60)
+    }
+
+    #[test]
+    fn london_midsummer() {
+        // 21st June, British Summer Time, really about 04:43 and 21:21
+        match sky(171, 51.5, -0.13, 60.0) {
+            Sky::Rises { sunrise, sunset } => {
+                assert_eq!(hhmm(sunrise), "04:43");
+                assert_eq!(hhmm(sunset), "21:21");
+            }
+            sky => panic!("{:?}", sky),
+        }
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        assert_eq!(sky(171, 78.2, 15.6, 120.0), Sky::Up);
+        assert_eq!(sky(355, 78.2, 15.6, 60.0), Sky::Down);
+    }
+
+    #[test]
+    fn gradual_daylight() {
+        let sky = Sky::Rises {
+            sunrise: 6.0 * 60.0,
+            sunset: 20.0 * 60.0,
+        };
+
+        assert_eq!(daylight(&sky, 3.0 * 60.0, 30.0), 0.0);
+        assert_eq!(daylight(&sky, 6.0 * 60.0 - 15.0, 30.0), 0.0);
+        assert_eq!(daylight(&sky, 6.0 * 60.0, 30.0), 0.5);
+        assert_eq!(daylight(&sky, 12.0 * 60.0, 30.0), 1.0);
+        assert_eq!(daylight(&sky, 20.0 * 60.0 + 5.0, 30.0), 1.0 / 3.0);
+        assert_eq!(daylight(&sky, 23.0 * 60.0, 30.0), 0.0);
+
+        assert_eq!(daylight(&sky, 6.0 * 60.0 - 1.0, 0.0), 0.0);
+        assert_eq!(daylight(&sky, 6.0 * 60.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn day_night_and_between() {
+        let config = SunConfig {
+            latitude: Some(51.5),
+            longitude: Some(-0.13),
+            day: Some("colour white".to_string()),
+            night: Some("colour black".to_string()),
+            transition: 30,
+        };
+
+        let mut sun = Sun::new(&config).unwrap().unwrap();
+
+        let at = |minutes: f64| LocalTime {
+            day_of_year: 171,
+            minutes,
+            utc_offset: 60.0,
+        };
+
+        let sunrise = match sky(171, 51.5, -0.13, 60.0) {
+            Sky::Rises { sunrise, .. } => sunrise,
+            sky => panic!("{:?}", sky),
+        };
+
+        assert!(
+            matches!(sun.due(&at(12.0 * 60.0)), Some(SunChange::Run(Command::Colour(args))) if args == ["white"])
+        );
+        assert!(sun.due(&at(12.0 * 60.0 + 1.0)).is_none());
+        assert!(
+            matches!(sun.due(&at(sunrise)), Some(SunChange::Colours(colours)) if colours == [0x808080; 5])
+        );
+        assert!(sun.due(&at(sunrise + 0.01)).is_none());
+        assert!(
+            matches!(sun.due(&at(0.0)), Some(SunChange::Run(Command::Colour(args))) if args == ["black"])
+        );
+    }
+
+    #[test]
+    fn config_mistakes() {
+        let config = SunConfig {
+            latitude: Some(51.5),
+            ..Default::default()
+        };
+
+        assert!(Sun::new(&SunConfig::default()).unwrap().is_none());
+        assert!(Sun::new(&config).is_err());
+    }
+}
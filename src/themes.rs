@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use std::fs::read_to_string;
+use std::process;
+
+use dirs::{cache_dir, home_dir};
+use serde::Deserialize;
+
+const WAL_FILE: &str = "wal/colors.json";
+const XRESOURCES_FILE: &str = ".Xresources";
+
+// Only the parts of pywal's colors.json we use
+#[derive(Deserialize)]
+struct WalTheme {
+    colors: BTreeMap<String, String>,
+}
+
+pub fn wal_file() -> String {
+    cache_dir()
+        .unwrap_or_default()
+        .join(WAL_FILE)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn hash_colour(colour: &str) -> Option<u32> {
+    let hex = colour.trim().strip_prefix('#')?;
+
+    match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok(),
+        _ => None,
+    }
+}
+
+/// The `num` colours to use from a pywal theme, `color1` onwards as `color0`
+/// is the background, which is usually close to black.
+pub fn wal_colours(theme: &str, num: u8) -> Result<Vec<u32>, String> {
+    let theme: WalTheme =
+        serde_json::from_str(theme).map_err(|err| format!("Not a pywal theme: {}", err))?;
+
+    (1..=num)
+        .map(|n| {
+            let name = format!("color{}", n);
+
+            theme
+                .colors
+                .get(&name)
+                .and_then(|colour| hash_colour(colour))
+                .ok_or_else(|| format!("No usable '{}' in pywal theme", name))
+        })
+        .collect()
+}
+
+/// The X resources loaded with xrdb, or if xrdb can't be asked, eg outside of X,
+/// those in `~/.Xresources`.
+pub fn read_xresources(file: Option<&str>) -> Result<String, String> {
+    if let Some(file) = file {
+        return read_to_string(file).map_err(|err| format!("Unable to read {}: {}", file, err));
+    }
+
+    match process::Command::new("xrdb").arg("-query").output() {
+        Ok(output) if output.status.success() && !output.stdout.is_empty() => {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        }
+        _ => {
+            let path = home_dir().unwrap_or_default().join(XRESOURCES_FILE);
+
+            read_to_string(&path)
+                .map_err(|err| format!("Unable to read {}: {}", path.display(), err))
+        }
+    }
+}
+
+/// The `*color0` to `*color15`, `*background` and `*foreground` resources,
+/// from an Xresources file or `xrdb -query`, by name.
+///
+/// Values can be `#define`d names, as themes often do.
+pub fn xresources_colours(resources: &str) -> BTreeMap<String, u32> {
+    let mut defines = BTreeMap::new();
+    let mut colours = BTreeMap::new();
+
+    for line in resources.lines() {
+        let line = line.trim();
+
+        if let Some(define) = line.strip_prefix("#define") {
+            if let Some((name, value)) = define.trim().split_once(char::is_whitespace) {
+                defines.insert(name.to_string(), value.trim().to_string());
+            }
+
+            continue;
+        }
+
+        if line.starts_with('!') || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+
+        // 'URxvt*color1', '*.color1' and 'color1' all set color1
+        let name = key.rsplit(['*', '.']).next().unwrap_or(key);
+
+        let wanted = name == "background"
+            || name == "foreground"
+            || name
+                .strip_prefix("color")
+                .and_then(|n| n.parse::<u8>().ok())
+                .is_some_and(|n| n < 16);
+
+        let value = defines.get(value).map_or(value, String::as_str);
+
+        if let (true, Some(colour)) = (wanted, hash_colour(value)) {
+            colours.insert(name.to_string(), colour);
+        }
+    }
+
+    colours
+}
+
+/// The colours called `names`, the last being repeated to make up `num`.
+pub fn pick_colours(
+    colours: &BTreeMap<String, u32>,
+    names: &[String],
+    num: u8,
+) -> Result<Vec<u32>, String> {
+    let mut picked = names
+        .iter()
+        .take(num as usize)
+        .map(|name| {
+            colours
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("No '{}' colour found", name))
+        })
+        .collect::<Result<Vec<u32>, String>>()?;
+
+    while let (Some(last), true) = (picked.last().copied(), picked.len() < num as usize) {
+        picked.push(last);
+    }
+
+    Ok(picked)
+}
+
+#[cfg(test)]
+mod themes_tests {
+    use super::*;
+
+    const WAL: &str = r##"{
+        "wallpaper": "/home/me/wall.jpg",
+        "special": { "background": "#0b0c10", "foreground": "#c5c6c7" },
+        "colors": {
+            "color0": "#0b0c10", "color1": "#1f2833", "color2": "#45a29e",
+            "color3": "#66fcf1", "color4": "#c5c6c7", "color5": "#e85a4f"
+        }
+    }"##;
+
+    #[test]
+    fn wal_regions() {
+        assert_eq!(
+            wal_colours(WAL, 5).unwrap(),
+            vec![0x1f2833, 0x45a29e, 0x66fcf1, 0xc5c6c7, 0xe85a4f]
+        );
+        assert_eq!(wal_colours(WAL, 1).unwrap(), vec![0x1f2833]);
+    }
+
+    #[test]
+    fn wal_missing_colours() {
+        assert_eq!(
+            wal_colours(r##"{"colors": {"color1": "#123456"}}"##, 2),
+            Err("No usable 'color2' in pywal theme".to_string())
+        );
+        assert!(wal_colours("[]", 1).is_err());
+    }
+
+    #[test]
+    fn hash_colours() {
+        assert_eq!(hash_colour("#66fcf1"), Some(0x66fcf1));
+        assert_eq!(hash_colour("66fcf1"), None);
+        assert_eq!(hash_colour("#fff"), None);
+    }
+
+    const XRESOURCES: &str = "
+! Tomorrow Night
+#define t_red #cc6666
+*.foreground:   #c5c8c6
+*.background:   #1d1f21
+*.color1:       t_red
+URxvt*color2:   #b5bd68
+XTerm.color3:   #f0c674
+*color16:       #de935f
+*cursorColor:   #aeafad
+";
+
+    #[test]
+    fn xresources() {
+        let colours = xresources_colours(XRESOURCES);
+
+        assert_eq!(colours.len(), 5);
+        assert_eq!(colours["background"], 0x1d1f21);
+        assert_eq!(colours["color1"], 0xcc6666);
+        assert_eq!(colours["color2"], 0xb5bd68);
+        assert_eq!(colours["color3"], 0xf0c674);
+    }
+
+    #[test]
+    fn picked_colours() {
+        let colours = xresources_colours(XRESOURCES);
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            pick_colours(&colours, &names(&["color1", "foreground"]), 3),
+            Ok(vec![0xcc6666, 0xc5c8c6, 0xc5c8c6])
+        );
+        assert_eq!(
+            pick_colours(&colours, &names(&["color9"]), 5),
+            Err("No 'color9' colour found".to_string())
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use crate::effects::pulse_level;
+use crate::g213_keyboard::scale_colour;
+
+// Tomato, for the Pomodoro technique's kitchen timer
+pub const WORK_COLOUR: u32 = 0xff6347;
+pub const BREAK_COLOUR: u32 = 0x32cd32;
+const FLASH_COLOUR: u32 = 0xffffff;
+
+const FLASH_FOR: Duration = Duration::from_millis(2000);
+const FLASH_EVERY: Duration = Duration::from_millis(250);
+const BREATHE_EVERY: Duration = Duration::from_millis(4000);
+const ALERT_FOR: Duration = Duration::from_secs(10);
+const LONGEST_TIMER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A duration like `90s`, `25m` or `1h30m`, or a number of minutes.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let total = match duration.parse::<u64>() {
+        Ok(minutes) => minutes.checked_mul(60)?,
+        Err(_) => duration_secs(duration)?,
+    };
+
+    Some(Duration::from_secs(total)).filter(|total| !total.is_zero() && *total <= LONGEST_TIMER)
+}
+
+fn duration_secs(duration: &str) -> Option<u64> {
+    let mut total = 0u64;
+    let mut number = String::new();
+
+    for c in duration.to_ascii_lowercase().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+
+            continue;
+        }
+
+        let unit = match c {
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+
+        total = total.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+
+    // Every number needs a unit after it
+    Some(total).filter(|_| number.is_empty())
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Work,
+    Break,
+}
+
+/// The phase `elapsed` into a run of Pomodoros, and how long it's been going.
+pub fn pomodoro_phase(elapsed: Duration, work: Duration, rest: Duration) -> (Phase, Duration) {
+    let cycle = (work + rest).as_millis().max(1);
+    let into = Duration::from_millis((elapsed.as_millis() % cycle) as u64);
+
+    match into.checked_sub(work) {
+        Some(resting) => (Phase::Break, resting),
+        None => (Phase::Work, into),
+    }
+}
+
+// On and off quickly, to catch the eye, for `length`
+fn flashing(into: Duration, length: Duration, colour: u32) -> Option<u32> {
+    if into >= length {
+        return None;
+    }
+
+    match (into.as_millis() / FLASH_EVERY.as_millis()) % 2 {
+        0 => Some(colour),
+        _ => Some(0),
+    }
+}
+
+/// Solid during work, and breathing, between a fifth and full brightness,
+/// during breaks, flashing as each starts.
+pub fn pomodoro_colours(
+    elapsed: Duration,
+    work: Duration,
+    rest: Duration,
+    regions: u8,
+) -> Vec<u32> {
+    let (phase, into) = pomodoro_phase(elapsed, work, rest);
+
+    let colour = flashing(into, FLASH_FOR, FLASH_COLOUR).unwrap_or_else(|| match phase {
+        Phase::Work => WORK_COLOUR,
+        Phase::Break => scale_colour(BREAK_COLOUR, pulse_level(into, BREATHE_EVERY, 20, 100)),
+    });
+
+    vec![colour; regions as usize]
+}
+
+/// All the regions lit at the start, going out right to left as the time
+/// runs down, then flashing once it's up. Nothing once the alert's over.
+pub fn countdown_colours(
+    elapsed: Duration,
+    length: Duration,
+    colour: u32,
+    regions: u8,
+) -> Option<Vec<u32>> {
+    if let Some(over) = elapsed.checked_sub(length) {
+        let flash = flashing(over, ALERT_FOR, colour)?;
+
+        return Some(vec![flash; regions as usize]);
+    }
+
+    // The last region lit fades out, rather than going out all at once
+    let left = (length - elapsed).as_secs_f64() / length.as_secs_f64() * regions as f64;
+
+    Some(
+        (0..regions)
+            .map(|region| {
+                let level = (left - region as f64).clamp(0.0, 1.0);
+
+                scale_colour(colour, (level * 100.0).round() as u32)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod timers_tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("5"), Some(secs(5 * 60)));
+        assert_eq!(parse_duration("90s"), Some(secs(90)));
+        assert_eq!(parse_duration("1h30m"), Some(secs(90 * 60)));
+        assert_eq!(parse_duration("2M10S"), Some(secs(130)));
+        assert_eq!(parse_duration("0"), None);
+        assert_eq!(parse_duration("0s"), None);
+        assert_eq!(parse_duration("10m5"), None);
+        assert_eq!(parse_duration("five"), None);
+        assert_eq!(parse_duration("25h"), None);
+        assert_eq!(parse_duration("99999999999999999999s"), None);
+    }
+
+    #[test]
+    fn pomodoro_phases() {
+        let work = secs(25 * 60);
+        let rest = secs(5 * 60);
+
+        assert_eq!(pomodoro_phase(secs(0), work, rest), (Phase::Work, secs(0)));
+        assert_eq!(
+            pomodoro_phase(secs(26 * 60), work, rest),
+            (Phase::Break, secs(60))
+        );
+        assert_eq!(
+            pomodoro_phase(secs(31 * 60), work, rest),
+            (Phase::Work, secs(60))
+        );
+    }
+
+    #[test]
+    fn pomodoro_lighting() {
+        let work = secs(60);
+        let rest = secs(60);
+        let colours = |elapsed| pomodoro_colours(elapsed, work, rest, 5);
+
+        assert_eq!(colours(Duration::ZERO), [FLASH_COLOUR; 5]);
+        assert_eq!(colours(Duration::from_millis(300)), [0; 5]);
+        assert_eq!(colours(secs(30)), [WORK_COLOUR; 5]);
+
+        // Breathing in from a fifth, and fully in half way through a breath
+        assert_eq!(colours(secs(64)), [scale_colour(BREAK_COLOUR, 20); 5]);
+        assert_eq!(colours(secs(66)), [BREAK_COLOUR; 5]);
+        assert_eq!(colours(secs(120)), [FLASH_COLOUR; 5]);
+    }
+
+    #[test]
+    fn countdown_lighting() {
+        let colours = |elapsed| countdown_colours(elapsed, secs(50), 0x0000ff, 5);
+
+        assert_eq!(colours(secs(0)), Some(vec![0x0000ff; 5]));
+        assert_eq!(
+            colours(secs(25)),
+            Some(vec![0x0000ff, 0x0000ff, 0x00007f, 0, 0])
+        );
+        assert_eq!(colours(secs(50)), Some(vec![0x0000ff; 5]));
+        assert_eq!(colours(secs(50) + FLASH_EVERY), Some(vec![0; 5]));
+        assert_eq!(colours(secs(50) + ALERT_FOR), None);
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const HEADER: &str = "# g213-cols trace: <ms since start> <OUT|IN> <hex>";
+
+// Set up by '--trace', every transfer with the keyboard is written here
+static TRACE: Mutex<Option<(File, Instant)>> = Mutex::new(None);
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Direction {
+    Out,
+    In,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Out => "OUT",
+            Direction::In => "IN",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Transfer {
+    pub at: Duration,
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+}
+
+pub fn start_trace(path: &str) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+
+    writeln!(file, "{}", HEADER)?;
+
+    *TRACE.lock().unwrap() = Some((file, Instant::now()));
+
+    Ok(())
+}
+
+fn format_transfer(at: Duration, direction: Direction, bytes: &[u8]) -> String {
+    format!(
+        "{:>8} {:<3} {}",
+        at.as_millis(),
+        direction.as_str(),
+        hex::encode(bytes)
+    )
+}
+
+// Tracing is best effort, it shouldn't stop the keyboard being driven
+pub fn record(direction: Direction, bytes: &[u8]) {
+    if let Some((file, start)) = TRACE.lock().unwrap().as_mut() {
+        let _ = writeln!(
+            file,
+            "{}",
+            format_transfer(start.elapsed(), direction, bytes)
+        );
+    }
+}
+
+pub fn parse_trace(trace: &str) -> Result<Vec<Transfer>, String> {
+    let mut transfers = Vec::new();
+
+    for (number, line) in trace.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let bad_line = || format!("Bad trace line {}: {}", number + 1, line);
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if fields.len() != 3 {
+            return Err(bad_line());
+        }
+
+        let at = fields[0].parse::<u64>().map_err(|_| bad_line())?;
+
+        let direction = match fields[1] {
+            "OUT" => Direction::Out,
+            "IN" => Direction::In,
+            _ => return Err(bad_line()),
+        };
+
+        let bytes = hex::decode(fields[2]).map_err(|_| bad_line())?;
+
+        transfers.push(Transfer {
+            at: Duration::from_millis(at),
+            direction,
+            bytes,
+        });
+    }
+
+    Ok(transfers)
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use super::*;
+
+    #[test]
+    fn transfer_line() {
+        assert_eq!(
+            format_transfer(Duration::from_millis(42), Direction::Out, &[0x11, 0xff]),
+            "      42 OUT 11ff"
+        );
+    }
+
+    #[test]
+    fn parse_written_lines() {
+        let trace = format!(
+            "{}\n{}\n{}\n",
+            HEADER,
+            format_transfer(Duration::from_millis(0), Direction::Out, &[0x11, 0xff]),
+            format_transfer(Duration::from_millis(3), Direction::In, &[0x11, 0xfe]),
+        );
+
+        let transfers = parse_trace(&trace).unwrap();
+
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0].direction, Direction::Out);
+        assert_eq!(transfers[1].at, Duration::from_millis(3));
+        assert_eq!(transfers[1].bytes, vec![0x11, 0xfe]);
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        assert_eq!(
+            parse_trace("1 SIDEWAYS 11ff"),
+            Err("Bad trace line 1: 1 SIDEWAYS 11ff".to_string())
+        );
+        assert!(parse_trace("1 OUT xyz").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rusb::{DeviceHandle, Error, GlobalContext, Result};
+
+/// The two USB transfers used to talk to a keyboard, so something other
+/// than a real keyboard can be on the other end.
+pub trait Transport: Send + Sync {
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize>;
+}
+
+impl Transport for DeviceHandle<GlobalContext> {
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        DeviceHandle::write_control(self, request_type, request, value, index, buf, timeout)
+    }
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        DeviceHandle::read_interrupt(self, endpoint, buf, timeout)
+    }
+}
+
+// Prints what would be sent, and 'replies' with the command it was sent
+pub(crate) struct DryRun {
+    pub name: &'static str,
+}
+
+impl Transport for DryRun {
+    fn write_control(
+        &self,
+        _request_type: u8,
+        _request: u8,
+        _value: u16,
+        _index: u16,
+        buf: &[u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        println!("{}: {}", self.name, hex::encode(buf));
+
+        Ok(buf.len())
+    }
+
+    fn read_interrupt(&self, _endpoint: u8, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+/// An in-memory keyboard for tests, it keeps everything written to it and
+/// echoes each command back as its reply, unless a reply has been queued.
+#[derive(Default)]
+pub struct FakeTransport {
+    sent: Mutex<Vec<Vec<u8>>>,
+    replies: Mutex<VecDeque<Vec<u8>>>,
+    errors: Mutex<VecDeque<Error>>,
+}
+
+impl FakeTransport {
+    /// Everything written so far, oldest first.
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// Everything written so far, as hex.
+    pub fn sent_hex(&self) -> Vec<String> {
+        self.sent().iter().map(hex::encode).collect()
+    }
+
+    /// The next read gets `reply` rather than an echo.
+    pub fn queue_reply(&self, reply: &[u8]) {
+        self.replies.lock().unwrap().push_back(reply.to_vec());
+    }
+
+    /// The next write fails with `error`, as a real keyboard's sometimes do.
+    pub fn queue_error(&self, error: Error) {
+        self.errors.lock().unwrap().push_back(error);
+    }
+}
+
+impl Transport for FakeTransport {
+    fn write_control(
+        &self,
+        _request_type: u8,
+        _request: u8,
+        _value: u16,
+        _index: u16,
+        buf: &[u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        if let Some(error) = self.errors.lock().unwrap().pop_front() {
+            return Err(error);
+        }
+
+        self.sent.lock().unwrap().push(buf.to_vec());
+
+        Ok(buf.len())
+    }
+
+    fn read_interrupt(&self, _endpoint: u8, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        let reply = match self.replies.lock().unwrap().pop_front() {
+            Some(reply) => reply,
+            None => self
+                .sent
+                .lock()
+                .unwrap()
+                .last()
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        let len = reply.len().min(buf.len());
+
+        buf[..len].copy_from_slice(&reply[..len]);
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod transport_tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn fake_echoes_commands() {
+        let fake = FakeTransport::default();
+        let mut reply = [0u8; 4];
+
+        fake.write_control(0x21, 0x09, 0x0211, 1, &[1, 2, 3, 4], TIMEOUT)
+            .unwrap();
+
+        assert_eq!(fake.read_interrupt(0x82, &mut reply, TIMEOUT), Ok(4));
+        assert_eq!(reply, [1, 2, 3, 4]);
+        assert_eq!(fake.sent_hex(), vec!["01020304".to_string()]);
+    }
+
+    #[test]
+    fn fake_queued_reply() {
+        let fake = FakeTransport::default();
+        let mut reply = [0u8; 4];
+
+        fake.queue_reply(&[9, 9]);
+        fake.write_control(0x21, 0x09, 0x0211, 1, &[1, 2, 3, 4], TIMEOUT)
+            .unwrap();
+
+        assert_eq!(fake.read_interrupt(0x82, &mut reply, TIMEOUT), Ok(2));
+        assert_eq!(reply[..2], [9, 9]);
+    }
+
+    #[test]
+    fn fake_queued_error() {
+        let fake = FakeTransport::default();
+
+        fake.queue_error(Error::Pipe);
+
+        assert_eq!(
+            fake.write_control(0x21, 0x09, 0x0211, 1, &[1], TIMEOUT),
+            Err(Error::Pipe)
+        );
+        assert_eq!(
+            fake.write_control(0x21, 0x09, 0x0211, 1, &[1], TIMEOUT),
+            Ok(1)
+        );
+        assert_eq!(fake.sent().len(), 1);
+    }
+}
@@ -0,0 +1,398 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Gauge, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::commands::{Command, Run, Successful};
+use crate::g213_keyboard::{limit_speed, Keyboard};
+use crate::x11_colours::{hsv_to_rgb, rgb_to_hsv};
+
+const MAX_TUI_SPEED: u16 = 20000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    Static,
+    Breathe,
+    Cycle,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Slider {
+    Hue,
+    Saturation,
+    Value,
+    Speed,
+}
+
+const SLIDERS: [Slider; 4] = [
+    Slider::Hue,
+    Slider::Saturation,
+    Slider::Value,
+    Slider::Speed,
+];
+
+#[derive(PartialEq, Debug)]
+enum Action {
+    Nothing,
+    Apply,
+    Save,
+    Quit,
+}
+
+/// What's being edited in the `tui`, each region's colour as hue, saturation
+/// and value, as those are easier to move around than red, green and blue.
+pub struct TuiState {
+    mode: Mode,
+    // 0 is every region
+    region: usize,
+    colours: Vec<(f64, f64, f64)>,
+    speed: u16,
+    slider: Slider,
+    message: String,
+}
+
+impl TuiState {
+    pub fn new(colours: &[u32], speed: u16) -> Self {
+        TuiState {
+            mode: Mode::Static,
+            region: 0,
+            colours: colours.iter().map(|colour| rgb_to_hsv(*colour)).collect(),
+            speed: limit_speed(speed).min(MAX_TUI_SPEED),
+            slider: Slider::Hue,
+            message: String::new(),
+        }
+    }
+
+    fn colour(&self, region: usize) -> u32 {
+        let (hue, saturation, value) = self.colours[region];
+
+        hsv_to_rgb(hue, saturation, value)
+    }
+
+    // The region whose colour is shown on the sliders, the first for all of them
+    fn shown_region(&self) -> usize {
+        self.region.max(1) - 1
+    }
+
+    fn slider_value(&self, slider: Slider) -> f64 {
+        let (hue, saturation, value) = self.colours[self.shown_region()];
+
+        match slider {
+            Slider::Hue => hue,
+            Slider::Saturation => saturation,
+            Slider::Value => value,
+            Slider::Speed => self.speed as f64,
+        }
+    }
+
+    // Small steps, or big ones with page up and down
+    fn adjust(&mut self, direction: f64, big: bool) {
+        let (small_step, big_step) = match self.slider {
+            Slider::Hue => (5.0, 30.0),
+            Slider::Saturation | Slider::Value => (2.0, 10.0),
+            Slider::Speed => (100.0, 1000.0),
+        };
+
+        let change = direction * if big { big_step } else { small_step };
+        let shown = self.colours[self.shown_region()];
+
+        let (hue, saturation, value) = match self.slider {
+            Slider::Hue => ((shown.0 + change).rem_euclid(360.0), shown.1, shown.2),
+            Slider::Saturation => (shown.0, (shown.1 + change).clamp(0.0, 100.0), shown.2),
+            Slider::Value => (shown.0, shown.1, (shown.2 + change).clamp(0.0, 100.0)),
+            Slider::Speed => {
+                let speed = (self.speed as f64 + change).clamp(0.0, MAX_TUI_SPEED as f64);
+
+                self.speed = limit_speed(speed as u16);
+
+                return;
+            }
+        };
+
+        // Every region gets the part that was changed, so all can be dimmed together
+        let regions = match self.region {
+            0 => 0..self.colours.len(),
+            region => region - 1..region,
+        };
+
+        for colour in &mut self.colours[regions] {
+            match self.slider {
+                Slider::Hue => colour.0 = hue,
+                Slider::Saturation => colour.1 = saturation,
+                _ => colour.2 = value,
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyCode) -> Action {
+        let slider = SLIDERS.iter().position(|s| *s == self.slider).unwrap();
+
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('s') | KeyCode::Enter => return Action::Save,
+            KeyCode::Char('a') | KeyCode::Char('0') => self.region = 0,
+            KeyCode::Char(c @ '1'..='9') => {
+                let region = c as usize - '0' as usize;
+
+                if region <= self.colours.len() {
+                    self.region = region;
+                }
+            }
+            KeyCode::Char('m') | KeyCode::Tab => {
+                self.mode = match self.mode {
+                    Mode::Static => Mode::Breathe,
+                    Mode::Breathe => Mode::Cycle,
+                    Mode::Cycle => Mode::Static,
+                };
+
+                return Action::Apply;
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.slider = SLIDERS[(slider + 3) % 4],
+            KeyCode::Down | KeyCode::Char('j') => self.slider = SLIDERS[(slider + 1) % 4],
+            KeyCode::Left | KeyCode::Char('h') => self.adjust(-1.0, false),
+            KeyCode::Right | KeyCode::Char('l') => self.adjust(1.0, false),
+            KeyCode::PageDown => self.adjust(-1.0, true),
+            KeyCode::PageUp => self.adjust(1.0, true),
+            _ => return Action::Nothing,
+        }
+
+        match key {
+            KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::PageDown
+            | KeyCode::PageUp
+            | KeyCode::Char('h')
+            | KeyCode::Char('l') => Action::Apply,
+            _ => Action::Nothing,
+        }
+    }
+
+    /// The command that sets the lighting being shown, which is what's saved.
+    pub fn command(&self) -> Command {
+        let hex = |region: usize| format!("{:06x}", self.colour(region));
+
+        match self.mode {
+            Mode::Static => {
+                let colours: Vec<String> = (0..self.colours.len()).map(hex).collect();
+
+                match colours.iter().all(|colour| *colour == colours[0]) {
+                    true => Command::Colour(vec![colours[0].clone()]),
+                    false => Command::Regions(colours),
+                }
+            }
+            Mode::Breathe => Command::Breathe(vec![self.speed.to_string(), hex(0)]),
+            Mode::Cycle => Command::Cycle(vec![self.speed.to_string()]),
+        }
+    }
+}
+
+fn rgb(colour: u32) -> Color {
+    Color::Rgb((colour >> 16) as u8, (colour >> 8) as u8, colour as u8)
+}
+
+fn draw_regions(frame: &mut Frame, state: &TuiState, area: Rect) {
+    let regions = state.colours.len();
+    let areas = Layout::horizontal(vec![Constraint::Ratio(1, regions as u32); regions]).split(area);
+
+    for (region, area) in areas.iter().enumerate() {
+        let selected = state.region == 0 || state.region == region + 1;
+
+        let colour = match state.mode {
+            Mode::Static => Some(state.colour(region)),
+            Mode::Breathe => Some(state.colour(0)),
+            Mode::Cycle => None,
+        };
+
+        let border = match selected {
+            true => Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            false => Style::default(),
+        };
+
+        let block = Block::bordered()
+            .title(format!(" {} ", region + 1))
+            .border_style(border);
+
+        let text = match colour {
+            Some(colour) => format!("{:06x}", colour),
+            None => "cycling".to_string(),
+        };
+
+        let style = colour.map_or_else(Style::default, |colour| Style::default().bg(rgb(colour)));
+
+        frame.render_widget(
+            Paragraph::new(text).block(block).style(style).centered(),
+            *area,
+        );
+    }
+}
+
+fn draw(frame: &mut Frame, state: &TuiState) {
+    let [title, regions, sliders, help] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(5),
+        Constraint::Length(12),
+        Constraint::Min(2),
+    ])
+    .areas(frame.area());
+
+    let mode = match state.mode {
+        Mode::Static => "static",
+        Mode::Breathe => "breathe",
+        Mode::Cycle => "cycle",
+    };
+
+    frame.render_widget(
+        Paragraph::new(format!("g213-cols - {} - {}", mode, state.command())),
+        title,
+    );
+
+    draw_regions(frame, state, regions);
+
+    let slider_areas = Layout::vertical([Constraint::Length(3); 4]).split(sliders);
+
+    for (slider, area) in SLIDERS.iter().zip(slider_areas.iter()) {
+        let value = state.slider_value(*slider);
+
+        let (name, max, label) = match slider {
+            Slider::Hue => ("Hue", 360.0, format!("{:.0}°", value)),
+            Slider::Saturation => ("Saturation", 100.0, format!("{:.0}%", value)),
+            Slider::Value => ("Value", 100.0, format!("{:.0}%", value)),
+            Slider::Speed => ("Speed", MAX_TUI_SPEED as f64, format!("{}ms", state.speed)),
+        };
+
+        let border = match *slider == state.slider {
+            true => Style::default().fg(Color::Yellow),
+            false => Style::default(),
+        };
+
+        let gauge = Gauge::default()
+            .block(Block::bordered().title(name).border_style(border))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio((value / max).clamp(0.0, 1.0))
+            .label(label);
+
+        frame.render_widget(gauge, *area);
+    }
+
+    frame.render_widget(
+        Paragraph::new(format!(
+            "1-{} region, a all, m mode, up/down slider, left/right change, pgup/pgdn more\n\
+             s or enter save and quit, q or esc quit without saving  {}",
+            state.colours.len(),
+            state.message
+        )),
+        help,
+    );
+}
+
+/// Lets the lighting be changed with the keyboard, and shows the changes as
+/// they're made. The command for the lighting is returned if it's to be saved.
+pub fn run_tui(
+    terminal: &mut DefaultTerminal,
+    device: &Keyboard,
+    state: &mut TuiState,
+) -> std::io::Result<Option<Command>> {
+    loop {
+        terminal.draw(|frame| draw(frame, state))?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key.code,
+            _ => continue,
+        };
+
+        match state.handle_key(key) {
+            Action::Apply => {
+                state.message = match state.command().run(device).successful() {
+                    true => String::new(),
+                    false => "- the keyboard couldn't be set".to_string(),
+                };
+
+                // Errors are printed over the top of the screen
+                if !state.message.is_empty() {
+                    terminal.clear()?;
+                }
+            }
+            Action::Save => return Ok(Some(state.command())),
+            Action::Quit => return Ok(None),
+            Action::Nothing => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tui_tests {
+    use super::*;
+
+    #[test]
+    fn saved_commands() {
+        let mut state = TuiState::new(&[0xff0000; 5], 2000);
+
+        assert_eq!(state.command().to_string(), "colour ff0000");
+
+        state.handle_key(KeyCode::Char('2'));
+        state.handle_key(KeyCode::PageUp);
+        state.handle_key(KeyCode::PageUp);
+        state.handle_key(KeyCode::PageUp);
+        state.handle_key(KeyCode::PageUp);
+
+        assert_eq!(
+            state.command().to_string(),
+            "regions ff0000 00ff00 ff0000 ff0000 ff0000"
+        );
+
+        assert_eq!(state.handle_key(KeyCode::Char('m')), Action::Apply);
+        assert_eq!(state.command().to_string(), "breathe 2000 ff0000");
+
+        state.handle_key(KeyCode::Tab);
+
+        assert_eq!(state.command().to_string(), "cycle 2000");
+    }
+
+    #[test]
+    fn all_regions_change_together() {
+        let mut state = TuiState::new(&[0xff0000, 0x0000ff], 1000);
+
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Down);
+
+        for _ in 0..25 {
+            assert_eq!(state.handle_key(KeyCode::Left), Action::Apply);
+        }
+
+        assert_eq!(state.command().to_string(), "regions 800000 000080");
+    }
+
+    #[test]
+    fn speed_is_limited() {
+        let mut state = TuiState::new(&[0xff0000], 1000);
+
+        state.handle_key(KeyCode::Up);
+
+        assert_eq!(state.slider, Slider::Speed);
+
+        for _ in 0..5 {
+            state.handle_key(KeyCode::PageDown);
+        }
+
+        assert_eq!(state.speed, 32);
+
+        for _ in 0..30 {
+            state.handle_key(KeyCode::PageUp);
+        }
+
+        assert_eq!(state.speed, MAX_TUI_SPEED);
+    }
+
+    #[test]
+    fn keys_to_leave() {
+        let mut state = TuiState::new(&[0xff0000; 5], 1000);
+
+        assert_eq!(state.handle_key(KeyCode::Char('9')), Action::Nothing);
+        assert_eq!(state.region, 0);
+        assert_eq!(state.handle_key(KeyCode::Char('s')), Action::Save);
+        assert_eq!(state.handle_key(KeyCode::Esc), Action::Quit);
+    }
+}
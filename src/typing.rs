@@ -0,0 +1,265 @@
+use std::fs::{read_to_string, File};
+use std::io::{ErrorKind, Read};
+use std::mem::size_of;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::models::Model;
+
+const INPUT_DEVICES: &str = "/proc/bus/input/devices";
+const EV_KEY: u16 = 1;
+
+// The G213's regions, left to right, by Linux key code, see linux/input-event-codes.h
+const REGION_KEYS: [&[u16]; 5] = [
+    // Esc, F1 to F4, ` to 4, Tab to R, Caps Lock to F, Shift to C, Ctrl, Super, Alt
+    &[
+        1, 59, 60, 61, 62, 41, 2, 3, 4, 5, 15, 16, 17, 18, 19, 58, 30, 31, 32, 33, 42, 86, 44, 45,
+        46, 29, 125, 56,
+    ],
+    // F5 to F8, 5 to 9, T to O, G to L, V to comma, Space
+    &[
+        63, 64, 65, 66, 6, 7, 8, 9, 10, 20, 21, 22, 23, 24, 34, 35, 36, 37, 38, 47, 48, 49, 50, 51,
+        57,
+    ],
+    // F9 to F12, 0 to Backspace, P to \, ; to Enter, . to Shift, Alt Gr, Super, Menu, Ctrl
+    &[
+        67, 68, 87, 88, 11, 12, 13, 14, 25, 26, 27, 43, 39, 40, 28, 52, 53, 54, 100, 126, 127, 97,
+    ],
+    // Print Screen, Scroll Lock, Pause, Insert to Page Down and the arrows
+    &[
+        99, 70, 119, 110, 102, 104, 111, 107, 109, 103, 105, 108, 106,
+    ],
+    // The number pad and media keys
+    &[
+        69, 98, 55, 74, 71, 72, 73, 78, 75, 76, 77, 79, 80, 81, 96, 82, 83, 113, 114, 115, 163,
+        164, 165, 166,
+    ],
+];
+
+/// The region, from 0, a key is under on a keyboard with `regions` regions.
+pub fn key_region(code: u16, regions: u8) -> Option<u8> {
+    let region = REGION_KEYS.iter().position(|keys| keys.contains(&code))?;
+
+    Some((region * regions as usize / REGION_KEYS.len()) as u8)
+}
+
+// Each input device's name, vendor and product, and event handler
+fn parse_input_devices(devices: &str) -> Vec<(String, u16, u16, String)> {
+    devices
+        .split("\n\n")
+        .filter_map(|device| {
+            let mut name = String::new();
+            let (mut vendor, mut product, mut event) = (0, 0, None);
+
+            for line in device.lines() {
+                if let Some(ids) = line.strip_prefix("I: ") {
+                    for field in ids.split_whitespace() {
+                        match field.split_once('=') {
+                            Some(("Vendor", id)) => vendor = u16::from_str_radix(id, 16).ok()?,
+                            Some(("Product", id)) => product = u16::from_str_radix(id, 16).ok()?,
+                            _ => (),
+                        }
+                    }
+                } else if let Some(quoted) = line.strip_prefix("N: Name=") {
+                    name = quoted.trim_matches('"').to_string();
+                } else if let Some(handlers) = line.strip_prefix("H: Handlers=") {
+                    let handlers: Vec<&str> = handlers.split_whitespace().collect();
+
+                    // Only the part with the keys, not the media keys or mouse
+                    if handlers.contains(&"kbd") && handlers.contains(&"leds") {
+                        event = handlers.into_iter().find(|h| h.starts_with("event"));
+                    }
+                }
+            }
+
+            Some((name, vendor, product, event?.to_string()))
+        })
+        .collect()
+}
+
+/// The keyboard's key events, or the first keyboard's if it can't be found.
+pub fn find_key_events(model: Option<&Model>) -> Result<String, String> {
+    let devices = read_to_string(INPUT_DEVICES)
+        .map_err(|err| format!("Unable to read {}: {}", INPUT_DEVICES, err))?;
+    let devices = parse_input_devices(&devices);
+
+    let ours = |(_, vendor, product, _): &&(String, u16, u16, String)| {
+        model.is_some_and(|model| *vendor == model.vendor_id() && *product == model.product_id())
+    };
+
+    devices
+        .iter()
+        .find(ours)
+        .or(devices.first())
+        .map(|(_, _, _, event)| format!("/dev/input/{}", event))
+        .ok_or("No keyboard found in /dev/input, use '--input'".to_string())
+}
+
+/// The key pressed, or held, in one `input_event`.
+pub fn key_pressed(event: &[u8]) -> Option<u16> {
+    // After the time, which is as big as the platform's
+    let at = event.len().checked_sub(8)?;
+    let field = |from: usize| [event[at + from], event[at + from + 1]];
+
+    let kind = u16::from_ne_bytes(field(0));
+    let code = u16::from_ne_bytes(field(2));
+    let value = i32::from_ne_bytes([event[at + 4], event[at + 5], event[at + 6], event[at + 7]]);
+
+    (kind == EV_KEY && value > 0).then_some(code)
+}
+
+/// Keys as they're pressed, read from an evdev device on another thread. The
+/// channel closes if the keyboard goes away.
+pub fn watch_keys(path: &str) -> Result<Receiver<u16>, String> {
+    let mut input = File::open(path).map_err(|err| match err.kind() {
+        ErrorKind::PermissionDenied => format!(
+            "Unable to read key presses from {}, is your user in the 'input' group?",
+            path
+        ),
+        _ => format!("Unable to read key presses from {}: {}", path, err),
+    })?;
+
+    let (sender, receiver) = channel();
+
+    thread::spawn(move || {
+        let mut event = vec![0u8; size_of::<libc::input_event>()];
+
+        while input.read_exact(&mut event).is_ok() {
+            if let Some(code) = key_pressed(&event) {
+                if sender.send(code).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// How brightly each region glows after a key in it was pressed, fading
+/// away over `decay`.
+pub struct Glow {
+    decay: Duration,
+    pressed: Vec<Option<Instant>>,
+}
+
+impl Glow {
+    pub fn new(regions: u8, decay: Duration) -> Glow {
+        Glow {
+            decay,
+            pressed: vec![None; regions as usize],
+        }
+    }
+
+    pub fn press(&mut self, region: u8, at: Instant) {
+        if let Some(pressed) = self.pressed.get_mut(region as usize) {
+            *pressed = Some(at);
+        }
+    }
+
+    /// From 1 just pressed, to 0 when faded away.
+    pub fn levels(&self, now: Instant) -> Vec<f64> {
+        self.pressed
+            .iter()
+            .map(|pressed| match pressed {
+                Some(pressed) => {
+                    let since = now.saturating_duration_since(*pressed);
+
+                    1.0 - (since.as_secs_f64() / self.decay.as_secs_f64()).min(1.0)
+                }
+                None => 0.0,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod typing_tests {
+    use super::*;
+
+    const DEVICES: &str = r#"I: Bus=0003 Vendor=046d Product=c336 Version=0111
+N: Name="Logitech Gaming Keyboard G213"
+H: Handlers=sysrq kbd leds event5
+B: EV=120013
+
+I: Bus=0003 Vendor=046d Product=c336 Version=0111
+N: Name="Logitech Gaming Keyboard G213 Consumer Control"
+H: Handlers=kbd event6
+
+I: Bus=0011 Vendor=0001 Product=0001 Version=ab41
+N: Name="AT Translated Set 2 keyboard"
+H: Handlers=sysrq kbd leds event2
+"#;
+
+    #[test]
+    fn keys_to_regions() {
+        assert_eq!(key_region(1, 5), Some(0));
+        assert_eq!(key_region(57, 5), Some(1));
+        assert_eq!(key_region(28, 5), Some(2));
+        assert_eq!(key_region(103, 5), Some(3));
+        assert_eq!(key_region(82, 5), Some(4));
+        assert_eq!(key_region(82, 1), Some(0));
+        assert_eq!(key_region(0, 5), None);
+
+        // No key is in more than one region
+        let mut keys: Vec<u16> = REGION_KEYS.concat();
+        let all = keys.len();
+
+        keys.sort();
+        keys.dedup();
+
+        assert_eq!(keys.len(), all);
+    }
+
+    #[test]
+    fn input_devices() {
+        assert_eq!(
+            parse_input_devices(DEVICES),
+            [
+                (
+                    "Logitech Gaming Keyboard G213".to_string(),
+                    0x046d,
+                    0xc336,
+                    "event5".to_string()
+                ),
+                (
+                    "AT Translated Set 2 keyboard".to_string(),
+                    1,
+                    1,
+                    "event2".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn key_events() {
+        let event = |kind: u16, code: u16, value: i32| {
+            let mut event = vec![0u8; 16];
+
+            event.extend(kind.to_ne_bytes());
+            event.extend(code.to_ne_bytes());
+            event.extend(value.to_ne_bytes());
+            event
+        };
+
+        assert_eq!(key_pressed(&event(EV_KEY, 30, 1)), Some(30));
+        assert_eq!(key_pressed(&event(EV_KEY, 30, 2)), Some(30));
+        assert_eq!(key_pressed(&event(EV_KEY, 30, 0)), None);
+        assert_eq!(key_pressed(&event(4, 30, 1)), None);
+    }
+
+    #[test]
+    fn glow_fades() {
+        let start = Instant::now();
+        let mut glow = Glow::new(2, Duration::from_millis(400));
+
+        glow.press(1, start);
+        glow.press(7, start);
+
+        assert_eq!(glow.levels(start), [0.0, 1.0]);
+        assert_eq!(glow.levels(start + Duration::from_millis(100)), [0.0, 0.75]);
+        assert_eq!(glow.levels(start + Duration::from_secs(1)), [0.0, 0.0]);
+    }
+}
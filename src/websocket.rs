@@ -0,0 +1,187 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use sha1::{Digest, Sha1};
+
+// From RFC 6455, added to the client's key to show we understood it
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const MAX_MESSAGE: usize = 64 * 1024;
+
+pub const TEXT: u8 = 0x1;
+pub const CLOSE: u8 = 0x8;
+pub const PING: u8 = 0x9;
+pub const PONG: u8 = 0xa;
+
+fn base64(bytes: &[u8]) -> String {
+    bytes
+        .chunks(3)
+        .flat_map(|chunk| {
+            let word = chunk.iter().enumerate().fold(0u32, |word, (i, byte)| {
+                word | (*byte as u32) << (16 - 8 * i)
+            });
+
+            (0..4).map(move |i| match i <= chunk.len() {
+                true => BASE64[(word >> (18 - 6 * i) & 0x3f) as usize] as char,
+                false => '=',
+            })
+        })
+        .collect()
+}
+
+/// The `Sec-WebSocket-Accept` answer to a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    base64(&Sha1::digest(format!("{}{}", key.trim(), ACCEPT_GUID)))
+}
+
+/// An unmasked frame, as sent by servers.
+pub fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend((length as u64).to_be_bytes());
+        }
+    }
+
+    frame.extend(payload);
+    frame
+}
+
+/// The opcode and unmasked payload of the first whole frame in `data`, and
+/// how much of `data` it took up.
+pub fn parse_frame(data: &[u8]) -> Option<(u8, Vec<u8>, usize)> {
+    let opcode = data.first()? & 0x0f;
+    let second = *data.get(1)?;
+
+    let (length, mut at) = match second & 0x7f {
+        126 => (
+            u16::from_be_bytes(data.get(2..4)?.try_into().ok()?) as usize,
+            4,
+        ),
+        127 => (
+            u64::from_be_bytes(data.get(2..10)?.try_into().ok()?) as usize,
+            10,
+        ),
+        length => (length as usize, 2),
+    };
+
+    // Clients always mask their frames
+    let mask = match second & 0x80 {
+        0 => [0; 4],
+        _ => {
+            at += 4;
+
+            data.get(at - 4..at)?.try_into().ok()?
+        }
+    };
+
+    let payload = data
+        .get(at..at.checked_add(length)?)?
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ mask[i % 4])
+        .collect();
+
+    Some((opcode, payload, at + length))
+}
+
+/// A client that's been upgraded to a WebSocket, read without waiting.
+pub struct WebSocket {
+    stream: TcpStream,
+    received: Vec<u8>,
+}
+
+impl WebSocket {
+    pub fn new(stream: TcpStream) -> std::io::Result<WebSocket> {
+        stream.set_nonblocking(true)?;
+
+        Ok(WebSocket {
+            stream,
+            received: Vec::new(),
+        })
+    }
+
+    /// Text messages that have arrived, answering pings, or nothing once
+    /// the client has gone.
+    pub fn messages(&mut self) -> Option<Vec<String>> {
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return None,
+                Ok(read) => self.received.extend(&buffer[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return None,
+            }
+        }
+
+        let mut messages = Vec::new();
+
+        while let Some((opcode, payload, used)) = parse_frame(&self.received) {
+            self.received.drain(..used);
+
+            match opcode {
+                TEXT => messages.push(String::from_utf8_lossy(&payload).to_string()),
+                PING => self.send(PONG, &payload).ok()?,
+                CLOSE => {
+                    let _ = self.send(CLOSE, &[]);
+
+                    return None;
+                }
+                _ => (),
+            }
+        }
+
+        // Too big to ever be a command
+        match self.received.len() > MAX_MESSAGE {
+            true => None,
+            false => Some(messages),
+        }
+    }
+
+    pub fn send(&mut self, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+        self.stream.write_all(&frame(opcode, payload))
+    }
+}
+
+#[cfg(test)]
+mod websocket_tests {
+    use super::*;
+
+    #[test]
+    fn handshake() {
+        // The example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn frames() {
+        assert_eq!(frame(TEXT, b"hi"), [0x81, 2, b'h', b'i']);
+        assert_eq!(&frame(TEXT, &[0; 300])[..4], [0x81, 126, 1, 44]);
+
+        // "Hello", masked, from RFC 6455
+        let masked = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+
+        assert_eq!(
+            parse_frame(&masked),
+            Some((TEXT, b"Hello".to_vec(), masked.len()))
+        );
+        assert_eq!(parse_frame(&masked[..6]), None);
+        assert_eq!(parse_frame(&frame(CLOSE, &[])), Some((CLOSE, vec![], 2)));
+    }
+}
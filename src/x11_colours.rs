@@ -1,7 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use rand::random;
 
+use crate::config::config;
+use crate::palette::palette;
+
 const X11_COLOURS: &str = include_str!("../rgb.txt");
 
 type ColourLookup = HashMap<String, u32>;
@@ -80,28 +83,360 @@ fn adjust_3_digit_colour(colour: u32) -> u32 {
     }
 }
 
+// 'ff00aa', '0xff00aa' or CSS style '#ff00aa', with 3 digits being shorthand for 6.
+// CSS has no other lengths, but plain and 0x hex can be shorter, eg 'ff' for blue
+fn get_hex_colour(arg: &str) -> Option<u32> {
+    let (digits, css) = match arg.strip_prefix('#') {
+        Some(digits) => (digits, true),
+        None => match arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+            Some(digits) => (digits, false),
+            None => (arg, false),
+        },
+    };
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let colour = u32::from_str_radix(digits, 16).ok()? & 0xffffff;
+
+    match digits.len() {
+        3 => Some(adjust_3_digit_colour(colour)),
+        6 => Some(colour),
+        _ if css => None,
+        _ => Some(colour),
+    }
+}
+
+// The 0 to 255 value of an rgb() channel, which can also be a percentage
+fn rgb_channel(value: &str) -> Option<u32> {
+    match value.strip_suffix('%') {
+        Some(percent) => {
+            let percent = percent.parse::<f32>().ok()?;
+
+            (0.0..=100.0)
+                .contains(&percent)
+                .then(|| (percent * 2.55).round() as u32)
+        }
+        None => value.parse::<u8>().ok().map(u32::from),
+    }
+}
+
+// The colour of `hue` with the given chroma, plus `m` on each channel. All but
+// the hue are percentages, kept so until the end to round the same as browsers,
+// eg to 26 rather than 25 when exactly 25.5
+fn hue_to_rgb(hue: f64, chroma: f64, m: f64) -> u32 {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let channel = |value: f64| ((value + m) * 255.0 / 100.0).round() as u32;
+
+    channel(r) << 16 | channel(g) << 8 | channel(b)
+}
+
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> u32 {
+    let chroma = (100.0 - (2.0 * lightness - 100.0).abs()) * saturation / 100.0;
+
+    hue_to_rgb(hue, chroma, lightness - chroma / 2.0)
+}
+
+// Hue in degrees, saturation and lightness as percentages
+pub(crate) fn rgb_to_hsl(colour: u32) -> (f64, f64, f64) {
+    let channel = |shift: u32| ((colour >> shift) & 0xff) as f64 / 255.0;
+    let (r, g, b) = (channel(16), channel(8), channel(0));
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    let lightness = (max + min) / 2.0;
+
+    if chroma == 0.0 {
+        return (0.0, 0.0, lightness * 100.0);
+    }
+
+    let hue = match max {
+        _ if max == r => ((g - b) / chroma).rem_euclid(6.0),
+        _ if max == g => (b - r) / chroma + 2.0,
+        _ => (r - g) / chroma + 4.0,
+    } * 60.0;
+
+    let saturation = chroma / (1.0 - (2.0 * lightness - 1.0).abs());
+
+    (hue, saturation * 100.0, lightness * 100.0)
+}
+
+// Hue in degrees, saturation and value as percentages
+pub(crate) fn rgb_to_hsv(colour: u32) -> (f64, f64, f64) {
+    let (hue, _, _) = rgb_to_hsl(colour);
+    let channel = |shift: u32| ((colour >> shift) & 0xff) as f64 / 255.0;
+
+    let max = channel(16).max(channel(8)).max(channel(0));
+    let min = channel(16).min(channel(8)).min(channel(0));
+    let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+
+    (hue, saturation * 100.0, max * 100.0)
+}
+
+pub(crate) fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> u32 {
+    let chroma = value * saturation / 100.0;
+
+    hue_to_rgb(hue, chroma, value - chroma)
+}
+
+// A 0 to 100 percentage, which must have a '%' if `required`
+fn percentage(value: &str, required: bool) -> Option<f64> {
+    let value = match value.strip_suffix('%') {
+        Some(value) => value,
+        None if required => return None,
+        None => value,
+    };
+
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+}
+
+// CSS 'rgb(255, 0, 128)' and 'hsl(210, 80%, 50%)', as copied from browser dev
+// tools, and 'hsv(210, 80, 50)' for scripts, where the '%'s are optional.
+// Spaces can separate the values too, and any alpha is ignored
+fn get_css_colour(arg: &str) -> Option<u32> {
+    let arg = arg.trim().to_ascii_lowercase();
+    let (function, values) = arg.strip_suffix(')')?.split_once('(')?;
+    let values: Vec<&str> = values
+        .split([',', ' ', '/'])
+        .filter(|value| !value.is_empty())
+        .collect();
+
+    if !(3..=4).contains(&values.len()) {
+        return None;
+    }
+
+    let hue = || {
+        values[0]
+            .strip_suffix("deg")
+            .unwrap_or(values[0])
+            .parse::<f64>()
+            .ok()
+            .filter(|hue| hue.is_finite())
+    };
+
+    match function.trim() {
+        "rgb" | "rgba" => {
+            let mut colour = 0;
+
+            for value in &values[..3] {
+                colour = colour << 8 | rgb_channel(value)?;
+            }
+
+            Some(colour)
+        }
+        "hsl" | "hsla" => Some(hsl_to_rgb(
+            hue()?,
+            percentage(values[1], true)?,
+            percentage(values[2], true)?,
+        )),
+        "hsv" | "hsva" => Some(hsv_to_rgb(
+            hue()?,
+            percentage(values[1], false)?,
+            percentage(values[2], false)?,
+        )),
+        _ => None,
+    }
+}
+
+// CIE L*a*b*, where distances are close to how different colours look
+fn rgb_to_lab(colour: u32) -> (f64, f64, f64) {
+    let linear = |shift: u32| {
+        let value = ((colour >> shift) & 0xff) as f64 / 255.0;
+
+        match value <= 0.04045 {
+            true => value / 12.92,
+            false => ((value + 0.055) / 1.055).powf(2.4),
+        }
+    };
+    let (r, g, b) = (linear(16), linear(8), linear(0));
+
+    // sRGB to XYZ, relative to the D65 white point
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f64| match t > 216.0 / 24389.0 {
+        true => t.cbrt(),
+        false => (24389.0 / 27.0 * t + 16.0) / 116.0,
+    };
+
+    (
+        116.0 * f(y) - 16.0,
+        500.0 * (f(x) - f(y)),
+        200.0 * (f(y) - f(z)),
+    )
+}
+
+/// The CIE76 difference between two colours, about 2.3 is just noticeable.
+pub fn colour_distance(a: u32, b: u32) -> f64 {
+    let (l1, a1, b1) = rgb_to_lab(a);
+    let (l2, a2, b2) = rgb_to_lab(b);
+
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+/// The X11 colour that looks closest to `colour`, its value and how far away it is.
+pub fn nearest_x11_colour(colour: u32) -> (&'static String, u32, f64) {
+    DEFINITIONS
+        .iter()
+        .map(|(name, x11)| (name, *x11, colour_distance(colour, *x11)))
+        .fold(
+            None,
+            |nearest: Option<(&String, u32, f64)>, candidate| match nearest {
+                Some(nearest) if nearest.2 <= candidate.2 => Some(nearest),
+                _ => Some(candidate),
+            },
+        )
+        .unwrap()
+}
+
 pub const NUM_X11_COLOURS: usize = 759;
 pub const DEFAULT_WHITE: u32 = 0xffd0c0;
-const RANDOM: &str = "random";
-const RANDOM_X11: &str = "randomx11";
+pub(crate) const RANDOM: &str = "random";
+pub(crate) const RANDOM_X11: &str = "randomx11";
 
 pub fn x11_colour_names() -> Vec<&'static String> {
     COLOUR_NAMES.iter().collect()
 }
 
+// User names are matched ignoring case and spaces, as 'regions' runs words together
+fn find_named<'a, T>(names: &'a BTreeMap<String, T>, name: &str) -> Option<&'a T> {
+    let squash = |name: &str| name.replace(' ', "").to_ascii_lowercase();
+    let name = squash(name);
+
+    names
+        .iter()
+        .find(|(user_name, _)| squash(user_name) == name)
+        .map(|(_, colour)| colour)
+}
+
+// The values between a function's brackets, split at the commas that aren't
+// in a nested function, eg 'rgb(1,2,3), blue' is two values
+fn function_values<'a>(name: &'a str, function: &str) -> Option<Vec<&'a str>> {
+    let name = name.trim();
+    let values = name
+        .get(..function.len())
+        .filter(|start| start.eq_ignore_ascii_case(function))
+        .and_then(|_| name[function.len()..].trim_start().strip_prefix('('))?
+        .strip_suffix(')')?;
+
+    let mut depth = 0;
+    let mut start = 0;
+    let mut split = Vec::new();
+
+    for (i, c) in values.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(values[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    split.push(values[start..].trim());
+
+    Some(split)
+}
+
+pub(crate) fn mix_colours(from: u32, to: u32, percent: f64) -> u32 {
+    let channel = |shift: u32| {
+        let start = ((from >> shift) & 0xff) as f64;
+        let end = ((to >> shift) & 0xff) as f64;
+
+        ((start + (end - start) * percent / 100.0).round() as u32) << shift
+    };
+
+    channel(16) | channel(8) | channel(0)
+}
+
+// 'mix(red, blue, 30%)' is 30% of the way from red to blue, half way if not given
+fn get_mixed_colour(name: &str) -> Option<u32> {
+    let values = function_values(name, "mix")?;
+
+    let percent = match values.len() {
+        2 => 50.0,
+        3 => percentage(values[2], false)?,
+        _ => return None,
+    };
+
+    let colour = |value: &str| get_x11_colour(&[value.to_string()]);
+
+    Some(mix_colours(colour(values[0])?, colour(values[1])?, percent))
+}
+
+/// A colour from a hex string, X11 name, 'random', one of the `[colours]`
+/// aliases in config.toml, an imported palette colour, or a mix of two colours.
 pub fn get_x11_colour(args: &[String]) -> Option<u32> {
+    if args.is_empty() {
+        return Some(DEFAULT_WHITE);
+    }
+
+    let name = args.join(" ");
+
+    if let Some(colour) = get_mixed_colour(&name) {
+        return Some(colour);
+    }
+
+    // Aliases can't refer to other aliases, so can't loop
+    if let Some(colour) = find_named(&config().colours, &name) {
+        return get_plain_colour(std::slice::from_ref(colour));
+    }
+
+    match find_named(palette(), &name) {
+        Some(colour) => Some(*colour),
+        None => get_plain_colour(args).or_else(|| get_adjusted_colour(&name)),
+    }
+}
+
+// 'steelblue+20' is 20% lighter, and 'steelblue-40' 40% darker, by changing
+// the HSL lightness, eg from 50% to 70%
+fn get_adjusted_colour(name: &str) -> Option<u32> {
+    let name = name.trim().trim_end_matches('%');
+    let sign = name.rfind(['+', '-'])?;
+    let amount = name[sign + 1..]
+        .parse::<u8>()
+        .ok()
+        .filter(|amount| *amount <= 100)? as f64;
+
+    let colour = get_x11_colour(&[name[..sign].trim().to_string()])?;
+    let (hue, saturation, lightness) = rgb_to_hsl(colour);
+
+    let lightness = match &name[sign..sign + 1] {
+        "+" => (lightness + amount).min(100.0),
+        _ => (lightness - amount).max(0.0),
+    };
+
+    Some(hsl_to_rgb(hue, saturation, lightness))
+}
+
+fn get_plain_colour(args: &[String]) -> Option<u32> {
     let mut colour: Option<u32> = None;
 
     if args.is_empty() {
         colour = Some(DEFAULT_WHITE);
     } else if args.len() == 1 {
-        if let Ok(mut numeric_col) = u32::from_str_radix(args[0].trim_start_matches("0x"), 16) {
-            numeric_col &= 0xffffff;
-
-            let digits = args[0].trim_start_matches("0x").len();
-            if digits == 3 {
-                numeric_col = adjust_3_digit_colour(numeric_col);
-            }
+        if let Some(numeric_col) = get_hex_colour(&args[0]) {
+            colour = Some(numeric_col);
+        } else if let Some(numeric_col) = get_css_colour(&args[0]) {
             colour = Some(numeric_col);
         } else if args[0].to_ascii_lowercase() == RANDOM {
             colour = Some(random_colour())
@@ -113,7 +448,7 @@ pub fn get_x11_colour(args: &[String]) -> Option<u32> {
     } else {
         let name = args.join(" ");
 
-        if let Some(named_col) = get_colour_def(&name) {
+        if let Some(named_col) = get_colour_def(&name).or_else(|| get_css_colour(&name)) {
             colour = Some(named_col)
         }
     }
@@ -121,6 +456,24 @@ pub fn get_x11_colour(args: &[String]) -> Option<u32> {
     colour
 }
 
+/// As many colours as `args` name, eg `dark red white`, or nothing if
+/// any words are left over.
+pub fn get_x11_colour_list(args: &[String]) -> Option<Vec<u32>> {
+    let mut colours = Vec::new();
+    let mut name = String::new();
+
+    for arg in args {
+        name += arg;
+
+        if let Some(colour) = get_x11_colour(&[name.clone()]) {
+            colours.push(colour);
+            name.clear();
+        }
+    }
+
+    Some(colours).filter(|_| name.is_empty())
+}
+
 pub fn get_x11_colours(args: &[String], num: u8) -> Option<Vec<u32>> {
     let mut col_str: String = "".to_string();
     let mut n: u8 = 0;
@@ -148,7 +501,8 @@ pub fn get_x11_colours(args: &[String], num: u8) -> Option<Vec<u32>> {
         n = num;
     }
 
-    if !cols.is_empty() && n < num {
+    // Words left over that never made a colour are a mistake, not ignored
+    if !cols.is_empty() && n < num && col_str.is_empty() {
         while n != num {
             cols.push(get_x11_colour(&[last_col_str.clone()]).unwrap());
             n += 1;
@@ -162,6 +516,125 @@ pub fn get_x11_colours(args: &[String], num: u8) -> Option<Vec<u32>> {
     }
 }
 
+// Typos, as insertions, deletions, substitutions or swapped letters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    d[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// The colour names, aliases and palette colours that are a few typos from
+/// `name`, ignoring case, spaces and underscores, the closest first.
+pub fn similar_colour_names(name: &str) -> Vec<String> {
+    let squash = |name: &str| name.replace([' ', '_'], "").to_ascii_lowercase();
+    let name = squash(name);
+    let max_distance = (name.len() / 4).clamp(1, 3);
+
+    let mut seen = HashSet::new();
+    let mut similar: Vec<(usize, String)> = config()
+        .colours
+        .keys()
+        .chain(palette().keys())
+        .chain(COLOUR_NAMES.iter())
+        .filter(|candidate| seen.insert(squash(candidate)))
+        .map(|candidate| (edit_distance(&name, &squash(candidate)), candidate.clone()))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+
+    similar.sort();
+
+    similar
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// The one colour name that's closer to `name` than any other, if there is one.
+pub fn close_colour(name: &str) -> Option<String> {
+    let squash = |name: &str| name.replace([' ', '_'], "").to_ascii_lowercase();
+    let similar = similar_colour_names(name);
+    let distance = |candidate: &String| edit_distance(&squash(name), &squash(candidate));
+
+    match similar.as_slice() {
+        [only] => Some(only.clone()),
+        [first, second, ..] if distance(first) < distance(second) => Some(first.clone()),
+        _ => None,
+    }
+}
+
+/// The families colours are grouped into, in the order of the rainbow.
+pub const HUE_FAMILIES: [&str; 9] = [
+    "reds", "oranges", "yellows", "greens", "cyans", "blues", "purples", "pinks", "greys",
+];
+
+/// The index in [`HUE_FAMILIES`] of the colour's family, and a hue that
+/// sorts in rainbow order, for reds which are either side of 0.
+pub fn hue_family(colour: u32) -> (usize, f64) {
+    let (hue, saturation, lightness) = rgb_to_hsl(colour);
+    let hue = (hue + 25.0) % 360.0;
+
+    if saturation < 10.0 || !(8.0..=96.0).contains(&lightness) {
+        return (HUE_FAMILIES.len() - 1, hue);
+    }
+
+    let family = [40.0, 70.0, 95.0, 185.0, 225.0, 285.0, 315.0, 360.0]
+        .iter()
+        .position(|end| hue < *end)
+        .unwrap_or(0);
+
+    (family, hue)
+}
+
+pub fn gradient_colours(from: u32, to: u32, num: u8) -> Vec<u32> {
+    let steps = (num as i32 - 1).max(1);
+
+    let channel = |colour: u32, shift: u32| ((colour >> shift) & 0xff) as i32;
+
+    let interpolate = |step: i32, shift: u32| {
+        let start = channel(from, shift);
+        let end = channel(to, shift);
+
+        ((start + (end - start) * step / steps) as u32) << shift
+    };
+
+    (0..num as i32)
+        .map(|step| interpolate(step, 16) | interpolate(step, 8) | interpolate(step, 0))
+        .collect()
+}
+
+/// Hues evenly spaced around the colour wheel, starting from `hue`.
+pub fn rainbow_colours(hue: f64, saturation: f64, value: f64, num: u8) -> Vec<u32> {
+    (0..num)
+        .map(|step| {
+            let hue = (hue + 360.0 * step as f64 / num.max(1) as f64) % 360.0;
+
+            hsv_to_rgb(hue, saturation, value)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod x11_colours_tests {
     use crate::g213_keyboard::NUM_REGIONS;
@@ -318,6 +791,259 @@ mod x11_colours_tests {
         assert_eq!(get_x11_colour(&args), Some(0xbeefee));
     }
 
+    #[test]
+    fn get_colour_css_hex() {
+        assert_eq!(
+            get_x11_colour(&to_string_vec(vec!["#ff00aa"])),
+            Some(0xff00aa)
+        );
+        assert_eq!(
+            get_x11_colour(&to_string_vec(vec!["#FF00AA"])),
+            Some(0xff00aa)
+        );
+        assert_eq!(get_x11_colour(&to_string_vec(vec!["#fff"])), Some(0xffffff));
+        assert_eq!(get_x11_colour(&to_string_vec(vec!["#1af"])), Some(0x11aaff));
+    }
+
+    #[test]
+    fn get_colour_0x_hex_3digits() {
+        assert_eq!(
+            get_x11_colour(&to_string_vec(vec!["0x1af"])),
+            Some(0x11aaff)
+        );
+        assert_eq!(
+            get_x11_colour(&to_string_vec(vec!["0X1AF"])),
+            Some(0x11aaff)
+        );
+    }
+
+    #[test]
+    fn get_colour_none_for_bad_hex() {
+        for bad in ["#ff00", "#ff00aa00", "#", "0x", "#gggggg", "0x+ff", "##fff"] {
+            assert_eq!(get_hex_colour(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn get_5_css_hex_colours() {
+        let args = to_string_vec(vec!["#f00", "#00ff00", "0x0000ff"]);
+
+        assert_eq!(
+            get_x11_colours(&args, 5),
+            Some(vec![0xff0000, 0xff00, 0xff, 0xff, 0xff])
+        );
+    }
+
+    #[test]
+    fn get_colour_css_rgb() {
+        assert_eq!(get_css_colour("rgb(255,0,128)"), Some(0xff0080));
+        assert_eq!(get_css_colour("RGB( 255, 0, 128 )"), Some(0xff0080));
+        assert_eq!(get_css_colour("rgb(255 0 128 / 50%)"), Some(0xff0080));
+        assert_eq!(get_css_colour("rgba(255,0,128,0.5)"), Some(0xff0080));
+        assert_eq!(get_css_colour("rgb(100%,0%,50%)"), Some(0xff0080));
+    }
+
+    #[test]
+    fn get_colour_css_hsl() {
+        assert_eq!(get_css_colour("hsl(0,100%,50%)"), Some(0xff0000));
+        assert_eq!(get_css_colour("hsl(120, 100%, 25%)"), Some(0x008000));
+        assert_eq!(get_css_colour("hsl(210,80%,50%)"), Some(0x1a80e6));
+        assert_eq!(get_css_colour("hsl(300deg 100% 50%)"), Some(0xff00ff));
+        assert_eq!(get_css_colour("hsl(-60,100%,50%)"), Some(0xff00ff));
+        assert_eq!(get_css_colour("hsl(0,0%,100%)"), Some(0xffffff));
+        assert_eq!(get_css_colour("hsl(0,0%,0%)"), Some(0x000000));
+    }
+
+    #[test]
+    fn get_colour_hsv() {
+        assert_eq!(get_css_colour("hsv(0,100,100)"), Some(0xff0000));
+        assert_eq!(get_css_colour("hsv(120,100%,50%)"), Some(0x008000));
+        assert_eq!(get_css_colour("hsv(210 80 50)"), Some(0x1a4d80));
+        assert_eq!(get_css_colour("hsv(240,0,100)"), Some(0xffffff));
+        assert_eq!(get_css_colour("hsv(240,100,0)"), Some(0x000000));
+        assert_eq!(get_css_colour("hsv(0,101,100)"), None);
+    }
+
+    #[test]
+    fn mixed_colours() {
+        let mix = |name: &str| get_x11_colour(&[name.to_string()]);
+
+        assert_eq!(mix("mix(red,blue)"), Some(0x800080));
+        assert_eq!(mix("mix(red, blue, 30%)"), Some(0xb3004d));
+        assert_eq!(mix("MIX(red,blue,0)"), Some(0xff0000));
+        assert_eq!(mix("mix(red,blue,100%)"), Some(0x0000ff));
+        assert_eq!(mix("mix(rgb(255,255,255), #000, 25%)"), Some(0xbfbfbf));
+        assert_eq!(mix("mix(mix(red,blue),lawn green,50)"), Some(0x7e7e40));
+    }
+
+    #[test]
+    fn mixed_colours_split_by_shell() {
+        let args = to_string_vec(vec!["mix(red,", "dark", "blue,", "50%)"]);
+
+        assert_eq!(get_x11_colour(&args), Some(0x800046));
+        assert_eq!(
+            get_x11_colours(&to_string_vec(vec!["mix(red,", "blue)", "green"]), 2),
+            Some(vec![0x800080, 0x00ff00])
+        );
+    }
+
+    #[test]
+    fn bad_mixed_colours() {
+        for bad in [
+            "mix(red)",
+            "mix(red,blue,101%)",
+            "mix(red,bluuue)",
+            "mix(red,blue,5%,6%)",
+            "mix(red,,blue)",
+            "mix(red,blue",
+            "mixer(red,blue)",
+        ] {
+            assert_eq!(get_x11_colour(&[bad.to_string()]), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn hsl_round_trip() {
+        for colour in [
+            0x4682b4, 0xff0000, 0x1a80e6, 0xc71585, 0x808080, 0x0, 0xffffff,
+        ] {
+            let (hue, saturation, lightness) = rgb_to_hsl(colour);
+
+            assert_eq!(
+                hsl_to_rgb(hue, saturation, lightness),
+                colour,
+                "{:06x}",
+                colour
+            );
+        }
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for colour in [
+            0x4682b4, 0xff0000, 0x1a80e6, 0xc71585, 0x808080, 0x0, 0xffffff,
+        ] {
+            let (hue, saturation, value) = rgb_to_hsv(colour);
+
+            assert_eq!(hsv_to_rgb(hue, saturation, value), colour, "{:06x}", colour);
+        }
+    }
+
+    #[test]
+    fn lighter_and_darker_colours() {
+        let adjust = |name: &str| get_x11_colour(&to_string_vec(name.split(' ').collect()));
+
+        assert_eq!(adjust("red+20"), Some(0xff6666));
+        assert_eq!(adjust("red-20"), Some(0x990000));
+        assert_eq!(adjust("red+20%"), Some(0xff6666));
+        assert_eq!(adjust("steel blue-100"), Some(0x000000));
+        assert_eq!(adjust("steelblue+100"), Some(0xffffff));
+        assert_eq!(adjust("#808080+0"), Some(0x808080));
+        assert_eq!(adjust("mix(red,blue)-10"), Some(0x4d004d));
+        assert_eq!(adjust("red+101"), None);
+        assert_eq!(adjust("red+"), None);
+        assert_eq!(adjust("+20"), None);
+        assert_eq!(adjust("bluuue-20"), None);
+    }
+
+    #[test]
+    fn get_colour_none_for_bad_css() {
+        for bad in [
+            "rgb(256,0,0)",
+            "rgb(1,2)",
+            "rgb(-1,0,0)",
+            "rgb(101%,0,0)",
+            "hsl(0,100,50)",
+            "hsl(0,100%,150%)",
+            "hsl(nan,0%,0%)",
+            "cmyk(0,0,0,0)",
+            "rgb(1,2,3",
+        ] {
+            assert_eq!(get_css_colour(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn get_colour_css_split_by_shell() {
+        let args = to_string_vec(vec!["rgb(255,", "0,", "128)"]);
+
+        assert_eq!(get_x11_colour(&args), Some(0xff0080));
+        assert_eq!(get_x11_colours(&args, 2), Some(vec![0xff0080, 0xff0080]));
+    }
+
+    #[test]
+    fn nearest_colours() {
+        assert_eq!(
+            nearest_x11_colour(0x4682b4),
+            (&"steel blue".to_string(), 0x4682b4, 0.0)
+        );
+
+        let (name, colour, distance) = nearest_x11_colour(0x4683b5);
+
+        assert_eq!((name.as_str(), colour), ("steel blue", 0x4682b4));
+        assert!(distance > 0.0 && distance < 1.0);
+
+        assert_eq!(nearest_x11_colour(0xfe0101).0, "red");
+        assert_eq!(nearest_x11_colour(0x010101).0, "black");
+    }
+
+    #[test]
+    fn lab_distances() {
+        assert_eq!(colour_distance(0xff0000, 0xff0000), 0.0);
+        assert!((colour_distance(0x000000, 0xffffff) - 100.0).abs() < 0.01);
+        assert!(colour_distance(0x808080, 0x818181) < colour_distance(0x808080, 0x8080ff));
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("green", "green"), 0);
+        assert_eq!(edit_distance("gren", "green"), 1);
+        assert_eq!(edit_distance("darkslategrey", "darkslategray"), 1);
+        assert_eq!(edit_distance("", "red"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("bleu", "blue"), 1);
+    }
+
+    #[test]
+    fn similar_names() {
+        assert_eq!(similar_colour_names("lawngren"), vec!["lawn green"]);
+        assert_eq!(similar_colour_names("gren"), vec!["green", "grey"]);
+        assert!(similar_colour_names("xyzzy").is_empty());
+    }
+
+    #[test]
+    fn close_colours() {
+        assert_eq!(
+            close_colour("Dark SlateGray").as_deref(),
+            Some("dark slate gray")
+        );
+        assert_eq!(
+            close_colour("darkslatgray").as_deref(),
+            Some("dark slate gray")
+        );
+        assert_eq!(close_colour("alice_bleu").as_deref(), Some("alice blue"));
+        assert_eq!(close_colour("gren"), None);
+        assert_eq!(close_colour("xyzzy"), None);
+    }
+
+    #[test]
+    fn hue_families() {
+        let family = |name: &str| HUE_FAMILIES[hue_family(get_colour_def(name).unwrap()).0];
+
+        assert_eq!(family("red"), "reds");
+        assert_eq!(family("crimson"), "reds");
+        assert_eq!(family("dark orange"), "oranges");
+        assert_eq!(family("gold"), "yellows");
+        assert_eq!(family("lawn green"), "greens");
+        assert_eq!(family("turquoise"), "cyans");
+        assert_eq!(family("steel blue"), "blues");
+        assert_eq!(family("blue violet"), "purples");
+        assert_eq!(family("hot pink"), "pinks");
+        assert_eq!(family("slate gray"), "blues");
+        assert_eq!(family("gray50"), "greys");
+        assert_eq!(family("black"), "greys");
+    }
+
     #[test]
     fn get_random_colour() {
         let col = random_colour();
@@ -343,6 +1069,13 @@ mod x11_colours_tests {
         );
     }
 
+    #[test]
+    fn get_5_colours_none_for_unknown_last() {
+        let args = to_string_vec(vec!["red", "bluuue"]);
+
+        assert_eq!(get_x11_colours(&args, 5), None);
+    }
+
     #[test]
     fn get_5_colours_empty_args() {
         let args = to_string_vec(vec![]);
@@ -389,6 +1122,18 @@ mod x11_colours_tests {
         assert_eq!(get_x11_colours(&args, 2), Some(vec![0xf0f8ff, 0xc71585]));
     }
 
+    #[test]
+    fn colour_lists() {
+        let list = |args| get_x11_colour_list(&to_string_vec(args));
+
+        assert_eq!(
+            list(vec!["dark", "red", "white", "0x123456"]),
+            Some(vec![0x8b0000, 0xffffff, 0x123456])
+        );
+        assert_eq!(list(vec![]), Some(vec![]));
+        assert_eq!(list(vec!["red", "dark"]), None);
+    }
+
     #[test]
     fn adjust_3_digit_111() {
         assert_eq!(adjust_3_digit_colour(0x111), 0x111111);
@@ -413,4 +1158,57 @@ mod x11_colours_tests {
     fn adjust_3_digit_3678_unchanged() {
         assert_eq!(adjust_3_digit_colour(0x3678), 0x3678);
     }
+
+    #[test]
+    fn gradient_black_to_white() {
+        assert_eq!(
+            gradient_colours(0x000000, 0xffffff, 5),
+            vec![0x000000, 0x3f3f3f, 0x7f7f7f, 0xbfbfbf, 0xffffff]
+        );
+    }
+
+    #[test]
+    fn gradient_per_channel() {
+        assert_eq!(
+            gradient_colours(0xff0000, 0x0000ff, 3),
+            vec![0xff0000, 0x80007f, 0x0000ff]
+        );
+    }
+
+    #[test]
+    fn gradient_single_colour() {
+        assert_eq!(gradient_colours(0x123456, 0xffffff, 1), vec![0x123456]);
+    }
+
+    #[test]
+    fn rainbow_around_the_wheel() {
+        assert_eq!(
+            rainbow_colours(0.0, 100.0, 100.0, 3),
+            vec![0xff0000, 0x00ff00, 0x0000ff]
+        );
+        assert_eq!(
+            rainbow_colours(240.0, 100.0, 100.0, 2),
+            vec![0x0000ff, 0xffff00]
+        );
+        assert_eq!(rainbow_colours(0.0, 0.0, 50.0, 2), vec![0x808080; 2]);
+    }
+
+    #[test]
+    fn aliases_ignore_case_and_spaces() {
+        let aliases = BTreeMap::from([
+            ("desk".to_string(), "0x20c0ff".to_string()),
+            ("My Warning".to_string(), "orange red".to_string()),
+        ]);
+
+        assert_eq!(find_named(&aliases, "DESK"), Some(&"0x20c0ff".to_string()));
+        assert_eq!(
+            find_named(&aliases, "mywarning"),
+            Some(&"orange red".to_string())
+        );
+        assert_eq!(find_named(&aliases, "red"), None);
+        assert_eq!(
+            get_plain_colour(&["orange red".to_string()]),
+            Some(0xff4500)
+        );
+    }
 }